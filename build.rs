@@ -201,7 +201,7 @@ fn compile_i18n() {
             writer,
             "];\n\
         \n\
-        const TRANSLATIONS: [[&str; {}]; {}] = [\n",
+        static TRANSLATIONS: [[&str; {}]; {}] = [\n",
             translations.len(),
             languages.len(),
         );
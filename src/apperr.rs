@@ -8,6 +8,8 @@ use std::{io, result};
 use crate::sys;
 
 pub const APP_ICU_MISSING: Error = Error::new_app(0);
+pub const APP_BINARY_FILE: Error = Error::new_app(1);
+pub const APP_REMOTE_FAILED: Error = Error::new_app(2);
 
 /// Edit's transparent `Result` type.
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Autosave writes modified documents to their files without being asked,
+//! after a while without input or when the terminal loses focus. See the
+//! `autosave_*` settings in [`crate::config`]. It's off by default.
+//!
+//! Untitled and read-only documents are left alone, as are files that changed on disk,
+//! since saving them needs a decision from the user.
+
+use std::time::Duration;
+
+use edit::tui::Context;
+
+use crate::documents::Document;
+use crate::localization::*;
+use crate::state::*;
+
+impl Document {
+    /// Whether the document has changes that autosave should write.
+    fn autosave_pending(&self) -> bool {
+        let tb = self.buffer.borrow();
+        self.path.is_some()
+            && tb.is_dirty()
+            && !tb.is_read_only()
+            && self.autosave_failed != Some(tb.generation())
+    }
+}
+
+impl State {
+    /// Returns how long until [`draw_handle_autosave`] needs to be called.
+    pub fn autosave_timeout(&self) -> Duration {
+        let delay = self.config.autosave_delay;
+        if delay.is_zero() || !self.documents.iter().any(Document::autosave_pending) {
+            return Duration::MAX;
+        }
+        delay.saturating_sub(self.last_input.elapsed())
+    }
+}
+
+/// Saves the documents with pending changes. Those that can't be saved are reported
+/// and not tried again until they change.
+pub fn draw_handle_autosave(ctx: &mut Context, state: &mut State) {
+    state.wants_autosave = false;
+
+    let mut attempted = false;
+    let mut messages = Vec::new();
+    let mut errors = Vec::new();
+
+    for doc in state.documents.iter_mut() {
+        if !doc.autosave_pending() {
+            continue;
+        }
+        attempted = true;
+
+        if doc.is_modified_on_disk() {
+            messages.push(loc(LocId::NoticeAutosaveConflict).replace("{filename}", &doc.filename));
+        } else {
+            let result = if state.config.autosave_clean_up {
+                doc.save_with_clean_up(None, &state.config)
+            } else {
+                doc.save(None)
+            };
+            match result {
+                Ok(()) => continue,
+                Err(err) => errors.push(err),
+            }
+        }
+
+        doc.autosave_failed = Some(doc.buffer.borrow().generation());
+    }
+
+    for msg in messages {
+        error_log_add_message(ctx, state, msg);
+    }
+    for err in errors {
+        error_log_add(ctx, state, err);
+    }
+    if attempted {
+        // The tabs lose their modified marker.
+        ctx.needs_rerender();
+    }
+}
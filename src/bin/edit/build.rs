@@ -0,0 +1,533 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Runs the build command from the `[build]` section of the config, e.g. `cargo check`,
+//! in the project root and collects what it prints for the output panel. Lines that
+//! mention a `file:line:column` can be activated to jump there.
+//!
+//! The errors and warnings in the output are collected as diagnostics, which mark their lines
+//! in the margin. The `format` under `[build]` picks how they're found: `"plain"` looks for
+//! `file:line:column: error: message` and the like, which most compilers print, while
+//! `"rustc-json"` reads the JSON of `cargo build --message-format=json`.
+
+use std::io::{BufRead as _, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::{CoordType, Point};
+use edit::json::{self, Value};
+use edit::{path, sys};
+
+use crate::documents::DocumentManager;
+use crate::draw_filetree::find_project_root;
+use crate::localization::*;
+use crate::state::*;
+
+/// The most lines the output panel keeps. The oldest ones are dropped beyond that.
+const MAX_LINES: usize = 10_000;
+
+/// A line of output and the place in a file it refers to, if any.
+pub struct OutputLine {
+    pub text: String,
+    pub location: Option<(PathBuf, Point)>,
+}
+
+/// How the output of the build command is read. See `format` under `[build]` in the config.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Plain,
+    RustcJson,
+}
+
+/// How bad a diagnostic is. The most severe comes first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn color(self) -> IndexedColor {
+        match self {
+            Self::Error => IndexedColor::BrightRed,
+            Self::Warning => IndexedColor::BrightYellow,
+            Self::Note => IndexedColor::BrightBlue,
+        }
+    }
+}
+
+/// An error or warning that the build command reported for a place in a file.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub pos: Point,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BuildStatus {
+    NotRun,
+    Running,
+    /// The exit code, or `None` if it was terminated by a signal.
+    Exited(Option<i32>),
+    Canceled,
+}
+
+/// The state of the output panel and the command shown in it.
+pub struct Build {
+    pub visible: bool,
+    pub status: BuildStatus,
+    /// The command that ran last and the directory it ran in, which file names are relative to.
+    pub command: String,
+    pub dir: PathBuf,
+    format: OutputFormat,
+    pub lines: Vec<OutputLine>,
+    pub job: Option<Job>,
+    /// Set when lines were added, so that the panel scrolls down to them.
+    pub follow: bool,
+    /// What the last run reported. They're kept until the next one.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Incremented whenever `diagnostics` change, so that documents know to update their marks.
+    pub diagnostics_generation: u32,
+    /// A message without a location, like `error[E0425]: ...` from rustc, which is waiting
+    /// for the location on the next line.
+    pending: Option<(Severity, String)>,
+}
+
+impl Default for Build {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            status: BuildStatus::NotRun,
+            command: String::new(),
+            dir: PathBuf::new(),
+            format: OutputFormat::Plain,
+            lines: Vec::new(),
+            job: None,
+            follow: false,
+            diagnostics: Vec::new(),
+            diagnostics_generation: 0,
+            pending: None,
+        }
+    }
+}
+
+/// A running command. Dropping it kills the command.
+pub struct Job {
+    child: Child,
+    lines: mpsc::Receiver<String>,
+}
+
+impl Job {
+    /// Runs `command` with the shell in `dir`. Its stdout and stderr are read on threads,
+    /// so that it doesn't block on a full pipe while the editor waits for input.
+    fn start(command: &str, dir: &Path) -> std::io::Result<Self> {
+        let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+        shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+        shell.current_dir(dir).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        sys::command_isolate(&mut shell);
+        let mut child = shell.spawn()?;
+
+        let (tx, rx) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward_lines(stdout, tx.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_lines(stderr, tx);
+        }
+        Ok(Self { child, lines: rx })
+    }
+
+    /// Moves the lines printed since the last call to `out`. Returns the exit status
+    /// once the command has finished and all of its output was read.
+    fn read(&mut self, out: &mut Vec<String>) -> Option<ExitStatus> {
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => out.push(line),
+                Err(mpsc::TryRecvError::Empty) => return None,
+                // Both pipes were closed, so it's done, or about to be. A command can close
+                // them and keep running, so this doesn't wait for it, but checks again later.
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return self.child.try_wait().ok().flatten();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if matches!(self.child.try_wait(), Ok(None)) {
+            sys::kill_process_tree(&mut self.child);
+        }
+    }
+}
+
+/// Sends the lines read from `stream` to `tx` until it ends.
+fn forward_lines(stream: impl Read + Send + 'static, tx: mpsc::Sender<String>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        while reader.read_until(b'\n', &mut buf).is_ok_and(|n| n > 0) {
+            if tx.send(sanitize(&String::from_utf8_lossy(&buf))).is_err() {
+                break;
+            }
+            buf.clear();
+        }
+    });
+}
+
+/// Prepares a line of output for display: Color codes and other escape sequences
+/// are removed, since they'd be passed through to the terminal, and tabs are expanded.
+fn sanitize(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            // CSI sequences end in a letter, e.g. `\x1b[1;31m`.
+            '\x1b' => {
+                if chars.next() == Some('[') {
+                    for ch in chars.by_ref() {
+                        if ch.is_ascii_alphabetic() || ch == '~' {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\t' => text.push_str(&" ".repeat(4 - text.chars().count() % 4)),
+            _ if ch.is_control() => {}
+            _ => text.push(ch),
+        }
+    }
+    text
+}
+
+/// Finds a `file:line` or `file:line:column` in `text` as printed by compilers,
+/// e.g. `src/main.rs:12:5` or `main.c:3:1: error`, and resolves the file against `dir`.
+/// Only files that exist count, since anything else with colons would match as well.
+fn parse_location(text: &str, dir: &Path) -> Option<(PathBuf, Point)> {
+    text.split_whitespace().find_map(|word| {
+        let word = word.trim_end_matches([':', ',']);
+        let (path, goto) = DocumentManager::parse_filename_goto(Path::new(word));
+        let path = dir.join(path);
+        Some((path, goto?)).filter(|(path, _)| path.is_file())
+    })
+}
+
+/// Finds a severity followed by the message, like `error: message`, `error[E0425]: message`,
+/// or `main.c:3:1: warning: message`, anywhere in `text`.
+fn parse_severity(text: &str) -> Option<(Severity, &str)> {
+    let mut start = 0;
+    for (end, _) in text.match_indices(": ") {
+        // `= help: ...` is how rustc adds a hint to a message.
+        let word = text[start..end].trim_start_matches([' ', '=']).trim();
+        let word = word.split_once('[').map_or(word, |(word, _)| word);
+        let severity = match word.to_ascii_lowercase().as_str() {
+            "error" | "fatal error" => Severity::Error,
+            "warning" => Severity::Warning,
+            "note" | "help" | "info" => Severity::Note,
+            _ => {
+                start = end + 2;
+                continue;
+            }
+        };
+        return Some((severity, text[end + 2..].trim()));
+    }
+    None
+}
+
+/// Reads a line of `--message-format=json` output from cargo, or `--error-format=json` from rustc.
+/// Returns `None` if it isn't JSON. Otherwise, the text that rustc would've printed,
+/// if any, and the diagnostic for the primary location of the message, if any.
+fn parse_rustc_json(line: &str, dir: &Path) -> Option<(String, Option<Diagnostic>)> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let value = json::parse(line)?;
+    let message = match value.get("reason").and_then(Value::as_str) {
+        Some("compiler-message") => value.get("message")?,
+        // Cargo's other messages are about the artifacts it built.
+        Some(_) => return Some((String::new(), None)),
+        None => &value,
+    };
+    let rendered = message.get("rendered").and_then(Value::as_str).unwrap_or_default();
+    Some((rendered.to_string(), rustc_json_diagnostic(message, dir)))
+}
+
+/// Turns a diagnostic message from rustc into a [`Diagnostic`] at its primary span.
+fn rustc_json_diagnostic(message: &Value, dir: &Path) -> Option<Diagnostic> {
+    let span = message
+        .get("spans")?
+        .as_array()?
+        .iter()
+        .find(|span| span.get("is_primary").and_then(Value::as_bool) == Some(true))?;
+    // The lines and columns are 1-based.
+    let number = |key: &str| Some(span.get(key)?.as_i64()?.max(1) as CoordType - 1);
+    let severity = match message.get("level")?.as_str()? {
+        level if level.starts_with("error") => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => Severity::Note,
+    };
+    Some(Diagnostic {
+        path: path::normalize(&dir.join(span.get("file_name")?.as_str()?)),
+        pos: Point { x: number("column_start")?, y: number("line_start")? },
+        severity,
+        message: message.get("message")?.as_str()?.to_string(),
+    })
+}
+
+impl Build {
+    /// Adds a line of output to the panel and collects the diagnostic it reports, if any.
+    fn add_line(&mut self, text: String) {
+        if self.format == OutputFormat::RustcJson
+            && let Some((rendered, diagnostic)) = parse_rustc_json(&text, &self.dir)
+        {
+            for text in rendered.lines().map(sanitize) {
+                let location = parse_location(&text, &self.dir);
+                self.lines.push(OutputLine { text, location });
+            }
+            if let Some(diagnostic) = diagnostic {
+                self.add_diagnostic(diagnostic);
+            }
+            return;
+        }
+
+        let location = parse_location(&text, &self.dir);
+        if self.format == OutputFormat::Plain {
+            // rustc and others print the location on the line after the message.
+            let found = match (&location, parse_severity(&text)) {
+                (Some(location), Some((severity, message))) => {
+                    Some((location.clone(), severity, message.to_string()))
+                }
+                (Some(location), None) => self
+                    .pending
+                    .take()
+                    .map(|(severity, message)| (location.clone(), severity, message)),
+                (None, found) => {
+                    self.pending = found.map(|(severity, message)| (severity, message.to_string()));
+                    None
+                }
+            };
+            if let Some(((path, pos), severity, message)) = found {
+                let path = path::normalize(&path);
+                self.add_diagnostic(Diagnostic { path, pos, severity, message });
+            }
+        }
+        self.lines.push(OutputLine { text, location });
+    }
+
+    fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+        self.diagnostics_generation = self.diagnostics_generation.wrapping_add(1);
+    }
+
+    /// Returns the diagnostics in the file at `path`.
+    pub fn diagnostics_in(&self, path: &Path) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(move |d| d.path == path)
+    }
+
+    /// Returns the most severe diagnostic on the logical line `y` of the file at `path`.
+    pub fn diagnostic_at(&self, path: &Path, y: CoordType) -> Option<&Diagnostic> {
+        self.diagnostics_in(path).filter(|d| d.pos.y == y).min_by_key(|d| d.severity)
+    }
+}
+
+impl State {
+    /// Runs the build command in the project root of the active document, or else the root
+    /// of the file tree. A command that's still running is canceled first.
+    pub fn build_run(&mut self) {
+        let command = self.config.build_command.clone();
+        if command.is_empty() {
+            self.error_log_push(LogSeverity::Info, loc(LocId::NoticeNoBuildCommand).into());
+            return;
+        }
+
+        let dir = self
+            .documents
+            .active()
+            .and_then(|doc| find_project_root(doc.path.as_ref()?.parent()?))
+            .or_else(|| self.file_tree.roots.first().cloned())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+
+        let build = &mut self.build;
+        build.job = None;
+        build.lines.clear();
+        build.diagnostics.clear();
+        build.diagnostics_generation = build.diagnostics_generation.wrapping_add(1);
+        build.pending = None;
+        build.format = self.config.build_format;
+        build.visible = true;
+        match Job::start(&command, &dir) {
+            Ok(job) => {
+                build.job = Some(job);
+                build.status = BuildStatus::Running;
+            }
+            Err(err) => {
+                build.status = BuildStatus::NotRun;
+                self.error_log_push(LogSeverity::Error, err.to_string());
+            }
+        }
+        self.build.command = command;
+        self.build.dir = dir;
+    }
+
+    /// Kills the build command if it's running.
+    pub fn build_cancel(&mut self) {
+        if self.build.job.take().is_some() {
+            self.build.status = BuildStatus::Canceled;
+        }
+    }
+
+    /// Collects the output of the running build command. Returns true if there's any news.
+    pub fn build_update(&mut self) -> bool {
+        let build = &mut self.build;
+        let Some(job) = &mut build.job else {
+            return false;
+        };
+
+        let mut lines = Vec::new();
+        let status = job.read(&mut lines);
+        let changed = !lines.is_empty() || status.is_some();
+
+        build.follow |= !lines.is_empty();
+        for text in lines {
+            build.add_line(text);
+        }
+        if build.lines.len() > MAX_LINES {
+            build.lines.drain(..build.lines.len() - MAX_LINES);
+        }
+        if let Some(status) = status {
+            build.job = None;
+            build.status = BuildStatus::Exited(status.code());
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("\x1b[1;31merror\x1b[0m: oops\r\n"), "error: oops");
+        assert_eq!(sanitize("a\tb\t\tc"), "a   b       c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_job_read_does_not_wait() {
+        // The command closes its output, but keeps running for a while after.
+        let mut job = Job::start("echo hi; exec >&- 2>&-; sleep 1", Path::new(".")).unwrap();
+        let mut lines = Vec::new();
+        let start = Instant::now();
+        while lines.is_empty() {
+            assert_eq!(job.read(&mut lines), None);
+        }
+        assert_eq!(lines, ["hi"]);
+
+        // Once the output is closed, reading returns right away until the command exits.
+        let status = loop {
+            let before = Instant::now();
+            let status = job.read(&mut lines);
+            assert!(before.elapsed() < Duration::from_millis(100));
+            if let Some(status) = status {
+                break status;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(status.success());
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_parse_location() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let file = dir.join("src/lib.rs");
+        let at = |y, x| Some((file.clone(), Point { x, y }));
+        assert_eq!(parse_location("  --> src/lib.rs:12:5", dir), at(11, 4));
+        assert_eq!(parse_location("src/lib.rs:3:1: error: x", dir), at(2, 0));
+        assert_eq!(parse_location("src/lib.rs:7, in here", dir), at(6, 0));
+        assert_eq!(parse_location("src/lib.rs has no line", dir), None);
+        assert_eq!(parse_location("src/missing.rs:1:1", dir), None);
+        assert_eq!(parse_location("https://example.com:80", dir), None);
+    }
+
+    #[test]
+    fn test_parse_severity() {
+        assert_eq!(
+            parse_severity("main.c:3:1: error: expected ';'"),
+            Some((Severity::Error, "expected ';'"))
+        );
+        assert_eq!(
+            parse_severity("error[E0425]: cannot find `x`"),
+            Some((Severity::Error, "cannot find `x`"))
+        );
+        assert_eq!(
+            parse_severity("warning: unused: `y`"),
+            Some((Severity::Warning, "unused: `y`"))
+        );
+        assert_eq!(parse_severity("   = help: try this"), Some((Severity::Note, "try this")));
+        assert_eq!(parse_severity("  --> src/lib.rs:1:1"), None);
+        assert_eq!(parse_severity("no errors: none"), None);
+    }
+
+    #[test]
+    fn test_plain_diagnostics() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut build = Build { dir: dir.to_path_buf(), ..Default::default() };
+        for line in [
+            "error[E0425]: cannot find value `x`",
+            " --> src/lib.rs:12:5",
+            "warning: `edit` generated 1 warning",
+            "",
+            "src/lib.rs:3:1: warning: unused",
+        ] {
+            build.add_line(line.to_string());
+        }
+
+        let diagnostic = |y, x, severity, message: &str| Diagnostic {
+            path: dir.join("src/lib.rs"),
+            pos: Point { x, y },
+            severity,
+            message: message.to_string(),
+        };
+        assert_eq!(
+            build.diagnostics,
+            [
+                diagnostic(11, 4, Severity::Error, "cannot find value `x`"),
+                diagnostic(2, 0, Severity::Warning, "unused"),
+            ]
+        );
+        assert_eq!(build.lines.len(), 5);
+    }
+
+    #[test]
+    fn test_parse_rustc_json() {
+        let dir = Path::new("/project");
+        let line = r#"{"reason":"compiler-message","message":{"message":"unused variable: `a`","level":"warning","spans":[{"file_name":"src/x.rs","line_start":2,"column_start":9,"is_primary":true}],"rendered":"warning: unused variable\n --> src/x.rs:2:9\n"}}"#;
+        let (rendered, diagnostic) = parse_rustc_json(line, dir).unwrap();
+        assert_eq!(rendered, "warning: unused variable\n --> src/x.rs:2:9\n");
+        assert_eq!(
+            diagnostic,
+            Some(Diagnostic {
+                path: PathBuf::from("/project/src/x.rs"),
+                pos: Point { x: 8, y: 1 },
+                severity: Severity::Warning,
+                message: "unused variable: `a`".to_string(),
+            })
+        );
+
+        let artifact = r#"{"reason":"compiler-artifact","target":{}}"#;
+        assert_eq!(parse_rustc_json(artifact, dir), Some((String::new(), None)));
+        assert_eq!(parse_rustc_json("   Compiling edit v1.2.1", dir), None);
+    }
+}
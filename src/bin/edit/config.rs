@@ -0,0 +1,756 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The user's settings, read from `config.toml` in the config directory.
+//!
+//! ```toml
+//! [editor]
+//! tab_size = 4
+//! indent_with_tabs = false
+//! word_wrap = false
+//! highlight_current_line = true
+//! ruler = 100
+//! scroll_off = 3
+//! render_whitespace = "trailing"
+//! trim_trailing_whitespace = false
+//! insert_final_newline = false
+//! autosave_delay = 0
+//! autosave_on_focus_loss = false
+//! autosave_clean_up = false
+//!
+//! [keybindings]
+//! go_to_file = "Ctrl+Shift+N"
+//!
+//! [theme]
+//! background = "#1e1e1e"
+//! bright_blue = "#3794ff"
+//!
+//! [syntax_theme]
+//! base = "light"
+//! keyword = "magenta"
+//! comment = "#6a9955"
+//!
+//! [syntax]
+//! enabled = true
+//! cpp = false
+//!
+//! [snippets.rust]
+//! fn = "fn ${1:name}(${2:args}) {\n\t$0\n}"
+//!
+//! [snippets.all]
+//! todo = "TODO($1): $0"
+//!
+//! [build]
+//! command = "cargo check --message-format=json"
+//! format = "rustc-json"
+//! ```
+//!
+//! Settings that can't be understood are reported and left at their defaults.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use edit::buffer::WhitespaceVisibility;
+use edit::framebuffer::{INDEXED_COLORS_COUNT, IndexedColor};
+use edit::helpers::*;
+use edit::input::{InputKey, kbmod, vk};
+use edit::snippet::Snippet;
+use edit::syntax::{SupportedLanguage, Theme, ThemeColor};
+use edit::sys;
+use toml_span::Span;
+
+use crate::build::OutputFormat;
+use crate::documents::Indentation;
+use crate::localization::*;
+
+/// The commands that can be bound to a different key in the `[keybindings]` table.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    FileNew,
+    FileOpen,
+    FileSave,
+    FileSaveAs,
+    FileClose,
+    FileExit,
+    FileGoto,
+    Find,
+    FindNext,
+    Replace,
+    GoToFile,
+    CommandPalette,
+    ToggleFileTree,
+    ToggleOutline,
+    SplitPane,
+    ClosePane,
+    FocusNextPane,
+    NextTab,
+    PreviousTab,
+    GoBack,
+    GoForward,
+    MoveTabLeft,
+    MoveTabRight,
+    NextChange,
+    PreviousChange,
+    ExpandSelection,
+    ShrinkSelection,
+    SelectAllMatches,
+    ReplaceAll,
+    GotoMatchingBracket,
+    DuplicateLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    ToggleLineComment,
+    ToggleBlockComment,
+    ToggleWordWrap,
+    FoldToggle,
+    FoldAll,
+    UnfoldAll,
+    RunBuild,
+    CancelBuild,
+    KeyboardShortcuts,
+}
+
+impl Action {
+    pub const ALL: [Action; 42] = [
+        Action::FileNew,
+        Action::FileOpen,
+        Action::FileSave,
+        Action::FileSaveAs,
+        Action::FileClose,
+        Action::FileExit,
+        Action::FileGoto,
+        Action::Find,
+        Action::FindNext,
+        Action::Replace,
+        Action::GoToFile,
+        Action::CommandPalette,
+        Action::ToggleFileTree,
+        Action::ToggleOutline,
+        Action::SplitPane,
+        Action::ClosePane,
+        Action::FocusNextPane,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::GoBack,
+        Action::GoForward,
+        Action::MoveTabLeft,
+        Action::MoveTabRight,
+        Action::NextChange,
+        Action::PreviousChange,
+        Action::ExpandSelection,
+        Action::ShrinkSelection,
+        Action::SelectAllMatches,
+        Action::ReplaceAll,
+        Action::GotoMatchingBracket,
+        Action::DuplicateLines,
+        Action::MoveLinesUp,
+        Action::MoveLinesDown,
+        Action::ToggleLineComment,
+        Action::ToggleBlockComment,
+        Action::ToggleWordWrap,
+        Action::FoldToggle,
+        Action::FoldAll,
+        Action::UnfoldAll,
+        Action::RunBuild,
+        Action::CancelBuild,
+        Action::KeyboardShortcuts,
+    ];
+
+    /// The name of the action in the config file.
+    fn name(self) -> &'static str {
+        match self {
+            Action::FileNew => "new_file",
+            Action::FileOpen => "open_file",
+            Action::FileSave => "save",
+            Action::FileSaveAs => "save_as",
+            Action::FileClose => "close",
+            Action::FileExit => "exit",
+            Action::FileGoto => "go_to_line",
+            Action::Find => "find",
+            Action::FindNext => "find_next",
+            Action::Replace => "replace",
+            Action::GoToFile => "go_to_file",
+            Action::CommandPalette => "command_palette",
+            Action::ToggleFileTree => "toggle_file_tree",
+            Action::ToggleOutline => "toggle_outline",
+            Action::SplitPane => "split_pane",
+            Action::ClosePane => "close_pane",
+            Action::FocusNextPane => "focus_next_pane",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::GoBack => "go_back",
+            Action::GoForward => "go_forward",
+            Action::MoveTabLeft => "move_tab_left",
+            Action::MoveTabRight => "move_tab_right",
+            Action::NextChange => "next_change",
+            Action::PreviousChange => "previous_change",
+            Action::ExpandSelection => "expand_selection",
+            Action::ShrinkSelection => "shrink_selection",
+            Action::SelectAllMatches => "select_all_matches",
+            Action::ReplaceAll => "replace_all",
+            Action::GotoMatchingBracket => "go_to_matching_bracket",
+            Action::DuplicateLines => "duplicate_lines",
+            Action::MoveLinesUp => "move_lines_up",
+            Action::MoveLinesDown => "move_lines_down",
+            Action::ToggleLineComment => "toggle_line_comment",
+            Action::ToggleBlockComment => "toggle_block_comment",
+            Action::ToggleWordWrap => "toggle_word_wrap",
+            Action::FoldToggle => "toggle_fold",
+            Action::FoldAll => "fold_all",
+            Action::UnfoldAll => "unfold_all",
+            Action::RunBuild => "run_build",
+            Action::CancelBuild => "cancel_build",
+            Action::KeyboardShortcuts => "keyboard_shortcuts",
+        }
+    }
+
+    fn default_shortcut(self) -> InputKey {
+        match self {
+            Action::FileNew => kbmod::CTRL | vk::N,
+            Action::FileOpen => kbmod::CTRL | vk::O,
+            Action::FileSave => kbmod::CTRL | vk::S,
+            Action::FileSaveAs => kbmod::CTRL_SHIFT | vk::S,
+            Action::FileClose => kbmod::CTRL | vk::W,
+            Action::FileExit => kbmod::CTRL | vk::Q,
+            Action::FileGoto => kbmod::CTRL | vk::G,
+            Action::Find => kbmod::CTRL | vk::F,
+            Action::FindNext => vk::F3,
+            Action::Replace => kbmod::CTRL | vk::R,
+            Action::GoToFile => kbmod::CTRL | vk::P,
+            Action::CommandPalette => kbmod::CTRL_SHIFT | vk::P,
+            Action::ToggleFileTree => kbmod::CTRL | vk::E,
+            Action::ToggleOutline => kbmod::CTRL_SHIFT | vk::O,
+            Action::SplitPane => kbmod::CTRL | vk::K,
+            Action::ClosePane => kbmod::CTRL_SHIFT | vk::W,
+            Action::FocusNextPane => vk::F6,
+            Action::NextTab => kbmod::CTRL | vk::TAB,
+            Action::PreviousTab => kbmod::CTRL_SHIFT | vk::TAB,
+            // On macOS, Alt+Left/Right already move by words.
+            Action::GoBack if cfg!(target_os = "macos") => kbmod::CTRL_ALT | vk::LEFT,
+            Action::GoForward if cfg!(target_os = "macos") => kbmod::CTRL_ALT | vk::RIGHT,
+            Action::GoBack => kbmod::ALT | vk::LEFT,
+            Action::GoForward => kbmod::ALT | vk::RIGHT,
+            Action::MoveTabLeft => kbmod::CTRL_SHIFT | vk::PRIOR,
+            Action::MoveTabRight => kbmod::CTRL_SHIFT | vk::NEXT,
+            Action::NextChange => vk::F7,
+            Action::PreviousChange => kbmod::SHIFT | vk::F7,
+            Action::ExpandSelection => kbmod::CTRL_SHIFT | vk::UP,
+            Action::ShrinkSelection => kbmod::CTRL_SHIFT | vk::DOWN,
+            Action::SelectAllMatches => kbmod::ALT | vk::RETURN,
+            Action::ReplaceAll => kbmod::CTRL_ALT | vk::RETURN,
+            Action::GotoMatchingBracket => kbmod::CTRL | vk::B,
+            Action::DuplicateLines => kbmod::ALT_SHIFT | vk::D,
+            Action::MoveLinesUp => kbmod::ALT | vk::UP,
+            Action::MoveLinesDown => kbmod::ALT | vk::DOWN,
+            Action::ToggleLineComment => kbmod::CTRL | vk::OEM_2,
+            Action::ToggleBlockComment => kbmod::ALT_SHIFT | vk::A,
+            Action::ToggleWordWrap => kbmod::ALT | vk::Z,
+            Action::FoldToggle => kbmod::CTRL_SHIFT | vk::F,
+            Action::FoldAll => kbmod::CTRL_SHIFT | vk::A,
+            Action::UnfoldAll => kbmod::CTRL_SHIFT | vk::E,
+            Action::RunBuild => vk::F5,
+            Action::CancelBuild => kbmod::SHIFT | vk::F5,
+            Action::KeyboardShortcuts => vk::F1,
+        }
+    }
+
+    /// The name of the action in the UI.
+    pub fn label(self) -> LocId {
+        match self {
+            Action::FileNew => LocId::FileNew,
+            Action::FileOpen => LocId::FileOpen,
+            Action::FileSave => LocId::FileSave,
+            Action::FileSaveAs => LocId::FileSaveAs,
+            Action::FileClose => LocId::FileClose,
+            Action::FileExit => LocId::FileExit,
+            Action::FileGoto => LocId::FileGoto,
+            Action::Find => LocId::EditFind,
+            Action::FindNext => LocId::EditFindNext,
+            Action::Replace => LocId::EditReplace,
+            Action::GoToFile => LocId::ViewGoToFile,
+            Action::CommandPalette => LocId::ViewCommandPalette,
+            Action::ToggleFileTree => LocId::ViewFileTree,
+            Action::ToggleOutline => LocId::ViewOutline,
+            Action::SplitPane => LocId::ViewSplitPane,
+            Action::ClosePane => LocId::ViewClosePane,
+            Action::FocusNextPane => LocId::ViewFocusNextPane,
+            Action::NextTab => LocId::ViewNextTab,
+            Action::PreviousTab => LocId::ViewPreviousTab,
+            Action::GoBack => LocId::ViewGoBack,
+            Action::GoForward => LocId::ViewGoForward,
+            Action::MoveTabLeft => LocId::FileMoveTabLeft,
+            Action::MoveTabRight => LocId::FileMoveTabRight,
+            Action::NextChange => LocId::ViewNextChange,
+            Action::PreviousChange => LocId::ViewPreviousChange,
+            Action::ExpandSelection => LocId::EditExpandSelection,
+            Action::ShrinkSelection => LocId::EditShrinkSelection,
+            Action::SelectAllMatches => LocId::EditSelectAllMatches,
+            Action::ReplaceAll => LocId::EditReplaceAll,
+            Action::GotoMatchingBracket => LocId::EditGotoMatchingBracket,
+            Action::DuplicateLines => LocId::EditDuplicateLines,
+            Action::MoveLinesUp => LocId::EditMoveLinesUp,
+            Action::MoveLinesDown => LocId::EditMoveLinesDown,
+            Action::ToggleLineComment => LocId::EditToggleLineComment,
+            Action::ToggleBlockComment => LocId::EditToggleBlockComment,
+            Action::ToggleWordWrap => LocId::ViewWordWrap,
+            Action::FoldToggle => LocId::ViewFoldToggle,
+            Action::FoldAll => LocId::ViewFoldAll,
+            Action::UnfoldAll => LocId::ViewUnfoldAll,
+            Action::RunBuild => LocId::ViewRunBuild,
+            Action::CancelBuild => LocId::ViewCancelBuild,
+            Action::KeyboardShortcuts => LocId::HelpKeyboardShortcuts,
+        }
+    }
+
+    /// The menu the action is listed under in the keyboard shortcut overview.
+    pub fn category(self) -> LocId {
+        match self {
+            Action::FileNew
+            | Action::FileOpen
+            | Action::FileSave
+            | Action::FileSaveAs
+            | Action::FileClose
+            | Action::FileExit
+            | Action::FileGoto
+            | Action::MoveTabLeft
+            | Action::MoveTabRight => LocId::File,
+            Action::Find
+            | Action::FindNext
+            | Action::Replace
+            | Action::SelectAllMatches
+            | Action::ReplaceAll
+            | Action::ExpandSelection
+            | Action::ShrinkSelection
+            | Action::GotoMatchingBracket
+            | Action::DuplicateLines
+            | Action::MoveLinesUp
+            | Action::MoveLinesDown
+            | Action::ToggleLineComment
+            | Action::ToggleBlockComment => LocId::Edit,
+            Action::KeyboardShortcuts => LocId::Help,
+            _ => LocId::View,
+        }
+    }
+}
+
+pub struct Config {
+    /// The keys that differ from [`Action::default_shortcut`].
+    shortcuts: Vec<(Action, InputKey)>,
+    /// Colors that replace the terminal's, as `0xAABBGGRR`, indexed by `IndexedColor`.
+    pub theme: [Option<u32>; INDEXED_COLORS_COUNT],
+    /// The colors of the syntax highlighting.
+    pub syntax_theme: Theme,
+    /// Whether documents get parsed for highlighting, folding and symbols at all.
+    pub syntax_enabled: bool,
+    /// The languages whose documents are treated like plain text nonetheless.
+    pub syntax_disabled: Vec<SupportedLanguage>,
+    pub indentation: Indentation,
+    pub word_wrap: bool,
+    pub highlight_current_line: bool,
+    /// The column to draw a vertical ruler at, or 0 for none.
+    pub ruler: CoordType,
+    /// The lines to keep visible above and below the cursor.
+    pub scroll_off: CoordType,
+    pub render_whitespace: WhitespaceVisibility,
+    /// The clean-ups on save, unless `.editorconfig` says otherwise.
+    pub trim_trailing_whitespace: bool,
+    pub insert_final_newline: bool,
+    /// How long after the last input modified documents get saved, or zero for never.
+    pub autosave_delay: Duration,
+    pub autosave_on_focus_loss: bool,
+    /// Whether autosaving does the clean-ups on save too. They'd otherwise remove
+    /// the space that was just typed, for instance.
+    pub autosave_clean_up: bool,
+    /// The snippets by language, `None` for those in all documents, and their prefix.
+    pub snippets: Vec<(Option<SupportedLanguage>, String, Snippet)>,
+    /// The shell command that "Run Build Command" runs in the project root, if any.
+    pub build_command: String,
+    /// How errors and warnings are found in the output of the build command.
+    pub build_format: OutputFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shortcuts: Vec::new(),
+            theme: [None; INDEXED_COLORS_COUNT],
+            syntax_theme: Theme::default(),
+            syntax_enabled: true,
+            syntax_disabled: Vec::new(),
+            indentation: Default::default(),
+            word_wrap: false,
+            highlight_current_line: true,
+            ruler: 0,
+            scroll_off: 0,
+            render_whitespace: WhitespaceVisibility::Off,
+            trim_trailing_whitespace: false,
+            insert_final_newline: false,
+            autosave_delay: Duration::ZERO,
+            autosave_on_focus_loss: false,
+            autosave_clean_up: false,
+            snippets: Vec::new(),
+            build_command: String::new(),
+            build_format: OutputFormat::Plain,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file. Returns the defaults if there is none.
+    /// Any problems with it are returned as messages for the error log.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = config_path() else {
+            return (Self::default(), Vec::new());
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return (Self::default(), Vec::new());
+        };
+        Self::parse(&text, &path.to_string_lossy())
+    }
+
+    fn parse(text: &str, path: &str) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+
+        let root = match toml_span::parse(text) {
+            Ok(root) => root,
+            Err(err) => {
+                errors.push(
+                    loc(LocId::ConfigErrorSyntax)
+                        .replace("{path}", path)
+                        .replace("{line}", &line_of(text, err.span.start).to_string())
+                        .replace("{error}", &err.to_string()),
+                );
+                return (config, errors);
+            }
+        };
+
+        // Reports a setting that was ignored.
+        let mut invalid = |span: Span, key: &str| {
+            errors.push(
+                loc(LocId::ConfigErrorSetting)
+                    .replace("{path}", path)
+                    .replace("{line}", &line_of(text, span.start).to_string())
+                    .replace("{key}", key),
+            );
+        };
+
+        for (section, value) in root.as_table().into_iter().flatten() {
+            let Some(table) = value.as_table() else {
+                invalid(section.span, &section.name);
+                continue;
+            };
+
+            match &*section.name {
+                "editor" => {
+                    for (key, value) in table {
+                        match &*key.name {
+                            "tab_size" => match value.as_integer() {
+                                Some(n @ 1..=8) => config.indentation.tab_size = n as CoordType,
+                                _ => invalid(key.span, &key.name),
+                            },
+                            "indent_with_tabs" => match value.as_bool() {
+                                Some(b) => config.indentation.use_tabs = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "word_wrap" => match value.as_bool() {
+                                Some(b) => config.word_wrap = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "highlight_current_line" => match value.as_bool() {
+                                Some(b) => config.highlight_current_line = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "ruler" => match value.as_integer() {
+                                Some(n @ 0..=1000) => config.ruler = n as CoordType,
+                                _ => invalid(key.span, &key.name),
+                            },
+                            "scroll_off" => match value.as_integer() {
+                                Some(n @ 0..=100) => config.scroll_off = n as CoordType,
+                                _ => invalid(key.span, &key.name),
+                            },
+                            "render_whitespace" => match value.as_str() {
+                                Some("none") => {
+                                    config.render_whitespace = WhitespaceVisibility::Off
+                                }
+                                Some("trailing") => {
+                                    config.render_whitespace = WhitespaceVisibility::Trailing
+                                }
+                                Some("all") => config.render_whitespace = WhitespaceVisibility::All,
+                                _ => invalid(key.span, &key.name),
+                            },
+                            "trim_trailing_whitespace" => match value.as_bool() {
+                                Some(b) => config.trim_trailing_whitespace = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "insert_final_newline" => match value.as_bool() {
+                                Some(b) => config.insert_final_newline = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "autosave_delay" => match value.as_integer() {
+                                Some(n @ 0..=3600) => {
+                                    config.autosave_delay = Duration::from_secs(n as u64)
+                                }
+                                _ => invalid(key.span, &key.name),
+                            },
+                            "autosave_on_focus_loss" => match value.as_bool() {
+                                Some(b) => config.autosave_on_focus_loss = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            "autosave_clean_up" => match value.as_bool() {
+                                Some(b) => config.autosave_clean_up = b,
+                                None => invalid(key.span, &key.name),
+                            },
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                }
+                "keybindings" => {
+                    for (key, value) in table {
+                        let action = Action::ALL.into_iter().find(|a| a.name() == key.name);
+                        match (action, value.as_str().and_then(InputKey::parse)) {
+                            (Some(action), Some(shortcut)) => {
+                                config.shortcuts.push((action, shortcut))
+                            }
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                }
+                "theme" => {
+                    for (key, value) in table {
+                        let index = COLORS.iter().position(|&(name, _)| name == key.name);
+                        match (index, value.as_str().and_then(parse_color)) {
+                            (Some(index), Some(color)) => config.theme[index] = Some(color),
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                }
+                "syntax_theme" => {
+                    // The built-in theme to start from has to be applied before the overrides.
+                    for (key, value) in table.iter().filter(|(key, _)| key.name == "base") {
+                        match value.as_str() {
+                            Some("dark") => config.syntax_theme = Theme::dark(),
+                            Some("light") => config.syntax_theme = Theme::light(),
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                    for (key, value) in table.iter().filter(|(key, _)| key.name != "base") {
+                        let color = value.as_str().and_then(|s| {
+                            match COLORS.iter().find(|&&(name, _)| name == s) {
+                                Some(&(_, indexed)) => Some(ThemeColor::Indexed(indexed)),
+                                None => parse_color(s).map(ThemeColor::Rgba),
+                            }
+                        });
+                        if !color.is_some_and(|color| config.syntax_theme.set(&key.name, color)) {
+                            invalid(key.span, &key.name);
+                        }
+                    }
+                }
+                "syntax" => {
+                    for (key, value) in table {
+                        let language =
+                            SupportedLanguage::ALL.into_iter().find(|l| l.to_string() == key.name);
+                        match (&*key.name, language, value.as_bool()) {
+                            ("enabled", _, Some(b)) => config.syntax_enabled = b,
+                            (_, Some(language), Some(true)) => {
+                                config.syntax_disabled.retain(|&l| l != language)
+                            }
+                            (_, Some(language), Some(false)) => {
+                                config.syntax_disabled.push(language)
+                            }
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                }
+                "snippets" => {
+                    for (lang, value) in table {
+                        let language = match &*lang.name {
+                            "all" => Some(None),
+                            name => SupportedLanguage::ALL
+                                .into_iter()
+                                .find(|l| l.to_string() == name)
+                                .map(Some),
+                        };
+                        let (Some(language), Some(table)) = (language, value.as_table()) else {
+                            invalid(lang.span, &lang.name);
+                            continue;
+                        };
+                        for (key, value) in table {
+                            match value.as_str() {
+                                Some(s) => config.snippets.push((
+                                    language,
+                                    key.name.to_string(),
+                                    Snippet::parse(s),
+                                )),
+                                None => invalid(key.span, &key.name),
+                            }
+                        }
+                    }
+                }
+                "build" => {
+                    for (key, value) in table {
+                        match (&*key.name, value.as_str()) {
+                            ("command", Some(command)) => {
+                                config.build_command = command.to_string();
+                            }
+                            ("format", Some("plain")) => config.build_format = OutputFormat::Plain,
+                            ("format", Some("rustc-json")) => {
+                                config.build_format = OutputFormat::RustcJson
+                            }
+                            _ => invalid(key.span, &key.name),
+                        }
+                    }
+                }
+                _ => invalid(section.span, &section.name),
+            }
+        }
+
+        (config, errors)
+    }
+
+    /// Returns the key that triggers the given action.
+    pub fn shortcut(&self, action: Action) -> InputKey {
+        self.shortcuts
+            .iter()
+            .rev()
+            .find(|&&(a, _)| a == action)
+            .map_or_else(|| action.default_shortcut(), |&(_, key)| key)
+    }
+
+    /// Returns true if documents in the given language are to be parsed and highlighted.
+    pub fn syntax_enabled_for(&self, language: SupportedLanguage) -> bool {
+        self.syntax_enabled && !self.syntax_disabled.contains(&language)
+    }
+
+    /// Returns the snippet with the given prefix for a document in `language`.
+    /// Those of the language take precedence, and later ones over earlier ones.
+    pub fn snippet(&self, language: Option<SupportedLanguage>, prefix: &str) -> Option<&Snippet> {
+        self.snippets
+            .iter()
+            .filter(|(l, p, _)| p == prefix && (l.is_none() || *l == language))
+            .max_by_key(|(l, _, _)| l.is_some())
+            .map(|(_, _, snippet)| snippet)
+    }
+
+    /// Returns the action that the given key triggers, if any.
+    pub fn action(&self, key: InputKey) -> Option<Action> {
+        Action::ALL.into_iter().find(|&action| self.shortcut(action) == key)
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    sys::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// The names of the terminal colors, in the order of `IndexedColor`.
+const COLORS: [(&str, IndexedColor); INDEXED_COLORS_COUNT] = [
+    ("black", IndexedColor::Black),
+    ("red", IndexedColor::Red),
+    ("green", IndexedColor::Green),
+    ("yellow", IndexedColor::Yellow),
+    ("blue", IndexedColor::Blue),
+    ("magenta", IndexedColor::Magenta),
+    ("cyan", IndexedColor::Cyan),
+    ("white", IndexedColor::White),
+    ("bright_black", IndexedColor::BrightBlack),
+    ("bright_red", IndexedColor::BrightRed),
+    ("bright_green", IndexedColor::BrightGreen),
+    ("bright_yellow", IndexedColor::BrightYellow),
+    ("bright_blue", IndexedColor::BrightBlue),
+    ("bright_magenta", IndexedColor::BrightMagenta),
+    ("bright_cyan", IndexedColor::BrightCyan),
+    ("bright_white", IndexedColor::BrightWhite),
+    ("background", IndexedColor::Background),
+    ("foreground", IndexedColor::Foreground),
+];
+
+/// Parses a color in the form `#rrggbb` into `0xAABBGGRR`.
+fn parse_color(text: &str) -> Option<u32> {
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(0xff000000 | ((rgb & 0xff) << 16) | (rgb & 0xff00) | (rgb >> 16))
+}
+
+/// Returns the 1-based line number of the byte `offset` in `text`.
+fn line_of(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset.min(text.len())].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use edit::framebuffer::IndexedColor;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = r##"
+[editor]
+tab_size = 2
+indent_with_tabs = true
+word_wrap = true
+render_whitespace = "trailing"
+
+[keybindings]
+find = "Ctrl+Shift+F"
+
+[theme]
+red = "#ff8000"
+
+[build]
+command = "cargo build"
+format = "rustc-json"
+"##;
+        let (config, errors) = Config::parse(text, "config.toml");
+        assert!(errors.is_empty());
+        assert_eq!(config.indentation.tab_size, 2);
+        assert!(config.indentation.use_tabs);
+        assert!(config.word_wrap);
+        assert!(config.render_whitespace == WhitespaceVisibility::Trailing);
+        assert!(config.shortcut(Action::Find) == kbmod::CTRL_SHIFT | vk::F);
+        assert!(config.action(kbmod::CTRL_SHIFT | vk::F) == Some(Action::Find));
+        assert!(config.shortcut(Action::Replace) == Action::Replace.default_shortcut());
+        assert_eq!(config.theme[IndexedColor::Red as usize], Some(0xff0080ff));
+        assert_eq!(config.build_command, "cargo build");
+        assert!(config.build_format == OutputFormat::RustcJson);
+    }
+
+    #[test]
+    fn test_parse_invalid_settings() {
+        let text = r#"[editor]
+tab_size = 20
+indent_with_tabs = "yes"
+
+[keybindings]
+find = "Ctrl+Foo"
+no_such_action = "Ctrl+K"
+
+[colors]
+"#;
+        let (config, errors) = Config::parse(text, "config.toml");
+        // The invalid settings are reported with their line and otherwise ignored.
+        let reported = |line: usize, key: &str| {
+            let prefix = format!("config.toml, line {line}: The setting \"{key}\"");
+            errors.iter().any(|e| e.starts_with(&prefix))
+        };
+        assert_eq!(errors.len(), 5);
+        assert!(reported(2, "tab_size"));
+        assert!(reported(3, "indent_with_tabs"));
+        assert!(reported(6, "find"));
+        assert!(reported(7, "no_such_action"));
+        assert!(reported(9, "colors"));
+        assert_eq!(config.indentation.tab_size, Indentation::default().tab_size);
+        assert!(!config.indentation.use_tabs);
+        assert!(config.shortcut(Action::Find) == Action::Find.default_shortcut());
+    }
+
+    #[test]
+    fn test_parse_syntax_error() {
+        let text = "[editor]\nword_wrap = true\ntab_size = \n";
+        let (config, errors) = Config::parse(text, "x.toml");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("x.toml") && errors[0].contains("Line 3"));
+        assert!(!config.word_wrap);
+    }
+}
@@ -3,17 +3,88 @@
 
 use std::collections::LinkedList;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Instant, SystemTime};
 
-use edit::buffer::{RcTextBuffer, TextBuffer};
-use edit::helpers::{CoordType, Point};
+use edit::buffer::{self, RcTextBuffer, SearchOptions, Sniffed, TextBuffer, WhitespaceVisibility};
+use edit::helpers::{CoordType, MEBI, Point};
 use edit::{apperr, path, sys, syntax};
 use std::ops::Range;
 use tree_sitter_highlight::Highlight;
 use tree_sitter::Tree;
 
+use crate::config::Config;
+use crate::editorconfig::EditorConfig;
+use crate::remote::Remote;
 use crate::state::DisplayablePathBuf;
+use crate::{git, positions};
+
+/// Files larger than this are opened without the features that go over the whole text
+/// after edits, like syntax highlighting, because those take too long to keep up.
+/// They're still read into memory in full, like any other file. See [`Document::large_file`].
+const LARGE_FILE_SIZE: u64 = 16 * MEBI as u64;
+
+/// The modification time and size of a file.
+/// Used to detect whether a file was changed by someone else.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+/// A named point in the undo history that the user can return to.
+pub struct Checkpoint {
+    pub name: String,
+    /// The [`HistoryStep::id`] of the last applied step, or `None` for the original text.
+    ///
+    /// [`HistoryStep::id`]: edit::buffer::HistoryStep::id
+    pub step: Option<u64>,
+}
+
+/// How the lines of a document are indented.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Indentation {
+    /// The width of a tab and of one level of indentation, 1-8.
+    pub tab_size: CoordType,
+    /// Whether the Tab key inserts tabs, as opposed to spaces.
+    pub use_tabs: bool,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self { tab_size: 4, use_tabs: false }
+    }
+}
+
+impl Indentation {
+    fn of(tb: &TextBuffer) -> Self {
+        Self { tab_size: tb.tab_size(), use_tabs: tb.indent_with_tabs() }
+    }
+
+    fn apply(self, tb: &mut TextBuffer) {
+        tb.set_tab_size(self.tab_size);
+        tb.set_indent_with_tabs(self.use_tabs);
+    }
+}
+
+/// What the scrollbar of a document is marked for.
+#[derive(PartialEq, Eq)]
+pub struct ScrollbarMarkers {
+    /// The search needle and options, if the matches are marked.
+    pub search: Option<(String, SearchOptions)>,
+    pub diagnostics_generation: u32,
+    pub buffer_generation: u32,
+}
 
 pub struct Document {
     pub buffer: RcTextBuffer,
@@ -21,11 +92,71 @@ pub struct Document {
     pub dir: Option<DisplayablePathBuf>,
     pub filename: String,
     pub file_id: Option<sys::FileId>,
+    /// The state of the file on disk as of when we last read or wrote it.
+    pub file_stamp: Option<FileStamp>,
+    /// Set when a file with mixed LF and CRLF newlines was read. Cleared once the user was told.
+    pub mixed_newlines_unreported: bool,
+    /// Set when a file was read as UTF-8 despite containing invalid bytes. Cleared once the user was told.
+    pub invalid_utf8_unreported: bool,
+    /// Set if the file was larger than [`LARGE_FILE_SIZE`] when it was opened.
+    /// Syntax highlighting, swap files, change and search markers and the
+    /// whitespace clean-ups are skipped for it, since they go over the whole text.
+    pub large_file: bool,
+    /// Set along with `large_file`. Cleared once the user was told.
+    pub large_file_unreported: bool,
+    /// The encoding the user or `.editorconfig` chose for the file, if any.
+    /// Unlike a detected one, it's kept when the file is reloaded.
+    pub chosen_encoding: Option<&'static str>,
+    /// Detected when the file is read, unless it has no indentation to go by.
+    pub indentation: Indentation,
+    /// Overrides for the whitespace clean-ups on save from `.editorconfig`.
+    pub trim_trailing_whitespace: Option<bool>,
+    pub final_newline: Option<bool>,
     pub new_file_counter: usize,
+    /// Documents are shown in the tab bar in the order of this number, i.e. the order they were opened in.
+    pub tab_order: u64,
+    /// Pinned documents come first in the tab bar and aren't closed by "Close Unpinned Tabs".
+    pub pinned: bool,
+    pub checkpoints: Vec<Checkpoint>,
+    /// What the scrollbar was last marked for.
+    pub scrollbar_markers_for: Option<ScrollbarMarkers>,
+    /// When the search matches were last marked. Used to throttle that while typing.
+    pub search_marked_at: Option<Instant>,
+    /// Whether the buffer has its diff base, while changed lines are marked.
+    /// Cleared when the file is saved or read, so that it's loaded anew.
+    pub diff_base_loaded: bool,
+    /// Set by `edit --diff`: the file this one is compared with side by side,
+    /// and whether that's the newer of the two. Changed lines are always marked against it.
+    pub diff_against: Option<(PathBuf, bool)>,
+    /// The buffer generation the margin was last annotated with blame for, if it is.
+    /// Cleared when the file is saved, since that's when commits usually happen.
+    pub blame_generation: Option<u32>,
+    /// When blame was last requested. Used to throttle that while typing.
+    pub blamed_at: Option<Instant>,
+    /// The pending result from [`git::blame`], if any.
+    pub blame_job: Option<mpsc::Receiver<Option<Vec<git::BlameLine>>>>,
+    /// The buffer generation the swap file was last brought up to date with, if ever.
+    pub swap_generation: Option<u32>,
+    /// Set when writing the swap file failed and the user was told. Cleared once it succeeds.
+    pub swap_failed: bool,
+    /// The buffer generation autosaving last failed at, so that it's not retried until
+    /// the next change.
+    pub autosave_failed: Option<u32>,
+    /// The [`crate::build::Build::diagnostics_generation`] whose diagnostics the margin
+    /// was last marked with, if any. Cleared when the path changes.
+    pub diagnostics_generation: Option<u32>,
+    /// Set for a file on another host. `path` is then its local copy, which is downloaded
+    /// again when the file is re-read and uploaded when it's saved.
+    pub remote: Option<Remote>,
     pub syntax_tree: Option<Tree>,
     pub language: Option<syntax::SupportedLanguage>,
     pub buffer_generation: u32,
+    /// When the syntax tree and highlights were last requested. Used to throttle that while typing.
+    pub highlighted_at: Option<Instant>,
+    /// The pending result from [`syntax::SyntaxWorker`], if any.
+    pub syntax_job: Option<mpsc::Receiver<syntax::Analysis>>,
     pub highlights: Vec<(Range<usize>, Highlight)>,
+    pub symbols: Vec<syntax::Symbol>,
 }
 
 impl Document {
@@ -38,39 +169,207 @@ impl Document {
             tb.write_file(&mut file)?;
         }
 
+        // Saving under a new name makes it a local file.
+        if new_path.is_none()
+            && let Some(remote) = &self.remote
+            && let Err(err) = remote.upload(path)
+        {
+            // It's only saved once it's on the host, but the local copy is up to date.
+            self.file_stamp = FileStamp::of(path);
+            self.buffer.borrow_mut().mark_as_dirty();
+            return Err(err);
+        }
+
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.file_stamp = FileStamp::of(path);
+
+        // The swap file is named after the old path, so it must go before the path changes.
+        self.swap_remove();
 
         if let Some(path) = new_path {
+            // We just wrote to the new file, so it's evidently not read-only.
+            self.buffer.borrow_mut().set_read_only(false);
+            if self.remote.take().is_some()
+                && let Some(local) = &self.path
+            {
+                Remote::remove_local_copy(local);
+            }
             self.set_path(path);
+            self.diagnostics_generation = None;
         }
 
+        self.position_remember();
+        self.diff_base_loaded = false;
+        self.blame_generation = None;
         Ok(())
     }
 
+    /// Returns what changed lines are marked against: the file as of the last commit
+    /// if it's tracked by git, or else the file on disk. Untitled documents have none.
+    /// See [`Document::diff_against`] for the exception.
+    pub fn diff_base(&self) -> Option<Vec<u8>> {
+        if let Some((other, _)) = &self.diff_against {
+            return fs::read(other).ok();
+        }
+        let path = self.path.as_ref()?;
+        git::show_head(path).or_else(|| fs::read(path).ok())
+    }
+
+    /// Reads the file from disk again, in the given `encoding` or in the detected one.
     pub fn reread(&mut self, encoding: Option<&'static str>) -> apperr::Result<()> {
         let path = self.path.as_ref().unwrap().as_path();
+        if let Some(remote) = &self.remote {
+            remote.download(path)?;
+        }
         let mut file = DocumentManager::open_for_reading(path)?;
 
         {
             let mut tb = self.buffer.borrow_mut();
             tb.read_file(&mut file, encoding)?;
+            self.mixed_newlines_unreported = tb.has_mixed_newlines();
+            self.invalid_utf8_unreported = tb.has_invalid_utf8();
+            self.indentation = Indentation::of(&tb);
+        }
+        self.chosen_encoding = encoding;
+
+        if let Ok(id) = sys::file_id(None, path) {
+            self.file_id = Some(id);
+        }
+        self.file_stamp = FileStamp::of(path);
+        self.diff_base_loaded = false;
+
+        Ok(())
+    }
+
+    /// Like [`Document::save`], but runs the whitespace clean-ups first, as a single
+    /// undoable edit. `.editorconfig` takes precedence over the config.
+    ///
+    /// The clean-ups are only wanted as part of the save, so they're undone if it fails.
+    pub fn save_with_clean_up(
+        &mut self,
+        new_path: Option<PathBuf>,
+        config: &Config,
+    ) -> apperr::Result<()> {
+        let generation = self.buffer.borrow().generation();
+        self.clean_up_whitespace(config);
+
+        let result = self.save(new_path);
+        if result.is_err() {
+            let mut tb = self.buffer.borrow_mut();
+            if tb.generation() != generation {
+                let cursor = tb.cursor_logical_pos();
+                tb.undo();
+                tb.cursor_move_to_logical(cursor);
+            }
+        }
+        result
+    }
+
+    fn clean_up_whitespace(&mut self, config: &Config) {
+        if self.large_file {
+            return;
+        }
+        self.buffer.borrow_mut().clean_up_whitespace(
+            self.trim_trailing_whitespace.unwrap_or(config.trim_trailing_whitespace),
+            self.final_newline.unwrap_or(config.insert_final_newline),
+        );
+    }
+
+    /// Re-reads the file from disk as a single undoable edit.
+    pub fn reload(&mut self) -> apperr::Result<()> {
+        let path = self.path.as_ref().unwrap().as_path();
+        if let Some(remote) = &self.remote {
+            remote.download(path)?;
+        }
+        let mut file = DocumentManager::open_for_reading(path)?;
+
+        {
+            let mut tb = self.buffer.borrow_mut();
+            // Detect the encoding again, unless it was chosen, since the file may
+            // have gained a BOM or been fixed since an earlier guess.
+            tb.reload_file(&mut file, self.chosen_encoding)?;
+            self.mixed_newlines_unreported = tb.has_mixed_newlines();
+            self.invalid_utf8_unreported = tb.has_invalid_utf8();
         }
 
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.file_stamp = FileStamp::of(path);
+        self.diff_base_loaded = false;
 
         Ok(())
     }
 
+    pub fn set_indentation(&mut self, indentation: Indentation) {
+        self.indentation = indentation;
+        indentation.apply(&mut self.buffer.borrow_mut());
+    }
+
+    /// Converts the existing indentation to tabs or spaces as a single undoable edit,
+    /// and keeps using that style from then on.
+    pub fn convert_indentation(&mut self, use_tabs: bool) {
+        self.buffer.borrow_mut().convert_indentation(use_tabs);
+        self.indentation.use_tabs = use_tabs;
+    }
+
+    /// Applies the settings from `.editorconfig`. An existing file keeps the newlines
+    /// and encoding it was read with, since changing them would modify the whole file.
+    fn apply_editorconfig(&mut self, config: &EditorConfig) {
+        let mut indentation = self.indentation;
+        if let Some(use_tabs) = config.indent_with_tabs {
+            indentation.use_tabs = use_tabs;
+        }
+        if let Some(tab_size) = config.tab_size {
+            indentation.tab_size = tab_size;
+        }
+        self.set_indentation(indentation);
+        self.trim_trailing_whitespace = config.trim_trailing_whitespace;
+        self.final_newline = config.insert_final_newline;
+
+        let mut tb = self.buffer.borrow_mut();
+        if let Some(final_newline) = config.insert_final_newline {
+            tb.set_insert_final_newline(final_newline);
+        }
+        if self.file_id.is_none() {
+            if let Some(crlf) = config.crlf {
+                tb.set_crlf(crlf);
+            }
+            if let Some(encoding) = config.encoding {
+                tb.set_encoding(encoding);
+            }
+        }
+    }
+
+    /// Returns true if the file on disk changed since we last read or wrote it.
+    /// A file that was deleted in the meantime doesn't count, since saving can't clobber it.
+    pub fn is_modified_on_disk(&self) -> bool {
+        match (&self.path, self.file_stamp) {
+            (Some(path), Some(stamp)) => FileStamp::of(path).is_some_and(|s| s != stamp),
+            _ => false,
+        }
+    }
+
+    /// Folds every region of the given kind, on top of the folds that are already there.
+    /// `tb` is the document's buffer, which callers usually have borrowed already.
+    pub fn smart_fold(&self, tb: &mut TextBuffer, kind: syntax::SmartFold) {
+        // The tree is only of use while it matches the text.
+        if let Some(tree) = &self.syntax_tree
+            && self.buffer_generation == tb.generation()
+        {
+            tb.fold_ranges(&syntax::smart_fold_regions(tree, kind));
+        }
+    }
+
     fn set_path(&mut self, path: PathBuf) {
         let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
         let dir = path.parent().map(ToOwned::to_owned).unwrap_or_default();
         self.filename = filename;
         self.dir = Some(DisplayablePathBuf::from_path(dir));
         self.language = match path.extension().and_then(OsStr::to_str) {
+            _ if self.large_file => None,
             Some("rs") => Some(syntax::SupportedLanguage::Rust),
             Some("cpp") | Some("hpp") | Some("h") => Some(syntax::SupportedLanguage::Cpp),
             Some("py") => Some(syntax::SupportedLanguage::Python),
@@ -82,13 +381,31 @@ impl Document {
 
     fn update_file_mode(&mut self) {
         let mut tb = self.buffer.borrow_mut();
-        tb.set_ruler(if self.filename == "COMMIT_EDITMSG" { 72 } else { 0 });
+        // Git wants the body of commit messages wrapped at 72 columns.
+        if self.filename == "COMMIT_EDITMSG" {
+            tb.set_ruler(72);
+        }
+        tb.set_auto_close_pairs(self.language.map(|l| l.auto_close_pairs()).unwrap_or_default());
+        tb.set_auto_indent_after(self.language.map(|l| l.indent_after()).unwrap_or_default());
     }
 }
 
 #[derive(Default)]
 pub struct DocumentManager {
     list: LinkedList<Document>,
+    /// Used for new documents and for files without any indentation.
+    pub default_indentation: Indentation,
+    /// Whether new documents start out with word wrap.
+    pub default_word_wrap: bool,
+    /// Whether the line the cursor is on is highlighted.
+    pub default_line_highlight: bool,
+    /// The column of the vertical ruler, or 0 for none.
+    pub default_ruler: CoordType,
+    /// The lines kept visible above and below the cursor.
+    pub default_scroll_off: CoordType,
+    /// Which whitespace is made visible in new documents.
+    pub default_whitespace: WhitespaceVisibility,
+    next_tab_order: u64,
 }
 
 impl DocumentManager {
@@ -97,6 +414,15 @@ impl DocumentManager {
         self.list.len()
     }
 
+    /// Iterates over the documents from most to least recently used.
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.list.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Document> {
+        self.list.iter_mut()
+    }
+
     #[inline]
     pub fn active(&self) -> Option<&Document> {
         self.list.front()
@@ -121,27 +447,63 @@ impl DocumentManager {
         false
     }
 
+    /// Closes the active document. Its unsaved changes are discarded, swap file included.
     pub fn remove_active(&mut self) {
-        self.list.pop_front();
+        if let Some(mut doc) = self.list.pop_front() {
+            doc.position_remember();
+            doc.swap_remove();
+            if doc.remote.is_some()
+                && let Some(path) = &doc.path
+            {
+                Remote::remove_local_copy(path);
+            }
+        }
     }
 
     pub fn add_untitled(&mut self) -> apperr::Result<&mut Document> {
-        let buffer = Self::create_buffer()?;
+        let buffer = self.create_buffer()?;
         let mut doc = Document {
             buffer,
             path: None,
             dir: Default::default(),
             filename: Default::default(),
             file_id: None,
+            file_stamp: None,
+            mixed_newlines_unreported: false,
+            invalid_utf8_unreported: false,
+            large_file: false,
+            large_file_unreported: false,
+            chosen_encoding: None,
+            indentation: self.default_indentation,
+            trim_trailing_whitespace: None,
+            final_newline: None,
             new_file_counter: 0,
+            tab_order: 0,
+            pinned: false,
+            checkpoints: Vec::new(),
+            scrollbar_markers_for: None,
+            search_marked_at: None,
+            diff_base_loaded: false,
+            diff_against: None,
+            blame_generation: None,
+            blamed_at: None,
+            blame_job: None,
+            swap_generation: None,
+            swap_failed: false,
+            autosave_failed: None,
+            diagnostics_generation: None,
+            remote: None,
             syntax_tree: None,
             language: None,
             buffer_generation: 0,
+            highlighted_at: None,
+            syntax_job: None,
             highlights: Vec::new(),
+            symbols: Vec::new(),
         };
         self.gen_untitled_name(&mut doc);
 
-        self.list.push_front(doc);
+        self.push_front(doc);
         Ok(self.list.front_mut().unwrap())
     }
 
@@ -156,8 +518,11 @@ impl DocumentManager {
         doc.new_file_counter = new_file_counter;
     }
 
+    /// Opens `path`, which may end in a `:line` or `:line:column` suffix to jump to,
+    /// as printed by compilers and grep. A file whose name actually ends like that wins.
     pub fn add_file_path(&mut self, path: &Path) -> apperr::Result<&mut Document> {
-        let (path, goto) = Self::parse_filename_goto(path);
+        let (path, goto) =
+            if path.exists() { (path, None) } else { Self::parse_filename_goto(path) };
         let path = path::normalize(path);
 
         let mut file = match Self::open_for_reading(&path) {
@@ -166,44 +531,99 @@ impl DocumentManager {
             Err(err) => return Err(err),
         };
 
+        if let Some(file) = &mut file
+            && Self::looks_binary(file)?
+        {
+            return Err(apperr::APP_BINARY_FILE);
+        }
+
         let file_id = if file.is_some() { Some(sys::file_id(file.as_ref(), &path)?) } else { None };
+        let file_stamp = if file.is_some() { FileStamp::of(&path) } else { None };
+        let large_file = file_stamp.is_some_and(|stamp| stamp.len > LARGE_FILE_SIZE);
 
         // Check if the file is already open.
         if file_id.is_some() && self.update_active(|doc| doc.file_id == file_id) {
             let doc = self.active_mut().unwrap();
             if let Some(goto) = goto {
-                doc.buffer.borrow_mut().cursor_move_to_logical(goto);
+                let mut tb = doc.buffer.borrow_mut();
+                tb.cursor_move_to_logical(goto);
+                tb.make_cursor_visible();
             }
             return Ok(doc);
         }
 
-        let buffer = Self::create_buffer()?;
+        let editorconfig = EditorConfig::lookup(&path);
+        let buffer = self.create_buffer()?;
+        let mut mixed_newlines_unreported = false;
+        let mut invalid_utf8_unreported = false;
+        // The UTF-8 variants are best left to the BOM detection.
+        let chosen_encoding = editorconfig.encoding.filter(|e| !e.starts_with("UTF-8"));
         {
             if let Some(file) = &mut file {
                 let mut tb = buffer.borrow_mut();
-                tb.read_file(file, None)?;
-
-                if let Some(goto) = goto
+                tb.read_file(file, chosen_encoding)?;
+                tb.set_read_only(sys::is_read_only(&path));
+                mixed_newlines_unreported = tb.has_mixed_newlines();
+                invalid_utf8_unreported = tb.has_invalid_utf8();
+
+                // Without an explicit position, the cursor and the view return
+                // to where they were last time.
+                let recalled = goto.is_none().then(|| positions::recall(&path)).flatten();
+                if let Some(pos) = recalled.filter(|pos| pos.cursor.y < tb.logical_line_count()) {
+                    tb.cursor_move_to_logical(pos.cursor);
+                    tb.scroll_to(pos.scroll);
+                    tb.make_cursor_visible();
+                } else if let Some(goto) = goto
                     && goto != Default::default()
                 {
                     tb.cursor_move_to_logical(goto);
+                    tb.make_cursor_visible();
                 }
             }
         }
 
+        let indentation = Indentation::of(&buffer.borrow());
         let mut doc = Document {
             buffer,
             path: None,
             dir: None,
             filename: Default::default(),
             file_id,
+            file_stamp,
+            mixed_newlines_unreported,
+            invalid_utf8_unreported,
+            large_file,
+            large_file_unreported: large_file,
+            chosen_encoding,
+            indentation,
+            trim_trailing_whitespace: None,
+            final_newline: None,
             new_file_counter: 0,
+            tab_order: 0,
+            pinned: false,
+            checkpoints: Vec::new(),
+            scrollbar_markers_for: None,
+            search_marked_at: None,
+            diff_base_loaded: false,
+            diff_against: None,
+            blame_generation: None,
+            blamed_at: None,
+            blame_job: None,
+            swap_generation: None,
+            swap_failed: false,
+            autosave_failed: None,
+            diagnostics_generation: None,
+            remote: None,
             syntax_tree: None,
             language: None,
             buffer_generation: 0,
+            highlighted_at: None,
+            syntax_job: None,
             highlights: Vec::new(),
+            symbols: Vec::new(),
         };
         doc.set_path(path);
+        doc.apply_editorconfig(&editorconfig);
 
         if let Some(active) = self.active()
             && active.path.is_none()
@@ -215,10 +635,77 @@ impl DocumentManager {
             self.remove_active();
         }
 
-        self.list.push_front(doc);
+        self.push_front(doc);
         Ok(self.list.front_mut().unwrap())
     }
 
+    /// Opens the file on another host by downloading it, or activates it if it's open already.
+    pub fn add_remote(&mut self, remote: Remote) -> apperr::Result<&mut Document> {
+        // Downloading it again would overwrite the local copy with any unsaved changes.
+        if self.update_active(|doc| doc.remote.as_ref() == Some(&remote)) {
+            return Ok(self.active_mut().unwrap());
+        }
+
+        let path = remote.local_path().ok_or(apperr::APP_REMOTE_FAILED)?;
+        remote.download(&path)?;
+
+        let doc = self.add_file_path(&path)?;
+        doc.dir = Some(DisplayablePathBuf::from_string(remote.dir_url()));
+        doc.remote = Some(remote);
+        Ok(doc)
+    }
+
+    /// Adds a new document as the active one. It gets the last spot in the tab bar.
+    fn push_front(&mut self, mut doc: Document) {
+        doc.tab_order = self.next_tab_order;
+        self.next_tab_order += 1;
+        self.list.push_front(doc);
+    }
+
+    /// Returns the documents in the order of the tab bar, with the pinned ones first.
+    pub fn tabs(&self) -> Vec<&Document> {
+        let mut tabs: Vec<_> = self.list.iter().collect();
+        tabs.sort_by_key(|doc| (!doc.pinned, doc.tab_order));
+        tabs
+    }
+
+    /// Swaps the active document's tab with the one `delta` tabs to the right of it.
+    /// Tabs don't move past the boundary between the pinned and unpinned ones.
+    pub fn move_tab_relative(&mut self, delta: isize) {
+        let Some(active) = self.active() else {
+            return;
+        };
+        let tabs = self.tabs();
+        let index = tabs.iter().position(|doc| doc.tab_order == active.tab_order).unwrap_or(0);
+        let Some(target) = index.checked_add_signed(delta).and_then(|i| tabs.get(i)) else {
+            return;
+        };
+        if target.pinned != active.pinned {
+            return;
+        }
+
+        let (a, b) = (active.tab_order, target.tab_order);
+        for doc in self.list.iter_mut() {
+            if doc.tab_order == a {
+                doc.tab_order = b;
+            } else if doc.tab_order == b {
+                doc.tab_order = a;
+            }
+        }
+    }
+
+    /// Activates the document `delta` tabs to the right of the active one, wrapping around.
+    pub fn activate_tab_relative(&mut self, delta: isize) {
+        let Some(active) = self.active() else {
+            return;
+        };
+        let tabs = self.tabs();
+        let index = tabs.iter().position(|doc| doc.tab_order == active.tab_order).unwrap_or(0);
+        let target = tabs[(index as isize + delta).rem_euclid(tabs.len() as isize) as usize];
+        let tab_order = target.tab_order;
+        self.update_active(|doc| doc.tab_order == tab_order);
+    }
+
     pub fn reflow_all(&self) {
         for doc in &self.list {
             let mut tb = doc.buffer.borrow_mut();
@@ -234,20 +721,61 @@ impl DocumentManager {
         File::create(path).map_err(apperr::Error::from)
     }
 
-    fn create_buffer() -> apperr::Result<RcTextBuffer> {
+    /// Returns what "Show Whitespace" switches `visibility` to: all whitespace if it isn't
+    /// shown yet, or otherwise back to the configured default, unless that's all of it.
+    pub fn whitespace_toggled(&self, visibility: WhitespaceVisibility) -> WhitespaceVisibility {
+        match visibility {
+            WhitespaceVisibility::All if self.default_whitespace != WhitespaceVisibility::All => {
+                self.default_whitespace
+            }
+            WhitespaceVisibility::All => WhitespaceVisibility::Off,
+            _ => WhitespaceVisibility::All,
+        }
+    }
+
+    fn create_buffer(&self) -> apperr::Result<RcTextBuffer> {
         let buffer = TextBuffer::new_rc(false)?;
         {
             let mut tb = buffer.borrow_mut();
+            self.default_indentation.apply(&mut tb);
             tb.set_insert_final_newline(!cfg!(windows)); // As mandated by POSIX.
             tb.set_margin_enabled(true);
-            tb.set_line_highlight_enabled(true);
+            tb.set_line_highlight_enabled(self.default_line_highlight);
+            tb.set_ruler(self.default_ruler);
+            tb.set_scroll_off(self.default_scroll_off);
+            tb.set_whitespace_visibility(self.default_whitespace);
+            tb.set_word_wrap(self.default_word_wrap);
         }
         Ok(buffer)
     }
 
+    /// Checks whether the start of a file looks like anything but text, see [`buffer::sniff`].
+    /// Anything that isn't a regular file is assumed to be text, since it can't be rewound.
+    fn looks_binary(file: &mut File) -> apperr::Result<bool> {
+        if !file.metadata()?.is_file() {
+            return Ok(false);
+        }
+
+        let mut buf = [0; 8 * 1024];
+        let mut len = 0;
+        while len < buf.len() {
+            match file.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        file.rewind()?;
+
+        let sample = &buf[..len];
+        let has_bom = [&b"\xFF\xFE"[..], b"\xFE\xFF", b"\x00\x00\xFE\xFF"]
+            .iter()
+            .any(|bom| sample.starts_with(bom));
+        Ok(!has_bom && buffer::sniff(sample) == Sniffed::Binary)
+    }
+
     // Parse a filename in the form of "filename:line:char".
     // Returns the position of the first colon and the line/char coordinates.
-    fn parse_filename_goto(path: &Path) -> (&Path, Option<Point>) {
+    pub fn parse_filename_goto(path: &Path) -> (&Path, Option<Point>) {
         fn parse(s: &[u8]) -> Option<CoordType> {
             if s.is_empty() {
                 return None;
@@ -269,10 +797,16 @@ impl DocumentManager {
         }
 
         let bytes = path.as_os_str().as_encoded_bytes();
+        // The colon of a drive letter like "C:" on Windows is part of the filename.
+        let prefix = if cfg!(windows) && matches!(bytes, [b'A'..=b'Z' | b'a'..=b'z', b':', ..]) {
+            2
+        } else {
+            0
+        };
         let colend = match find_colon_rev(bytes, bytes.len()) {
             // Reject filenames that would result in an empty filename after stripping off the :line:char suffix.
             // For instance, a filename like ":123:456" will not be processed by this function.
-            Some(colend) if colend > 0 => colend,
+            Some(colend) if colend > prefix => colend,
             _ => return (path, None),
         };
 
@@ -286,7 +820,7 @@ impl DocumentManager {
 
         if let Some(colbeg) = find_colon_rev(bytes, colend) {
             // Same here: Don't allow empty filenames.
-            if colbeg != 0
+            if colbeg > prefix
                 && let Some(first) = parse(&bytes[colbeg + 1..colend])
             {
                 let first = (first - 1).max(0);
@@ -333,4 +867,20 @@ mod tests {
         assert_eq!(parse("file.txt:10"), ("file.txt", Some(Point { x: 0, y: 9 })));
         assert_eq!(parse("file.txt:10:5"), ("file.txt", Some(Point { x: 4, y: 9 })));
     }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_parse_drive_letters() {
+        fn parse(s: &str) -> (&str, Option<Point>) {
+            let (p, g) = DocumentManager::parse_filename_goto(Path::new(s));
+            (p.to_str().unwrap(), g)
+        }
+
+        assert_eq!(parse(r"C:\a.rs"), (r"C:\a.rs", None));
+        assert_eq!(parse(r"C:\a.rs:12"), (r"C:\a.rs", Some(Point { x: 0, y: 11 })));
+        assert_eq!(parse(r"C:\a.rs:12:5"), (r"C:\a.rs", Some(Point { x: 4, y: 11 })));
+        assert_eq!(parse("C:12"), ("C:12", None));
+        assert_eq!(parse("C:a.rs:12"), ("C:a.rs", Some(Point { x: 0, y: 11 })));
+        assert_eq!(parse("C::12"), ("C::12", None));
+    }
 }
@@ -0,0 +1,111 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::time::Duration;
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::build::BuildStatus;
+use crate::draw_editor::editor_width;
+use crate::localization::*;
+use crate::state::*;
+
+/// The height of the output panel, including its title.
+const OUTPUT_PANEL_HEIGHT: CoordType = 12;
+/// How often to check for output while the build command is running.
+const BUILD_POLL: Duration = Duration::from_millis(50);
+
+/// Returns the height that the output panel takes away from the panes.
+/// It gets at most half of the screen.
+pub fn output_panel_height(ctx: &Context, state: &State) -> CoordType {
+    if state.build.visible { OUTPUT_PANEL_HEIGHT.min(ctx.size().height / 2) } else { 0 }
+}
+
+/// Picks up the output of the running build command.
+pub fn update_build_output(ctx: &mut Context, state: &mut State) {
+    if state.build_update() {
+        ctx.needs_rerender();
+    }
+    if state.build.job.is_some() {
+        ctx.needs_rerender_in(BUILD_POLL);
+    }
+}
+
+/// Draws the output panel below the panes. Activating a line that mentions a file jumps there.
+/// Escape cancels the build command, or hides the panel if it isn't running.
+pub fn draw_output_panel(ctx: &mut Context, state: &mut State) {
+    let width = editor_width(ctx, state);
+    let height = output_panel_height(ctx, state);
+    let mut activated = None;
+
+    ctx.block_begin("output");
+    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Black));
+    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::White));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        let build = &state.build;
+        let title = match build.status {
+            BuildStatus::NotRun => loc(LocId::BuildNotRun).to_string(),
+            BuildStatus::Running => loc(LocId::BuildRunning).replace("{command}", &build.command),
+            BuildStatus::Exited(code) => loc(LocId::BuildExited)
+                .replace("{command}", &build.command)
+                .replace("{code}", &code.map_or_else(|| "?".to_string(), |c| c.to_string())),
+            BuildStatus::Canceled => loc(LocId::BuildCanceled).replace("{command}", &build.command),
+        };
+        ctx.label("title", &title);
+        ctx.attr_overflow(Overflow::TruncateTail);
+        ctx.attr_reverse();
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height: (height - 1).max(0) });
+        if state.build.follow {
+            state.build.follow = false;
+            ctx.scrollarea_scroll_to(Point { x: 0, y: CoordType::MAX });
+        }
+        {
+            ctx.list_begin("lines");
+            for (i, line) in state.build.lines.iter().enumerate() {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                if line.location.is_some() {
+                    ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::BrightBlue));
+                }
+                ctx.styled_label_add_text(&line.text);
+                if ctx.styled_list_item_end(false) == ListSelection::Activated {
+                    activated = Some(i);
+                }
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+
+        if contains_focus && ctx.consume_shortcut(vk::ESCAPE) {
+            if state.build.job.is_some() {
+                state.build_cancel();
+            } else {
+                state.build.visible = false;
+                state.panes.focus_changed = true;
+            }
+            ctx.needs_rerender();
+        }
+    }
+    ctx.block_end();
+
+    if let Some((path, goto)) = activated.and_then(|i| state.build.lines[i].location.clone()) {
+        state.jump_push();
+        match state.documents.add_file_path(&path) {
+            Ok(doc) => {
+                let mut tb = doc.buffer.borrow_mut();
+                tb.cursor_move_to_logical(goto);
+                tb.make_cursor_visible();
+                // Give the focus back to the editor.
+                state.panes.focus_changed = true;
+            }
+            Err(err) => error_log_add(ctx, state, err),
+        }
+        ctx.needs_rerender();
+    }
+}
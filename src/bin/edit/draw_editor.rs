@@ -1,64 +1,474 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::num::ParseIntError;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::{mem, thread};
 
+use edit::arena::ArenaString;
+use edit::buffer::{SearchOptions, TextBuffer};
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
-use edit::icu;
-use edit::input::{kbmod, vk};
+use edit::input::vk;
 use edit::tui::*;
+use edit::{apperr, arena_format, icu, syntax};
 
+use crate::build::{Build, Severity};
+use crate::config::Action;
+use crate::documents::{Document, ScrollbarMarkers};
+use crate::draw_build::{draw_output_panel, output_panel_height, update_build_output};
+use crate::draw_outline::{carry_over_expansion, draw_breadcrumbs};
+use crate::git;
 use crate::localization::*;
+use crate::snippets::handle_snippet_keys;
 use crate::state::*;
 
 pub fn draw_editor(ctx: &mut Context, state: &mut State) {
+    if let Some(doc) = state.documents.active_mut()
+        && mem::take(&mut doc.mixed_newlines_unreported)
+    {
+        let newline = if doc.buffer.borrow().is_crlf() { "CRLF" } else { "LF" };
+        let msg = loc(LocId::NoticeMixedNewlines)
+            .replace("{filename}", &doc.filename)
+            .replace("{newline}", newline);
+        error_log_add_message(ctx, state, msg);
+    }
+    if let Some(doc) = state.documents.active_mut()
+        && mem::take(&mut doc.invalid_utf8_unreported)
+    {
+        let msg = loc(LocId::NoticeInvalidUtf8).replace("{filename}", &doc.filename);
+        error_log_add_message(ctx, state, msg);
+    }
+    if let Some(doc) = state.documents.active_mut()
+        && mem::take(&mut doc.large_file_unreported)
+    {
+        let msg = loc(LocId::NoticeLargeFile).replace("{filename}", &doc.filename);
+        error_log_add_message(ctx, state, msg);
+    }
+
+    // The search bar and the panes are stacked within the editor's column of the main layout.
+    ctx.block_begin("editor");
+    ctx.inherit_focus();
+
     if !matches!(state.wants_search.kind, StateSearchKind::Hidden | StateSearchKind::Disabled) {
         draw_search(ctx, state);
     }
 
     let size = ctx.size();
     // TODO: The layout code should be able to just figure out the height on its own.
-    let height_reduction = match state.wants_search.kind {
+    let mut height_reduction = match state.wants_search.kind {
         StateSearchKind::Search => 4,
         StateSearchKind::Replace => 5,
         _ => 2,
     };
+    // The error message below the needle.
+    if matches!(state.wants_search.kind, StateSearchKind::Search | StateSearchKind::Replace)
+        && matches!(state.search_status, SearchStatus::Invalid(_))
+    {
+        height_reduction += 1;
+    }
+    // The tab bar.
+    if state.documents.len() != 0 {
+        height_reduction += 1;
+    }
+    // Only documents that get parsed have definitions to show.
+    if state
+        .documents
+        .active()
+        .and_then(|doc| doc.language)
+        .is_some_and(|lang| state.config.syntax_enabled_for(lang))
+    {
+        draw_breadcrumbs(ctx, state);
+        height_reduction += 1;
+    }
+    height_reduction += output_panel_height(ctx, state);
+    // A terminal that got resized down to a few lines has no room left for the text.
+    let height = (size.height - height_reduction).max(0);
+
+    state.panes_prune();
+    update_scrollbar_markers(ctx, state);
+    update_diff_bases(state);
+    update_diagnostic_marks(state);
+    update_blame(ctx, state);
+    update_build_output(ctx, state);
+
+    // All panes get the same width, so that panes showing the same document
+    // don't fight over the width to word wrap it at. Any remainder is left empty.
+    let count = state.panes.list.len() as CoordType;
+    let width = ((editor_width(ctx, state) - (count - 1)) / count).max(0);
+    let mut clicked = None;
+
+    ctx.table_begin("panes");
+    ctx.table_set_columns(&vec![width; count as usize]);
+    ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+    ctx.inherit_focus();
+    if ctx.contains_focus() {
+        handle_snippet_keys(ctx, state);
+    }
+    {
+        ctx.table_next_row();
+        ctx.inherit_focus();
+
+        for index in 0..state.panes.list.len() {
+            ctx.next_block_id_mixin(state.panes.list[index].id);
+            if draw_pane(ctx, state, index) {
+                clicked = Some(index);
+            }
+            ctx.attr_intrinsic_size(Size { width, height });
+        }
+    }
+    ctx.table_end();
+
+    if state.build.visible {
+        draw_output_panel(ctx, state);
+    }
+    ctx.block_end();
+
+    if let Some(index) = clicked {
+        state.pane_focus(index);
+        ctx.needs_rerender();
+    }
+}
+
+/// Loads or drops what the changed lines of each document are marked against,
+/// depending on [`State::show_changes`]. Documents opened with `--diff` always have it.
+fn update_diff_bases(state: &mut State) {
+    for doc in state.documents.iter_mut() {
+        let show = (state.show_changes && !doc.large_file) || doc.diff_against.is_some();
+        if doc.diff_base_loaded != show {
+            let base = if show { doc.diff_base() } else { None };
+            match doc.diff_against {
+                Some((_, true)) => doc.buffer.borrow_mut().set_diff_target(base),
+                _ => doc.buffer.borrow_mut().set_diff_base(base),
+            }
+            doc.diff_base_loaded = show;
+        }
+    }
+}
+
+/// Marks the lines that the build command reported errors and warnings for,
+/// in the margin of each document.
+fn update_diagnostic_marks(state: &mut State) {
+    let generation = state.build.diagnostics_generation;
+    for doc in state.documents.iter_mut() {
+        if doc.diagnostics_generation == Some(generation) {
+            continue;
+        }
+        let marks = diagnostic_marks(&state.build, doc, Severity::Note);
+        doc.buffer.borrow_mut().set_line_marks(marks);
+        doc.diagnostics_generation = Some(generation);
+    }
+}
+
+/// Returns the lines of `doc` that the build command reported diagnostics for, at least as
+/// severe as `least`, in the color of the most severe one on each line, sorted by line.
+fn diagnostic_marks(
+    build: &Build,
+    doc: &Document,
+    least: Severity,
+) -> Vec<(CoordType, IndexedColor)> {
+    let Some(path) = &doc.path else {
+        return Vec::new();
+    };
+    let mut marks: Vec<_> = build
+        .diagnostics_in(path)
+        .filter(|d| d.severity <= least)
+        .map(|d| (d.pos.y, d.severity))
+        .collect();
+    // The most severe one on each line comes first, and that's the one that's kept.
+    marks.sort();
+    marks.dedup_by_key(|&mut (y, _)| y);
+    marks.into_iter().map(|(y, severity)| (y, severity.color())).collect()
+}
 
+/// Moves the cursor to the next or previous run of changed lines, e.g. the hunks of `edit --diff`.
+pub fn goto_change(state: &mut State, forward: bool) {
+    state.jump_push();
     if let Some(doc) = state.documents.active() {
-        if doc.language.is_some() {
-            draw_highlighted_editor(ctx, state);
-        } else {
-            ctx.textarea("textarea", doc.buffer.clone());
-            ctx.inherit_focus();
+        let mut tb = doc.buffer.borrow_mut();
+        if tb.cursor_move_to_change(forward) {
+            tb.make_cursor_visible();
+        }
+    }
+}
+
+/// How often blame is run again while typing. It's slow for files with a long history.
+const BLAME_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to check for the result of a blame that's still running.
+const BLAME_POLL: Duration = Duration::from_millis(50);
+
+/// Annotates the margin of the documents shown in a pane with who last changed each line,
+/// depending on [`State::show_blame`]. `git blame` runs on a thread, since it can take a while.
+/// Files that aren't tracked by git simply get no annotations.
+fn update_blame(ctx: &mut Context, state: &mut State) {
+    // The focused pane shows the active document. The `buffer` it remembers is stale.
+    let focused = state.panes.focused;
+    let visible: Vec<_> = state
+        .documents
+        .active()
+        .map(|doc| doc.buffer.clone())
+        .into_iter()
+        .chain(
+            (state.panes.list.iter().enumerate())
+                .filter(|&(i, _)| i != focused)
+                .filter_map(|(_, p)| p.buffer.clone()),
+        )
+        .collect();
+
+    for doc in state.documents.iter_mut() {
+        let visible = visible.iter().any(|tb| Rc::ptr_eq(tb, &doc.buffer));
+        if !state.show_blame || !visible {
+            if doc.blame_generation.take().is_some() {
+                doc.blame_job = None;
+                doc.buffer.borrow_mut().set_margin_annotations(Vec::new());
+            }
+            continue;
+        }
+        let Some(path) = doc.path.clone() else {
+            continue;
+        };
+
+        let generation = doc.buffer.borrow().generation();
+        if doc.blame_job.is_none() && doc.blame_generation != Some(generation) {
+            let wait = doc
+                .blamed_at
+                .map_or(Duration::ZERO, |at| BLAME_INTERVAL.saturating_sub(at.elapsed()));
+            if wait.is_zero() {
+                let mut contents = String::new();
+                doc.buffer.borrow().copy_into(&mut contents);
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || _ = tx.send(git::blame(&path, contents.into_bytes())));
+                doc.blame_job = Some(rx);
+                doc.blame_generation = Some(generation);
+                doc.blamed_at = Some(Instant::now());
+            } else {
+                ctx.needs_rerender_in(wait);
+            }
+        }
+
+        if let Some(job) = &doc.blame_job {
+            match job.try_recv() {
+                Ok(blame) => {
+                    doc.blame_job = None;
+                    let blame = blame.unwrap_or_default();
+                    let annotations = blame.iter().map(blame_annotation).collect();
+                    doc.buffer.borrow_mut().set_margin_annotations(annotations);
+                    ctx.needs_rerender();
+                }
+                Err(mpsc::TryRecvError::Empty) => ctx.needs_rerender_in(BLAME_POLL),
+                Err(mpsc::TryRecvError::Disconnected) => doc.blame_job = None,
+            }
+        }
+    }
+}
+
+/// Formats a line's blame as "author     YYYY-MM-DD", with the author cut off if they're long.
+fn blame_annotation(line: &git::BlameLine) -> String {
+    if line.uncommitted {
+        return format!("{:23.23}", loc(LocId::BlameUncommitted));
+    }
+
+    // Converts days since the Unix epoch into a (UTC) date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = line.time.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!("{:12.12} {year:04}-{month:02}-{day:02}", line.author)
+}
+
+/// How often the search matches are marked again while typing. It searches the whole document.
+const SEARCH_MARKERS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Marks the errors and warnings from the build command on the scrollbar of each document,
+/// and the matches of the search bar's needle on that of the active one.
+fn update_scrollbar_markers(ctx: &mut Context, state: &mut State) {
+    /// Beyond this many matches the scrollbar is covered in marks anyway.
+    const MAX_MARKERS: usize = 10000;
+
+    let searching =
+        matches!(state.wants_search.kind, StateSearchKind::Search | StateSearchKind::Replace)
+            && !state.search_needle.is_empty()
+            && !matches!(state.search_status, SearchStatus::Invalid(_));
+    let active = state.documents.active().map(|doc| Rc::as_ptr(&doc.buffer));
+
+    for doc in state.documents.iter_mut() {
+        let mut tb = doc.buffer.borrow_mut();
+        let search = (searching && !doc.large_file && active == Some(Rc::as_ptr(&doc.buffer)))
+            .then(|| (state.search_needle.clone(), state.search_options));
+        // The marks are dropped on every edit, since the matches move. The diagnostics don't,
+        // but they have to be marked again along with the matches.
+        let key = ScrollbarMarkers {
+            search,
+            diagnostics_generation: state.build.diagnostics_generation,
+            buffer_generation: tb.generation(),
+        };
+        if doc.scrollbar_markers_for.as_ref() == Some(&key) {
+            continue;
         }
+
+        let matches: Vec<_> = match &key.search {
+            Some((needle, options)) => {
+                let wait = doc.search_marked_at.map_or(Duration::ZERO, |at| {
+                    SEARCH_MARKERS_INTERVAL.saturating_sub(at.elapsed())
+                });
+                if !wait.is_zero() {
+                    ctx.needs_rerender_in(wait);
+                    continue;
+                }
+                doc.search_marked_at = Some(Instant::now());
+                tb.search_iter(needle, *options, 0..usize::MAX)
+                    .map(|hits| {
+                        hits.take(MAX_MARKERS)
+                            .map(|hit| (hit.start, IndexedColor::BrightBlue))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            }
+            None => Vec::new(),
+        };
+        let diagnostics = diagnostic_marks(&state.build, doc, Severity::Warning);
+        tb.set_scrollbar_markers(&matches, &diagnostics);
+        doc.scrollbar_markers_for = Some(key);
+    }
+}
+
+/// The width of the file tree and the outline.
+pub const SIDE_PANEL_WIDTH: CoordType = 30;
+
+/// Returns the width that's left for the editor next to the visible side panels.
+pub fn editor_width(ctx: &Context, state: &State) -> CoordType {
+    let panels = state.file_tree.visible as CoordType + state.outline.visible as CoordType;
+    (ctx.size().width - panels * SIDE_PANEL_WIDTH).max(0)
+}
+
+/// Draws the pane at `index`. Returns true if it isn't the focused pane, but got the
+/// input focus, e.g. because it was clicked. It should then become the focused pane.
+fn draw_pane(ctx: &mut Context, state: &mut State, index: usize) -> bool {
+    let focused = index == state.panes.focused;
+    let doc = if focused {
+        state.documents.active_mut()
     } else {
+        let buffer = state.panes.list[index].buffer.as_ref();
+        state.documents.iter_mut().find(|doc| buffer.is_some_and(|b| Rc::ptr_eq(&doc.buffer, b)))
+    };
+    let Some(doc) = doc else {
         ctx.block_begin("empty");
         ctx.block_end();
+        return false;
+    };
+
+    if doc.language.is_some_and(|lang| state.config.syntax_enabled_for(lang)) {
+        update_highlights(ctx, &state.syntax, &state.config.syntax_theme, doc);
     }
 
-    ctx.attr_intrinsic_size(Size { width: 0, height: size.height - height_reduction });
+    // If the document is shown in several panes, only the focused one should scroll to the cursor.
+    let buffer = doc.buffer.clone();
+    let visibility_request = !focused && buffer.borrow_mut().take_cursor_visibility_request();
+    ctx.textarea("textarea", buffer.clone());
+    if visibility_request {
+        buffer.borrow_mut().make_cursor_visible();
+    }
+
+    if focused {
+        ctx.inherit_focus();
+        if mem::take(&mut state.panes.focus_changed) {
+            ctx.steal_focus();
+        }
+        false
+    } else {
+        ctx.is_focused()
+    }
 }
 
-fn draw_highlighted_editor(ctx: &mut Context, state: &mut State) {
-    let doc = state.documents.active_mut().unwrap();
+const HIGHLIGHT_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to block rendering for a syntax job that was just started. Small files
+/// finish within that, so that typing doesn't show stale highlights for a frame.
+const HIGHLIGHT_WAIT: Duration = Duration::from_millis(4);
+/// How often to check for the result of a syntax job that's still running.
+const HIGHLIGHT_POLL: Duration = Duration::from_millis(16);
+
+fn update_highlights(
+    ctx: &mut Context,
+    worker: &syntax::SyntaxWorker,
+    theme: &syntax::Theme,
+    doc: &mut Document,
+) {
     let lang = doc.language.unwrap();
 
+    // Parsing and highlighting happen on a worker thread. While typing, they're started
+    // at most every `HIGHLIGHT_INTERVAL`. Meanwhile, the highlights from before the edits are shown.
     let current_generation = doc.buffer.borrow().generation();
-    if doc.buffer_generation != current_generation {
-        let mut code = String::new();
-        doc.buffer.borrow_mut().save_as_string(&mut code);
+    let wait = doc
+        .highlighted_at
+        .map_or(Duration::ZERO, |at| HIGHLIGHT_INTERVAL.saturating_sub(at.elapsed()));
+    let mut submitted = false;
+    if doc.syntax_job.is_none() && doc.buffer_generation != current_generation {
+        if wait.is_zero() {
+            let mut code = String::new();
+            doc.buffer.borrow().copy_into(&mut code);
+            doc.syntax_job = Some(worker.submit(code, lang, current_generation));
+            doc.highlighted_at = Some(Instant::now());
+            submitted = true;
+        } else {
+            ctx.needs_rerender_in(wait);
+        }
+    }
 
-        doc.syntax_tree = state.syntax.parse(&code, lang);
-        doc.highlights = state.syntax.highlight(&code, lang).collect();
-        doc.buffer_generation = current_generation;
+    if let Some(job) = &doc.syntax_job {
+        let timeout = if submitted { HIGHLIGHT_WAIT } else { Duration::ZERO };
+        match job.recv_timeout(timeout) {
+            Ok(analysis) => {
+                doc.syntax_job = None;
+                apply_analysis(doc, analysis);
+                // There may have been more edits while the job was running.
+                if doc.buffer_generation != current_generation {
+                    ctx.needs_rerender();
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => ctx.needs_rerender_in(HIGHLIGHT_POLL),
+            Err(mpsc::RecvTimeoutError::Disconnected) => doc.syntax_job = None,
+        }
     }
 
-    let highlights = doc.highlights.clone();
+    let highlights = doc
+        .highlights
+        .iter()
+        .filter_map(|(range, highlight)| Some((range.clone(), theme.color(*highlight)?)))
+        .collect();
     doc.buffer.borrow_mut().set_highlights(highlights);
+}
 
-    ctx.textarea("textarea", doc.buffer.clone());
-    ctx.inherit_focus();
+fn apply_analysis(doc: &mut Document, analysis: syntax::Analysis) {
+    let syntax::Analysis { code, generation, tree, highlights } = analysis;
+    // The offsets are only right for the text the job got. If there were edits since,
+    // the old highlights are close enough until the next job, but brackets, folds and
+    // the like would point at the wrong text, so those wait for an up-to-date result.
+    if let Some(tree) = &tree
+        && generation == doc.buffer.borrow().generation()
+    {
+        let mut tb = doc.buffer.borrow_mut();
+        tb.set_bracket_pairs(syntax::bracket_pairs(tree));
+        tb.set_fold_regions(syntax::fold_regions(tree));
+        tb.set_literal_ranges(syntax::literal_ranges(tree));
+        tb.set_indent_regions(syntax::indent_regions(tree));
+    }
+
+    let mut symbols =
+        tree.as_ref().map_or_else(Vec::new, |tree| syntax::document_symbols(tree, code.as_bytes()));
+    carry_over_expansion(&doc.symbols, &mut symbols);
+    doc.symbols = symbols;
+    doc.syntax_tree = tree;
+    doc.highlights = highlights;
+    doc.buffer_generation = generation;
 }
 
 fn draw_search(ctx: &mut Context, state: &mut State) {
@@ -75,16 +485,36 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
 
     let mut action = None;
     let mut focus = StateSearchKind::Hidden;
+    // Set when the user explicitly submits the search, as opposed to typing into it.
+    let mut remember = false;
+    let select_all_matches = state.config.shortcut(Action::SelectAllMatches);
+    let replace_all = state.config.shortcut(Action::ReplaceAll);
 
     if state.wants_search.focus {
         state.wants_search.focus = false;
         focus = StateSearchKind::Search;
+        // Typing into the search moves the cursor from match to match. Going back returns here.
+        state.jumps.push(doc);
+        if state.search_origin.is_none() {
+            let tb = doc.buffer.borrow();
+            state.search_origin = Some((tb.cursor_logical_pos(), tb.scroll_offset()));
+        }
 
         // If the selection is empty, focus the search input field.
         // Otherwise, focus the replace input field, if it exists.
-        if let Some(selection) = doc.buffer.borrow_mut().extract_user_selection(false) {
-            state.search_needle = String::from_utf8_lossy_owned(selection);
-            focus = state.wants_search.kind;
+        let mut tb = doc.buffer.borrow_mut();
+        if let Some((beg, end)) = tb.selection_range()
+            && let Some(selection) = tb.extract_user_selection(false)
+        {
+            // Remember the selection, so that "In Selection" can be confined to it.
+            state.search_scope =
+                Some(SearchScope::new(&doc.buffer, beg.offset..end.offset, tb.generation()));
+
+            // A selection spanning multiple lines is more likely meant as the scope than the needle.
+            if beg.logical_pos.y == end.logical_pos.y {
+                state.search_needle = String::from_utf8_lossy_owned(selection);
+                focus = state.wants_search.kind;
+            }
         }
     }
 
@@ -95,6 +525,15 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
     {
         if ctx.contains_focus() && ctx.consume_shortcut(vk::ESCAPE) {
             state.wants_search.kind = StateSearchKind::Hidden;
+            // Cancelling the search returns to where it started, before typing moved the cursor.
+            if let Some((cursor, scroll)) = state.search_origin.take() {
+                let mut tb = doc.buffer.borrow_mut();
+                tb.cursor_move_to_logical(cursor);
+                tb.scroll_to(scroll);
+            }
+        } else if !ctx.contains_focus() && focus == StateSearchKind::Hidden {
+            // Moving on to the editor keeps the match the cursor is at.
+            state.search_origin = None;
         }
 
         ctx.table_begin("needle");
@@ -105,26 +544,70 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 ctx.label("label", loc(LocId::SearchNeedleLabel));
 
                 if ctx.editline("needle", &mut state.search_needle) {
-                    action = Some(SearchAction::Search);
+                    state.search_needle_history.reset();
+                    action = Some(SearchAction::Preview);
+                    // After a match was committed, typing on starts from there.
+                    if state.search_origin.is_none() {
+                        let tb = doc.buffer.borrow();
+                        state.search_origin = Some((tb.cursor_logical_pos(), tb.scroll_offset()));
+                    }
                 }
-                if !state.search_success {
-                    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
-                    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+                match state.search_status {
+                    SearchStatus::Found => {}
+                    SearchStatus::NoMatches => {
+                        ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::Red));
+                    }
+                    SearchStatus::Invalid(_) => {
+                        ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+                        ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+                    }
                 }
                 ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
                 if focus == StateSearchKind::Search {
                     ctx.steal_focus();
                 }
-                if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
-                    action = Some(SearchAction::Search);
+                if ctx.is_focused() {
+                    if ctx.consume_shortcut(vk::RETURN) {
+                        // The first Enter settles on the match found while typing. Any further
+                        // ones move on to the next match.
+                        let previewed = state.search_origin.is_some_and(|(cursor, _)| {
+                            doc.buffer.borrow().cursor_logical_pos() != cursor
+                        });
+                        if !previewed {
+                            state.jumps.push(doc);
+                            action = Some(SearchAction::Search);
+                        }
+                        remember = true;
+                    } else if ctx.consume_shortcut(select_all_matches) {
+                        action = Some(SearchAction::SelectAll);
+                        remember = true;
+                    } else if ctx.consume_shortcut(vk::UP) {
+                        if state.search_needle_history.recall_older(&mut state.search_needle) {
+                            action = Some(SearchAction::Preview);
+                        }
+                    } else if ctx.consume_shortcut(vk::DOWN)
+                        && state.search_needle_history.recall_newer(&mut state.search_needle)
+                    {
+                        action = Some(SearchAction::Preview);
+                    }
                 }
             }
 
+            if let SearchStatus::Invalid(msg) = &state.search_status {
+                ctx.table_next_row();
+                ctx.label("error-label", "");
+                ctx.label("error", msg);
+                ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::Red));
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+
             if state.wants_search.kind == StateSearchKind::Replace {
                 ctx.table_next_row();
                 ctx.label("label", loc(LocId::SearchReplacementLabel));
 
-                ctx.editline("replacement", &mut state.search_replacement);
+                if ctx.editline("replacement", &mut state.search_replacement) {
+                    state.search_replacement_history.reset();
+                }
                 ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
                 if focus == StateSearchKind::Replace {
                     ctx.steal_focus();
@@ -132,8 +615,23 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 if ctx.is_focused() {
                     if ctx.consume_shortcut(vk::RETURN) {
                         action = Some(SearchAction::Replace);
-                    } else if ctx.consume_shortcut(kbmod::CTRL_ALT | vk::RETURN) {
+                        remember = true;
+                    } else if ctx.consume_shortcut(replace_all) {
                         action = Some(SearchAction::ReplaceAll);
+                        remember = true;
+                    } else if ctx.consume_shortcut(vk::UP) {
+                        if state
+                            .search_replacement_history
+                            .recall_older(&mut state.search_replacement)
+                        {
+                            ctx.needs_rerender();
+                        }
+                    } else if ctx.consume_shortcut(vk::DOWN)
+                        && state
+                            .search_replacement_history
+                            .recall_newer(&mut state.search_replacement)
+                    {
+                        ctx.needs_rerender();
                     }
                 }
             }
@@ -144,7 +642,7 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
         ctx.table_set_cell_gap(Size { width: 2, height: 0 });
         {
             let mut change = false;
-            let mut change_action = Some(SearchAction::Search);
+            let mut change_action = Some(SearchAction::Preview);
 
             ctx.table_next_row();
 
@@ -163,11 +661,28 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 loc(LocId::SearchUseRegex),
                 &mut state.search_options.use_regex,
             );
+            if state.wants_search.kind == StateSearchKind::Replace {
+                change |= ctx.checkbox(
+                    "preserve-case",
+                    loc(LocId::SearchPreserveCase),
+                    &mut state.search_options.preserve_case,
+                );
+            }
+            change |= ctx.checkbox(
+                "in-selection",
+                loc(LocId::SearchInSelection),
+                &mut state.search_in_selection,
+            );
             if state.wants_search.kind == StateSearchKind::Replace
                 && ctx.button("replace-all", loc(LocId::SearchReplaceAll), ButtonStyle::default())
             {
                 change = true;
                 change_action = Some(SearchAction::ReplaceAll);
+                remember = true;
+            }
+            if ctx.button("select-all", loc(LocId::SearchSelectAll), ButtonStyle::default()) {
+                action = Some(SearchAction::SelectAll);
+                remember = true;
             }
             if ctx.button("close", loc(LocId::SearchClose), ButtonStyle::default()) {
                 state.wants_search.kind = StateSearchKind::Hidden;
@@ -183,46 +698,164 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
     }
     ctx.block_end();
 
+    if remember {
+        state.search_origin = None;
+        state.search_needle_history.push(&state.search_needle);
+        if state.wants_search.kind == StateSearchKind::Replace {
+            state.search_replacement_history.push(&state.search_replacement);
+        }
+    }
+
     if let Some(action) = action {
         search_execute(ctx, state, action);
     }
 }
 
 pub enum SearchAction {
+    /// Like `Search`, but from where the search started, so that each keystroke
+    /// shows the nearest match instead of moving on from the previous one.
+    Preview,
     Search,
     Replace,
     ReplaceAll,
+    SelectAll,
 }
 
 pub fn search_execute(ctx: &mut Context, state: &mut State, action: SearchAction) {
+    let scope = search_scope_range(state);
+    if state.search_in_selection && scope.is_none() {
+        // The selection is gone, was edited since, or belongs to another document.
+        // Say so, instead of quietly searching the whole document with the box still checked.
+        state.search_in_selection = false;
+        state.search_scope = None;
+        state.error_log_push(LogSeverity::Info, loc(LocId::NoticeSearchScopeStale).into());
+    }
     let Some(doc) = state.documents.active_mut() else {
         return;
     };
 
-    state.search_success = match action {
-        SearchAction::Search => {
-            doc.buffer.borrow_mut().find_and_select(&state.search_needle, state.search_options)
+    let searched = !matches!(action, SearchAction::ReplaceAll);
+    let result = match action {
+        SearchAction::Preview => {
+            let mut tb = doc.buffer.borrow_mut();
+            if let Some((cursor, scroll)) = state.search_origin {
+                tb.cursor_move_to_logical(cursor);
+                tb.scroll_to(scroll);
+            }
+            find_in_scope(&mut tb, &state.search_needle, state.search_options, scope)
         }
-        SearchAction::Replace => doc.buffer.borrow_mut().find_and_replace(
-            &state.search_needle,
-            state.search_options,
-            state.search_replacement.as_bytes(),
-        ),
-        SearchAction::ReplaceAll => doc.buffer.borrow_mut().find_and_replace_all(
+        SearchAction::Search => find_in_scope(
+            &mut doc.buffer.borrow_mut(),
             &state.search_needle,
             state.search_options,
-            state.search_replacement.as_bytes(),
+            scope,
         ),
-    }
-    .is_ok();
+        SearchAction::Replace => {
+            let mut tb = doc.buffer.borrow_mut();
+            match scope {
+                Some(range) => tb
+                    .find_and_replace_in_range(
+                        &state.search_needle,
+                        state.search_options,
+                        state.search_replacement.as_bytes(),
+                        range,
+                    )
+                    .map(|range| {
+                        state.search_scope =
+                            Some(SearchScope::new(&doc.buffer, range, tb.generation()));
+                    }),
+                None => tb.find_and_replace(
+                    &state.search_needle,
+                    state.search_options,
+                    state.search_replacement.as_bytes(),
+                ),
+            }
+        }
+        SearchAction::ReplaceAll => {
+            let mut tb = doc.buffer.borrow_mut();
+            match scope {
+                Some(range) => tb
+                    .find_and_replace_all_in_range(
+                        &state.search_needle,
+                        state.search_options,
+                        state.search_replacement.as_bytes(),
+                        range,
+                    )
+                    .map(|range| {
+                        state.search_scope =
+                            Some(SearchScope::new(&doc.buffer, range, tb.generation()));
+                    }),
+                None => tb.find_and_replace_all(
+                    &state.search_needle,
+                    state.search_options,
+                    state.search_replacement.as_bytes(),
+                ),
+            }
+        }
+        SearchAction::SelectAll => {
+            let mut tb = doc.buffer.borrow_mut();
+            let result = tb.find_and_select_all(&state.search_needle, state.search_options);
+            if result.is_ok() && tb.has_extra_cursors() {
+                // Hand the cursors over to the editor, so that typing edits all matches.
+                state.wants_search.kind = StateSearchKind::Hidden;
+            }
+            result
+        }
+    };
+
+    state.search_status = match result {
+        Err(err) => SearchStatus::Invalid(format!("{}", FormatApperr::from(err))),
+        // Searching leaves the match selected, so no selection means there was nothing to find.
+        Ok(())
+            if searched
+                && !state.search_needle.is_empty()
+                && !doc.buffer.borrow().has_selection() =>
+        {
+            SearchStatus::NoMatches
+        }
+        Ok(()) => SearchStatus::Found,
+    };
 
     ctx.needs_rerender();
 }
 
+/// Finds the next match, within `scope` if "In Selection" applies.
+fn find_in_scope(
+    tb: &mut TextBuffer,
+    needle: &str,
+    options: SearchOptions,
+    scope: Option<Range<usize>>,
+) -> apperr::Result<()> {
+    match scope {
+        Some(range) => tb.find_and_select_in_range(needle, options, range),
+        None => tb.find_and_select(needle, options),
+    }
+}
+
+/// Returns the range that searches and replacements should be confined to, if "In Selection"
+/// is checked and the selection it was captured from is still valid.
+fn search_scope_range(state: &State) -> Option<Range<usize>> {
+    if !state.search_in_selection {
+        return None;
+    }
+    let doc = state.documents.active()?;
+    state.search_scope.as_ref()?.range_in(&doc.buffer)
+}
+
 pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
     if let Some(doc) = state.documents.active_mut() {
         if doc.path.is_some() {
-            if let Err(err) = doc.save(None) {
+            if doc.buffer.borrow().is_read_only() {
+                let msg = loc(LocId::NoticeSaveReadOnly).replace("{filename}", &doc.filename);
+                error_log_add_message(ctx, state, msg);
+                state.wants_save = false;
+                return;
+            }
+            if doc.is_modified_on_disk() {
+                draw_dialog_save_conflict(ctx, state);
+                return;
+            }
+            if let Err(err) = doc.save_with_clean_up(None, &state.config) {
                 error_log_add(ctx, state, err);
             }
         } else {
@@ -236,12 +869,202 @@ pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
     state.wants_save = false;
 }
 
+fn draw_dialog_save_conflict(ctx: &mut Context, state: &mut State) {
+    enum Action {
+        None,
+        Overwrite,
+        Reload,
+        Cancel,
+    }
+    let mut action = Action::None;
+
+    ctx.modal_begin("save-conflict", loc(LocId::SaveConflictDialogTitle));
+    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        ctx.label("description", loc(LocId::SaveConflictDialogDescription));
+        ctx.attr_padding(Rect::three(1, 2, 1));
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            if ctx.button(
+                "overwrite",
+                loc(LocId::SaveConflictDialogOverwrite),
+                ButtonStyle::default().accelerator('O'),
+            ) {
+                action = Action::Overwrite;
+            }
+            ctx.inherit_focus();
+            if ctx.button(
+                "reload",
+                loc(LocId::ReloadDialogReload),
+                ButtonStyle::default().accelerator('R'),
+            ) {
+                action = Action::Reload;
+            }
+            if ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default()) {
+                action = Action::Cancel;
+            }
+
+            // Handle accelerator shortcuts
+            if contains_focus {
+                if ctx.consume_shortcut(vk::O) {
+                    action = Action::Overwrite;
+                } else if ctx.consume_shortcut(vk::R) {
+                    action = Action::Reload;
+                }
+            }
+        }
+        ctx.table_end();
+    }
+    if ctx.modal_end() {
+        action = Action::Cancel;
+    }
+
+    let result = match action {
+        Action::None => return,
+        Action::Overwrite => state
+            .documents
+            .active_mut()
+            .map_or(Ok(()), |doc| doc.save_with_clean_up(None, &state.config)),
+        Action::Reload => state.documents.active_mut().map_or(Ok(()), |doc| doc.reload()),
+        Action::Cancel => Ok(()),
+    };
+    if let Err(err) = result {
+        error_log_add(ctx, state, err);
+    }
+
+    // Unless the file got saved, a pending close or exit can't proceed without losing changes.
+    if !matches!(action, Action::Overwrite) {
+        state.wants_exit = false;
+        state.exit_session_saved = false;
+        state.wants_close = false;
+    }
+
+    state.wants_save = false;
+    ctx.needs_rerender();
+}
+
+pub fn draw_dialog_swap_recovery(ctx: &mut Context, state: &mut State) {
+    enum Action {
+        None,
+        Recover,
+        Discard,
+    }
+    let mut action = Action::None;
+
+    ctx.modal_begin("swap-recovery", loc(LocId::SwapRecoveryDialogTitle));
+    {
+        let contains_focus = ctx.contains_focus();
+
+        let files: Vec<&str> = state.swap_recoverable.iter().map(|r| r.filename.as_str()).collect();
+        let description =
+            loc(LocId::SwapRecoveryDialogDescription).replace("{files}", &files.join(", "));
+        ctx.label("description", &description);
+        ctx.attr_padding(Rect::three(1, 2, 1));
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(0, 2, 1));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            if ctx.button(
+                "recover",
+                loc(LocId::SwapRecoveryDialogRecover),
+                ButtonStyle::default().accelerator('R'),
+            ) {
+                action = Action::Recover;
+            }
+            ctx.inherit_focus();
+            if ctx.button(
+                "discard",
+                loc(LocId::SwapRecoveryDialogDiscard),
+                ButtonStyle::default().accelerator('D'),
+            ) {
+                action = Action::Discard;
+            }
+
+            // Handle accelerator shortcuts
+            if contains_focus {
+                if ctx.consume_shortcut(vk::R) {
+                    action = Action::Recover;
+                } else if ctx.consume_shortcut(vk::D) {
+                    action = Action::Discard;
+                }
+            }
+        }
+        ctx.table_end();
+    }
+    // Closing the dialog keeps the swap files around for the next start.
+    if ctx.modal_end() {
+        state.swap_recoverable.clear();
+        ctx.needs_rerender();
+        return;
+    }
+
+    match action {
+        Action::None => return,
+        Action::Recover => {
+            for recoverable in mem::take(&mut state.swap_recoverable) {
+                let doc = match &recoverable.path {
+                    Some(path) => state.documents.add_file_path(path),
+                    None => state.documents.add_untitled(),
+                };
+                let doc = match doc {
+                    Ok(doc) => doc,
+                    Err(err) => {
+                        error_log_add(ctx, state, err);
+                        continue;
+                    }
+                };
+
+                // Replacing the contents as a single edit leaves the document
+                // modified, and the recovered changes can be undone.
+                let mut tb = doc.buffer.borrow_mut();
+                tb.select_all();
+                tb.write_raw(recoverable.text.as_bytes());
+                tb.cursor_move_to_logical(recoverable.cursor);
+                tb.make_cursor_visible();
+                drop(tb);
+
+                // The document gets a swap file of its own with the next flush.
+                _ = std::fs::remove_file(&recoverable.swap_path);
+            }
+        }
+        Action::Discard => {
+            for recoverable in mem::take(&mut state.swap_recoverable) {
+                _ = std::fs::remove_file(&recoverable.swap_path);
+            }
+        }
+    }
+
+    ctx.needs_rerender();
+}
+
 pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
     let Some(doc) = state.documents.active() else {
         state.wants_close = false;
         return;
     };
 
+    // A save requested by this dialog is still in progress (e.g. it awaits confirmation).
+    if state.wants_save {
+        return;
+    }
+
     if !doc.buffer.borrow().is_dirty() {
         state.documents.remove_active();
         state.wants_close = false;
@@ -320,6 +1143,8 @@ pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
         }
         Action::Cancel => {
             state.wants_exit = false;
+            state.exit_session_saved = false;
+            state.wants_close_unpinned = false;
             state.wants_close = false;
         }
     }
@@ -327,14 +1152,100 @@ pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
     ctx.needs_rerender();
 }
 
+pub fn draw_handle_reload(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active_mut() else {
+        state.wants_reload = false;
+        return;
+    };
+    if doc.path.is_none() {
+        state.wants_reload = false;
+        return;
+    }
+
+    // Reloading is undoable, but we still ask before replacing unsaved changes.
+    let mut reload = !doc.buffer.borrow().is_dirty();
+    let mut done = false;
+
+    if !reload {
+        ctx.modal_begin("reload", loc(LocId::UnsavedChangesDialogTitle));
+        ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+        ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+        {
+            let contains_focus = ctx.contains_focus();
+
+            ctx.label("description", loc(LocId::ReloadDialogDescription));
+            ctx.attr_padding(Rect::three(1, 2, 1));
+
+            ctx.table_begin("choices");
+            ctx.inherit_focus();
+            ctx.attr_padding(Rect::three(0, 2, 1));
+            ctx.attr_position(Position::Center);
+            ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+            {
+                ctx.table_next_row();
+                ctx.inherit_focus();
+
+                if ctx.button(
+                    "reload",
+                    loc(LocId::ReloadDialogReload),
+                    ButtonStyle::default().accelerator('R'),
+                ) {
+                    reload = true;
+                }
+                ctx.inherit_focus();
+                if ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default()) {
+                    done = true;
+                }
+
+                // Handle accelerator shortcuts
+                if contains_focus && ctx.consume_shortcut(vk::R) {
+                    reload = true;
+                }
+            }
+            ctx.table_end();
+        }
+        done |= ctx.modal_end();
+    }
+
+    if reload {
+        let result = doc.reload();
+        if let Err(err) = result {
+            error_log_add(ctx, state, err);
+        }
+        done = true;
+    }
+
+    if done {
+        state.wants_reload = false;
+        ctx.needs_rerender();
+    }
+}
+
 pub fn draw_goto_menu(ctx: &mut Context, state: &mut State) {
     let mut done = false;
+    let mut committed = false;
+    let mut notice = None;
 
     if let Some(doc) = state.documents.active_mut() {
+        let origin = *state.goto_origin.get_or_insert_with(|| {
+            let tb = doc.buffer.borrow();
+            (tb.cursor_logical_pos(), tb.scroll_offset())
+        });
+
         ctx.modal_begin("goto", loc(LocId::FileGoto));
         {
             if ctx.editline("goto-line", &mut state.goto_target) {
                 state.goto_invalid = false;
+
+                // Preview the target while typing. Invalid input just doesn't move.
+                let mut tb = doc.buffer.borrow_mut();
+                if state.goto_target.trim().is_empty() {
+                    tb.cursor_move_to_logical(origin.0);
+                    tb.scroll_to(origin.1);
+                } else if let Some(target) = goto_parse_target(doc, &state.goto_target) {
+                    goto_move(&mut tb, target);
+                    tb.make_cursor_visible();
+                }
             }
             if state.goto_invalid {
                 ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
@@ -344,39 +1255,187 @@ pub fn draw_goto_menu(ctx: &mut Context, state: &mut State) {
             ctx.attr_intrinsic_size(Size { width: 24, height: 1 });
             ctx.steal_focus();
 
+            let line_count = doc.buffer.borrow().logical_line_count();
+            let hint = {
+                let template = loc(LocId::GotoHint);
+                let lines = arena_format!(ctx.arena(), "{line_count}");
+                let mut hint =
+                    ArenaString::with_capacity_in(template.len() + lines.len(), ctx.arena());
+                hint.push_str(template);
+                hint.replace_once_in_place("{lines}", &lines);
+                hint
+            };
+            ctx.label("hint", &hint);
+
             if ctx.consume_shortcut(vk::RETURN) {
-                match validate_goto_point(&state.goto_target) {
-                    Ok(point) => {
-                        let mut buf = doc.buffer.borrow_mut();
-                        buf.cursor_move_to_logical(point);
-                        buf.make_cursor_visible();
+                match goto_parse_target(doc, &state.goto_target) {
+                    Some(target) => {
+                        // The jump is from where the cursor was before the preview moved it.
+                        doc.buffer.borrow_mut().cursor_move_to_logical(origin.0);
+                        state.jumps.push(doc);
+                        let mut tb = doc.buffer.borrow_mut();
+                        notice = goto_move(&mut tb, target);
+                        tb.make_cursor_visible();
                         done = true;
+                        committed = true;
                     }
-                    Err(_) => state.goto_invalid = true,
+                    None => state.goto_invalid = true,
                 }
                 ctx.needs_rerender();
             }
         }
         done |= ctx.modal_end();
+
+        if done && !committed {
+            let mut tb = doc.buffer.borrow_mut();
+            tb.cursor_move_to_logical(origin.0);
+            tb.scroll_to(origin.1);
+        }
     } else {
         done = true;
     }
 
+    if let Some(msg) = notice {
+        error_log_add_message(ctx, state, msg);
+    }
     if done {
         state.wants_goto = false;
         state.goto_target.clear();
+        state.goto_origin = None;
         state.goto_invalid = false;
         ctx.needs_rerender();
     }
 }
 
-fn validate_goto_point(line: &str) -> Result<Point, ParseIntError> {
-    let mut coords = [0; 2];
-    let (y, x) = line.split_once(':').unwrap_or((line, "0"));
-    // Using a loop here avoids 2 copies of the str->int code.
-    // This makes the binary more compact.
-    for (i, s) in [x, y].iter().enumerate() {
-        coords[i] = s.parse::<CoordType>()?.saturating_sub(1);
+/// Parses the text of the goto dialog: a symbol name after an `@`, or see [`validate_goto_target`].
+fn goto_parse_target(doc: &Document, text: &str) -> Option<GotoTarget> {
+    match text.strip_prefix('@') {
+        Some(symbol) => goto_find_symbol(doc, symbol).map(GotoTarget::Offset),
+        None => validate_goto_target(text),
+    }
+}
+
+/// Moves the cursor to the `target`, clamped to the document.
+/// Returns a notice if it had to be clamped.
+fn goto_move(tb: &mut TextBuffer, target: GotoTarget) -> Option<String> {
+    match target {
+        GotoTarget::Point(point) => {
+            // Lines past the end of the document are clamped to the last one,
+            // and columns past the end of the line to the end of it.
+            let last = (tb.logical_line_count() - 1).max(0);
+            tb.cursor_move_to_logical(Point { x: point.x, y: point.y.min(last) });
+            let pos = tb.cursor_logical_pos();
+            if point.y > last {
+                Some(
+                    loc(LocId::NoticeGotoLineClamped)
+                        .replace("{line}", &(point.y + 1).to_string())
+                        .replace("{lines}", &(last + 1).to_string()),
+                )
+            } else if pos.x < point.x {
+                Some(
+                    loc(LocId::NoticeGotoColumnClamped)
+                        .replace("{column}", &(point.x + 1).to_string())
+                        .replace("{line}", &(point.y + 1).to_string())
+                        .replace("{end}", &(pos.x + 1).to_string()),
+                )
+            } else {
+                None
+            }
+        }
+        GotoTarget::Offset(offset) => {
+            let len = tb.text_length();
+            tb.cursor_move_to_offset(offset.min(len));
+            (offset > len).then(|| {
+                loc(LocId::NoticeGotoOffsetClamped)
+                    .replace("{offset}", &format!("{offset:#x}"))
+                    .replace("{length}", &format!("{len:#x}"))
+            })
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum GotoTarget {
+    Point(Point),
+    /// A byte offset into the document.
+    Offset(usize),
+}
+
+/// Parses a goto target of the form `line`, `line:column` or, for a byte offset,
+/// a hexadecimal number prefixed with `0x` or `x`.
+/// Lines and columns are 1-based and must be positive. The returned point is 0-based.
+fn validate_goto_target(text: &str) -> Option<GotoTarget> {
+    let text = text.trim();
+    if let Some(hex) = ["0x", "0X", "x", "X"].iter().find_map(|prefix| text.strip_prefix(prefix)) {
+        // `from_str_radix` would accept a sign, which doesn't make sense here.
+        if hex.starts_with(['+', '-']) {
+            return None;
+        }
+        return usize::from_str_radix(hex, 16).ok().map(GotoTarget::Offset);
+    }
+
+    let (line, column) = match text.split_once(':') {
+        Some((line, column)) => (line, Some(column)),
+        None => (text, None),
+    };
+    let parse = |s: &str| s.parse::<CoordType>().ok().filter(|&n| n > 0).map(|n| n - 1);
+
+    let y = parse(line)?;
+    let x = match column {
+        Some(column) => parse(column)?,
+        None => 0,
+    };
+    Some(GotoTarget::Point(Point { x, y }))
+}
+
+/// Finds the definition of `name` closest to the cursor, using the document's syntax tree.
+fn goto_find_symbol(doc: &Document, name: &str) -> Option<usize> {
+    let name = name.trim();
+    let tree = doc.syntax_tree.as_ref()?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let tb = doc.buffer.borrow();
+    let mut code = String::new();
+    tb.copy_into(&mut code);
+    let offset = tb.cursor_offset();
+    syntax::find_symbol(tree, code.as_bytes(), name, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_goto_target() {
+        assert_eq!(validate_goto_target("1"), Some(GotoTarget::Point(Point { x: 0, y: 0 })));
+        assert_eq!(validate_goto_target("12"), Some(GotoTarget::Point(Point { x: 0, y: 11 })));
+        assert_eq!(validate_goto_target(" 12 "), Some(GotoTarget::Point(Point { x: 0, y: 11 })));
+        assert_eq!(validate_goto_target("12:5"), Some(GotoTarget::Point(Point { x: 4, y: 11 })));
+        assert_eq!(validate_goto_target("12:1"), Some(GotoTarget::Point(Point { x: 0, y: 11 })));
+        assert_eq!(validate_goto_target("0x1f"), Some(GotoTarget::Offset(0x1f)));
+        assert_eq!(validate_goto_target("X1F"), Some(GotoTarget::Offset(0x1f)));
+        assert_eq!(validate_goto_target("0x0"), Some(GotoTarget::Offset(0)));
+    }
+
+    #[test]
+    fn test_validate_goto_target_invalid() {
+        assert_eq!(validate_goto_target(""), None);
+        assert_eq!(validate_goto_target(":"), None);
+        assert_eq!(validate_goto_target(":5"), None);
+        assert_eq!(validate_goto_target("12:"), None);
+        assert_eq!(validate_goto_target("0"), None);
+        assert_eq!(validate_goto_target("-3"), None);
+        assert_eq!(validate_goto_target("12:0"), None);
+        assert_eq!(validate_goto_target("12:-1"), None);
+        assert_eq!(validate_goto_target("10:abc"), None);
+        assert_eq!(validate_goto_target("abc"), None);
+        assert_eq!(validate_goto_target("1:2:3"), None);
+        assert_eq!(validate_goto_target("@main"), None);
+        assert_eq!(validate_goto_target("0x"), None);
+        assert_eq!(validate_goto_target("0xg"), None);
+        assert_eq!(validate_goto_target("0x-1"), None);
+        assert_eq!(validate_goto_target("0x+1"), None);
     }
-    Ok(Point { x: coords[0], y: coords[1] })
 }
@@ -13,9 +13,25 @@ use edit::tui::*;
 use edit::{icu, path};
 
 use crate::localization::*;
+use crate::remote::Remote;
 use crate::state::*;
 
 pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
+    // The open dialog starts out in the directory of the current document, so that relative
+    // paths are resolved against it. Otherwise, it stays in the directory it was last in,
+    // which initially is the one the editor was started in.
+    if state.wants_file_picker == StateFilePicker::Open {
+        state.wants_file_picker = StateFilePicker::OpenShown;
+
+        if let Some(dir) = state
+            .documents
+            .active()
+            .and_then(|doc| Some(doc.path.as_ref()?.parent()?.to_path_buf()))
+        {
+            file_picker_set_dir(state, dir);
+        }
+    }
+
     // The save dialog is pre-filled with the current document filename.
     if state.wants_file_picker == StateFilePicker::SaveAs {
         state.wants_file_picker = StateFilePicker::SaveAsShown;
@@ -33,7 +49,7 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
 
     ctx.modal_begin(
         "file-picker",
-        if state.wants_file_picker == StateFilePicker::Open {
+        if state.wants_file_picker == StateFilePicker::OpenShown {
             loc(LocId::FileOpen)
         } else {
             loc(LocId::FileSaveAs)
@@ -66,7 +82,12 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
 
             if ctx.contains_focus() {
                 if name_changed && ctx.is_focused() {
-                    update_autocomplete_suggestions(state);
+                    file_picker_name_changed(state);
+                }
+                // Tab completes the name as far as the suggestions agree.
+                if ctx.is_focused() && ctx.consume_shortcut(vk::TAB) {
+                    file_picker_complete_name(state);
+                    ctx.needs_rerender();
                 }
             } else if !state.file_picker_autocomplete.is_empty() {
                 state.file_picker_autocomplete.clear();
@@ -93,10 +114,12 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 {
                     for (idx, suggestion) in state.file_picker_autocomplete.iter().enumerate() {
                         let sel = ctx.list_item(false, suggestion.as_str());
-                        if sel != ListSelection::Unchanged {
+                        // Unlike in the listing, a single click accepts a suggestion.
+                        let clicked = ctx.click_count() == 1;
+                        if sel != ListSelection::Unchanged || clicked {
                             state.file_picker_pending_name = suggestion.as_path().into();
                         }
-                        if sel == ListSelection::Activated {
+                        if sel == ListSelection::Activated || clicked {
                             autocomplete_done = true;
                         }
 
@@ -176,11 +199,23 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
             activated = true;
         }
 
-        if activated {
+        if activated
+            && state.wants_file_picker == StateFilePicker::OpenShown
+            && let Some(remote) = state.file_picker_pending_name.to_str().and_then(Remote::parse)
+        {
+            state.jump_push();
+            match state.documents.add_remote(remote) {
+                Ok(_) => {
+                    ctx.needs_rerender();
+                    done = true;
+                }
+                Err(err) => error_log_add(ctx, state, err),
+            }
+        } else if activated {
             doit = draw_file_picker_update_path(state);
 
             // Check if the file already exists and show an overwrite warning in that case.
-            if state.wants_file_picker != StateFilePicker::Open
+            if state.wants_file_picker != StateFilePicker::OpenShown
                 && let Some(path) = doit.as_deref()
                 && path.exists()
             {
@@ -240,10 +275,11 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
     }
 
     if let Some(path) = doit {
-        let res = if state.wants_file_picker == StateFilePicker::Open {
+        let res = if state.wants_file_picker == StateFilePicker::OpenShown {
+            state.jump_push();
             state.documents.add_file_path(&path).map(|_| ())
         } else if let Some(doc) = state.documents.active_mut() {
-            doc.save(Some(path))
+            doc.save_with_clean_up(Some(path), &state.config)
         } else {
             Ok(())
         };
@@ -267,8 +303,9 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
 
 // Returns Some(path) if the path refers to a file.
 fn draw_file_picker_update_path(state: &mut State) -> Option<PathBuf> {
+    let name = state.expand_path(&state.file_picker_pending_name.clone());
     let old_path = state.file_picker_pending_dir.as_path();
-    let path = old_path.join(&state.file_picker_pending_name);
+    let path = old_path.join(name);
     let path = path::normalize(&path);
 
     let (dir, name) = if path.is_dir() {
@@ -291,16 +328,65 @@ fn draw_file_picker_update_path(state: &mut State) -> Option<PathBuf> {
         (dir, name)
     };
     if dir != state.file_picker_pending_dir.as_path() {
-        state.file_picker_pending_dir = DisplayablePathBuf::from_path(dir.to_path_buf());
-        state.file_picker_pending_dir_revision =
-            state.file_picker_pending_dir_revision.wrapping_add(1);
-        state.file_picker_entries = None;
+        file_picker_set_dir(state, dir.to_path_buf());
     }
 
     state.file_picker_pending_name = name;
     if state.file_picker_pending_name.as_os_str().is_empty() { None } else { Some(path) }
 }
 
+fn file_picker_set_dir(state: &mut State, dir: PathBuf) {
+    state.file_picker_pending_dir = DisplayablePathBuf::from_path(dir);
+    state.file_picker_pending_dir_revision = state.file_picker_pending_dir_revision.wrapping_add(1);
+    state.file_picker_entries = None;
+}
+
+/// If the user typed a path like `src/ma`, this moves into `src` and leaves `ma` as the name,
+/// so that the listing and the suggestions follow along while typing.
+fn file_picker_name_changed(state: &mut State) {
+    if let Some(name) = state.file_picker_pending_name.to_str()
+        && let Some(sep) = name.rfind(std::path::is_separator)
+    {
+        let (dir, rest) = name.split_at(sep + 1);
+        // Unknown variables are only pointed out once the user is done typing.
+        let dir = path::normalize(&state.file_picker_pending_dir.as_path().join(path::expand(dir)));
+        if dir.is_dir() {
+            state.file_picker_pending_name = rest.into();
+            file_picker_set_dir(state, dir);
+            draw_dialog_saveas_refresh_files(state);
+        }
+    }
+
+    update_autocomplete_suggestions(state);
+}
+
+/// Extends the name to the longest prefix that all suggestions have in common.
+fn file_picker_complete_name(state: &mut State) {
+    if state.file_picker_autocomplete.is_empty() {
+        update_autocomplete_suggestions(state);
+    }
+
+    let Some((first, rest)) = state.file_picker_autocomplete.split_first() else {
+        return;
+    };
+    let mut prefix = first.as_str();
+    for suggestion in rest {
+        let len = prefix
+            .chars()
+            .zip(suggestion.as_str().chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        prefix = &prefix[..len];
+    }
+
+    if prefix.len() > state.file_picker_pending_name.as_os_str().len() {
+        state.file_picker_pending_name = prefix.into();
+        // Completing a directory name ends in a "/", which moves into it.
+        file_picker_name_changed(state);
+    }
+}
+
 fn draw_dialog_saveas_refresh_files(state: &mut State) {
     let dir = state.file_picker_pending_dir.as_path();
     // ["..", directories, files]
@@ -1,11 +1,21 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 use edit::tui::*;
+use edit::{path, sys};
+use crate::localization::*;
 use crate::state::*;
 
+/// The files or directories that mark the root of a project, from most to least preferred.
+/// A repository wins over a package nested in it, like a crate in a Cargo workspace.
+const ROOT_MARKERS: [&[&str]; 2] = [
+    &[".git", ".hg", ".svn"],
+    &["Cargo.toml", "package.json", "pyproject.toml", "go.mod", "CMakeLists.txt"],
+];
+
 #[derive(Clone, Debug)]
 pub struct FileTreeNode {
     pub path: PathBuf,
@@ -14,6 +24,228 @@ pub struct FileTreeNode {
     pub expanded: bool,
 }
 
+impl State {
+    /// Shows the contents of the directory `root` in the file tree, instead of all other roots.
+    pub fn file_tree_set_root(&mut self, root: PathBuf) {
+        self.file_tree.roots = vec![root];
+        self.file_tree_rebuild();
+    }
+
+    /// Adds the project of the active document, or else its directory, as another root.
+    pub fn file_tree_add_root(&mut self) {
+        let Some(dir) = self.documents.active().and_then(|doc| doc.path.as_deref()).map(parent_of)
+        else {
+            return;
+        };
+
+        let root = find_project_root(&dir).unwrap_or(dir);
+        if !self.file_tree.roots.contains(&root) {
+            self.file_tree.roots.push(root);
+            self.file_tree_rebuild();
+        }
+        self.file_tree.visible = true;
+    }
+
+    /// Removes the root that contains the selected entry of the tree, or else the active document.
+    /// The last root can't be removed.
+    pub fn file_tree_remove_root(&mut self) {
+        if self.file_tree.roots.len() > 1
+            && let Some(index) = self.file_tree_current_root()
+        {
+            self.file_tree.roots.remove(index);
+            self.file_tree_rebuild();
+        }
+    }
+
+    /// Swaps the root that contains the selected entry of the tree, or else the active document,
+    /// with the one `delta` roots below it.
+    pub fn file_tree_move_root(&mut self, delta: isize) {
+        let roots = self.file_tree.roots.len();
+        if let Some(index) = self.file_tree_current_root()
+            && let Some(target) = index.checked_add_signed(delta).filter(|&i| i < roots)
+        {
+            self.file_tree.roots.swap(index, target);
+            self.file_tree_rebuild();
+        }
+    }
+
+    /// Returns the index of the root that contains the selected entry of the tree,
+    /// or else the active document.
+    fn file_tree_current_root(&self) -> Option<usize> {
+        let selected = self
+            .file_tree
+            .selected_node
+            .and_then(|i| flatten_tree(&self.file_tree.nodes).get(i).map(|(node, _)| *node))
+            .map(|node| node.path.clone());
+        let active = || self.documents.active()?.path.clone();
+        let path = selected.or_else(active)?;
+
+        // Roots may be nested. The innermost one is meant then.
+        let roots = &self.file_tree.roots;
+        (0..roots.len())
+            .filter(|&i| path.starts_with(&roots[i]))
+            .max_by_key(|&i| roots[i].components().count())
+    }
+
+    /// Reads the directories of the roots anew. Expanded directories stay expanded.
+    /// A single root shows its contents, while several roots get an entry each.
+    pub fn file_tree_rebuild(&mut self) {
+        let mut expanded = Vec::new();
+        collect_expanded(&self.file_tree.nodes, &mut expanded);
+        let nodes = &self.file_tree.nodes;
+        let collapsed: Vec<_> =
+            nodes.iter().filter(|node| !node.expanded).map(|node| &node.path).collect();
+
+        let nodes = match &self.file_tree.roots[..] {
+            [root] => build_file_tree(root),
+            roots => roots
+                .iter()
+                .map(|root| FileTreeNode {
+                    path: root.clone(),
+                    is_dir: true,
+                    children: build_file_tree(root),
+                    // New roots start out expanded.
+                    expanded: !collapsed.contains(&root),
+                })
+                .collect(),
+        };
+
+        self.file_tree.nodes = nodes;
+        self.file_tree.selected_node = None;
+        for path in &expanded {
+            expand_path(&mut self.file_tree.nodes, path);
+        }
+    }
+
+    /// Roots the file tree at the selected directory of the tree, or else at the directory
+    /// of the selected file or of the active document.
+    pub fn file_tree_set_root_here(&mut self) {
+        let selected = self
+            .file_tree
+            .selected_node
+            .and_then(|i| flatten_tree(&self.file_tree.nodes).get(i).map(|(node, _)| *node))
+            .map(|node| if node.is_dir { node.path.clone() } else { parent_of(&node.path) });
+        let active = || self.documents.active()?.path.as_deref().map(parent_of);
+
+        if let Some(root) = selected.or_else(active) {
+            self.file_tree_set_root(root);
+        }
+    }
+
+    /// Shows the active document in the system's file manager, selected if possible.
+    pub fn reveal_active_file(&mut self) {
+        let Some(path) = self.documents.active().and_then(|doc| doc.path.clone()) else {
+            self.error_log_push(LogSeverity::Info, loc(LocId::NoticeNoPath).into());
+            return;
+        };
+
+        let mut command = if cfg!(windows) {
+            let mut select = std::ffi::OsString::from("/select,");
+            select.push(&path);
+            let mut command = Command::new("explorer");
+            command.arg(select);
+            command
+        } else if cfg!(target_os = "macos") {
+            let mut command = Command::new("open");
+            command.arg("-R").arg(&path);
+            command
+        } else {
+            // xdg-open can only open the directory, not select the file in it.
+            let mut command = Command::new("xdg-open");
+            command.arg(parent_of(&path));
+            command
+        };
+        // Anything it prints would end up in the middle of the editor.
+        let spawned =
+            command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+        if let Err(err) = spawned {
+            self.error_log_push(LogSeverity::Error, err.to_string());
+        }
+    }
+
+    /// Returns `path` relative to the file tree root or else the project that it's in.
+    /// Returns it unchanged if it's in neither.
+    fn project_relative_path(&self, path: &Path) -> PathBuf {
+        self.file_tree
+            .roots
+            .iter()
+            .find_map(|root| path::relativize(path, root))
+            .or_else(|| {
+                let root = find_project_root(&parent_of(path))?;
+                path::relativize(path, &root)
+            })
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+/// Appends the paths of the expanded directories among `nodes` to `out`.
+pub fn collect_expanded(nodes: &[FileTreeNode], out: &mut Vec<PathBuf>) {
+    for node in nodes {
+        if node.expanded {
+            out.push(node.path.clone());
+        }
+        collect_expanded(&node.children, out);
+    }
+}
+
+/// Expands the directory at `path`, if it's among the `nodes`.
+pub fn expand_path(nodes: &mut [FileTreeNode], path: &Path) {
+    for node in nodes {
+        if node.path.as_path() == path {
+            node.expanded = true;
+            return;
+        }
+        if path.starts_with(&node.path) {
+            expand_path(&mut node.children, path);
+            return;
+        }
+    }
+}
+
+/// Copies the path of the active document to the clipboard, as asked for by
+/// [`State::wants_copy_path`].
+pub fn draw_handle_copy_path(ctx: &mut Context, state: &mut State) {
+    let Some(kind) = state.wants_copy_path.take() else {
+        return;
+    };
+    let Some(path) = state.documents.active().and_then(|doc| doc.path.clone()) else {
+        state.error_log_push(LogSeverity::Info, loc(LocId::NoticeNoPath).into());
+        return;
+    };
+
+    let remote = state.documents.active().and_then(|doc| doc.remote.clone());
+    let text = match (kind, remote) {
+        // The local copy of a file on another host is of no use elsewhere.
+        (CopyPath::Absolute, Some(remote)) => remote.url(),
+        (CopyPath::Relative, Some(remote)) => remote.path,
+        (CopyPath::Absolute, None) => path.to_string_lossy().into_owned(),
+        (CopyPath::Relative, None) => {
+            state.project_relative_path(&path).to_string_lossy().into_owned()
+        }
+        (CopyPath::FileName, _) => {
+            path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+        }
+    };
+    ctx.clipboard_mut().write(text.into_bytes());
+    ctx.needs_rerender();
+}
+
+fn parent_of(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(path).to_path_buf()
+}
+
+/// Returns the nearest ancestor of `dir`, or `dir` itself, that contains a project root marker.
+/// The home directory doesn't count, even if it's a repository of dotfiles.
+pub fn find_project_root(dir: &Path) -> Option<PathBuf> {
+    let home = sys::home_dir("");
+    ROOT_MARKERS.iter().find_map(|markers| {
+        dir.ancestors()
+            .take_while(|&ancestor| Some(ancestor) != home.as_deref())
+            .find(|ancestor| markers.iter().any(|marker| ancestor.join(marker).exists()))
+            .map(Path::to_path_buf)
+    })
+}
+
 pub fn draw_file_tree(ctx: &mut Context, state: &mut State) {
     if !state.file_tree.visible {
         return;
@@ -50,21 +282,28 @@ pub fn draw_file_tree(ctx: &mut Context, state: &mut State) {
             &label,
         );
 
+        // A single click expands or collapses a directory, while files open on a double-click.
+        // The double-click on a directory is ignored, since its first click already toggled it.
+        let clicks = ctx.click_count();
         match selection {
             ListSelection::Selected => {
                 state.file_tree.selected_node = Some(i);
             }
-            ListSelection::Activated => {
+            ListSelection::Activated if !(node.is_dir && clicks == 2) => {
                 activated_path = Some(node.path.clone());
             }
             _ => {}
         }
+        if node.is_dir && clicks == 1 {
+            activated_path = Some(node.path.clone());
+        }
     }
 
     if let Some(path) = activated_path {
         if path.is_dir() {
             toggle_expanded(&mut state.file_tree.nodes, &path);
         } else {
+            state.jump_push();
             state.documents.add_file_path(&path).ok();
         }
     }
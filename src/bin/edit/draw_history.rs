@@ -0,0 +1,147 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::time::Duration;
+
+use edit::buffer::HistoryStep;
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::documents::Checkpoint;
+use crate::localization::*;
+use crate::state::*;
+
+/// Lists the undo history of the active document, newest step first, along with its checkpoints.
+/// Activating a step undoes or redoes everything up to it.
+pub fn draw_undo_history(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        state.wants_undo_history = false;
+        return;
+    };
+
+    let (steps, applied) = doc.buffer.borrow().history();
+    let mut activated = None;
+
+    ctx.modal_begin("undo-history", loc(LocId::EditUndoHistory));
+    {
+        let width = (ctx.size().width - 20).max(10);
+        let height = (ctx.size().height - 10).max(10);
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.inherit_focus();
+        {
+            ctx.list_begin("steps");
+            ctx.inherit_focus();
+
+            // Each row is identified by the number of steps that are applied at that point.
+            for count in (0..=steps.len()).rev() {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                ctx.styled_label_add_text(if count == applied { "● " } else { "  " });
+
+                // Steps that were undone can still be redone. They're set apart in italics.
+                if count > applied {
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                }
+                let step = count.checked_sub(1).map(|i| &steps[i]);
+                match step {
+                    Some(step) => {
+                        ctx.styled_label_add_text(&step_description(step));
+                        ctx.styled_label_add_text("   ");
+                        ctx.styled_label_add_text(&format_age(step.time.elapsed()));
+                    }
+                    None => ctx.styled_label_add_text(loc(LocId::UndoHistoryOriginal)),
+                }
+
+                let id = step.map(|step| step.id);
+                for checkpoint in doc.checkpoints.iter().filter(|c| c.step == id) {
+                    ctx.styled_label_add_text("   ⚑ ");
+                    ctx.styled_label_add_text(&checkpoint.name);
+                }
+
+                if ctx.styled_list_item_end(false) == ListSelection::Activated {
+                    activated = Some(count);
+                }
+            }
+
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    if ctx.modal_end() {
+        state.wants_undo_history = false;
+    }
+
+    // The dialog stays open, so that the user can move back and forth through the history.
+    if let Some(count) = activated {
+        let mut tb = doc.buffer.borrow_mut();
+        tb.history_goto(count);
+        tb.make_cursor_visible();
+        ctx.needs_rerender();
+    }
+}
+
+/// Asks for the name of a new checkpoint at the current point of the undo history.
+pub fn draw_checkpoint_dialog(ctx: &mut Context, state: &mut State) {
+    let mut done = false;
+
+    if let Some(doc) = state.documents.active_mut() {
+        ctx.modal_begin("checkpoint", loc(LocId::EditSetCheckpoint));
+        {
+            ctx.editline("name", &mut state.checkpoint_name);
+            ctx.attr_intrinsic_size(Size { width: 32, height: 1 });
+            ctx.steal_focus();
+
+            if ctx.consume_shortcut(vk::RETURN) {
+                let (steps, applied) = doc.buffer.borrow().history();
+                let mut name = state.checkpoint_name.trim().to_string();
+                if name.is_empty() {
+                    name = loc(LocId::CheckpointDefaultName)
+                        .replace("{number}", &(doc.checkpoints.len() + 1).to_string());
+                }
+
+                doc.checkpoints
+                    .push(Checkpoint { name, step: applied.checked_sub(1).map(|i| steps[i].id) });
+                done = true;
+            }
+        }
+        done |= ctx.modal_end();
+    } else {
+        done = true;
+    }
+
+    if done {
+        state.wants_checkpoint = false;
+        state.checkpoint_name.clear();
+        ctx.needs_rerender();
+    }
+}
+
+/// Describes what a step did, e.g. `Typed "foo"`.
+fn step_description(step: &HistoryStep) -> String {
+    let mut description = if step.added.is_empty() {
+        loc(LocId::UndoHistoryDeleted).replace("{text}", &step.deleted)
+    } else if step.deleted.is_empty() {
+        loc(LocId::UndoHistoryTyped).replace("{text}", &step.added)
+    } else {
+        loc(LocId::UndoHistoryReplaced)
+            .replace("{old}", &step.deleted)
+            .replace("{new}", &step.added)
+    };
+
+    if step.edits > 1 {
+        description.push(' ');
+        description
+            .push_str(&loc(LocId::UndoHistoryEdits).replace("{count}", &step.edits.to_string()));
+    }
+    description
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let time = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    loc(LocId::UndoHistoryAge).replace("{time}", &time)
+}
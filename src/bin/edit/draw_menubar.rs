@@ -2,10 +2,14 @@
 // Licensed under the MIT License.
 
 use edit::arena_format;
+use edit::buffer::{TextBuffer, WhitespaceVisibility};
+use edit::framebuffer::{Attributes, IndexedColor};
 use edit::helpers::*;
 use edit::input::{kbmod, vk};
+use edit::syntax::{SmartFold, SupportedLanguage};
 use edit::tui::*;
 
+use crate::config::Action;
 use crate::localization::*;
 use crate::state::*;
 
@@ -38,24 +42,75 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
 }
 
 fn draw_menu_file(ctx: &mut Context, state: &mut State) {
-    if ctx.menubar_menu_button(loc(LocId::FileNew), 'N', kbmod::CTRL | vk::N) {
+    if ctx.menubar_menu_button(loc(LocId::FileNew), 'N', state.config.shortcut(Action::FileNew)) {
         draw_add_untitled_document(ctx, state);
     }
-    if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', kbmod::CTRL | vk::O) {
+    if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', state.config.shortcut(Action::FileOpen)) {
         state.wants_file_picker = StateFilePicker::Open;
     }
+    if ctx.menubar_menu_button(loc(LocId::FileOpenRecent), 'E', vk::NULL) {
+        state.wants_recent_files = true;
+    }
     if state.documents.active().is_some() {
-        if ctx.menubar_menu_button(loc(LocId::FileSave), 'S', kbmod::CTRL | vk::S) {
+        if ctx.menubar_menu_button(
+            loc(LocId::FileSave),
+            'S',
+            state.config.shortcut(Action::FileSave),
+        ) {
             state.wants_save = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileSaveAs), 'A', vk::NULL) {
+        if ctx.menubar_menu_button(
+            loc(LocId::FileSaveAs),
+            'A',
+            state.config.shortcut(Action::FileSaveAs),
+        ) {
             state.wants_file_picker = StateFilePicker::SaveAs;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileClose), 'C', kbmod::CTRL | vk::W) {
+        if state.documents.active().is_some_and(|doc| doc.path.is_some())
+            && ctx.menubar_menu_button(loc(LocId::FileReload), 'R', vk::NULL)
+        {
+            state.wants_reload = true;
+        }
+        if state.documents.active().is_some_and(|doc| doc.path.is_some()) {
+            if ctx.menubar_menu_button(loc(LocId::FileCopyPath), 'T', vk::NULL) {
+                state.wants_copy_path = Some(CopyPath::Absolute);
+            }
+            if ctx.menubar_menu_button(loc(LocId::FileCopyRelativePath), 'L', vk::NULL) {
+                state.wants_copy_path = Some(CopyPath::Relative);
+            }
+            if ctx.menubar_menu_button(loc(LocId::FileCopyFileName), 'M', vk::NULL) {
+                state.wants_copy_path = Some(CopyPath::FileName);
+            }
+            if ctx.menubar_menu_button(loc(LocId::FileReveal), 'V', vk::NULL) {
+                state.reveal_active_file();
+            }
+        }
+        if let Some(doc) = state.documents.active() {
+            let mut tb = doc.buffer.borrow_mut();
+            let read_only = tb.is_read_only();
+            if ctx.menubar_menu_checkbox(loc(LocId::FileReadOnly), 'D', vk::NULL, read_only) {
+                tb.set_read_only(!read_only);
+                ctx.needs_rerender();
+            }
+        }
+        if let Some(doc) = state.documents.active_mut()
+            && ctx.menubar_menu_checkbox(loc(LocId::FilePinTab), 'P', vk::NULL, doc.pinned)
+        {
+            doc.pinned = !doc.pinned;
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_button(
+            loc(LocId::FileClose),
+            'C',
+            state.config.shortcut(Action::FileClose),
+        ) {
             state.wants_close = true;
         }
+        if ctx.menubar_menu_button(loc(LocId::FileCloseUnpinned), 'U', vk::NULL) {
+            state.wants_close_unpinned = true;
+        }
     }
-    if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', kbmod::CTRL | vk::Q) {
+    if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', state.config.shortcut(Action::FileExit)) {
         state.wants_exit = true;
     }
     ctx.menubar_menu_end();
@@ -64,6 +119,7 @@ fn draw_menu_file(ctx: &mut Context, state: &mut State) {
 fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
     let doc = state.documents.active().unwrap();
     let mut tb = doc.buffer.borrow_mut();
+    let mut notice = None;
 
     if ctx.menubar_menu_button(loc(LocId::EditUndo), 'U', kbmod::CTRL | vk::Z) {
         tb.undo();
@@ -73,6 +129,14 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         tb.redo();
         ctx.needs_rerender();
     }
+    if ctx.menubar_menu_button(loc(LocId::EditUndoHistory), 'H', vk::NULL) {
+        state.wants_undo_history = true;
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditSetCheckpoint), 'K', vk::NULL) {
+        state.wants_checkpoint = true;
+        ctx.needs_rerender();
+    }
     if ctx.menubar_menu_button(loc(LocId::EditCut), 'T', kbmod::CTRL | vk::X) {
         tb.cut(ctx.clipboard_mut());
         ctx.needs_rerender();
@@ -82,15 +146,21 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         ctx.needs_rerender();
     }
     if ctx.menubar_menu_button(loc(LocId::EditPaste), 'P', kbmod::CTRL | vk::V) {
-        tb.paste(ctx.clipboard_ref());
+        let clipboard = ctx.clipboard_mut();
+        clipboard.sync_from_system();
+        tb.paste(clipboard);
         ctx.needs_rerender();
     }
     if state.wants_search.kind != StateSearchKind::Disabled {
-        if ctx.menubar_menu_button(loc(LocId::EditFind), 'F', kbmod::CTRL | vk::F) {
+        if ctx.menubar_menu_button(loc(LocId::EditFind), 'F', state.config.shortcut(Action::Find)) {
             state.wants_search.kind = StateSearchKind::Search;
             state.wants_search.focus = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::EditReplace), 'L', kbmod::CTRL | vk::R) {
+        if ctx.menubar_menu_button(
+            loc(LocId::EditReplace),
+            'L',
+            state.config.shortcut(Action::Replace),
+        ) {
             state.wants_search.kind = StateSearchKind::Replace;
             state.wants_search.focus = true;
         }
@@ -99,34 +169,274 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         tb.select_all();
         ctx.needs_rerender();
     }
+    // These need the state, which is borrowed for the buffer here. They run after the menu.
+    let expand = ctx.menubar_menu_button(
+        loc(LocId::EditExpandSelection),
+        'E',
+        state.config.shortcut(Action::ExpandSelection),
+    );
+    let shrink = ctx.menubar_menu_button(
+        loc(LocId::EditShrinkSelection),
+        'S',
+        state.config.shortcut(Action::ShrinkSelection),
+    );
+    if ctx.menubar_menu_button(
+        loc(LocId::EditGotoMatchingBracket),
+        'B',
+        state.config.shortcut(Action::GotoMatchingBracket),
+    ) {
+        tb.cursor_move_to_matching_bracket();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(
+        loc(LocId::EditDuplicateLines),
+        'D',
+        state.config.shortcut(Action::DuplicateLines),
+    ) {
+        tb.duplicate_selected_lines();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(
+        loc(LocId::EditToggleLineComment),
+        'M',
+        state.config.shortcut(Action::ToggleLineComment),
+    ) {
+        match doc.language {
+            Some(language) => tb.line_comment_toggle(language.line_comment()),
+            None => notice = Some(LocId::NoticeNoLineComment),
+        }
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(
+        loc(LocId::EditToggleBlockComment),
+        'O',
+        state.config.shortcut(Action::ToggleBlockComment),
+    ) {
+        notice = block_comment_toggle(doc.language, &mut tb).err();
+        ctx.needs_rerender();
+    }
     ctx.menubar_menu_end();
+
+    drop(tb);
+    if expand {
+        state.selection_expand();
+        ctx.needs_rerender();
+    }
+    if shrink {
+        state.selection_shrink();
+        ctx.needs_rerender();
+    }
+    if let Some(notice) = notice {
+        error_log_add_message(ctx, state, loc(notice).to_string());
+    }
+}
+
+/// Wraps the selection in a block comment, or falls back to line comments for languages
+/// without block comments. Returns the notice to show if neither is possible.
+pub fn block_comment_toggle(
+    language: Option<SupportedLanguage>,
+    tb: &mut TextBuffer,
+) -> Result<(), LocId> {
+    let Some(language) = language else {
+        return Err(LocId::NoticeNoLineComment);
+    };
+    match language.block_comment() {
+        Some((open, close)) => {
+            if !tb.block_comment_toggle(open, close, language.block_comments_nest()) {
+                return Err(LocId::NoticeNestedBlockComment);
+            }
+        }
+        None => tb.line_comment_toggle(language.line_comment()),
+    }
+    Ok(())
 }
 
 fn draw_menu_view(ctx: &mut Context, state: &mut State) {
     if let Some(doc) = state.documents.active() {
         let mut tb = doc.buffer.borrow_mut();
         let word_wrap = tb.is_word_wrap_enabled();
+        let whitespace = tb.whitespace_visibility() == WhitespaceVisibility::All;
 
         // All values on the statusbar are currently document specific.
         if ctx.menubar_menu_button(loc(LocId::ViewFocusStatusbar), 'S', vk::NULL) {
             state.wants_statusbar_focus = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::ViewGoToFile), 'F', kbmod::CTRL | vk::P) {
+        if ctx.menubar_menu_button(
+            loc(LocId::ViewCommandPalette),
+            'P',
+            state.config.shortcut(Action::CommandPalette),
+        ) {
+            state.wants_command_palette = true;
+        }
+        if ctx.menubar_menu_button(
+            loc(LocId::ViewGoToFile),
+            'F',
+            state.config.shortcut(Action::GoToFile),
+        ) {
             state.wants_go_to_file = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileGoto), 'G', kbmod::CTRL | vk::G) {
+        if ctx.menubar_menu_button(
+            loc(LocId::FileGoto),
+            'G',
+            state.config.shortcut(Action::FileGoto),
+        ) {
             state.wants_goto = true;
         }
-        if ctx.menubar_menu_checkbox(loc(LocId::ViewWordWrap), 'W', kbmod::ALT | vk::Z, word_wrap) {
+        if ctx.menubar_menu_checkbox(
+            loc(LocId::ViewWordWrap),
+            'W',
+            state.config.shortcut(Action::ToggleWordWrap),
+            word_wrap,
+        ) {
             tb.set_word_wrap(!word_wrap);
             ctx.needs_rerender();
         }
+        if ctx.menubar_menu_checkbox(loc(LocId::ViewWhitespace), 'H', vk::NULL, whitespace) {
+            let visibility = state.documents.whitespace_toggled(tb.whitespace_visibility());
+            tb.set_whitespace_visibility(visibility);
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_checkbox(loc(LocId::ViewChanges), 'K', vk::NULL, state.show_changes) {
+            state.show_changes = !state.show_changes;
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_checkbox(loc(LocId::ViewBlame), 'B', vk::NULL, state.show_blame) {
+            state.show_blame = !state.show_blame;
+            ctx.needs_rerender();
+        }
+        if doc.language.is_some() {
+            if ctx.menubar_menu_button(
+                loc(LocId::ViewFoldToggle),
+                'L',
+                state.config.shortcut(Action::FoldToggle),
+            ) {
+                tb.fold_toggle();
+                ctx.needs_rerender();
+            }
+            if ctx.menubar_menu_button(
+                loc(LocId::ViewFoldAll),
+                'A',
+                state.config.shortcut(Action::FoldAll),
+            ) {
+                tb.fold_all();
+                ctx.needs_rerender();
+            }
+            if ctx.menubar_menu_button(loc(LocId::ViewFoldImports), 'J', vk::NULL) {
+                doc.smart_fold(&mut tb, SmartFold::Imports);
+                ctx.needs_rerender();
+            }
+            if ctx.menubar_menu_button(loc(LocId::ViewFoldComments), 'Q', vk::NULL) {
+                doc.smart_fold(&mut tb, SmartFold::Comments);
+                ctx.needs_rerender();
+            }
+            if ctx.menubar_menu_button(
+                loc(LocId::ViewUnfoldAll),
+                'U',
+                state.config.shortcut(Action::UnfoldAll),
+            ) {
+                tb.unfold_all();
+                ctx.needs_rerender();
+            }
+        }
+        if ctx.menubar_menu_checkbox(
+            loc(LocId::ViewOutline),
+            'O',
+            state.config.shortcut(Action::ToggleOutline),
+            state.outline.visible,
+        ) {
+            state.outline.visible = !state.outline.visible;
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_checkbox(
+            loc(LocId::ViewFileTree),
+            'T',
+            state.config.shortcut(Action::ToggleFileTree),
+            state.file_tree.visible,
+        ) {
+            state.file_tree.visible = !state.file_tree.visible;
+            ctx.needs_rerender();
+        }
+    }
+    // The build command runs without any document open, too.
+    if ctx.menubar_menu_checkbox(loc(LocId::ViewOutputPanel), 'Y', vk::NULL, state.build.visible) {
+        state.build.visible = !state.build.visible;
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(
+        loc(LocId::ViewRunBuild),
+        'D',
+        state.config.shortcut(Action::RunBuild),
+    ) {
+        state.build_run();
+        ctx.needs_rerender();
+    }
+    if state.build.job.is_some()
+        && ctx.menubar_menu_button(
+            loc(LocId::ViewCancelBuild),
+            'I',
+            state.config.shortcut(Action::CancelBuild),
+        )
+    {
+        state.build_cancel();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::ViewLog), 'M', vk::NULL) {
+        state.wants_log = true;
+        ctx.needs_rerender();
+    }
+    // These live outside the block above, because they need to borrow the state mutably.
+    if ctx.menubar_menu_button(loc(LocId::ViewGoBack), 'O', state.config.shortcut(Action::GoBack)) {
+        state.jump_back();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(
+        loc(LocId::ViewGoForward),
+        'R',
+        state.config.shortcut(Action::GoForward),
+    ) {
+        state.jump_forward();
+        ctx.needs_rerender();
+    }
+    if state.documents.active().is_some()
+        && ctx.menubar_menu_button(
+            loc(LocId::ViewSplitPane),
+            'E',
+            state.config.shortcut(Action::SplitPane),
+        )
+    {
+        state.pane_split();
+        ctx.needs_rerender();
+    }
+    if state.panes.list.len() > 1 {
+        if ctx.menubar_menu_button(
+            loc(LocId::ViewFocusNextPane),
+            'N',
+            state.config.shortcut(Action::FocusNextPane),
+        ) {
+            state.pane_focus_next();
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_button(
+            loc(LocId::ViewClosePane),
+            'C',
+            state.config.shortcut(Action::ClosePane),
+        ) {
+            state.pane_close();
+            ctx.needs_rerender();
+        }
     }
 
     ctx.menubar_menu_end();
 }
 
 fn draw_menu_help(ctx: &mut Context, state: &mut State) {
+    if ctx.menubar_menu_button(
+        loc(LocId::HelpKeyboardShortcuts),
+        'K',
+        state.config.shortcut(Action::KeyboardShortcuts),
+    ) {
+        state.wants_keyboard_shortcuts = true;
+    }
     if ctx.menubar_menu_button(loc(LocId::HelpAbout), 'A', vk::NULL) {
         state.wants_about = true;
     }
@@ -178,3 +488,51 @@ pub fn draw_dialog_about(ctx: &mut Context, state: &mut State) {
         state.wants_about = false;
     }
 }
+
+/// Lists the shortcuts of all [`Action`]s as they're configured, grouped by menu.
+/// Any key closes it.
+pub fn draw_dialog_keyboard_shortcuts(ctx: &mut Context, state: &mut State) {
+    let width = (ctx.size().width - 20).clamp(10, 60);
+    let height = (ctx.size().height - 10).max(10);
+    // The key that opened the overlay was consumed in the frame before it showed up.
+    let mut done = ctx.keyboard_input().is_some();
+    if done {
+        ctx.set_input_consumed();
+    }
+
+    ctx.modal_begin("keyboard-shortcuts", loc(LocId::HelpKeyboardShortcuts));
+    {
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        {
+            ctx.table_begin("shortcuts");
+            ctx.table_set_cell_gap(Size { width: 3, height: 0 });
+            ctx.attr_padding(Rect::two(0, 1));
+            {
+                for category in [LocId::File, LocId::Edit, LocId::View, LocId::Help] {
+                    ctx.table_next_row();
+                    ctx.label("category", loc(category));
+                    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightBlue));
+
+                    for action in Action::ALL.into_iter().filter(|a| a.category() == category) {
+                        ctx.table_next_row();
+                        ctx.label("name", loc(action.label()));
+
+                        let shortcut = ctx.shortcut_text(state.config.shortcut(action));
+                        ctx.styled_label_begin("shortcut");
+                        ctx.styled_label_set_attributes(Attributes::Italic);
+                        ctx.styled_label_add_text(&shortcut);
+                        ctx.styled_label_end();
+                    }
+                }
+            }
+            ctx.table_end();
+        }
+        ctx.scrollarea_end();
+    }
+    done |= ctx.modal_end();
+
+    if done {
+        state.wants_keyboard_shortcuts = false;
+        ctx.needs_rerender();
+    }
+}
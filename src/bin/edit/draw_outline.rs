@@ -0,0 +1,165 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::syntax::Symbol;
+use edit::tui::*;
+
+use crate::localization::*;
+use crate::state::*;
+
+pub fn draw_outline(ctx: &mut Context, state: &mut State) {
+    ctx.block_begin("outline");
+    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Black));
+    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::White));
+
+    let Some(doc) = state.documents.active_mut() else {
+        ctx.block_end();
+        return;
+    };
+
+    if doc.symbols.is_empty() {
+        ctx.label("empty", loc(LocId::OutlineEmpty));
+        ctx.block_end();
+        return;
+    }
+
+    let flattened = flatten_symbols(&doc.symbols);
+    let mut activated = None;
+
+    ctx.list_begin("symbol_list");
+
+    for (i, &(symbol, depth)) in flattened.iter().enumerate() {
+        let mut label = "  ".repeat(depth);
+        label.push_str(match (symbol.children.is_empty(), symbol.expanded) {
+            (true, _) => "  ",
+            (false, true) => "- ",
+            (false, false) => "+ ",
+        });
+        label.push_str(&symbol.name);
+
+        ctx.next_block_id_mixin(i as u64);
+        match ctx.list_item(state.outline.selected == Some(i), &label) {
+            ListSelection::Selected => state.outline.selected = Some(i),
+            ListSelection::Activated => activated = Some((i, symbol.offset)),
+            _ => {}
+        }
+    }
+
+    ctx.list_end();
+    ctx.block_end();
+
+    // Activating a symbol jumps to it and, if it has any, toggles its children.
+    if let Some((index, offset)) = activated {
+        let mut index = index;
+        toggle_expanded(&mut doc.symbols, &mut index);
+
+        state.jumps.push(doc);
+        let mut tb = doc.buffer.borrow_mut();
+        tb.cursor_move_to_offset(offset);
+        tb.make_cursor_visible();
+        ctx.needs_rerender();
+    }
+}
+
+/// Shows the definitions that enclose the cursor, outermost first, after the filename.
+/// Clicking one of them jumps to it.
+pub fn draw_breadcrumbs(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+
+    let offset = doc.buffer.borrow().cursor_offset();
+    let path = symbol_path(&doc.symbols, offset);
+    let mut activated = None;
+
+    ctx.table_begin("breadcrumbs");
+    ctx.attr_focus_well();
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+    ctx.attr_padding(Rect::two(0, 1));
+    ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+    {
+        ctx.table_next_row();
+        ctx.label("filename", &doc.filename);
+
+        for (i, symbol) in path.iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            ctx.label("separator", "›");
+            ctx.next_block_id_mixin(i as u64);
+            if ctx.button("symbol", &symbol.name, ButtonStyle::default().bracketed(false)) {
+                activated = Some(symbol.offset);
+            }
+        }
+    }
+    ctx.table_end();
+
+    if let Some(offset) = activated {
+        state.jumps.push(doc);
+        let mut tb = doc.buffer.borrow_mut();
+        tb.cursor_move_to_offset(offset);
+        tb.make_cursor_visible();
+        // Give the focus back to the editor.
+        state.panes.focus_changed = true;
+        ctx.needs_rerender();
+    }
+}
+
+/// Returns the definitions that contain `offset`, outermost first.
+fn symbol_path(symbols: &[Symbol], offset: usize) -> Vec<&Symbol> {
+    let mut path = Vec::new();
+    let mut level = symbols;
+    while let Some(symbol) = level.iter().find(|symbol| symbol.range.contains(&offset)) {
+        path.push(symbol);
+        level = &symbol.children;
+    }
+    path
+}
+
+/// Copies the expansion state of `old` onto the matching symbols in `new`.
+/// Used to keep the outline stable while the document is being edited.
+pub fn carry_over_expansion(old: &[Symbol], new: &mut [Symbol]) {
+    for symbol in new {
+        if let Some(prev) = old.iter().find(|s| s.name == symbol.name && s.kind == symbol.kind) {
+            symbol.expanded = prev.expanded;
+            carry_over_expansion(&prev.children, &mut symbol.children);
+        }
+    }
+}
+
+fn flatten_symbols(symbols: &[Symbol]) -> Vec<(&Symbol, usize)> {
+    let mut flattened = vec![];
+    for symbol in symbols {
+        flatten_recursive(symbol, 0, &mut flattened);
+    }
+    flattened
+}
+
+fn flatten_recursive<'a>(
+    symbol: &'a Symbol,
+    depth: usize,
+    flattened: &mut Vec<(&'a Symbol, usize)>,
+) {
+    flattened.push((symbol, depth));
+    if symbol.expanded {
+        for child in &symbol.children {
+            flatten_recursive(child, depth + 1, flattened);
+        }
+    }
+}
+
+/// Toggles the symbol at the given position of the flattened list.
+/// `index` is counted down while walking the visible symbols.
+fn toggle_expanded(symbols: &mut [Symbol], index: &mut usize) -> bool {
+    for symbol in symbols {
+        if *index == 0 {
+            symbol.expanded = !symbol.expanded;
+            return true;
+        }
+        *index -= 1;
+        if symbol.expanded && toggle_expanded(&mut symbol.children, index) {
+            return true;
+        }
+    }
+    false
+}
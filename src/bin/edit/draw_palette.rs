@@ -0,0 +1,283 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::cmp::Reverse;
+
+use edit::arena::scratch_arena;
+use edit::buffer::{MoveLineDirection, TextBuffer};
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::fuzzy::score_fuzzy;
+use edit::helpers::*;
+use edit::input::{InputKey, vk};
+use edit::syntax::SmartFold;
+use edit::tui::*;
+
+use crate::config::{Action, Config};
+use crate::draw_editor::goto_change;
+use crate::draw_menubar::block_comment_toggle;
+use crate::localization::*;
+use crate::state::*;
+
+/// A command in the palette: its name, its shortcut (or `vk::NULL`) and what it does.
+type Command = (LocId, InputKey, fn(&mut State));
+
+fn command(name: LocId, shortcut: InputKey, run: fn(&mut State)) -> Command {
+    (name, shortcut, run)
+}
+
+fn commands(config: &Config) -> Vec<Command> {
+    let key = |action| config.shortcut(action);
+    vec![
+        command(LocId::FileOpen, key(Action::FileOpen), |s| {
+            s.wants_file_picker = StateFilePicker::Open
+        }),
+        command(LocId::FileSave, key(Action::FileSave), |s| s.wants_save = true),
+        command(LocId::FileSaveAs, key(Action::FileSaveAs), |s| {
+            s.wants_file_picker = StateFilePicker::SaveAs
+        }),
+        command(LocId::FileOpenRecent, vk::NULL, |s| s.wants_recent_files = true),
+        command(LocId::FileReload, vk::NULL, |s| s.wants_reload = true),
+        command(LocId::FileCopyPath, vk::NULL, |s| s.wants_copy_path = Some(CopyPath::Absolute)),
+        command(LocId::FileCopyRelativePath, vk::NULL, |s| {
+            s.wants_copy_path = Some(CopyPath::Relative)
+        }),
+        command(LocId::FileCopyFileName, vk::NULL, |s| {
+            s.wants_copy_path = Some(CopyPath::FileName)
+        }),
+        command(LocId::FileReveal, vk::NULL, State::reveal_active_file),
+        command(LocId::FileReadOnly, vk::NULL, |s| {
+            with_active_buffer(s, |tb| tb.set_read_only(!tb.is_read_only()))
+        }),
+        command(LocId::FilePinTab, vk::NULL, |s| {
+            if let Some(doc) = s.documents.active_mut() {
+                doc.pinned = !doc.pinned;
+            }
+        }),
+        command(LocId::FileMoveTabLeft, key(Action::MoveTabLeft), |s| {
+            s.documents.move_tab_relative(-1)
+        }),
+        command(LocId::FileMoveTabRight, key(Action::MoveTabRight), |s| {
+            s.documents.move_tab_relative(1)
+        }),
+        command(LocId::FileClose, key(Action::FileClose), |s| s.wants_close = true),
+        command(LocId::FileCloseUnpinned, vk::NULL, |s| s.wants_close_unpinned = true),
+        command(LocId::FileExit, key(Action::FileExit), |s| s.wants_exit = true),
+        command(LocId::EditUndoHistory, vk::NULL, |s| s.wants_undo_history = true),
+        command(LocId::EditSetCheckpoint, vk::NULL, |s| s.wants_checkpoint = true),
+        command(LocId::EditFind, key(Action::Find), |s| search_show(s, StateSearchKind::Search)),
+        command(LocId::EditReplace, key(Action::Replace), |s| {
+            search_show(s, StateSearchKind::Replace)
+        }),
+        command(LocId::FileGoto, key(Action::FileGoto), |s| s.wants_goto = true),
+        command(LocId::EditGotoMatchingBracket, key(Action::GotoMatchingBracket), |s| {
+            with_active_buffer(s, |tb| _ = tb.cursor_move_to_matching_bracket())
+        }),
+        command(LocId::EditDuplicateLines, key(Action::DuplicateLines), |s| {
+            with_active_buffer(s, TextBuffer::duplicate_selected_lines)
+        }),
+        command(LocId::EditMoveLinesUp, key(Action::MoveLinesUp), |s| {
+            with_active_buffer(s, |tb| tb.move_selected_lines(MoveLineDirection::Up))
+        }),
+        command(LocId::EditMoveLinesDown, key(Action::MoveLinesDown), |s| {
+            with_active_buffer(s, |tb| tb.move_selected_lines(MoveLineDirection::Down))
+        }),
+        command(LocId::EditExpandSelection, key(Action::ExpandSelection), State::selection_expand),
+        command(LocId::EditShrinkSelection, key(Action::ShrinkSelection), State::selection_shrink),
+        command(LocId::EditToggleLineComment, key(Action::ToggleLineComment), line_comment_toggle),
+        command(LocId::EditToggleBlockComment, key(Action::ToggleBlockComment), |s| {
+            let Some(doc) = s.documents.active() else {
+                return;
+            };
+            let res = block_comment_toggle(doc.language, &mut doc.buffer.borrow_mut());
+            if let Err(notice) = res {
+                s.error_log_push(LogSeverity::Info, loc(notice).into());
+            }
+        }),
+        command(LocId::ViewGoToFile, key(Action::GoToFile), |s| s.wants_go_to_file = true),
+        command(LocId::ViewGoBack, key(Action::GoBack), State::jump_back),
+        command(LocId::ViewGoForward, key(Action::GoForward), State::jump_forward),
+        command(LocId::ViewNextChange, key(Action::NextChange), |s| goto_change(s, true)),
+        command(LocId::ViewPreviousChange, key(Action::PreviousChange), |s| goto_change(s, false)),
+        command(LocId::ViewFileTree, key(Action::ToggleFileTree), |s| {
+            s.file_tree.visible = !s.file_tree.visible
+        }),
+        command(LocId::FileTreeSetRoot, vk::NULL, State::file_tree_set_root_here),
+        command(LocId::FileTreeAddRoot, vk::NULL, State::file_tree_add_root),
+        command(LocId::FileTreeRemoveRoot, vk::NULL, State::file_tree_remove_root),
+        command(LocId::FileTreeMoveRootUp, vk::NULL, |s| s.file_tree_move_root(-1)),
+        command(LocId::FileTreeMoveRootDown, vk::NULL, |s| s.file_tree_move_root(1)),
+        command(LocId::ViewOutline, key(Action::ToggleOutline), |s| {
+            s.outline.visible = !s.outline.visible
+        }),
+        command(LocId::ViewLog, vk::NULL, |s| s.wants_log = true),
+        command(LocId::ViewOutputPanel, vk::NULL, |s| s.build.visible = !s.build.visible),
+        command(LocId::ViewRunBuild, key(Action::RunBuild), State::build_run),
+        command(LocId::ViewCancelBuild, key(Action::CancelBuild), State::build_cancel),
+        command(LocId::ViewSplitPane, key(Action::SplitPane), State::pane_split),
+        command(LocId::ViewFocusNextPane, key(Action::FocusNextPane), State::pane_focus_next),
+        command(LocId::ViewClosePane, key(Action::ClosePane), State::pane_close),
+        command(LocId::ViewWordWrap, key(Action::ToggleWordWrap), |s| {
+            with_active_buffer(s, |tb| tb.set_word_wrap(!tb.is_word_wrap_enabled()))
+        }),
+        command(LocId::ViewWhitespace, vk::NULL, whitespace_toggle),
+        command(LocId::ViewChanges, vk::NULL, |s| s.show_changes = !s.show_changes),
+        command(LocId::ViewBlame, vk::NULL, |s| s.show_blame = !s.show_blame),
+        command(LocId::ViewFoldToggle, key(Action::FoldToggle), |s| {
+            with_active_buffer(s, |tb| _ = tb.fold_toggle())
+        }),
+        command(LocId::ViewFoldAll, key(Action::FoldAll), |s| {
+            with_active_buffer(s, TextBuffer::fold_all)
+        }),
+        command(LocId::ViewFoldImports, vk::NULL, |s| smart_fold(s, SmartFold::Imports)),
+        command(LocId::ViewFoldComments, vk::NULL, |s| smart_fold(s, SmartFold::Comments)),
+        command(LocId::ViewUnfoldAll, key(Action::UnfoldAll), |s| {
+            with_active_buffer(s, TextBuffer::unfold_all)
+        }),
+        command(LocId::ViewFocusStatusbar, vk::NULL, |s| s.wants_statusbar_focus = true),
+        command(LocId::IndentationConvertToTabs, vk::NULL, |s| convert_indentation(s, true)),
+        command(LocId::IndentationConvertToSpaces, vk::NULL, |s| convert_indentation(s, false)),
+        command(LocId::HelpKeyboardShortcuts, key(Action::KeyboardShortcuts), |s| {
+            s.wants_keyboard_shortcuts = true
+        }),
+        command(LocId::HelpAbout, vk::NULL, |s| s.wants_about = true),
+    ]
+}
+
+/// Runs the palette command with the given name. This way, the actions that aren't
+/// handled anywhere else get their shortcuts without repeating what they do.
+pub fn command_run(state: &mut State, name: LocId) {
+    let run = commands(&state.config).into_iter().find(|&(n, _, _)| n == name);
+    if let Some((_, _, run)) = run {
+        run(state);
+    }
+}
+
+/// Runs `f` on the active buffer and scrolls to the cursor, in case `f` moved it.
+fn with_active_buffer(state: &mut State, f: impl FnOnce(&mut TextBuffer)) {
+    if let Some(doc) = state.documents.active() {
+        let mut tb = doc.buffer.borrow_mut();
+        f(&mut tb);
+        tb.make_cursor_visible();
+    }
+}
+
+fn line_comment_toggle(state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    match doc.language {
+        Some(language) => doc.buffer.borrow_mut().line_comment_toggle(language.line_comment()),
+        None => state.error_log_push(LogSeverity::Info, loc(LocId::NoticeNoLineComment).into()),
+    }
+}
+
+fn smart_fold(state: &mut State, kind: SmartFold) {
+    if let Some(doc) = state.documents.active() {
+        doc.smart_fold(&mut doc.buffer.borrow_mut(), kind);
+    }
+}
+
+fn whitespace_toggle(state: &mut State) {
+    if let Some(doc) = state.documents.active() {
+        let mut tb = doc.buffer.borrow_mut();
+        let visibility = state.documents.whitespace_toggled(tb.whitespace_visibility());
+        tb.set_whitespace_visibility(visibility);
+    }
+}
+
+fn convert_indentation(state: &mut State, use_tabs: bool) {
+    if let Some(doc) = state.documents.active_mut() {
+        doc.convert_indentation(use_tabs);
+    }
+}
+
+fn search_show(state: &mut State, kind: StateSearchKind) {
+    if state.wants_search.kind != StateSearchKind::Disabled {
+        state.wants_search.kind = kind;
+        state.wants_search.focus = true;
+    }
+}
+
+pub fn draw_command_palette(ctx: &mut Context, state: &mut State) {
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).max(10);
+    let mut run = None;
+    let mut done = false;
+
+    let mut commands = commands(&state.config);
+    let needle = state.command_palette_needle.trim_ascii();
+    if !needle.is_empty() {
+        let scratch = scratch_arena(None);
+        let mut scored: Vec<_> = commands
+            .into_iter()
+            .filter_map(|command| {
+                let local_scratch = scratch_arena(Some(&scratch));
+                let (score, _) = score_fuzzy(&local_scratch, loc(command.0), needle, true);
+                (score > 0).then_some((score, command))
+            })
+            .collect();
+        scored.sort_by_key(|s| Reverse(s.0));
+        commands = scored.into_iter().map(|(_, command)| command).collect();
+    }
+
+    ctx.modal_begin("command-palette", loc(LocId::ViewCommandPalette));
+    {
+        ctx.table_begin("command-search");
+        ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.inherit_focus();
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            ctx.label("needle-label", loc(LocId::SearchNeedleLabel));
+
+            ctx.editline("needle", &mut state.command_palette_needle);
+            ctx.inherit_focus();
+
+            // Enter in the search field runs the best match.
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                run = commands.first().map(|command| command.2);
+            }
+        }
+        ctx.table_end();
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        {
+            ctx.list_begin("commands");
+
+            for &(name, shortcut, command_run) in &commands {
+                ctx.styled_list_item_begin();
+                ctx.attr_overflow(Overflow::TruncateTail);
+                ctx.styled_label_add_text(loc(name));
+
+                let shortcut = ctx.shortcut_text(shortcut);
+                if !shortcut.is_empty() {
+                    ctx.styled_label_add_text("   ");
+                    ctx.styled_label_set_attributes(Attributes::Italic);
+                    ctx.styled_label_add_text(&shortcut);
+                }
+
+                if ctx.styled_list_item_end(false) == ListSelection::Activated {
+                    run = Some(command_run);
+                }
+            }
+
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    done |= ctx.modal_end();
+
+    if let Some(run) = run {
+        run(state);
+        done = true;
+    }
+
+    if done {
+        state.wants_command_palette = false;
+        state.command_palette_needle.clear();
+        ctx.needs_rerender();
+    }
+}
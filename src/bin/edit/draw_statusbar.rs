@@ -9,10 +9,13 @@ use edit::input::vk;
 use edit::tui::*;
 use edit::{arena_format, icu};
 
+use crate::documents::Indentation;
 use crate::localization::*;
 use crate::state::*;
 
 pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
+    let mut indentation_change = None;
+
     ctx.table_begin("statusbar");
     ctx.attr_focus_well();
     ctx.attr_background_rgba(state.menubar_color_bg);
@@ -23,12 +26,13 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
 
     if let Some(doc) = state.documents.active() {
         let mut tb = doc.buffer.borrow_mut();
+        let indentation = doc.indentation;
 
         ctx.table_next_row();
 
         if ctx.button("newline", if tb.is_crlf() { "CRLF" } else { "LF" }, ButtonStyle::default()) {
             let is_crlf = tb.is_crlf();
-            tb.normalize_newlines(!is_crlf);
+            tb.convert_newlines(!is_crlf);
         }
         if state.wants_statusbar_focus {
             state.wants_statusbar_focus = false;
@@ -70,17 +74,19 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             }
         }
 
+        ctx.label("language", doc.language.map_or(loc(LocId::LanguagePlainText), |l| l.name()));
+
         state.wants_indentation_picker |= ctx.button(
             "indentation",
             &arena_format!(
                 ctx.arena(),
-                "{}:{}",
-                loc(if tb.indent_with_tabs() {
+                "{}: {}",
+                loc(if indentation.use_tabs {
                     LocId::IndentationTabs
                 } else {
                     LocId::IndentationSpaces
                 }),
-                tb.tab_size(),
+                indentation.tab_size,
             ),
             ButtonStyle::default(),
         );
@@ -107,16 +113,16 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
                 ctx.focus_on_first_present();
                 ctx.attr_padding(Rect::two(0, 1));
                 {
-                    if ctx.list_item(tb.indent_with_tabs(), loc(LocId::IndentationTabs))
+                    if ctx.list_item(indentation.use_tabs, loc(LocId::IndentationTabs))
                         != ListSelection::Unchanged
                     {
-                        tb.set_indent_with_tabs(true);
+                        indentation_change = Some(Indentation { use_tabs: true, ..indentation });
                         ctx.needs_rerender();
                     }
-                    if ctx.list_item(!tb.indent_with_tabs(), loc(LocId::IndentationSpaces))
+                    if ctx.list_item(!indentation.use_tabs, loc(LocId::IndentationSpaces))
                         != ListSelection::Unchanged
                     {
-                        tb.set_indent_with_tabs(false);
+                        indentation_change = Some(Indentation { use_tabs: false, ..indentation });
                         ctx.needs_rerender();
                     }
                 }
@@ -129,10 +135,11 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
                         let ch = [b'0' + width];
                         let label = unsafe { std::str::from_utf8_unchecked(&ch) };
 
-                        if ctx.list_item(tb.tab_size() == width as CoordType, label)
+                        if ctx.list_item(indentation.tab_size == width as CoordType, label)
                             != ListSelection::Unchanged
                         {
-                            tb.set_tab_size(width as CoordType);
+                            indentation_change =
+                                Some(Indentation { tab_size: width as CoordType, ..indentation });
                             ctx.needs_rerender();
                         }
                     }
@@ -157,6 +164,17 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             ),
         );
 
+        // What the build command reported for the line the cursor is on.
+        if let Some(path) = &doc.path
+            && let Some(diagnostic) = state.build.diagnostic_at(path, tb.cursor_logical_pos().y)
+        {
+            let width = (diagnostic.message.chars().count() as CoordType).min(ctx.size().width / 3);
+            ctx.label("diagnostic", &diagnostic.message);
+            ctx.attr_foreground_rgba(ctx.indexed(diagnostic.severity.color()));
+            ctx.attr_overflow(Overflow::TruncateTail);
+            ctx.attr_intrinsic_size(Size { width, height: 1 });
+        }
+
         #[cfg(feature = "debug-latency")]
         ctx.label(
             "stats",
@@ -168,6 +186,16 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             ctx.needs_rerender();
         }
 
+        if tb.is_read_only() && ctx.button("read-only", "R/O", ButtonStyle::default()) {
+            tb.set_read_only(false);
+            ctx.needs_rerender();
+        }
+
+        if tb.is_word_wrap_enabled() && ctx.button("word-wrap", "WRAP", ButtonStyle::default()) {
+            tb.set_word_wrap(false);
+            ctx.needs_rerender();
+        }
+
         if tb.is_dirty() {
             ctx.label("dirty", "*");
         }
@@ -197,6 +225,12 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
     }
 
     ctx.table_end();
+
+    if let Some(indentation) = indentation_change
+        && let Some(doc) = state.documents.active_mut()
+    {
+        doc.set_indentation(indentation);
+    }
 }
 
 pub fn draw_dialog_encoding_change(ctx: &mut Context, state: &mut State) {
@@ -269,6 +303,8 @@ pub fn draw_dialog_encoding_change(ctx: &mut Context, state: &mut State) {
             }
         } else {
             doc.buffer.borrow_mut().set_encoding(encoding);
+            // Once saved, that's the encoding of the file. UTF-8 is detected anyway.
+            doc.chosen_encoding = Some(encoding).filter(|e| !e.starts_with("UTF-8"));
         }
     }
 
@@ -334,6 +370,10 @@ pub fn draw_go_to_file(ctx: &mut Context, state: &mut State) {
 
                 ctx.styled_list_item_end(false) == ListSelection::Activated
             }) {
+                // The previously active document is now the second most recently used one.
+                if let Some(doc) = state.documents.iter().nth(1) {
+                    state.jumps.push(doc);
+                }
                 state.wants_go_to_file = false;
                 ctx.needs_rerender();
             }
@@ -346,3 +386,152 @@ pub fn draw_go_to_file(ctx: &mut Context, state: &mut State) {
         state.wants_go_to_file = false;
     }
 }
+
+pub fn draw_recent_files(ctx: &mut Context, state: &mut State) {
+    // Files may have been deleted or moved since they were last opened.
+    state.recent_files.retain(|path| path.is_file());
+
+    let mut activated = None;
+
+    ctx.modal_begin("recent-files", loc(LocId::FileOpenRecent));
+    {
+        let width = (ctx.size().width - 20).max(10);
+        let height = (ctx.size().height - 10).max(10);
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.inherit_focus();
+        {
+            if state.recent_files.is_empty() {
+                ctx.label("empty", loc(LocId::RecentFilesEmpty));
+                ctx.attr_padding(Rect::two(0, 1));
+            } else {
+                ctx.list_begin("files");
+                ctx.inherit_focus();
+
+                for path in &state.recent_files {
+                    ctx.styled_list_item_begin();
+                    ctx.attr_overflow(Overflow::TruncateTail);
+                    ctx.styled_label_add_text(
+                        &path.file_name().unwrap_or(path.as_os_str()).to_string_lossy(),
+                    );
+
+                    if let Some(dir) = path.parent() {
+                        ctx.styled_label_add_text("   ");
+                        ctx.styled_label_set_attributes(Attributes::Italic);
+                        ctx.styled_label_add_text(&dir.to_string_lossy());
+                    }
+
+                    if ctx.styled_list_item_end(false) == ListSelection::Activated {
+                        activated = Some(path.clone());
+                    }
+                }
+
+                ctx.list_end();
+            }
+        }
+        ctx.scrollarea_end();
+    }
+    if ctx.modal_end() {
+        state.wants_recent_files = false;
+    }
+
+    if let Some(path) = activated {
+        state.wants_recent_files = false;
+        state.jump_push();
+        if let Err(err) = state.documents.add_file_path(&path) {
+            error_log_add(ctx, state, err);
+        }
+        ctx.needs_rerender();
+    }
+}
+
+/// Lists the errors and notices of the session, newest first.
+pub fn draw_log(ctx: &mut Context, state: &mut State) {
+    let mut clear = false;
+    let mut close = false;
+
+    ctx.modal_begin("log", loc(LocId::ViewLog));
+    {
+        let contains_focus = ctx.contains_focus();
+        let width = (ctx.size().width - 20).max(10);
+        let height = (ctx.size().height - 12).max(10);
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.inherit_focus();
+        {
+            if state.log.entries.is_empty() {
+                ctx.label("empty", loc(LocId::LogEmpty));
+                ctx.attr_padding(Rect::two(0, 1));
+            } else {
+                ctx.list_begin("entries");
+                ctx.inherit_focus();
+
+                for entry in state.log.entries.iter().rev() {
+                    let secs = entry.time.as_secs();
+                    let time = arena_format!(
+                        ctx.arena(),
+                        "{}:{:02}:{:02}  ",
+                        secs / 3600,
+                        secs / 60 % 60,
+                        secs % 60
+                    );
+                    let severity = match entry.severity {
+                        LogSeverity::Info => loc(LocId::LogInfo),
+                        LogSeverity::Error => loc(LocId::ErrorDialogTitle),
+                    };
+
+                    ctx.styled_list_item_begin();
+                    ctx.attr_overflow(Overflow::TruncateTail);
+                    ctx.styled_label_add_text(&time);
+                    if entry.severity == LogSeverity::Error {
+                        ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::BrightRed));
+                    }
+                    ctx.styled_label_add_text(severity);
+                    ctx.styled_label_set_foreground(0);
+                    ctx.styled_label_add_text("  ");
+                    ctx.styled_label_add_text(&entry.message);
+                    ctx.styled_list_item_end(false);
+                }
+
+                ctx.list_end();
+            }
+        }
+        ctx.scrollarea_end();
+
+        ctx.table_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(1, 2, 0));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            if ctx.button("clear", loc(LocId::LogClear), ButtonStyle::default().accelerator('C')) {
+                clear = true;
+            }
+            if ctx.button("ok", loc(LocId::Ok), ButtonStyle::default()) {
+                close = true;
+            }
+
+            if contains_focus && ctx.consume_shortcut(vk::C) {
+                clear = true;
+            }
+        }
+        ctx.table_end();
+    }
+    if ctx.modal_end() {
+        close = true;
+    }
+
+    if clear {
+        state.log.entries.clear();
+        ctx.needs_rerender();
+    }
+    if close {
+        state.wants_log = false;
+        ctx.needs_rerender();
+    }
+}
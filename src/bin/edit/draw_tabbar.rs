@@ -0,0 +1,89 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::tui::*;
+
+use crate::documents::Document;
+use crate::state::*;
+
+pub fn draw_tabbar(ctx: &mut Context, state: &mut State) {
+    let mut activate = None;
+    let mut close = None;
+    let mut unpin = None;
+
+    ctx.table_begin("tabbar");
+    ctx.attr_focus_well();
+    ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+    {
+        ctx.table_next_row();
+
+        let tabs = state.documents.tabs();
+        let active = state.documents.active().map(|doc| doc.tab_order);
+
+        for doc in &tabs {
+            let is_active = Some(doc.tab_order) == active;
+
+            ctx.next_block_id_mixin(doc.tab_order);
+            if ctx.button("title", &tab_title(doc, &tabs), ButtonStyle::default().bracketed(false))
+            {
+                activate = Some(doc.tab_order);
+            }
+            ctx.attr_padding(Rect::two(0, 1));
+            if is_active {
+                ctx.attr_background_rgba(ctx.indexed(IndexedColor::Background));
+            }
+
+            // Pinned tabs have a marker instead of the close button, which unpins them.
+            ctx.next_block_id_mixin(doc.tab_order);
+            if doc.pinned {
+                if ctx.button("unpin", "•", ButtonStyle::default().bracketed(false)) {
+                    unpin = Some(doc.tab_order);
+                }
+            } else if ctx.button("close", "×", ButtonStyle::default().bracketed(false)) {
+                close = Some(doc.tab_order);
+            }
+            ctx.attr_padding(Rect { left: 0, top: 0, right: 1, bottom: 0 });
+            if is_active {
+                ctx.attr_background_rgba(ctx.indexed(IndexedColor::Background));
+            }
+        }
+    }
+    ctx.table_end();
+
+    if let Some(tab_order) = activate.or(close) {
+        state.documents.update_active(|doc| doc.tab_order == tab_order);
+        // Give the focus back to the editor.
+        state.panes.focus_changed = true;
+        ctx.needs_rerender();
+    }
+    if close.is_some() {
+        state.wants_close = true;
+    }
+    if let Some(tab_order) = unpin
+        && let Some(doc) = state.documents.iter_mut().find(|doc| doc.tab_order == tab_order)
+    {
+        doc.pinned = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// The filename, preceded by its directory if another tab has the same filename.
+fn tab_title(doc: &Document, tabs: &[&Document]) -> String {
+    let mut title = String::new();
+    if doc.buffer.borrow().is_dirty() {
+        title.push_str("* ");
+    }
+
+    let ambiguous =
+        tabs.iter().any(|other| other.tab_order != doc.tab_order && other.filename == doc.filename);
+    if ambiguous && let Some(dir) = doc.path.as_ref().and_then(|path| path.parent()?.file_name()) {
+        title.push_str(&dir.to_string_lossy());
+        title.push('/');
+    }
+
+    title.push_str(&doc.filename);
+    title
+}
@@ -0,0 +1,347 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reads the `.editorconfig` files that apply to a file. See <https://editorconfig.org>.
+
+use std::fs;
+use std::path::Path;
+
+use edit::helpers::CoordType;
+
+/// The settings the `.editorconfig` files specify for a file. `None` where they don't.
+#[derive(Default)]
+pub struct EditorConfig {
+    pub indent_with_tabs: Option<bool>,
+    pub tab_size: Option<CoordType>,
+    pub crlf: Option<bool>,
+    pub encoding: Option<&'static str>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    /// Collects the settings for `path` from the `.editorconfig` files in the directories
+    /// above it, up to the first one that says `root = true`. Nearer files take precedence.
+    pub fn lookup(path: &Path) -> Self {
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let Ok(text) = fs::read_to_string(dir.join(".editorconfig")) else {
+                continue;
+            };
+            let root = is_root(&text);
+            files.push((dir, text));
+            if root {
+                break;
+            }
+        }
+
+        // Within and across files, later sections override earlier ones,
+        // so the files are applied starting with the one closest to the root.
+        let mut props: Vec<(String, String)> = Vec::new();
+        for (dir, text) in files.iter().rev() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            collect_properties(text, &relative, &mut props);
+        }
+
+        let get = |key: &str| {
+            props.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str()).filter(|&v| v != "unset")
+        };
+        let get_bool = |key: &str| match get(key) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+        let get_width = |key: &str| {
+            get(key).and_then(|v| v.parse::<CoordType>().ok()).filter(|n| (1..=8).contains(n))
+        };
+
+        Self {
+            indent_with_tabs: match get("indent_style") {
+                Some("tab") => Some(true),
+                Some("space") => Some(false),
+                _ => None,
+            },
+            // There's only one width for both tabs and indentation, and indent_size wins,
+            // unless it defers to tab_width.
+            tab_size: match get("indent_size") {
+                Some("tab") => get_width("tab_width"),
+                _ => get_width("indent_size").or_else(|| get_width("tab_width")),
+            },
+            crlf: match get("end_of_line") {
+                Some("lf") => Some(false),
+                Some("crlf") => Some(true),
+                _ => None,
+            },
+            encoding: match get("charset") {
+                Some("utf-8") => Some("UTF-8"),
+                Some("utf-8-bom") => Some("UTF-8 BOM"),
+                Some("utf-16le") => Some("UTF-16LE"),
+                Some("utf-16be") => Some("UTF-16BE"),
+                Some("latin1") => Some("ISO-8859-1"),
+                _ => None,
+            },
+            trim_trailing_whitespace: get_bool("trim_trailing_whitespace"),
+            insert_final_newline: get_bool("insert_final_newline"),
+        }
+    }
+}
+
+/// Returns the lines of an INI file, without comments and blank lines.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with(['#', ';']))
+}
+
+fn parse_property(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once('=')?;
+    Some((key.trim().to_ascii_lowercase(), value.trim().to_ascii_lowercase()))
+}
+
+/// Checks for `root = true` before the first section.
+fn is_root(text: &str) -> bool {
+    lines(text)
+        .take_while(|l| !l.starts_with('['))
+        .filter_map(parse_property)
+        .any(|(key, value)| key == "root" && value == "true")
+}
+
+/// Adds the properties of all sections that match the `relative` path to `props`,
+/// replacing any previous values. `props` is kept with the latest values first.
+fn collect_properties(text: &str, relative: &str, props: &mut Vec<(String, String)>) {
+    let mut matches = false;
+
+    for line in lines(text) {
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            matches = section_matches(glob, relative);
+        } else if matches && let Some((key, value)) = parse_property(line) {
+            props.retain(|(k, _)| *k != key);
+            props.insert(0, (key, value));
+        }
+    }
+}
+
+/// Globs with a slash are relative to the directory of the `.editorconfig` file.
+/// Those without one match the file name in any directory below it.
+fn section_matches(glob: &str, relative: &str) -> bool {
+    let path = relative.as_bytes();
+    if glob.contains('/') {
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        glob_matches(glob.as_bytes(), path)
+    } else {
+        let starts = path.iter().enumerate().filter(|&(_, &c)| c == b'/').map(|(i, _)| i + 1);
+        std::iter::once(0).chain(starts).any(|i| glob_matches(glob.as_bytes(), &path[i..]))
+    }
+}
+
+/// Matches `path` against an EditorConfig glob, which supports `*`, `**`, `?`,
+/// `[abc]`, `[!a-z]`, `{foo,bar}` and `{1..10}`.
+fn glob_matches(glob: &[u8], path: &[u8]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_matches(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_matches(rest, &path[i..])),
+        [b'?', rest @ ..] => {
+            path.first().is_some_and(|&c| c != b'/') && glob_matches(rest, &path[1..])
+        }
+        [b'[', rest @ ..] => match_class(rest, path).unwrap_or_else(|| match_literal(glob, path)),
+        [b'{', rest @ ..] => match_braces(rest, path).unwrap_or_else(|| match_literal(glob, path)),
+        [b'\\', rest @ ..] if !rest.is_empty() => match_literal(rest, path),
+        _ => match_literal(glob, path),
+    }
+}
+
+fn match_literal(glob: &[u8], path: &[u8]) -> bool {
+    path.first() == glob.first() && glob_matches(&glob[1..], &path[1..])
+}
+
+/// Matches a `[...]` character class, given the glob after the `[`.
+/// Returns `None` if it's missing the `]` and is thus a literal `[`.
+fn match_class(glob: &[u8], path: &[u8]) -> Option<bool> {
+    let end = glob.iter().skip(1).position(|&c| c == b']')? + 1;
+    let (class, rest) = (&glob[..end], &glob[end + 1..]);
+    let Some(&c) = path.first().filter(|&&c| c != b'/') else {
+        return Some(false);
+    };
+    let (negate, class) = match class {
+        [b'!', class @ ..] => (true, class),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    Some(found != negate && glob_matches(rest, &path[1..]))
+}
+
+/// Matches a `{foo,bar}` or `{1..10}` group, given the glob after the `{`.
+/// Returns `None` if it's missing the `}` or has only one alternative,
+/// in which case the `{` is literal.
+fn match_braces(glob: &[u8], path: &[u8]) -> Option<bool> {
+    let end = closing_brace(glob)?;
+    let (inner, rest) = (&glob[..end], &glob[end + 1..]);
+
+    if let Some(range) = parse_range(inner) {
+        let len = path
+            .iter()
+            .enumerate()
+            .take_while(|&(i, &c)| c.is_ascii_digit() || (i == 0 && c == b'-'))
+            .count();
+        let number = std::str::from_utf8(&path[..len]).ok().and_then(|s| s.parse().ok());
+        return Some(
+            number.is_some_and(|n| range.contains(&n)) && glob_matches(rest, &path[len..]),
+        );
+    }
+
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, &c) in inner.iter().enumerate() {
+        match c {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                alternatives.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&inner[start..]);
+
+    if alternatives.len() == 1 {
+        return None;
+    }
+    Some(alternatives.into_iter().any(|alt| {
+        let mut expanded = alt.to_vec();
+        expanded.extend_from_slice(rest);
+        glob_matches(&expanded, path)
+    }))
+}
+
+/// Returns the index of the `}` that closes a `{` just before `glob`.
+fn closing_brace(glob: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &c) in glob.iter().enumerate() {
+        match c {
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses the `1..10` in `{1..10}`.
+fn parse_range(text: &[u8]) -> Option<std::ops::RangeInclusive<i64>> {
+    let text = std::str::from_utf8(text).ok()?;
+    let (start, end) = text.split_once("..")?;
+    Some(start.parse().ok()?..=end.parse().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_stars() {
+        assert!(section_matches("*", "main.rs"));
+        assert!(section_matches("*.rs", "src/main.rs"));
+        assert!(!section_matches("*.rs", "main.rs.bak"));
+        assert!(section_matches("src/*.rs", "src/main.rs"));
+        assert!(!section_matches("src/*.rs", "src/bin/main.rs"));
+        assert!(section_matches("/src/**.rs", "src/bin/main.rs"));
+        assert!(section_matches("src/**/*.rs", "src/bin/edit/main.rs"));
+        assert!(!section_matches("lib/**.rs", "src/lib/main.rs"));
+        assert!(section_matches("?.c", "a.c"));
+        assert!(!section_matches("?.c", "ab.c"));
+    }
+
+    #[test]
+    fn test_glob_braces() {
+        assert!(section_matches("*.{js,ts}", "app.ts"));
+        assert!(section_matches("{Makefile,*.mk}", "rules.mk"));
+        assert!(!section_matches("*.{js,ts}", "app.rs"));
+        assert!(section_matches("file{1..10}.txt", "file7.txt"));
+        assert!(section_matches("file{-5..5}.txt", "file-3.txt"));
+        assert!(!section_matches("file{1..10}.txt", "file11.txt"));
+        assert!(!section_matches("file{1..10}.txt", "filex.txt"));
+        // A single alternative or a missing `}` is matched literally.
+        assert!(section_matches("{a}.txt", "{a}.txt"));
+        assert!(section_matches("{a.txt", "{a.txt"));
+    }
+
+    #[test]
+    fn test_glob_classes() {
+        assert!(section_matches("[abc].txt", "b.txt"));
+        assert!(!section_matches("[abc].txt", "d.txt"));
+        assert!(section_matches("[a-z]1", "q1"));
+        assert!(!section_matches("[!a-z]1", "q1"));
+        assert!(section_matches("[!a-z]1", "Q1"));
+        assert!(section_matches("[a.txt", "[a.txt"));
+    }
+
+    #[test]
+    fn test_sections_override() {
+        let text = "\
+root = true
+
+[*]
+indent_style = space
+indent_size = 4
+
+# Later sections win.
+[*.md]
+indent_size = 2
+";
+        let mut props = Vec::new();
+        collect_properties(text, "docs/readme.md", &mut props);
+        assert!(props.contains(&("indent_size".to_string(), "2".to_string())));
+        assert!(props.contains(&("indent_style".to_string(), "space".to_string())));
+        assert!(!props.contains(&("indent_size".to_string(), "4".to_string())));
+
+        assert!(is_root(text));
+        assert!(!is_root("[*]\nroot = true\n"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let root = std::env::temp_dir().join(format!("edit-editorconfig-{}", std::process::id()));
+        let dir = root.join("project/src");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(root.join(".editorconfig"), "[*]\nend_of_line = crlf\ncharset = latin1\n")
+            .unwrap();
+        fs::write(
+            root.join("project/.editorconfig"),
+            "root = true\n[*]\nindent_style = tab\ntab_width = 8\ncharset = utf-8\n",
+        )
+        .unwrap();
+        fs::write(dir.join(".editorconfig"), "[*.rs]\nindent_size = 4\ncharset = unset\n").unwrap();
+
+        let config = EditorConfig::lookup(&dir.join("main.rs"));
+        let other = EditorConfig::lookup(&dir.join("main.c"));
+        fs::remove_dir_all(&root).unwrap();
+
+        // The nearer file overrides the indent_size and unsets the charset.
+        assert_eq!(config.indent_with_tabs, Some(true));
+        assert_eq!(config.tab_size, Some(4));
+        assert_eq!(config.encoding, None);
+        assert_eq!(other.tab_size, Some(8));
+        assert_eq!(other.encoding, Some("UTF-8"));
+        // The file above `root = true` is ignored.
+        assert_eq!(config.crlf, None);
+    }
+}
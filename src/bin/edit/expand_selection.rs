@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Grows the selection to ever larger syntax nodes, e.g. from a word to the expression,
+//! the statement and the block it's in, and shrinks it back step by step.
+//! Without a syntax tree, it grows from the word to the line, the paragraph and the document.
+
+use std::ops::Range;
+use std::rc::{Rc, Weak};
+
+use edit::buffer::{TextBuffer, TextBufferCell};
+use edit::syntax;
+
+use crate::state::State;
+
+/// The selections that the current one was grown from, so that shrinking returns to them exactly.
+#[derive(Default)]
+pub struct ExpandedSelections {
+    buffer: Weak<TextBufferCell>,
+    /// The buffer generation the selections are valid for. Any edit makes them meaningless.
+    generation: u32,
+    /// From the first to the last one that was grown from.
+    stack: Vec<Range<usize>>,
+    /// The selection after the last step. Once it's changed otherwise, the stack is forgotten.
+    current: Range<usize>,
+}
+
+impl State {
+    /// Grows the selection in the active document to the next larger syntax node.
+    pub fn selection_expand(&mut self) {
+        let Some(doc) = self.documents.active() else {
+            return;
+        };
+        let mut tb = doc.buffer.borrow_mut();
+        let selection = selection_of(&tb);
+
+        // The tree is only of use while it's up to date with the text.
+        let tree = doc.syntax_tree.as_ref().filter(|_| doc.buffer_generation == tb.generation());
+        let Some(next) = tree
+            .and_then(|tree| syntax::enclosing_node(tree, selection.clone()))
+            .or_else(|| tb.expand_range(selection.clone()))
+        else {
+            return;
+        };
+
+        let expanded = &mut self.expanded_selections;
+        if !expanded.is_current(&doc.buffer, &tb, &selection) {
+            expanded.buffer = Rc::downgrade(&doc.buffer);
+            expanded.generation = tb.generation();
+            expanded.stack.clear();
+        }
+        expanded.stack.push(selection);
+        expanded.current = next.clone();
+
+        tb.select_range(next);
+        tb.make_cursor_visible();
+    }
+
+    /// Returns to the selection that the current one was grown from by [`State::selection_expand`].
+    pub fn selection_shrink(&mut self) {
+        let Some(doc) = self.documents.active() else {
+            return;
+        };
+        let mut tb = doc.buffer.borrow_mut();
+        let selection = selection_of(&tb);
+
+        let expanded = &mut self.expanded_selections;
+        if !expanded.is_current(&doc.buffer, &tb, &selection) {
+            return;
+        }
+        let Some(prev) = expanded.stack.pop() else {
+            return;
+        };
+        expanded.current = prev.clone();
+
+        // An empty range leaves just the cursor, as it was before the first step.
+        tb.select_range(prev);
+        tb.make_cursor_visible();
+    }
+}
+
+impl ExpandedSelections {
+    /// Returns true if the `selection` in the buffer is still the one the last step left behind.
+    fn is_current(
+        &self,
+        buffer: &Rc<TextBufferCell>,
+        tb: &TextBuffer,
+        selection: &Range<usize>,
+    ) -> bool {
+        self.buffer.upgrade().is_some_and(|b| Rc::ptr_eq(&b, buffer))
+            && self.generation == tb.generation()
+            && self.current == *selection
+    }
+}
+
+/// Returns the selection as a byte range. Without one, it's the empty range at the cursor.
+fn selection_of(tb: &TextBuffer) -> Range<usize> {
+    match tb.selection_range() {
+        Some((beg, end)) => beg.offset..end.offset,
+        None => tb.cursor_offset()..tb.cursor_offset(),
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Asks the `git` command line tool about the files in a repository.
+
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Who last changed a line, and when.
+#[derive(Clone, Default)]
+pub struct BlameLine {
+    pub author: String,
+    /// Seconds since the Unix epoch.
+    pub time: i64,
+    /// The line was changed since the last commit.
+    pub uncommitted: bool,
+}
+
+/// Returns the contents of the file at `path` as of the last commit,
+/// or `None` if it's not tracked by git or git isn't installed.
+pub fn show_head(path: &Path) -> Option<Vec<u8>> {
+    let dir = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(format!("HEAD:./{name}"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/// Returns who last changed each line of the file at `path`, given its current `contents`,
+/// so that lines changed but not committed or saved yet are attributed as such.
+/// Returns `None` if it's not tracked by git or git isn't installed.
+pub fn blame(path: &Path, contents: Vec<u8>) -> Option<Vec<BlameLine>> {
+    let dir = path.parent()?;
+    let name = path.file_name()?;
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["blame", "--porcelain", "--contents", "-", "--"])
+        .arg(name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // git may start writing before it has read all of its input,
+    // so the input is written from another thread to avoid both ends waiting on a full pipe.
+    let mut stdin = child.stdin.take()?;
+    let writer = thread::spawn(move || stdin.write_all(&contents));
+    let output = child.wait_with_output().ok()?;
+    _ = writer.join();
+
+    output.status.success().then(|| parse_blame(&output.stdout))
+}
+
+/// Parses the output of `git blame --porcelain`. Each line is preceded by a header line
+/// "<sha> <original line> <final line> [<group size>]", which is followed by the details
+/// of the commit the first time that commit appears, and the line itself prefixed with a tab.
+fn parse_blame(output: &[u8]) -> Vec<BlameLine> {
+    let output = String::from_utf8_lossy(output);
+    let mut commits: HashMap<&str, BlameLine> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut sha = "";
+    let mut line = 0;
+
+    for row in output.split('\n') {
+        if row.starts_with('\t') {
+            if line >= 1 {
+                if lines.len() < line {
+                    lines.resize(line, BlameLine::default());
+                }
+                lines[line - 1] = commits.get(sha).cloned().unwrap_or_default();
+            }
+            continue;
+        }
+
+        match row.split_once(' ') {
+            Some(("author", author)) => {
+                commits.entry(sha).or_default().author = author.to_string();
+            }
+            Some(("author-time", time)) => {
+                commits.entry(sha).or_default().time = time.parse().unwrap_or(0);
+            }
+            Some((hash, rest))
+                if matches!(hash.len(), 40 | 64) && hash.bytes().all(|b| b.is_ascii_hexdigit()) =>
+            {
+                sha = hash;
+                line = rest.split(' ').nth(1).and_then(|l| l.parse().ok()).unwrap_or(0);
+                commits.entry(sha).or_default().uncommitted = hash.bytes().all(|b| b == b'0');
+            }
+            _ => {}
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blame() {
+        let sha = "1".repeat(40);
+        let zero = "0".repeat(40);
+        let output = format!(
+            "{sha} 1 1 1\nauthor Ada\nauthor-time 86400\nsummary x\n\tfirst\n\
+             {zero} 2 2 1\nauthor Not Committed Yet\nauthor-time 172800\n\tsecond\n\
+             {sha} 2 3\n\tthird\n"
+        );
+        let lines = parse_blame(output.as_bytes());
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0].author.as_str(), lines[0].time), ("Ada", 86400));
+        assert!(!lines[0].uncommitted);
+        assert!(lines[1].uncommitted);
+        assert_eq!((lines[2].author.as_str(), lines[2].time), ("Ada", 86400));
+    }
+}
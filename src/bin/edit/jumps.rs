@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Remembers where the cursor jumped from, so that one can go back and forth like in a browser.
+//!
+//! A jump is anything that moves the cursor somewhere else in one go:
+//! going to a line or symbol, to a search match, or opening a file.
+//! Those call [`State::jump_push`] or [`Jumps::push`] before they move the cursor.
+
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+
+use edit::buffer::TextBufferCell;
+use edit::helpers::*;
+
+use crate::documents::Document;
+use crate::state::State;
+
+/// Beyond this many locations the oldest ones are forgotten.
+const MAX_JUMPS: usize = 100;
+/// Locations in the same document closer than this many lines are considered the same one,
+/// so that jumping around within a screenful doesn't flood the history.
+const MIN_JUMP_DISTANCE: CoordType = 10;
+
+struct Jump {
+    /// The document, unless it was closed since. Then it's reopened from the `path`.
+    buffer: Weak<TextBufferCell>,
+    path: Option<PathBuf>,
+    cursor: Point,
+}
+
+impl Jump {
+    fn of(doc: &Document) -> Self {
+        Self {
+            buffer: Rc::downgrade(&doc.buffer),
+            path: doc.path.clone(),
+            cursor: doc.buffer.borrow().cursor_logical_pos(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Jumps {
+    /// From oldest to newest.
+    list: Vec<Jump>,
+    /// The location that was last gone back or forward to.
+    /// It's `list.len()` while not going through the history.
+    index: usize,
+}
+
+impl Jumps {
+    /// Remembers the cursor position in `doc`, before it jumps elsewhere.
+    /// Any locations that were gone back from are forgotten.
+    pub fn push(&mut self, doc: &Document) {
+        self.list.truncate(self.index + 1);
+        jumps_insert(&mut self.list, Jump::of(doc));
+        if self.list.len() > MAX_JUMPS {
+            self.list.remove(0);
+        }
+        self.index = self.list.len();
+    }
+}
+
+impl State {
+    /// Remembers the cursor position in the active document. See [`Jumps::push`].
+    pub fn jump_push(&mut self) {
+        if let Some(doc) = self.documents.active() {
+            self.jumps.push(doc);
+        }
+    }
+
+    /// Returns to the location before the last jump.
+    pub fn jump_back(&mut self) {
+        if self.jumps.index >= self.jumps.list.len() {
+            // Remember where we came from, so that we can go forward to it again.
+            if let Some(doc) = self.documents.active() {
+                jumps_insert(&mut self.jumps.list, Jump::of(doc));
+            }
+            self.jumps.index = self.jumps.list.len().saturating_sub(1);
+        }
+
+        while self.jumps.index > 0 {
+            self.jumps.index -= 1;
+            if self.jump_to(self.jumps.index) {
+                break;
+            }
+        }
+    }
+
+    /// Undoes [`State::jump_back`].
+    pub fn jump_forward(&mut self) {
+        while self.jumps.index + 1 < self.jumps.list.len() {
+            self.jumps.index += 1;
+            if self.jump_to(self.jumps.index) {
+                break;
+            }
+        }
+    }
+
+    /// Activates the document of the location at `index`, reopening it if necessary,
+    /// and moves the cursor there. Returns false if the document is gone for good.
+    fn jump_to(&mut self, index: usize) -> bool {
+        let jump = &self.jumps.list[index];
+        let cursor = jump.cursor;
+
+        let path = jump.path.clone();
+        let open = jump
+            .buffer
+            .upgrade()
+            .filter(|buffer| self.documents.update_active(|doc| Rc::ptr_eq(&doc.buffer, buffer)));
+
+        let buffer = match open {
+            Some(buffer) => buffer,
+            None => match path.map(|path| self.documents.add_file_path(&path)) {
+                Some(Ok(doc)) => {
+                    let buffer = doc.buffer.clone();
+                    self.jumps.list[index].buffer = Rc::downgrade(&buffer);
+                    buffer
+                }
+                _ => return false,
+            },
+        };
+
+        let mut tb = buffer.borrow_mut();
+        tb.cursor_move_to_logical(cursor);
+        tb.make_cursor_visible();
+        true
+    }
+}
+
+/// Appends `jump` to the `list`, unless it's close to the last location, which it then replaces.
+fn jumps_insert(list: &mut Vec<Jump>, jump: Jump) {
+    if let Some(last) = list.last_mut()
+        && Weak::ptr_eq(&last.buffer, &jump.buffer)
+        && (last.cursor.y - jump.cursor.y).abs() < MIN_JUMP_DISTANCE
+    {
+        *last = jump;
+    } else {
+        list.push(jump);
+    }
+}
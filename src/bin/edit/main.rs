@@ -3,36 +3,64 @@
 
 #![feature(allocator_api, linked_list_cursors, string_from_utf8_lossy_owned)]
 
+mod autosave;
+mod build;
+mod config;
 mod documents;
+mod draw_build;
 mod draw_editor;
 mod draw_filepicker;
 mod draw_filetree;
+mod draw_history;
 mod draw_menubar;
+mod draw_outline;
+mod draw_palette;
 mod draw_statusbar;
+mod draw_tabbar;
+mod editorconfig;
+mod expand_selection;
+mod git;
+mod jumps;
 mod localization;
+mod panes;
+mod positions;
+mod recent;
+mod remote;
+mod session;
+mod snippets;
 mod state;
+mod store;
+mod swap;
 
 use std::borrow::Cow;
+use std::ffi::OsStr;
 #[cfg(feature = "debug-latency")]
 use std::fmt::Write;
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::{env, process};
 
+use autosave::*;
+use config::{Action, Config};
 use draw_editor::*;
 use draw_filepicker::*;
 use draw_filetree::*;
+use draw_history::*;
 use draw_menubar::*;
+use draw_outline::*;
+use draw_palette::*;
 use draw_statusbar::*;
+use draw_tabbar::*;
 use edit::arena::{self, Arena, ArenaString, scratch_arena};
 use edit::framebuffer::{self, IndexedColor};
-use edit::helpers::{CoordType, KIBI, MEBI, MetricFormatter, Rect, Size, COORD_TYPE_SAFE_MAX};
-use edit::input::{self, kbmod, vk};
+use edit::helpers::{CoordType, KIBI, MEBI, MetricFormatter, Rect, Size};
 use edit::oklab::oklab_blend;
 use edit::tui::*;
 use edit::vt::{self, Token};
-use edit::{apperr, arena_format, base64, path, sys, unicode};
+use edit::{apperr, arena_format, base64, input, path, sys, unicode};
 use localization::*;
+use remote::Remote;
 use state::*;
 
 #[cfg(target_pointer_width = "32")]
@@ -68,9 +96,28 @@ fn run() -> apperr::Result<()> {
     localization::init();
 
     let mut state = State::new()?;
+    // Load the config before `handle_args`, so that the files it opens get the default settings.
+    let (config, errors) = Config::load();
+    state.documents.default_indentation = config.indentation;
+    state.documents.default_word_wrap = config.word_wrap;
+    state.documents.default_line_highlight = config.highlight_current_line;
+    state.documents.default_ruler = config.ruler;
+    state.documents.default_scroll_off = config.scroll_off;
+    state.documents.default_whitespace = config.render_whitespace;
+    state.config = config;
+    for err in errors {
+        state.error_log_push(LogSeverity::Error, err);
+    }
+
+    // If stdout is redirected, as in `edit | sort`, the UI goes to the terminal instead,
+    // and stdout receives the text of the document that's active when the user quits.
+    let stdout = sys::open_stdout_if_redirected();
+    state.stdout_redirected = stdout.is_some();
+
     if handle_args(&mut state)? {
         return Ok(());
     }
+    state.swap_recoverable = swap::find_recoverable();
 
     // This will reopen stdin if it's redirected (which may fail) and switch
     // the terminal to raw mode which prevents the user from pressing Ctrl+C.
@@ -119,7 +166,11 @@ fn run() -> apperr::Result<()> {
         // Process a batch of input.
         {
             let scratch = scratch_arena(None);
-            let read_timeout = vt_parser.read_timeout().min(tui.read_timeout());
+            let read_timeout = vt_parser
+                .read_timeout()
+                .min(tui.read_timeout())
+                .min(state.swap_timeout())
+                .min(state.autosave_timeout());
             let Some(input) = sys::read_stdin(&scratch, read_timeout) else {
                 break;
             };
@@ -136,6 +187,13 @@ fn run() -> apperr::Result<()> {
             while {
                 let input = input_iter.next();
                 let more = input.is_some();
+                match input {
+                    Some(input::Input::Focus(false)) => {
+                        state.wants_autosave = state.config.autosave_on_focus_loss;
+                    }
+                    Some(_) => state.last_input = std::time::Instant::now(),
+                    None => {}
+                }
                 let mut ctx = tui.create_context(input);
 
                 draw(&mut ctx, &mut state);
@@ -166,6 +224,11 @@ fn run() -> apperr::Result<()> {
             break;
         }
 
+        if state.swap_timeout().is_zero() {
+            state.swap_flush();
+        }
+        state.recent_files_update();
+
         // Render the UI and write it to the terminal.
         {
             let scratch = scratch_arena(None);
@@ -220,65 +283,171 @@ fn run() -> apperr::Result<()> {
         }
     }
 
+    if let Some(mut file) = stdout
+        && let Some(text) = &state.exit_stdout
+    {
+        file.write_all(text)?;
+    }
+
     Ok(())
 }
 
 // Returns true if the application should exit early.
+/// On Unix the shell already expanded `~` and variables in the arguments, and whatever is
+/// left, like the `$` in `'a$b.txt'`, was quoted on purpose. Only Windows needs our help.
+fn expand_arg(state: &mut State, arg: &OsStr) -> PathBuf {
+    if cfg!(windows) { state.expand_path(Path::new(arg)) } else { PathBuf::from(arg) }
+}
+
 fn handle_args(state: &mut State) -> apperr::Result<bool> {
     let scratch = scratch_arena(None);
     let mut paths: Vec<PathBuf, &Arena> = Vec::new_in(&*scratch);
     let mut cwd = env::current_dir()?;
+    // A directory to show in the file tree instead of the current one.
+    let mut dir = None;
+    // Whether to reopen the last session. Passing any path starts a fresh one instead.
+    let mut restore = true;
+    // The two files to compare with `--diff`.
+    let mut diff = None;
+    // The files on other hosts, given as `ssh://` URLs.
+    let mut remotes = Vec::new();
 
     // The best CLI argument parser in the world.
-    for arg in env::args_os().skip(1) {
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
         if arg == "-h" || arg == "--help" || (cfg!(windows) && arg == "/?") {
             print_help();
             return Ok(true);
         } else if arg == "-v" || arg == "--version" {
             print_version();
             return Ok(true);
+        } else if arg == "--diff" {
+            let (Some(old), Some(new)) = (args.next(), args.next()) else {
+                print_help();
+                return Ok(true);
+            };
+            let old = path::normalize(&cwd.join(expand_arg(state, &old)));
+            let new = path::normalize(&cwd.join(expand_arg(state, &new)));
+            diff = Some((old, new));
+            restore = false;
+            break;
+        } else if arg == "--no-restore" {
+            restore = false;
+            continue;
         } else if arg == "-" {
             paths.clear();
             break;
         }
-        let p = cwd.join(Path::new(&arg));
+        restore = false;
+        if let Some(remote) = arg.to_str().and_then(Remote::parse) {
+            remotes.push(remote);
+            continue;
+        }
+        let p = cwd.join(expand_arg(state, &arg));
         let p = path::normalize(&p);
-        if !p.is_dir() {
+        if p.is_dir() {
+            dir = Some(p);
+        } else {
             paths.push(p);
         }
     }
 
-    for p in &paths {
-        state.documents.add_file_path(p)?;
+    if let Some((old, new)) = &diff {
+        if open_diff(state, old, new)? {
+            return Ok(true);
+        }
+        paths.push(new.clone());
+    } else {
+        // The most recently opened document is the active one,
+        // so the files are opened in reverse to make the first one active.
+        for p in paths.iter().rev() {
+            state.documents.add_file_path(p)?;
+        }
+        // A host that can't be reached shouldn't keep the editor from starting.
+        for remote in remotes.iter().rev() {
+            if let Err(err) = state.documents.add_remote(remote.clone()) {
+                state.error_log_push(LogSeverity::Error, FormatApperr::from(err).to_string());
+            }
+        }
     }
-    if let Some(parent) = paths.first().and_then(|p| p.parent()) {
+    if let Some(dir) = &dir {
+        cwd = dir.clone();
+        state.file_tree.visible = true;
+    } else if let Some(parent) = paths.first().and_then(|p| p.parent()) {
         cwd = parent.to_path_buf();
     }
 
+    state.file_picker_pending_dir = DisplayablePathBuf::from_path(cwd.clone());
+    // A directory given on the command line is shown as is.
+    // Otherwise, the tree shows the project the files are in.
+    let root = if dir.is_some() { cwd } else { find_project_root(&cwd).unwrap_or(cwd) };
+    state.file_tree_set_root(root);
+
     if let Some(mut file) = sys::open_stdin_if_redirected() {
         let doc = state.documents.add_untitled()?;
         let mut tb = doc.buffer.borrow_mut();
         tb.read_file(&mut file, None)?;
-        tb.mark_as_dirty();
-    } else if paths.is_empty() {
-        // No files were passed, and stdin is not redirected.
+        // Text piped into `edit | sort` goes back out on exit, so it isn't unsaved.
+        if !state.stdout_redirected {
+            tb.mark_as_dirty();
+        }
+    } else if paths.is_empty()
+        && remotes.is_empty()
+        && dir.is_none()
+        && !(restore && state.restore_session())
+    {
+        // No files were passed, stdin is not redirected, and there's no session to restore.
         state.documents.add_untitled()?;
     }
 
-    state.file_picker_pending_dir = DisplayablePathBuf::from_path(cwd.clone());
-    state.file_tree.nodes = build_file_tree(&cwd);
+    Ok(false)
+}
+
+// Opens the `old` and `new` file side by side, read-only and with the lines that differ marked.
+// Returns true if the application should exit early, because they're identical.
+fn open_diff(state: &mut State, old: &Path, new: &Path) -> apperr::Result<bool> {
+    let mut texts = [Vec::new(), Vec::new()];
+    for (path, text) in [old, new].into_iter().zip(&mut texts) {
+        match std::fs::read(path) {
+            Ok(contents) => *text = contents,
+            Err(err) => {
+                // The error message doesn't say which of the two files it's about.
+                sys::write_stdout(&format!("{}: ", path.display()));
+                return Err(err.into());
+            }
+        }
+    }
+    if texts[0] == texts[1] {
+        sys::write_stdout(&format!("{}\n", loc(LocId::DiffFilesIdentical)));
+        return Ok(true);
+    }
+
+    // Splitting leaves the old file in the left pane, and the new one is opened in the right one.
+    for (path, other, other_is_newer) in [(old, new, true), (new, old, false)] {
+        let doc = state.documents.add_file_path(path)?;
+        doc.buffer.borrow_mut().set_read_only(true);
+        doc.diff_against = Some((other.to_path_buf(), other_is_newer));
+        if other_is_newer {
+            state.pane_split();
+        }
+    }
     Ok(false)
 }
 
 fn print_help() {
     sys::write_stdout(concat!(
-        "Usage: edit [OPTIONS] [FILE[:LINE[:COLUMN]]]\n",
+        "Usage: edit [OPTIONS] [FILE[:LINE[:COLUMN]]]... [DIRECTORY] [-]\n",
         "Options:\n",
         "    -h, --help       Print this help message\n",
         "    -v, --version    Print the version number\n",
+        "    --no-restore     Don't reopen the files of the last session\n",
+        "    --diff OLD NEW   Compare two files side by side\n",
         "\n",
         "Arguments:\n",
-        "    FILE[:LINE[:COLUMN]]    The file to open, optionally with line and column (e.g., foo.txt:123:45)\n",
+        "    FILE[:LINE[:COLUMN]]    The files to open, optionally with line and column (e.g., foo.txt:123:45)\n",
+        "    ssh://HOST/PATH         A file on another host, e.g. ssh://user@host:22/~/notes.txt\n",
+        "    DIRECTORY               The directory to show in the file tree\n",
+        "    -                       Read the text from stdin\n",
     ));
 }
 
@@ -288,16 +457,24 @@ fn print_version() {
 
 fn draw(ctx: &mut Context, state: &mut State) {
     draw_menubar(ctx, state);
+    if state.documents.len() != 0 {
+        draw_tabbar(ctx, state);
+    }
 
     ctx.table_begin("main_layout");
-    ctx.table_set_columns(&[COORD_TYPE_SAFE_MAX, 30]);
+    ctx.table_set_columns(&[editor_width(ctx, state), SIDE_PANEL_WIDTH, SIDE_PANEL_WIDTH]);
+    ctx.inherit_focus();
     ctx.table_next_row();
+    ctx.inherit_focus();
 
     draw_editor(ctx, state);
 
     if state.file_tree.visible {
         draw_file_tree(ctx, state);
     }
+    if state.outline.visible {
+        draw_outline(ctx, state);
+    }
     ctx.table_end();
 
     draw_statusbar(ctx, state);
@@ -305,12 +482,24 @@ fn draw(ctx: &mut Context, state: &mut State) {
     if state.wants_close {
         draw_handle_wants_close(ctx, state);
     }
+    if state.wants_reload {
+        draw_handle_reload(ctx, state);
+    }
+    if state.wants_close_unpinned {
+        draw_handle_wants_close_unpinned(ctx, state);
+    }
     if state.wants_exit {
         draw_handle_wants_exit(ctx, state);
     }
     if state.wants_goto {
         draw_goto_menu(ctx, state);
     }
+    if state.wants_undo_history {
+        draw_undo_history(ctx, state);
+    }
+    if state.wants_checkpoint {
+        draw_checkpoint_dialog(ctx, state);
+    }
     if state.wants_file_picker != StateFilePicker::None {
         draw_file_picker(ctx, state);
     }
@@ -323,49 +512,102 @@ fn draw(ctx: &mut Context, state: &mut State) {
     if state.wants_go_to_file {
         draw_go_to_file(ctx, state);
     }
+    if state.wants_recent_files {
+        draw_recent_files(ctx, state);
+    }
+    if state.wants_log {
+        draw_log(ctx, state);
+    }
+    if state.wants_command_palette {
+        draw_command_palette(ctx, state);
+    }
     if state.wants_about {
         draw_dialog_about(ctx, state);
     }
+    if state.wants_keyboard_shortcuts {
+        draw_dialog_keyboard_shortcuts(ctx, state);
+    }
+    if !state.swap_recoverable.is_empty() {
+        draw_dialog_swap_recovery(ctx, state);
+    }
     if ctx.clipboard_ref().wants_host_sync() {
         draw_handle_clipboard_change(ctx, state);
     }
+    if state.wants_autosave || state.autosave_timeout().is_zero() {
+        draw_handle_autosave(ctx, state);
+    }
+    if state.wants_copy_path.is_some() {
+        draw_handle_copy_path(ctx, state);
+    }
     if state.error_log_count != 0 {
         draw_error_log(ctx, state);
     }
 
     if let Some(key) = ctx.keyboard_input() {
         // Shortcuts that are not handled as part of the textarea, etc.
-
-        if key == kbmod::CTRL | vk::E {
-            state.file_tree.visible = !state.file_tree.visible;
-        } else if key == kbmod::CTRL | vk::N {
-            draw_add_untitled_document(ctx, state);
-        } else if key == kbmod::CTRL | vk::O {
-            state.wants_file_picker = StateFilePicker::Open;
-        } else if key == kbmod::CTRL | vk::S {
-            state.wants_save = true;
-        } else if key == kbmod::CTRL_SHIFT | vk::S {
-            state.wants_file_picker = StateFilePicker::SaveAs;
-        } else if key == kbmod::CTRL | vk::W {
-            state.wants_close = true;
-        } else if key == kbmod::CTRL | vk::P {
-            state.wants_go_to_file = true;
-        } else if key == kbmod::CTRL | vk::Q {
-            state.wants_exit = true;
-        } else if key == kbmod::CTRL | vk::G {
-            state.wants_goto = true;
-        } else if key == kbmod::CTRL | vk::F && state.wants_search.kind != StateSearchKind::Disabled
-        {
-            state.wants_search.kind = StateSearchKind::Search;
-            state.wants_search.focus = true;
-        } else if key == kbmod::CTRL | vk::R && state.wants_search.kind != StateSearchKind::Disabled
-        {
-            state.wants_search.kind = StateSearchKind::Replace;
-            state.wants_search.focus = true;
-        } else if key == vk::F3 {
-            search_execute(ctx, state, SearchAction::Search);
-        } else {
-            return;
+        let search_enabled = state.wants_search.kind != StateSearchKind::Disabled;
+
+        match state.config.action(key) {
+            Some(Action::ToggleFileTree) => state.file_tree.visible = !state.file_tree.visible,
+            Some(Action::ToggleOutline) => state.outline.visible = !state.outline.visible,
+            Some(Action::SplitPane) => state.pane_split(),
+            Some(Action::ClosePane) => state.pane_close(),
+            Some(Action::FocusNextPane) => state.pane_focus_next(),
+            Some(Action::RunBuild) => state.build_run(),
+            Some(Action::CancelBuild) => state.build_cancel(),
+            Some(Action::NextTab) => state.documents.activate_tab_relative(1),
+            Some(Action::PreviousTab) => state.documents.activate_tab_relative(-1),
+            Some(Action::GoBack) => state.jump_back(),
+            Some(Action::GoForward) => state.jump_forward(),
+            Some(Action::MoveTabLeft) => state.documents.move_tab_relative(-1),
+            Some(Action::MoveTabRight) => state.documents.move_tab_relative(1),
+            Some(Action::ExpandSelection) => state.selection_expand(),
+            Some(Action::ShrinkSelection) => state.selection_shrink(),
+            Some(action @ (Action::FoldToggle | Action::FoldAll | Action::UnfoldAll)) => {
+                if let Some(doc) = state.documents.active() {
+                    let mut tb = doc.buffer.borrow_mut();
+                    match action {
+                        Action::FoldToggle => _ = tb.fold_toggle(),
+                        Action::FoldAll => tb.fold_all(),
+                        _ => tb.unfold_all(),
+                    }
+                }
+            }
+            Some(
+                action @ (Action::GotoMatchingBracket
+                | Action::DuplicateLines
+                | Action::MoveLinesUp
+                | Action::MoveLinesDown
+                | Action::ToggleLineComment
+                | Action::ToggleBlockComment
+                | Action::ToggleWordWrap),
+            ) => command_run(state, action.label()),
+            Some(Action::NextChange) => goto_change(state, true),
+            Some(Action::PreviousChange) => goto_change(state, false),
+            Some(Action::FileNew) => draw_add_untitled_document(ctx, state),
+            Some(Action::FileOpen) => state.wants_file_picker = StateFilePicker::Open,
+            Some(Action::FileSave) => state.wants_save = true,
+            Some(Action::FileSaveAs) => state.wants_file_picker = StateFilePicker::SaveAs,
+            Some(Action::FileClose) => state.wants_close = true,
+            Some(Action::GoToFile) => state.wants_go_to_file = true,
+            Some(Action::CommandPalette) => state.wants_command_palette = true,
+            Some(Action::FileExit) => state.wants_exit = true,
+            Some(Action::FileGoto) => state.wants_goto = true,
+            Some(Action::KeyboardShortcuts) => state.wants_keyboard_shortcuts = true,
+            Some(Action::Find) if search_enabled => {
+                state.wants_search.kind = StateSearchKind::Search;
+                state.wants_search.focus = true;
+            }
+            Some(Action::Replace) if search_enabled => {
+                state.wants_search.kind = StateSearchKind::Replace;
+                state.wants_search.focus = true;
+            }
+            Some(Action::FindNext) => {
+                state.search_origin = None;
+                state.jump_push();
+                search_execute(ctx, state, SearchAction::Search);
+            }
+            _ => return,
         }
 
         // All of the above shortcuts happen to require a rerender.
@@ -374,7 +616,28 @@ fn draw(ctx: &mut Context, state: &mut State) {
     }
 }
 
-fn draw_handle_wants_exit(_ctx: &mut Context, state: &mut State) {
+fn draw_handle_wants_exit(ctx: &mut Context, state: &mut State) {
+    // Save the session before the loop below closes the documents one by one.
+    if !state.exit_session_saved {
+        // There's no good way to report an error while exiting, and a
+        // missing session isn't worth keeping the user from quitting.
+        _ = state.save_session();
+        state.exit_session_saved = true;
+
+        // Take the text for a redirected stdout now, too. Writing the active document
+        // out marks it as saved, so that the loop below closes it without asking.
+        if state.stdout_redirected
+            && let Some(doc) = state.documents.active()
+        {
+            let mut text = Vec::new();
+            let res = doc.buffer.borrow_mut().write_file(&mut text);
+            match res {
+                Ok(()) => state.exit_stdout = Some(text),
+                Err(err) => error_log_add(ctx, state, err),
+            }
+        }
+    }
+
     while let Some(doc) = state.documents.active() {
         if doc.buffer.borrow().is_dirty() {
             state.wants_close = true;
@@ -388,6 +651,20 @@ fn draw_handle_wants_exit(_ctx: &mut Context, state: &mut State) {
     }
 }
 
+/// Closes the documents that aren't pinned one by one, asking about unsaved changes.
+fn draw_handle_wants_close_unpinned(ctx: &mut Context, state: &mut State) {
+    while state.documents.update_active(|doc| !doc.pinned) {
+        if state.documents.active().is_some_and(|doc| doc.buffer.borrow().is_dirty()) {
+            state.wants_close = true;
+            return;
+        }
+        state.documents.remove_active();
+    }
+
+    state.wants_close_unpinned = false;
+    ctx.needs_rerender();
+}
+
 fn write_terminal_title(output: &mut ArenaString, state: &mut State) {
     let (filename, dirty) = state
         .documents
@@ -417,6 +694,13 @@ fn write_terminal_title(output: &mut ArenaString, state: &mut State) {
 const LARGE_CLIPBOARD_THRESHOLD: usize = 128 * KIBI;
 
 fn draw_handle_clipboard_change(ctx: &mut Context, state: &mut State) {
+    // The system clipboard takes any amount of text, unlike the terminal with OSC 52,
+    // which is only needed over SSH or without a display server.
+    if sys::clipboard_write(ctx.clipboard_ref().read()) {
+        ctx.clipboard_mut().mark_as_synchronized();
+        return;
+    }
+
     let data_len = ctx.clipboard_ref().read().len();
 
     if state.osc_clipboard_always_send || data_len < LARGE_CLIPBOARD_THRESHOLD {
@@ -533,7 +817,7 @@ impl Drop for RestoreModes {
         // Same as in the beginning but in the reverse order.
         // It also includes DECSCUSR 0 to reset the cursor style and DECTCEM to show the cursor.
         // We specifically don't reset mode 1036, because most applications expect it to be set nowadays.
-        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1006;2004l\x1b[?1049l");
+        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1004;1006;2004l\x1b[?1049l");
     }
 }
 
@@ -543,10 +827,11 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         //   I put the ASB switch in the beginning, just in case the terminal performs
         //   some additional state tracking beyond the modes we enable/disable.
         // 1002: Cell Motion Mouse Tracking
+        // 1004: Focus In/Out Events
         // 1006: SGR Mouse Mode
         // 2004: Bracketed Paste Mode
         // 1036: Xterm: "meta sends escape" (Alt keypresses should be encoded with ESC + char)
-        "\x1b[?1049h\x1b[?1002;1006;2004h\x1b[?1036h",
+        "\x1b[?1049h\x1b[?1002;1004;1006;2004h\x1b[?1036h",
         // OSC 4 color table requests for indices 0 through 15 (base colors).
         "\x1b]4;0;?;1;?;2;?;3;?;4;?;5;?;6;?;7;?\x07",
         "\x1b]4;8;?;9;?;10;?;11;?;12;?;13;?;14;?;15;?\x07",
@@ -650,9 +935,23 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         state.documents.reflow_all();
     }
 
-    if color_responses == indexed_colors.len() {
-        tui.setup_indexed_colors(indexed_colors);
+    // Colors from the config take precedence over the terminal's. If the terminal
+    // didn't report all of its colors, they're applied to the default theme instead.
+    if color_responses != indexed_colors.len() {
+        indexed_colors = framebuffer::DEFAULT_THEME;
     }
+    for (color, theme) in indexed_colors.iter_mut().zip(state.config.theme) {
+        if let Some(theme) = theme {
+            *color = theme;
+        }
+    }
+    tui.setup_indexed_colors(indexed_colors);
+
+    // There's no reliable way to query for 24-bit color support, but terminals that have it
+    // conventionally announce it via COLORTERM. The Windows console has supported it since 2016.
+    let truecolor = cfg!(windows)
+        || std::env::var_os("COLORTERM").is_some_and(|v| v == "truecolor" || v == "24bit");
+    tui.setup_truecolor(truecolor);
 
     RestoreModes
 }
@@ -0,0 +1,137 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The editor area can be split into several panes, side by side.
+//!
+//! The focused pane always shows the active document, so that everything else
+//! (the statusbar, search, saving, ...) keeps working on "the" document.
+//! The other panes remember which document they show and where their cursor was.
+//! Moving the focus to one of them makes its document the active one again.
+
+use std::rc::Rc;
+
+use edit::buffer::RcTextBuffer;
+use edit::helpers::*;
+
+use crate::state::State;
+
+pub struct Pane {
+    /// Identifies the pane's nodes in the UI tree, so that each pane keeps its scroll position.
+    pub id: u64,
+    /// The document shown in the pane. Stale for the focused pane, which shows the active document.
+    pub buffer: Option<RcTextBuffer>,
+    /// Where the cursor was when the pane lost the focus.
+    pub cursor: Point,
+}
+
+pub struct Panes {
+    /// From left to right. Never empty.
+    pub list: Vec<Pane>,
+    pub focused: usize,
+    /// Set when the focused pane changed, so that its text area can take the input focus.
+    pub focus_changed: bool,
+    next_id: u64,
+}
+
+impl Default for Panes {
+    fn default() -> Self {
+        Self {
+            list: vec![Pane { id: 0, buffer: None, cursor: Default::default() }],
+            focused: 0,
+            focus_changed: false,
+            next_id: 1,
+        }
+    }
+}
+
+impl State {
+    /// Splits the focused pane in two. The new pane opens to the right,
+    /// shows the same document and takes the focus.
+    pub fn pane_split(&mut self) {
+        let Some(doc) = self.documents.active() else {
+            return;
+        };
+
+        let mut tb = doc.buffer.borrow_mut();
+        let cursor = tb.cursor_logical_pos();
+        // The new pane starts out scrolled to the top. This scrolls it to the cursor instead.
+        tb.make_cursor_visible();
+
+        let focused = &mut self.panes.list[self.panes.focused];
+        focused.buffer = Some(doc.buffer.clone());
+        focused.cursor = cursor;
+
+        let pane = Pane { id: self.panes.next_id, buffer: Some(doc.buffer.clone()), cursor };
+        self.panes.next_id += 1;
+        self.panes.focused += 1;
+        self.panes.list.insert(self.panes.focused, pane);
+        self.panes.focus_changed = true;
+    }
+
+    /// Closes the focused pane. Its document stays open,
+    /// and the pane to the left (or right) of it takes the focus.
+    pub fn pane_close(&mut self) {
+        if self.panes.list.len() <= 1 {
+            return;
+        }
+        self.panes.list.remove(self.panes.focused);
+        self.pane_activate(self.panes.focused.saturating_sub(1));
+    }
+
+    /// Moves the focus to the pane at `index`.
+    pub fn pane_focus(&mut self, index: usize) {
+        if index == self.panes.focused || index >= self.panes.list.len() {
+            return;
+        }
+
+        if let Some(doc) = self.documents.active() {
+            let pane = &mut self.panes.list[self.panes.focused];
+            pane.cursor = doc.buffer.borrow().cursor_logical_pos();
+            pane.buffer = Some(doc.buffer.clone());
+        }
+
+        self.pane_activate(index);
+    }
+
+    /// Moves the focus to the pane to the right of the focused one, wrapping around.
+    pub fn pane_focus_next(&mut self) {
+        self.pane_focus((self.panes.focused + 1) % self.panes.list.len());
+    }
+
+    /// Closes the panes whose document was closed. The focused pane is left alone,
+    /// since it shows whichever document became active instead.
+    pub fn panes_prune(&mut self) {
+        let documents = &self.documents;
+        let focused = self.panes.focused;
+        let mut index = 0;
+        let mut removed_before_focused = 0;
+
+        self.panes.list.retain(|pane| {
+            let keep = index == focused
+                || pane.buffer.as_ref().is_none_or(|buffer| {
+                    documents.iter().any(|doc| Rc::ptr_eq(&doc.buffer, buffer))
+                });
+            if !keep && index < focused {
+                removed_before_focused += 1;
+            }
+            index += 1;
+            keep
+        });
+
+        self.panes.focused -= removed_before_focused;
+    }
+
+    /// Makes the pane at `index` the focused one and its document the active one.
+    /// Unlike [`State::pane_focus`] it doesn't save the state of the previously focused pane.
+    fn pane_activate(&mut self, index: usize) {
+        self.panes.focused = index;
+        self.panes.focus_changed = true;
+
+        let pane = &self.panes.list[index];
+        if let Some(buffer) = &pane.buffer
+            && self.documents.update_active(|doc| Rc::ptr_eq(&doc.buffer, buffer))
+        {
+            buffer.borrow_mut().cursor_move_to_logical(pane.cursor);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Remembers where the cursor was and how far the view was scrolled in the files
+//! that were last saved or closed, so that reopening one of them puts both back.
+//! It's kept in `positions.json` in the config directory, most recent first.
+
+use std::path::{Path, PathBuf};
+
+use edit::apperr;
+use edit::helpers::*;
+use edit::json::Value;
+
+use crate::documents::Document;
+use crate::store;
+
+/// How many files the store remembers.
+const POSITIONS_MAX: usize = 200;
+
+/// Where the cursor and the view were in a file.
+#[derive(Clone, Copy)]
+pub struct Position {
+    pub cursor: Point,
+    /// The scroll offset of the view, as returned by [`edit::buffer::TextBuffer::scroll_offset()`].
+    pub scroll: Point,
+}
+
+const POSITIONS_STORE: &str = "positions.json";
+
+fn load() -> Vec<(PathBuf, Position)> {
+    let Some(positions) = store::path(POSITIONS_STORE).and_then(|path| store::load(&path)) else {
+        return Vec::new();
+    };
+    let Some(entries) = positions.as_array() else {
+        return Vec::new();
+    };
+
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.get("path").and_then(Value::as_str)?;
+            let line = entry.get("line").and_then(Value::as_i64).unwrap_or(1);
+            let column = entry.get("column").and_then(Value::as_i64).unwrap_or(1);
+            let scroll_x = entry.get("scroll_x").and_then(Value::as_i64).unwrap_or(0);
+            let scroll_y = entry.get("scroll_y").and_then(Value::as_i64).unwrap_or(0);
+            Some((
+                PathBuf::from(path),
+                Position {
+                    cursor: Point {
+                        x: (column - 1).max(0) as CoordType,
+                        y: (line - 1).max(0) as CoordType,
+                    },
+                    scroll: Point {
+                        x: scroll_x.max(0) as CoordType,
+                        y: scroll_y.max(0) as CoordType,
+                    },
+                },
+            ))
+        })
+        .collect()
+}
+
+fn save(entries: &[(PathBuf, Position)]) -> apperr::Result<()> {
+    let Some(path) = store::path(POSITIONS_STORE) else {
+        return Ok(());
+    };
+
+    let positions = Value::Array(
+        entries
+            .iter()
+            .filter_map(|(path, pos)| {
+                Some(Value::Object(vec![
+                    ("path".into(), store::path_value(path)?),
+                    ("line".into(), (pos.cursor.y as i64 + 1).into()),
+                    ("column".into(), (pos.cursor.x as i64 + 1).into()),
+                    ("scroll_x".into(), (pos.scroll.x as i64).into()),
+                    ("scroll_y".into(), (pos.scroll.y as i64).into()),
+                ]))
+            })
+            .collect(),
+    );
+
+    store::save(&path, &positions)
+}
+
+/// Returns where the cursor and the view were when the file at `path` was last saved or closed.
+pub fn recall(path: &Path) -> Option<Position> {
+    load().into_iter().find(|(p, _)| p == path).map(|(_, pos)| pos)
+}
+
+impl Document {
+    /// Stores the cursor and scroll position of the document. Untitled documents are skipped.
+    pub fn position_remember(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let tb = self.buffer.borrow();
+        let pos = Position { cursor: tb.cursor_logical_pos(), scroll: tb.scroll_offset() };
+        drop(tb);
+
+        let mut entries = load();
+        // Files that were deleted or moved since are of no use anymore.
+        entries.retain(|(p, _)| p != path && p.is_file());
+        entries.insert(0, (path.clone(), pos));
+        entries.truncate(POSITIONS_MAX);
+
+        _ = save(&entries);
+    }
+}
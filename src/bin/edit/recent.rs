@@ -0,0 +1,65 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The list of recently opened files, most recent first.
+//! It's kept in `recent.json` in the config directory, so that it outlives the session.
+
+use std::path::PathBuf;
+
+use edit::apperr;
+use edit::json::Value;
+
+use crate::state::State;
+use crate::store;
+
+/// How many files the list remembers.
+const RECENT_FILES_MAX: usize = 30;
+
+const RECENT_STORE: &str = "recent.json";
+
+/// Reads the list that was written by a previous instance of the editor.
+pub fn load() -> Vec<PathBuf> {
+    let Some(recent) = store::path(RECENT_STORE).and_then(|path| store::load(&path)) else {
+        return Vec::new();
+    };
+
+    recent.as_array().map_or_else(Vec::new, |paths| {
+        paths.iter().filter_map(Value::as_str).map(PathBuf::from).take(RECENT_FILES_MAX).collect()
+    })
+}
+
+impl State {
+    /// Moves the path of the active document to the front of the list.
+    /// Opening a file, or saving it under a new name, makes it the active document,
+    /// so calling this once per frame is enough to catch both.
+    pub fn recent_files_update(&mut self) {
+        // The local copy of a file on another host isn't worth remembering.
+        let Some(path) = self
+            .documents
+            .active()
+            .filter(|doc| doc.remote.is_none())
+            .and_then(|doc| doc.path.as_ref())
+        else {
+            return;
+        };
+        if self.recent_files.first() == Some(path) {
+            return;
+        }
+
+        let path = path.clone();
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(RECENT_FILES_MAX);
+
+        _ = self.recent_files_save();
+    }
+
+    fn recent_files_save(&self) -> apperr::Result<()> {
+        let Some(path) = store::path(RECENT_STORE) else {
+            return Ok(());
+        };
+        let recent =
+            Value::Array(self.recent_files.iter().filter_map(|p| store::path_value(p)).collect());
+        store::save(&path, &recent)
+    }
+}
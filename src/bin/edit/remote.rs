@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Files on other hosts, opened as `ssh://[user@]host[:port]/path`, or `ssh://host/~/path`
+//! for a path in the home directory there. `sftp://` is accepted as well.
+//!
+//! The file is copied to a local file in the cache directory, which is what the document
+//! reads and writes, and it's transferred with the `ssh` command line tool. That way the
+//! user's `~/.ssh/config`, keys and agent apply as usual. Since the terminal belongs to
+//! the editor, ssh can't ask for a password, so logging in must work without one.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use edit::{apperr, sys};
+
+/// A file on another host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remote {
+    /// The host, possibly with a `user@` in front.
+    pub host: String,
+    pub port: Option<u16>,
+    /// The path on the host. It's absolute or starts with `~/`.
+    pub path: String,
+}
+
+impl Remote {
+    /// Parses an `ssh://` or `sftp://` URL. Returns `None` if `location` isn't one
+    /// or doesn't name a file.
+    pub fn parse(location: &str) -> Option<Self> {
+        let rest = location.strip_prefix("ssh://").or_else(|| location.strip_prefix("sftp://"))?;
+        let (authority, path) = rest.split_at(rest.find('/')?);
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse().ok()?)),
+            None => (authority, None),
+        };
+        // `/~/notes.txt` is relative to the home directory.
+        let path = if path.starts_with("/~/") { &path[1..] } else { path };
+
+        if host.is_empty() || host.starts_with('-') || path.ends_with('/') {
+            return None;
+        }
+        Some(Self { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    /// Returns the URL of the file, the way [`Remote::parse`] takes it.
+    pub fn url(&self) -> String {
+        let port = self.port.map(|port| format!(":{port}")).unwrap_or_default();
+        let slash = if self.path.starts_with('~') { "/" } else { "" };
+        format!("ssh://{}{port}{slash}{}", self.host, self.path)
+    }
+
+    /// Returns the URL of the directory that the file is in.
+    pub fn dir_url(&self) -> String {
+        let mut url = self.url();
+        url.truncate(url.rfind('/').unwrap_or(url.len()));
+        url
+    }
+
+    /// Returns where the local copy of the file goes. It keeps the file name,
+    /// so that the language is detected as usual. The process ID keeps two
+    /// instances of the editor from sharing, and then removing, the same copy.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        self.url().hash(&mut hasher);
+        let name = self.path.rsplit('/').next()?;
+        let dir = format!("{:016x}-{}", hasher.finish(), process::id());
+        Some(sys::cache_dir()?.join("remote").join(dir).join(name))
+    }
+
+    /// Removes the local copy made by [`Remote::download`], once the document is closed.
+    pub fn remove_local_copy(local: &Path) {
+        _ = fs::remove_file(local);
+        if let Some(dir) = local.parent() {
+            _ = fs::remove_dir(dir);
+        }
+    }
+
+    /// Copies the file to `local`. A file that doesn't exist on the host yet comes out empty,
+    /// and is created when it's uploaded. The download goes to a file next to `local`
+    /// first, so that a failed one leaves the previous copy as it was.
+    pub fn download(&self, local: &Path) -> apperr::Result<()> {
+        if let Some(dir) = local.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp = OsString::from(local);
+        temp.push(".download");
+        let temp = PathBuf::from(temp);
+
+        let file = File::create(&temp)?;
+        let path = self.quoted_path("");
+        let command = format!("test ! -e {path} || cat -- {path}");
+        if let Err(err) = self.run(&command, Stdio::null(), file.into()) {
+            _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+        fs::rename(&temp, local)?;
+        Ok(())
+    }
+
+    /// Copies `local` to the file on the host. It's transferred to a file next to it first,
+    /// so that a dropped connection doesn't leave half a file behind. Only then is it copied
+    /// over the file in place, which keeps its permissions, owner and any symlinks to it.
+    pub fn upload(&self, local: &Path) -> apperr::Result<()> {
+        let file = File::open(local)?;
+        let (path, temp) = (self.quoted_path(""), self.quoted_path(".edit-upload"));
+        let command =
+            format!("cat > {temp} && cat -- {temp} > {path}; s=$?; rm -f -- {temp}; exit $s");
+        self.run(&command, file.into(), Stdio::null())
+    }
+
+    /// Runs the shell `command` on the host. Anything ssh prints would end up
+    /// in the middle of the editor, so it only gets to tell whether it succeeded.
+    fn run(&self, command: &str, stdin: Stdio, stdout: Stdio) -> apperr::Result<()> {
+        let mut ssh = Command::new("ssh");
+        ssh.args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"]);
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        let status = ssh
+            .arg("--")
+            .arg(&self.host)
+            .arg(command)
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(Stdio::null())
+            .status()?;
+        if status.success() { Ok(()) } else { Err(apperr::APP_REMOTE_FAILED) }
+    }
+
+    /// Returns the path with `suffix` appended, quoted for the shell on the host.
+    /// A leading `~/` is left unquoted, so that the shell expands it.
+    fn quoted_path(&self, suffix: &str) -> String {
+        let (home, path) = match self.path.strip_prefix("~/") {
+            Some(path) => ("~/", path),
+            None => ("", self.path.as_str()),
+        };
+        format!("{home}'{}{suffix}'", path.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(host: &str, port: Option<u16>, path: &str) -> Option<Remote> {
+        Some(Remote { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Remote::parse("ssh://host/etc/hosts"), remote("host", None, "/etc/hosts"));
+        assert_eq!(Remote::parse("sftp://me@host:2222/a"), remote("me@host", Some(2222), "/a"));
+        assert_eq!(Remote::parse("ssh://host/~/notes.txt"), remote("host", None, "~/notes.txt"));
+        assert_eq!(Remote::parse("ssh://host/dir/"), None);
+        assert_eq!(Remote::parse("ssh://host"), None);
+        assert_eq!(Remote::parse("ssh://host:x/a"), None);
+        assert_eq!(Remote::parse("ssh://-oProxyCommand=x/a"), None);
+        assert_eq!(Remote::parse("/ssh://host/a"), None);
+    }
+
+    #[test]
+    fn test_url() {
+        for url in ["ssh://host/etc/hosts", "ssh://me@host:2222/a", "ssh://host/~/notes.txt"] {
+            assert_eq!(Remote::parse(url).unwrap().url(), url);
+        }
+        assert_eq!(Remote::parse("ssh://host/~/a").unwrap().dir_url(), "ssh://host/~");
+    }
+
+    #[test]
+    fn test_quoted_path() {
+        assert_eq!(remote("h", None, "/a b/it's").unwrap().quoted_path(""), r"'/a b/it'\''s'");
+        assert_eq!(remote("h", None, "~/$x").unwrap().quoted_path(".tmp"), "~/'$x.tmp'");
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Saving and restoring the session, that is, the open files,
+//! their cursor and scroll positions and the state of the file tree.
+
+use std::path::{Path, PathBuf};
+
+use edit::apperr;
+use edit::helpers::*;
+use edit::json::Value;
+
+use crate::draw_filetree::{collect_expanded, expand_path};
+use crate::state::*;
+use crate::store;
+
+const SESSION_STORE: &str = "session.json";
+
+impl State {
+    /// Writes the open documents and the file tree state to the session file.
+    /// Untitled documents are skipped, because there's nothing to reopen. So are files on
+    /// other hosts, since reopening them would connect to the hosts at startup.
+    pub fn save_session(&self) -> apperr::Result<()> {
+        let Some(path) = store::path(SESSION_STORE) else {
+            return Ok(());
+        };
+
+        // The document list is ordered from most to least recently used.
+        // The first entry is thus the active document.
+        let documents = self
+            .documents
+            .iter()
+            .filter(|doc| doc.remote.is_none())
+            .filter_map(|doc| {
+                let path = store::path_value(doc.path.as_ref()?)?;
+                let tb = doc.buffer.borrow();
+                let pos = tb.cursor_logical_pos();
+                let scroll = tb.scroll_offset();
+                Some(Value::Object(vec![
+                    ("path".into(), path),
+                    ("line".into(), (pos.y as i64 + 1).into()),
+                    ("column".into(), (pos.x as i64 + 1).into()),
+                    ("scroll_x".into(), (scroll.x as i64).into()),
+                    ("scroll_y".into(), (scroll.y as i64).into()),
+                    ("pinned".into(), doc.pinned.into()),
+                ]))
+            })
+            .collect();
+
+        let paths = |paths: &[PathBuf]| -> Value {
+            Value::Array(paths.iter().filter_map(|path| store::path_value(path)).collect())
+        };
+        let mut expanded = Vec::new();
+        collect_expanded(&self.file_tree.nodes, &mut expanded);
+
+        let session = Value::Object(vec![
+            ("documents".into(), Value::Array(documents)),
+            (
+                "file_tree".into(),
+                Value::Object(vec![
+                    ("visible".into(), self.file_tree.visible.into()),
+                    ("roots".into(), paths(&self.file_tree.roots)),
+                    ("expanded".into(), paths(&expanded)),
+                ]),
+            ),
+        ]);
+
+        store::save(&path, &session)
+    }
+
+    /// Reopens the documents of the last session and restores the file tree state.
+    /// Files that no longer exist are skipped. Returns true if any document was opened.
+    ///
+    /// The file tree must have been built already, so that its expansion state can be applied.
+    pub fn restore_session(&mut self) -> bool {
+        let Some(session) = store::path(SESSION_STORE).and_then(|path| store::load(&path)) else {
+            return false;
+        };
+
+        let mut restored = false;
+
+        if let Some(documents) = session.get("documents").and_then(Value::as_array) {
+            // Open them in reverse, so that the previously active document ends up in front.
+            for entry in documents.iter().rev() {
+                let Some(path) = entry.get("path").and_then(Value::as_str).map(Path::new) else {
+                    continue;
+                };
+                if !path.is_file() {
+                    continue;
+                }
+                let Ok(doc) = self.documents.add_file_path(path) else {
+                    continue;
+                };
+                doc.pinned = entry.get("pinned").and_then(Value::as_bool).unwrap_or(false);
+
+                let line = entry.get("line").and_then(Value::as_i64).unwrap_or(1);
+                let column = entry.get("column").and_then(Value::as_i64).unwrap_or(1);
+                let scroll_x = entry.get("scroll_x").and_then(Value::as_i64).unwrap_or(0);
+                let scroll_y = entry.get("scroll_y").and_then(Value::as_i64).unwrap_or(0);
+                let mut tb = doc.buffer.borrow_mut();
+                tb.cursor_move_to_logical(Point {
+                    x: (column - 1).max(0) as CoordType,
+                    y: (line - 1).max(0) as CoordType,
+                });
+                tb.scroll_to(Point {
+                    x: scroll_x.max(0) as CoordType,
+                    y: scroll_y.max(0) as CoordType,
+                });
+                // Only scrolls if the terminal got smaller and the cursor would be off-screen.
+                tb.make_cursor_visible();
+                restored = true;
+            }
+        }
+
+        if let Some(file_tree) = session.get("file_tree") {
+            self.file_tree.visible =
+                file_tree.get("visible").and_then(Value::as_bool).unwrap_or(false);
+
+            let roots: Vec<_> = (file_tree.get("roots").and_then(Value::as_array))
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(PathBuf::from)
+                .filter(|path| path.is_dir())
+                .collect();
+            if !roots.is_empty() {
+                self.file_tree.roots = roots;
+                self.file_tree_rebuild();
+            }
+
+            if let Some(expanded) = file_tree.get("expanded").and_then(Value::as_array) {
+                for path in expanded.iter().filter_map(Value::as_str) {
+                    expand_path(&mut self.file_tree.nodes, Path::new(path));
+                }
+            }
+        }
+
+        restored
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Snippets from the `[snippets]` section of the config: Typing a prefix like `fn` and
+//! pressing Tab inserts the snippet. Tab and Shift+Tab then move between its tabstops,
+//! until the last one is reached or Escape is pressed.
+
+use edit::input::{kbmod, vk};
+use edit::tui::Context;
+
+use crate::state::State;
+
+impl State {
+    /// Replaces the word before the cursor with the snippet it's the prefix of, if any.
+    pub fn snippet_expand(&mut self) -> bool {
+        let Some(doc) = self.documents.active() else {
+            return false;
+        };
+        let mut tb = doc.buffer.borrow_mut();
+        if tb.has_selection() || tb.is_read_only() {
+            return false;
+        }
+
+        let prefix = tb.word_before_cursor();
+        let Some(snippet) = self.config.snippet(doc.language, &prefix) else {
+            return false;
+        };
+        let end = tb.cursor_offset();
+        tb.snippet_insert(end - prefix.len()..end, snippet);
+        true
+    }
+
+    /// Moves to the next or previous tabstop of the snippet in the active document.
+    pub fn snippet_jump(&mut self, forward: bool) -> bool {
+        self.documents.active().is_some_and(|doc| doc.buffer.borrow_mut().snippet_jump(forward))
+    }
+
+    /// Stops moving between the tabstops of the snippet in the active document.
+    pub fn snippet_exit(&mut self) {
+        if let Some(doc) = self.documents.active() {
+            doc.buffer.borrow_mut().snippet_exit();
+        }
+    }
+}
+
+/// Handles the keys for snippets, before the editor gets to indent with Tab.
+pub fn handle_snippet_keys(ctx: &mut Context, state: &mut State) {
+    let Some(key) = ctx.keyboard_input() else {
+        return;
+    };
+
+    let handled = if key == vk::TAB {
+        state.snippet_jump(true) || state.snippet_expand()
+    } else if key == kbmod::SHIFT | vk::TAB {
+        state.snippet_jump(false)
+    } else {
+        // Escape also clears the selection, so it's left to the editor as well.
+        if key == vk::ESCAPE {
+            state.snippet_exit();
+        }
+        false
+    };
+
+    if handled {
+        ctx.set_input_consumed();
+        ctx.needs_rerender();
+    }
+}
@@ -2,19 +2,28 @@
 // Licensed under the MIT License.
 
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::ffi::{OsStr, OsString};
 use std::mem;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
 
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::syntax;
 use edit::tui::*;
-use edit::{apperr, buffer, icu, sys};
+use edit::{apperr, buffer, icu, path, sys};
 
+use crate::config::Config;
 use crate::documents::DocumentManager;
 use crate::draw_filetree::FileTreeNode;
+use crate::expand_selection::ExpandedSelections;
+use crate::jumps::Jumps;
 use crate::localization::*;
+use crate::panes::Panes;
+use crate::{build, recent, swap};
 
 #[repr(transparent)]
 pub struct FormatApperr(apperr::Error);
@@ -29,6 +38,8 @@ impl std::fmt::Display for FormatApperr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
             apperr::APP_ICU_MISSING => f.write_str(loc(LocId::ErrorIcuMissing)),
+            apperr::APP_BINARY_FILE => f.write_str(loc(LocId::ErrorBinaryFile)),
+            apperr::APP_REMOTE_FAILED => f.write_str(loc(LocId::ErrorRemoteFailed)),
             apperr::Error::App(code) => write!(f, "Unknown app error code: {code}"),
             apperr::Error::Icu(code) => icu::apperr_format(f, code),
             apperr::Error::Sys(code) => sys::apperr_format(f, code),
@@ -42,7 +53,6 @@ pub struct DisplayablePathBuf {
 }
 
 impl DisplayablePathBuf {
-    #[allow(dead_code, reason = "only used on Windows")]
     pub fn from_string(string: String) -> Self {
         let str = Cow::Borrowed(string.as_str());
         let str = unsafe { mem::transmute::<Cow<'_, str>, Cow<'_, str>>(str) };
@@ -106,12 +116,127 @@ pub enum StateSearchKind {
     Replace,
 }
 
+/// The outcome of the last search, which decides how the search bar looks.
+#[derive(PartialEq, Eq)]
+pub enum SearchStatus {
+    Found,
+    NoMatches,
+    /// The needle isn't a valid regular expression. Contains the error message.
+    Invalid(String),
+}
+
+/// The part of a document that "In Selection" searches and replacements are confined to.
+pub struct SearchScope {
+    /// The buffer `range` was captured from.
+    buffer: Weak<buffer::TextBufferCell>,
+    range: Range<usize>,
+    /// The buffer generation at the time `range` was captured.
+    /// If the buffer changes, the range is considered stale.
+    generation: u32,
+}
+
+impl SearchScope {
+    pub fn new(buffer: &buffer::RcTextBuffer, range: Range<usize>, generation: u32) -> Self {
+        Self { buffer: Rc::downgrade(buffer), range, generation }
+    }
+
+    /// Returns the range, unless it's empty, belongs to another buffer,
+    /// or `buffer` was edited since it was captured.
+    pub fn range_in(&self, buffer: &buffer::RcTextBuffer) -> Option<Range<usize>> {
+        let valid = !self.range.is_empty()
+            && self.buffer.as_ptr() == Rc::as_ptr(buffer)
+            && self.generation == buffer.borrow().generation();
+        valid.then(|| self.range.clone())
+    }
+}
+
+/// A bounded most-recently-used list of search needles or replacements.
+/// Entries are ordered from newest to oldest and never contain duplicates.
+#[derive(Default)]
+pub struct SearchHistory {
+    entries: Vec<String>,
+    // The entry currently shown in the input field, if the user is browsing the history.
+    index: Option<usize>,
+    // What the user had typed before they started browsing the history.
+    draft: String,
+}
+
+impl SearchHistory {
+    const MAX_ENTRIES: usize = 32;
+
+    /// Adds `entry` to the front of the history, removing any previous occurrence.
+    pub fn push(&mut self, entry: &str) {
+        self.index = None;
+
+        if entry.is_empty() {
+            return;
+        }
+
+        if let Some(pos) = self.entries.iter().position(|e| e == entry) {
+            let e = self.entries.remove(pos);
+            self.entries.insert(0, e);
+        } else {
+            self.entries.truncate(Self::MAX_ENTRIES - 1);
+            self.entries.insert(0, entry.to_string());
+        }
+    }
+
+    /// Replaces `text` with the next older entry.
+    /// Returns true if `text` was changed.
+    pub fn recall_older(&mut self, text: &mut String) -> bool {
+        let next = self.index.map_or(0, |i| i + 1);
+        if next >= self.entries.len() {
+            return false;
+        }
+        if self.index.is_none() {
+            self.draft = mem::take(text);
+        }
+        self.index = Some(next);
+        text.clone_from(&self.entries[next]);
+        true
+    }
+
+    /// Replaces `text` with the next newer entry, or with what the
+    /// user had typed before browsing, once the front is reached.
+    /// Returns true if `text` was changed.
+    pub fn recall_newer(&mut self, text: &mut String) -> bool {
+        match self.index {
+            None => false,
+            Some(0) => {
+                self.index = None;
+                *text = mem::take(&mut self.draft);
+                true
+            }
+            Some(i) => {
+                self.index = Some(i - 1);
+                text.clone_from(&self.entries[i - 1]);
+                true
+            }
+        }
+    }
+
+    /// Stops browsing, e.g. because the user edited the recalled text.
+    pub fn reset(&mut self) {
+        self.index = None;
+    }
+}
+
+/// What of the active document's path [`State::wants_copy_path`] copies to the clipboard.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CopyPath {
+    Absolute,
+    /// Relative to the file tree root or project it's in.
+    Relative,
+    FileName,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum StateFilePicker {
     None,
     Open,
     SaveAs,
 
+    OpenShown,   // Transitioned from Open
     SaveAsShown, // Transitioned from SaveAs
 }
 
@@ -130,6 +255,8 @@ pub struct OscTitleFileStatus {
 
 pub struct FileTree {
     pub visible: bool,
+    /// The directories whose contents are shown. Never empty after startup.
+    pub roots: Vec<PathBuf>,
     pub nodes: Vec<FileTreeNode>,
     pub selected_node: Option<usize>,
 }
@@ -138,24 +265,80 @@ impl Default for FileTree {
     fn default() -> Self {
         Self {
             visible: false,
+            roots: Vec::new(),
             nodes: vec![],
             selected_node: None,
         }
     }
 }
 
+#[derive(Default)]
+pub struct Outline {
+    pub visible: bool,
+    pub selected: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogSeverity {
+    Info,
+    Error,
+}
+
+pub struct LogEntry {
+    /// The time since the editor started.
+    pub time: Duration,
+    pub severity: LogSeverity,
+    pub message: String,
+}
+
+/// Every error and notice of the session, oldest first, so that they can be reviewed
+/// after the error dialog was dismissed. The oldest entries are dropped past [`Log::MAX`].
+pub struct Log {
+    pub entries: VecDeque<LogEntry>,
+    started: Instant,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self { entries: VecDeque::new(), started: Instant::now() }
+    }
+}
+
+impl Log {
+    const MAX: usize = 1000;
+
+    pub fn push(&mut self, severity: LogSeverity, message: String) {
+        if self.entries.len() >= Self::MAX {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry { time: self.started.elapsed(), severity, message });
+    }
+}
+
 pub struct State {
     pub menubar_color_bg: u32,
     pub menubar_color_fg: u32,
 
+    pub config: Config,
     pub documents: DocumentManager,
-    pub syntax: syntax::Syntax,
+    pub syntax: syntax::SyntaxWorker,
     pub file_tree: FileTree,
+    pub outline: Outline,
+    pub build: build::Build,
+    pub panes: Panes,
+    pub jumps: Jumps,
+    pub expanded_selections: ExpandedSelections,
 
     // A ring buffer of the last 10 errors.
     pub error_log: [String; 10],
     pub error_log_index: usize,
     pub error_log_count: usize,
+    pub log: Log,
+    pub wants_log: bool,
+    /// Whether the lines that changed since the last commit or save are marked in the margin.
+    pub show_changes: bool,
+    /// Whether who last changed each line, and when, is shown in the margin.
+    pub show_blame: bool,
 
     pub wants_file_picker: StateFilePicker,
     pub file_picker_pending_dir: DisplayablePathBuf,
@@ -169,7 +352,13 @@ pub struct State {
     pub search_needle: String,
     pub search_replacement: String,
     pub search_options: buffer::SearchOptions,
-    pub search_success: bool,
+    pub search_status: SearchStatus,
+    pub search_in_selection: bool,
+    pub search_scope: Option<SearchScope>,
+    /// The cursor and scroll position before the search started moving the cursor around.
+    pub search_origin: Option<(Point, Point)>,
+    pub search_needle_history: SearchHistory,
+    pub search_replacement_history: SearchHistory,
 
     pub wants_encoding_picker: bool,
     pub wants_encoding_change: StateEncodingChange,
@@ -180,11 +369,33 @@ pub struct State {
     pub wants_statusbar_focus: bool,
     pub wants_indentation_picker: bool,
     pub wants_go_to_file: bool,
+    pub wants_recent_files: bool,
+    pub recent_files: Vec<PathBuf>, // Most recent first.
+    pub wants_command_palette: bool,
+    pub command_palette_needle: String,
     pub wants_about: bool,
+    pub wants_keyboard_shortcuts: bool,
     pub wants_close: bool,
+    pub wants_reload: bool,
+    pub wants_copy_path: Option<CopyPath>,
     pub wants_exit: bool,
+    pub wants_close_unpinned: bool,
+    pub exit_session_saved: bool, // Whether the session was saved during the current exit attempt.
+    pub stdout_redirected: bool,
+    pub exit_stdout: Option<Vec<u8>>, // The text to write to the redirected stdout on exit.
+    pub swap_flushed: Instant,
+    /// When the last input arrived. Autosave waits for it to be a while ago.
+    pub last_input: Instant,
+    pub wants_autosave: bool,
+    pub swap_recoverable: Vec<swap::Recoverable>,
     pub wants_goto: bool,
     pub goto_target: String,
+    /// The cursor and scroll position from before the goto dialog opened,
+    /// which its preview returns to if it's canceled.
+    pub goto_origin: Option<(Point, Point)>,
+    pub wants_undo_history: bool,
+    pub wants_checkpoint: bool,
+    pub checkpoint_name: String,
     pub goto_invalid: bool,
 
     pub osc_title_file_status: OscTitleFileStatus,
@@ -199,13 +410,23 @@ impl State {
             menubar_color_bg: 0,
             menubar_color_fg: 0,
 
+            config: Default::default(),
             documents: Default::default(),
-            syntax: syntax::Syntax::new(),
+            syntax: syntax::SyntaxWorker::new(),
             file_tree: Default::default(),
+            outline: Default::default(),
+            build: Default::default(),
+            panes: Default::default(),
+            jumps: Default::default(),
+            expanded_selections: Default::default(),
 
             error_log: [const { String::new() }; 10],
             error_log_index: 0,
             error_log_count: 0,
+            log: Default::default(),
+            wants_log: false,
+            show_changes: false,
+            show_blame: false,
 
             wants_file_picker: StateFilePicker::None,
             file_picker_pending_dir: Default::default(),
@@ -219,7 +440,12 @@ impl State {
             search_needle: Default::default(),
             search_replacement: Default::default(),
             search_options: Default::default(),
-            search_success: true,
+            search_status: SearchStatus::Found,
+            search_in_selection: false,
+            search_scope: None,
+            search_origin: None,
+            search_needle_history: Default::default(),
+            search_replacement_history: Default::default(),
 
             wants_encoding_picker: false,
             encoding_picker_needle: Default::default(),
@@ -230,11 +456,30 @@ impl State {
             wants_encoding_change: StateEncodingChange::None,
             wants_indentation_picker: false,
             wants_go_to_file: false,
+            wants_recent_files: false,
+            recent_files: recent::load(),
+            wants_command_palette: false,
+            command_palette_needle: Default::default(),
             wants_about: false,
+            wants_keyboard_shortcuts: false,
             wants_close: false,
+            wants_reload: false,
+            wants_copy_path: None,
             wants_exit: false,
+            wants_close_unpinned: false,
+            exit_session_saved: false,
+            stdout_redirected: false,
+            exit_stdout: None,
+            swap_flushed: Instant::now(),
+            last_input: Instant::now(),
+            wants_autosave: false,
+            swap_recoverable: Vec::new(),
             wants_goto: false,
             goto_target: Default::default(),
+            goto_origin: None,
+            wants_undo_history: false,
+            wants_checkpoint: false,
+            checkpoint_name: Default::default(),
             goto_invalid: false,
 
             osc_title_file_status: Default::default(),
@@ -243,6 +488,30 @@ impl State {
             exit: false,
         })
     }
+
+    /// Adds a message to the error log. Outside of drawing, e.g. during startup.
+    pub fn error_log_push(&mut self, severity: LogSeverity, msg: String) {
+        self.log.push(severity, msg.clone());
+        self.error_log[self.error_log_index] = msg;
+        self.error_log_index = (self.error_log_index + 1) % self.error_log.len();
+        self.error_log_count = self.error_log.len().min(self.error_log_count + 1);
+    }
+
+    /// Expands `~` and environment variables in a path the user entered, see [`path::expand`].
+    /// Variables that aren't set are pointed out in the error log.
+    pub fn expand_path(&mut self, path: &Path) -> PathBuf {
+        let Some(text) = path.to_str() else {
+            return path.to_path_buf();
+        };
+
+        let mut unknown = Vec::new();
+        let expanded = path::expand_with(text, &mut |name| unknown.push(name.to_string()));
+        for name in unknown {
+            let msg = loc(LocId::NoticeUnknownVariable).replace("{name}", &name);
+            self.error_log_push(LogSeverity::Info, msg);
+        }
+        expanded
+    }
 }
 
 pub fn draw_add_untitled_document(ctx: &mut Context, state: &mut State) {
@@ -253,10 +522,22 @@ pub fn draw_add_untitled_document(ctx: &mut Context, state: &mut State) {
 
 pub fn error_log_add(ctx: &mut Context, state: &mut State, err: apperr::Error) {
     let msg = format!("{}", FormatApperr::from(err));
+    error_log_add_with_severity(ctx, state, LogSeverity::Error, msg);
+}
+
+/// Adds a message to the error log. Used for notices that aren't backed by an [`apperr::Error`].
+pub fn error_log_add_message(ctx: &mut Context, state: &mut State, msg: String) {
+    error_log_add_with_severity(ctx, state, LogSeverity::Info, msg);
+}
+
+fn error_log_add_with_severity(
+    ctx: &mut Context,
+    state: &mut State,
+    severity: LogSeverity,
+    msg: String,
+) {
     if !msg.is_empty() {
-        state.error_log[state.error_log_index] = msg;
-        state.error_log_index = (state.error_log_index + 1) % state.error_log.len();
-        state.error_log_count = state.error_log.len().min(state.error_log_count + 1);
+        state.error_log_push(severity, msg);
         ctx.needs_rerender();
     }
 }
@@ -294,3 +575,83 @@ pub fn draw_error_log(ctx: &mut Context, state: &mut State) {
         state.error_log_count = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use edit::arena;
+    use edit::buffer::TextBuffer;
+
+    use super::*;
+
+    #[test]
+    fn test_search_scope_range_in() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| arena::init(16 * MEBI).unwrap());
+
+        let doc = TextBuffer::new_rc(true).unwrap();
+        let other = TextBuffer::new_rc(true).unwrap();
+        doc.borrow_mut().write_raw(b"hello world");
+        other.borrow_mut().write_raw(b"hello world");
+
+        let scope = SearchScope::new(&doc, 0..5, doc.borrow().generation());
+        assert_eq!(scope.range_in(&doc), Some(0..5));
+        // A scope captured in one document never applies to another, even if their text matches.
+        assert_eq!(scope.range_in(&other), None);
+
+        // Nor once the document was edited since.
+        doc.borrow_mut().write_raw(b"!");
+        assert_eq!(scope.range_in(&doc), None);
+
+        let empty = SearchScope::new(&doc, 3..3, doc.borrow().generation());
+        assert_eq!(empty.range_in(&doc), None);
+    }
+
+    #[test]
+    fn test_search_history_push() {
+        let mut history = SearchHistory::default();
+        history.push("foo");
+        history.push("bar");
+        history.push("");
+        history.push("foo");
+        // The repeated entry moves to the front instead of being added twice.
+        assert_eq!(history.entries, ["foo", "bar"]);
+
+        for i in 0..SearchHistory::MAX_ENTRIES {
+            history.push(&i.to_string());
+        }
+        assert_eq!(history.entries.len(), SearchHistory::MAX_ENTRIES);
+        assert_eq!(history.entries[0], "31");
+        assert_eq!(history.entries.last().unwrap(), "0");
+    }
+
+    #[test]
+    fn test_search_history_recall() {
+        let mut history = SearchHistory::default();
+        history.push("old");
+        history.push("new");
+
+        let mut text = "draft".to_string();
+        assert!(!history.recall_newer(&mut text));
+        assert!(history.recall_older(&mut text));
+        assert_eq!(text, "new");
+        assert!(history.recall_older(&mut text));
+        assert_eq!(text, "old");
+        assert!(!history.recall_older(&mut text));
+        assert_eq!(text, "old");
+        assert!(history.recall_newer(&mut text));
+        assert_eq!(text, "new");
+        // Going past the newest entry restores what was typed before.
+        assert!(history.recall_newer(&mut text));
+        assert_eq!(text, "draft");
+        assert!(!history.recall_newer(&mut text));
+
+        // After a reset, browsing starts over from the newest entry.
+        history.recall_older(&mut text);
+        history.recall_older(&mut text);
+        history.reset();
+        assert!(history.recall_older(&mut text));
+        assert_eq!(text, "new");
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The JSON files in the config directory that carry state from one run of the
+//! editor to the next: the session, the recent files, the cursor positions and
+//! the swap files.
+//!
+//! None of them is worth interrupting the user for if it can't be written,
+//! so most callers ignore the errors of [`save`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use edit::json::{self, Value};
+use edit::{apperr, sys};
+
+/// Returns the path of the store called `name` in the config directory.
+pub fn path(name: &str) -> Option<PathBuf> {
+    sys::config_dir().map(|dir| dir.join(name))
+}
+
+/// Reads the store at `path`. Returns `None` if it doesn't exist or can't be parsed.
+pub fn load(path: &Path) -> Option<Value> {
+    fs::read_to_string(path).ok().and_then(|text| json::parse(&text))
+}
+
+/// Writes `value` to the store at `path`, creating its directory if needed.
+pub fn save(path: &Path, value: &Value) -> apperr::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, value.to_string())?;
+    Ok(())
+}
+
+/// Returns `path` as a JSON string, or `None` if it isn't valid UTF-8 and thus can't be one.
+pub fn path_value(path: &Path) -> Option<Value> {
+    path.to_str().map(Value::from)
+}
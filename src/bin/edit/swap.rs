@@ -0,0 +1,203 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Swap files hold the contents of modified documents, so that they can be recovered
+//! if the editor doesn't exit properly. They're written to the config directory every
+//! few seconds while there are unsaved changes, and removed once the changes are
+//! saved or discarded.
+//!
+//! Each instance of the editor writes its own swap files, named after its process ID,
+//! and those of instances that are still running aren't offered for recovery.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fs, mem, process};
+
+use edit::helpers::*;
+use edit::json::Value;
+use edit::{apperr, sys};
+
+use crate::documents::Document;
+use crate::localization::*;
+use crate::state::{FormatApperr, LogSeverity, State};
+use crate::store;
+
+/// How long changes may go without being written to the swap file.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+fn swap_dir() -> Option<PathBuf> {
+    store::path("swap")
+}
+
+/// The unsaved changes to a document, as found in a swap file at startup.
+pub struct Recoverable {
+    pub swap_path: PathBuf,
+    /// `None` for untitled documents.
+    pub path: Option<PathBuf>,
+    pub filename: String,
+    pub cursor: Point,
+    pub text: String,
+}
+
+impl Document {
+    fn swap_path(&self) -> Option<PathBuf> {
+        let name = match &self.path {
+            Some(path) => {
+                let mut hasher = DefaultHasher::new();
+                path.hash(&mut hasher);
+                format!("{:016x}-{}.json", hasher.finish(), process::id())
+            }
+            None => format!("untitled-{}-{}.json", process::id(), self.new_file_counter),
+        };
+        swap_dir().map(|dir| dir.join(name))
+    }
+
+    /// Writes the swap file if the document changed since the last time,
+    /// or removes it if the document is no longer modified, e.g. after an undo.
+    fn swap_update(&mut self) -> apperr::Result<()> {
+        if self.large_file {
+            return Ok(());
+        }
+
+        let tb = self.buffer.borrow();
+        let generation = tb.generation();
+        if self.swap_generation == Some(generation) {
+            return Ok(());
+        }
+        self.swap_generation = Some(generation);
+
+        let Some(swap_path) = self.swap_path() else {
+            return Ok(());
+        };
+        if !tb.is_dirty() {
+            _ = fs::remove_file(swap_path);
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        tb.copy_into(&mut text);
+        let pos = tb.cursor_logical_pos();
+        // A path that can't be stored is recovered as an untitled document.
+        let path = self.path.as_deref().and_then(store::path_value).unwrap_or(Value::Null);
+        let swap = Value::Object(vec![
+            ("pid".into(), (process::id() as i64).into()),
+            ("path".into(), path),
+            ("filename".into(), self.filename.as_str().into()),
+            ("line".into(), (pos.y as i64 + 1).into()),
+            ("column".into(), (pos.x as i64 + 1).into()),
+            ("text".into(), text.into()),
+        ]);
+
+        store::save(&swap_path, &swap)
+    }
+
+    /// Removes the swap file, because the changes were saved or discarded.
+    pub fn swap_remove(&mut self) {
+        if self.swap_generation.take().is_some()
+            && let Some(swap_path) = self.swap_path()
+        {
+            _ = fs::remove_file(swap_path);
+        }
+    }
+}
+
+impl State {
+    /// Returns how long until [`State::swap_flush`] needs to be called.
+    pub fn swap_timeout(&self) -> Duration {
+        let unflushed = self.documents.iter().any(|doc| {
+            let tb = doc.buffer.borrow();
+            doc.swap_generation != Some(tb.generation())
+                && (tb.is_dirty() || doc.swap_generation.is_some())
+        });
+        if unflushed {
+            FLUSH_INTERVAL.saturating_sub(self.swap_flushed.elapsed())
+        } else {
+            Duration::MAX
+        }
+    }
+
+    /// Brings the swap files of all documents up to date.
+    /// Unlike the other stores, a swap file that can't be written is reported, since
+    /// the user would otherwise count on it. Only once per document, though, until
+    /// writing it succeeds again, because it's retried every few seconds.
+    pub fn swap_flush(&mut self) {
+        let mut errors = Vec::new();
+        for doc in self.documents.iter_mut() {
+            match doc.swap_update() {
+                Ok(()) => doc.swap_failed = false,
+                Err(err) => {
+                    if !mem::replace(&mut doc.swap_failed, true) {
+                        let err = FormatApperr::from(err).to_string();
+                        errors.push(
+                            loc(LocId::ErrorSwapWrite)
+                                .replace("{filename}", &doc.filename)
+                                .replace("{error}", &err),
+                        );
+                    }
+                }
+            }
+        }
+        for msg in errors {
+            self.error_log_push(LogSeverity::Error, msg);
+        }
+        self.swap_flushed = Instant::now();
+    }
+}
+
+/// Reads the swap files that were left behind. Those that are older than the file
+/// they belong to were superseded by a save and are removed instead.
+/// Those of other instances that are still running are left alone.
+pub fn find_recoverable() -> Vec<Recoverable> {
+    let Some(entries) = swap_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut result = Vec::new();
+
+    for entry in entries.flatten() {
+        let swap_path = entry.path();
+        let Some(swap) = store::load(&swap_path) else {
+            continue;
+        };
+        // The ID of a process that crashed may have been reused by this one.
+        if let Some(pid) =
+            swap.get("pid").and_then(Value::as_i64).and_then(|p| u32::try_from(p).ok())
+            && pid != process::id()
+            && sys::is_process_alive(pid)
+        {
+            continue;
+        }
+        let (Some(filename), Some(text)) = (
+            swap.get("filename").and_then(Value::as_str),
+            swap.get("text").and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+
+        let path = swap.get("path").and_then(Value::as_str).map(PathBuf::from);
+        if let Some(path) = &path
+            && let (Some(file_modified), Some(swap_modified)) =
+                (modified(path), modified(&swap_path))
+            && file_modified >= swap_modified
+        {
+            _ = fs::remove_file(&swap_path);
+            continue;
+        }
+
+        let line = swap.get("line").and_then(Value::as_i64).unwrap_or(1);
+        let column = swap.get("column").and_then(Value::as_i64).unwrap_or(1);
+        result.push(Recoverable {
+            filename: filename.to_string(),
+            text: text.to_string(),
+            swap_path,
+            path,
+            cursor: Point {
+                x: (column - 1).max(0) as CoordType,
+                y: (line - 1).max(0) as CoordType,
+            },
+        });
+    }
+
+    result
+}
@@ -28,11 +28,12 @@ use std::cell::UnsafeCell;
 use std::collections::LinkedList;
 use std::fmt::Write as _;
 use std::fs::File;
-use std::io::{Read as _, Write as _};
+use std::io::{self, Read as _, Seek as _, SeekFrom};
 use std::mem::{self, MaybeUninit};
 use std::ops::Range;
 use std::rc::Rc;
 use std::str;
+use std::time::Instant;
 
 pub use gap_buffer::GapBuffer;
 
@@ -44,9 +45,10 @@ use crate::framebuffer::{Framebuffer, IndexedColor};
 use crate::helpers::*;
 use crate::oklab::oklab_blend;
 use crate::simd::memchr2;
-use tree_sitter_highlight::Highlight;
+use crate::snippet::Snippet;
+use crate::syntax::{IndentRegion, ThemeColor};
 use crate::unicode::{self, Cursor, MeasurementConfig, Utf8Chars};
-use crate::{apperr, icu, simd};
+use crate::{apperr, diff, icu, simd};
 
 /// The margin template is used for line numbers.
 /// The max. line number we should ever expect is probably 64-bit,
@@ -106,6 +108,28 @@ struct HistoryEntry {
     deleted: Vec<u8>,
     /// Text that was added to the buffer.
     added: Vec<u8>,
+    /// If set, the newlines in `deleted` and `added` are restored as-is on undo/redo,
+    /// along with [`TextBuffer::newlines_are_crlf`], instead of being normalized.
+    verbatim: Option<bool>,
+    /// Identifies the entry for as long as it's in the history. See [`HistoryStep::id`].
+    id: u64,
+    /// When the change was started.
+    time: Instant,
+}
+
+/// A group of edits that are undone and redone together, as listed by [`TextBuffer::history`].
+pub struct HistoryStep {
+    /// Identifies the step, even after older steps were dropped from the history.
+    /// Unlike a [`TextBuffer::generation`], it's never reused after undoing and editing anew.
+    pub id: u64,
+    /// When the step was started.
+    pub time: Instant,
+    /// An excerpt of the text that the first edit of the step added.
+    pub added: String,
+    /// An excerpt of the text that the first edit of the step deleted.
+    pub deleted: String,
+    /// The number of edits in the step, e.g. one for each cursor when typing at several.
+    pub edits: usize,
 }
 
 /// Caches an ICU search operation.
@@ -128,6 +152,8 @@ struct ActiveSearch {
     selection_generation: u32,
     /// Stores the text buffer offset in between searches.
     next_search_offset: usize,
+    /// The byte offsets that hits must lie within. Wrapping around restarts at its start.
+    range: Range<usize>,
     /// If we know there were no hits, we can skip searching.
     no_matches: bool,
 }
@@ -141,6 +167,9 @@ pub struct SearchOptions {
     pub whole_word: bool,
     /// If true, the search uses regex.
     pub use_regex: bool,
+    /// If true, replacements take on the case of the text they replace.
+    /// See [`TextBuffer::find_and_replace`] for the details.
+    pub preserve_case: bool,
 }
 
 enum RegexReplacement<'a> {
@@ -180,6 +209,7 @@ struct ActiveEditGroupInfo {
 }
 
 /// Char- or word-wise navigation? Your choice.
+#[derive(Clone, Copy)]
 pub enum CursorMovement {
     Grapheme,
     Word,
@@ -191,6 +221,16 @@ pub enum MoveLineDirection {
     Down,
 }
 
+/// Which whitespace [`TextBuffer::render()`] makes visible, besides the one in the selection.
+#[derive(Default, Clone, Copy, Eq, PartialEq)]
+pub enum WhitespaceVisibility {
+    #[default]
+    Off,
+    /// Only the whitespace at the end of lines.
+    Trailing,
+    All,
+}
+
 /// The result of a call to [`TextBuffer::render()`].
 pub struct RenderResult {
     /// The maximum visual X position we encountered during rendering.
@@ -213,12 +253,16 @@ pub struct TextBuffer {
     undo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
     redo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
     last_history_type: HistoryType,
+    last_history_id: u64,
     last_save_generation: u32,
 
     active_edit_group: Option<ActiveEditGroupInfo>,
     active_edit_line_info: Option<ActiveEditLineInfo>,
     active_edit_depth: i32,
     active_edit_off: usize,
+    // The logical line the active edit started on, the line count and
+    // the length of the undo entry's `added` text at the time.
+    active_edit_fold_info: (CoordType, CoordType, usize),
 
     stats: TextBufferStatistics,
     cursor: Cursor,
@@ -229,9 +273,54 @@ pub struct TextBuffer {
     cursor_for_rendering: Option<Cursor>,
     selection: Option<TextBufferSelection>,
     selection_generation: u32,
+    // Additional cursors besides `cursor`/`selection`, as byte ranges with the caret at the end.
+    // They're only valid as long as the buffer generation matches `extra_cursors_generation`.
+    extra_cursors: Vec<Range<usize>>,
+    extra_cursors_generation: u32,
+    // The corners of a block selection as (anchor, head), in logical lines (.y) and visual columns (.x).
+    // The block itself is made of `selection` and `extra_cursors`, one for each line. It's only valid
+    // as long as `selection_generation` matches `block_selection_generation` and the cursors are.
+    block_selection: Option<(Point, Point)>,
+    block_selection_generation: u32,
     search: Option<UnsafeCell<ActiveSearch>>,
-    highlights: Vec<(Range<usize>, Highlight)>,
+    highlights: Vec<(Range<usize>, ThemeColor)>,
     highlight_generation: u32,
+    bracket_pairs: Vec<(usize, usize)>,
+    bracket_pairs_generation: u32,
+    // The logical lines to mark on the scrollbar, e.g. for search matches. Sorted and unique.
+    scrollbar_markers: Vec<(CoordType, IndexedColor)>,
+    scrollbar_markers_generation: u32,
+    // The visual lines that `scrollbar_markers` start on, before folds are taken out.
+    scrollbar_marker_rows: Vec<(CoordType, IndexedColor)>,
+    // The text that changed lines are marked against in the margin. See `set_diff_base`.
+    diff_base: Option<Vec<u8>>,
+    // Whether the `diff_base` is what this text changed into, rather than from. See `set_diff_target`.
+    diff_base_is_newer: bool,
+    // The changed logical lines, with the color to mark them in. Sorted.
+    // They're only valid as long as the buffer generation matches `line_changes_generation`.
+    line_changes: Vec<(CoordType, IndexedColor)>,
+    line_changes_generation: Option<u32>,
+    // Text shown in the margin in front of the number of each logical line. See `set_margin_annotations`.
+    margin_annotations: Vec<String>,
+    // The width of the annotations, plus the space after them, or 0 if there are none.
+    margin_annotation_width: CoordType,
+    // The logical lines whose number gets colored, e.g. for errors. Sorted. See `set_line_marks`.
+    line_marks: Vec<(CoordType, IndexedColor)>,
+    // The logical lines that can be folded, with the line before each range staying visible.
+    fold_regions: Vec<Range<CoordType>>,
+    fold_regions_generation: u32,
+    // The logical lines that are currently folded away. Sorted and disjoint.
+    // They're only valid as long as the buffer generation matches `folds_generation`.
+    folds: Vec<Range<CoordType>>,
+    folds_generation: u32,
+    // The same as `folds`, but in visual lines.
+    fold_rows: Vec<Range<CoordType>>,
+    // The byte ranges of strings and comments, in which brackets aren't closed automatically.
+    literal_ranges: Vec<Range<usize>>,
+    literal_ranges_generation: u32,
+    // The nodes that indent their contents, which decide the indentation of new lines.
+    indent_regions: Vec<IndentRegion>,
+    indent_regions_generation: u32,
 
     width: CoordType,
     margin_width: CoordType,
@@ -242,12 +331,27 @@ pub struct TextBuffer {
     indent_with_tabs: bool,
     line_highlight_enabled: bool,
     ruler: CoordType,
+    scroll_off: CoordType,
+    whitespace_visibility: WhitespaceVisibility,
     encoding: &'static str,
     newlines_are_crlf: bool,
+    newlines_are_mixed: bool,
+    invalid_utf8: bool,
     insert_final_newline: bool,
     overtype: bool,
+    read_only: bool,
+    auto_close_pairs: &'static [(u8, u8)],
+    auto_indent_after: &'static [u8],
+    // The tabstops of the snippet that's being filled in, see `snippet_insert`.
+    // They're kept up to date with the edits until the last one is reached.
+    snippet_tabstops: Vec<Range<usize>>,
+    snippet_tabstop: usize,
 
     wants_cursor_visibility: bool,
+    // Where the buffer was scrolled to when it was last rendered with the focus. See `scroll_offset`.
+    scroll_offset: Point,
+    // A scroll position the TUI is asked to go to. See `scroll_to`.
+    wants_scroll_offset: Option<Point>,
 }
 
 impl TextBuffer {
@@ -267,21 +371,48 @@ impl TextBuffer {
             undo_stack: LinkedList::new(),
             redo_stack: LinkedList::new(),
             last_history_type: HistoryType::Other,
+            last_history_id: 0,
             last_save_generation: 0,
 
             active_edit_group: None,
             active_edit_line_info: None,
             active_edit_depth: 0,
             active_edit_off: 0,
+            active_edit_fold_info: (0, 0, 0),
 
             stats: TextBufferStatistics { logical_lines: 1, visual_lines: 1 },
             cursor: Default::default(),
             cursor_for_rendering: None,
             selection: None,
             selection_generation: 0,
+            extra_cursors: Vec::new(),
+            extra_cursors_generation: 0,
+            block_selection: None,
+            block_selection_generation: 0,
             search: None,
             highlights: Vec::new(),
             highlight_generation: 0,
+            bracket_pairs: Vec::new(),
+            bracket_pairs_generation: 0,
+            scrollbar_markers: Vec::new(),
+            scrollbar_markers_generation: 0,
+            scrollbar_marker_rows: Vec::new(),
+            diff_base: None,
+            diff_base_is_newer: false,
+            line_changes: Vec::new(),
+            line_changes_generation: None,
+            margin_annotations: Vec::new(),
+            margin_annotation_width: 0,
+            line_marks: Vec::new(),
+            fold_regions: Vec::new(),
+            fold_regions_generation: 0,
+            folds: Vec::new(),
+            folds_generation: 0,
+            fold_rows: Vec::new(),
+            literal_ranges: Vec::new(),
+            literal_ranges_generation: 0,
+            indent_regions: Vec::new(),
+            indent_regions_generation: 0,
 
             width: 0,
             margin_width: 0,
@@ -292,12 +423,23 @@ impl TextBuffer {
             indent_with_tabs: false,
             line_highlight_enabled: false,
             ruler: 0,
+            scroll_off: 0,
+            whitespace_visibility: WhitespaceVisibility::Off,
             encoding: "UTF-8",
             newlines_are_crlf: cfg!(windows), // Windows users want CRLF
+            newlines_are_mixed: false,
+            invalid_utf8: false,
             insert_final_newline: false,
             overtype: false,
+            read_only: false,
+            auto_close_pairs: &[],
+            auto_indent_after: &[],
+            snippet_tabstops: Vec::new(),
+            snippet_tabstop: 0,
 
             wants_cursor_visibility: false,
+            scroll_offset: Point::default(),
+            wants_scroll_offset: None,
         })
     }
 
@@ -314,8 +456,10 @@ impl TextBuffer {
 
     /// Number of visual lines in the document,
     /// that is, the number of lines after layout.
+    /// Folded lines are not counted.
     pub fn visual_line_count(&self) -> CoordType {
-        self.stats.visual_lines
+        let hidden: CoordType = self.fold_rows().iter().map(|r| r.end - r.start).sum();
+        self.stats.visual_lines - hidden
     }
 
     /// Does the buffer need to be saved?
@@ -362,6 +506,45 @@ impl TextBuffer {
         self.newlines_are_crlf = crlf;
     }
 
+    /// Whether the file contained both LF and CRLF newlines when it was read.
+    /// [`TextBuffer::is_crlf`] then reflects the majority.
+    pub fn has_mixed_newlines(&self) -> bool {
+        self.newlines_are_mixed
+    }
+
+    /// Whether the start of the file contained bytes that aren't valid UTF-8 when it was read.
+    /// They're shown as U+FFFD and written back unchanged.
+    pub fn has_invalid_utf8(&self) -> bool {
+        self.invalid_utf8
+    }
+
+    /// Changes the newline type used in the document, like [`TextBuffer::normalize_newlines`],
+    /// but as a single edit that can be undone.
+    pub fn convert_newlines(&mut self, crlf: bool) {
+        if self.read_only {
+            return;
+        }
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..self.text_length(), &mut text, 0);
+
+        let mut converted = Vec::with_capacity(text.len());
+        for (i, &b) in text.iter().enumerate() {
+            match b {
+                b'\r' if text.get(i + 1) == Some(&b'\n') => {}
+                b'\n' if crlf => converted.extend_from_slice(b"\r\n"),
+                _ => converted.push(b),
+            }
+        }
+
+        let cursor = self.cursor.logical_pos;
+        self.replace_contents(&converted);
+        self.cursor_move_to_logical(cursor);
+
+        self.newlines_are_crlf = crlf;
+        self.newlines_are_mixed = false;
+    }
+
     /// Changes the newline type used in the document.
     ///
     /// NOTE: Cannot be undone.
@@ -447,6 +630,17 @@ impl TextBuffer {
         self.overtype = overtype;
     }
 
+    /// Whether the user is prevented from making changes.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// If enabled, all methods that change the text do nothing, except for [`TextBuffer::cut`],
+    /// which only copies. Navigation, selection and searching still work.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
     /// Gets the logical cursor position, that is,
     /// the position in lines and graphemes per line.
     pub fn cursor_logical_pos(&self) -> Point {
@@ -455,8 +649,14 @@ impl TextBuffer {
 
     /// Gets the visual cursor position, that is,
     /// the position in laid out rows and columns.
+    /// Like all visual positions, the rows skip folded lines.
     pub fn cursor_visual_pos(&self) -> Point {
-        self.cursor.visual_pos
+        Point { x: self.cursor.visual_pos.x, y: self.fold_visual_y(self.cursor.visual_pos.y) }
+    }
+
+    /// Gets the cursor position as a byte offset into the buffer.
+    pub fn cursor_offset(&self) -> usize {
+        self.cursor.offset
     }
 
     /// Gets the width of the left margin.
@@ -496,6 +696,22 @@ impl TextBuffer {
         mem::take(&mut self.wants_cursor_visibility)
     }
 
+    /// Returns where the buffer was scrolled to, when it was last rendered with the focus.
+    /// Together with [`TextBuffer::scroll_to()`], this allows putting the view back where it was.
+    pub fn scroll_offset(&self) -> Point {
+        self.scroll_offset
+    }
+
+    /// Ask the TUI system to scroll the buffer to the given visual position.
+    pub fn scroll_to(&mut self, offset: Point) {
+        self.wants_scroll_offset = Some(offset);
+    }
+
+    /// For the TUI code to retrieve a prior [`TextBuffer::scroll_to()`] request.
+    pub fn take_scroll_request(&mut self) -> Option<Point> {
+        self.wants_scroll_offset.take()
+    }
+
     /// Is word-wrap enabled?
     ///
     /// Technically, this is a misnomer, because it's line-wrapping.
@@ -568,6 +784,190 @@ impl TextBuffer {
         (column - 1).max(0) / self.tab_size * self.tab_size
     }
 
+    /// Sets the pairs of brackets and quotes that are closed automatically when typing the
+    /// opening one. Typing the closing one in front of an existing one moves past it instead.
+    /// Typing the opening one while there's a selection wraps it in the pair.
+    pub fn set_auto_close_pairs(&mut self, pairs: &'static [(u8, u8)]) {
+        self.auto_close_pairs = pairs;
+    }
+
+    /// Sets the characters after which a new line is indented by one more level, e.g. `{`.
+    pub fn set_auto_indent_after(&mut self, chars: &'static [u8]) {
+        self.auto_indent_after = chars;
+    }
+
+    /// Replaces the byte `range` with the `snippet`, indented like the line it's on,
+    /// and selects its first tabstop. [`TextBuffer::snippet_jump`] moves on from there.
+    pub fn snippet_insert(&mut self, range: Range<usize>, snippet: &Snippet) {
+        if self.read_only {
+            return;
+        }
+
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let line_beg = self.goto_line_start(beg, beg.logical_pos.y);
+        let (chars, _) = self.measure_indent_internal(line_beg.offset, CoordType::MAX);
+        let mut indent = Vec::new();
+        self.buffer.extract_raw(line_beg.offset..line_beg.offset + chars as usize, &mut indent, 0);
+        let indent = String::from_utf8_lossy(&indent);
+        let unit =
+            if self.indent_with_tabs { "\t" } else { &TAB_WHITESPACE[..self.tab_size as usize] };
+        let snippet = snippet.indented(&indent, unit);
+
+        self.snippet_tabstops.clear();
+        self.select_range(range);
+        self.write(snippet.text.as_bytes(), self.cursor, true);
+
+        self.snippet_tabstops =
+            snippet.tabstops.iter().map(|r| beg.offset + r.start..beg.offset + r.end).collect();
+        self.snippet_tabstop = 0;
+        self.snippet_select();
+    }
+
+    /// Selects the next or previous tabstop of the snippet inserted by
+    /// [`TextBuffer::snippet_insert`]. Reaching the last one ends the snippet.
+    /// Returns false if there's no snippet, or the cursor has left its current tabstop.
+    pub fn snippet_jump(&mut self, forward: bool) -> bool {
+        let Some(current) = self.snippet_tabstops.get(self.snippet_tabstop) else {
+            return false;
+        };
+        if !(current.start..=current.end).contains(&self.cursor.offset) {
+            self.snippet_exit();
+            return false;
+        }
+
+        if forward {
+            self.snippet_tabstop += 1;
+        } else {
+            self.snippet_tabstop = self.snippet_tabstop.saturating_sub(1);
+        }
+        self.snippet_select();
+        true
+    }
+
+    /// Stops tracking the tabstops of the snippet inserted by [`TextBuffer::snippet_insert`].
+    pub fn snippet_exit(&mut self) {
+        self.snippet_tabstops.clear();
+    }
+
+    fn snippet_select(&mut self) {
+        let range = self.snippet_tabstops[self.snippet_tabstop].clone();
+        self.select_range(range);
+        self.make_cursor_visible();
+        if self.snippet_tabstop + 1 >= self.snippet_tabstops.len() {
+            self.snippet_exit();
+        }
+    }
+
+    /// Moves the snippet's tabstops along with an insertion of `len` bytes at `off`.
+    /// The current tabstop grows if the text is inserted within it or at either of its ends.
+    /// The others only do if it's strictly within them, e.g. `${1:a ${2:b} c}`.
+    fn snippet_adjust_insert(&mut self, off: usize, len: usize) {
+        for (i, r) in self.snippet_tabstops.iter_mut().enumerate() {
+            let grows = if i == self.snippet_tabstop {
+                r.start <= off && off <= r.end
+            } else {
+                r.start < off && off < r.end
+            };
+            if grows {
+                r.end += len;
+            } else if r.start >= off {
+                r.start += len;
+                r.end += len;
+            }
+        }
+    }
+
+    /// Moves the snippet's tabstops along with the deletion of the bytes `off..to`.
+    fn snippet_adjust_delete(&mut self, off: usize, to: usize) {
+        let adjust = |p: usize| if p >= to { p - (to - off) } else { p.min(off) };
+        for r in &mut self.snippet_tabstops {
+            *r = adjust(r.start)..adjust(r.end);
+        }
+    }
+
+    /// Converts the indentation of every line to tabs or spaces, based on the current tab size,
+    /// as a single edit that can be undone. Afterwards, new indentation uses the same style.
+    pub fn convert_indentation(&mut self, tabs: bool) {
+        if self.read_only {
+            return;
+        }
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..self.text_length(), &mut text, 0);
+
+        let mut converted = Vec::with_capacity(text.len());
+        for line in text.split_inclusive(|&b| b == b'\n') {
+            let mut columns = 0;
+            let mut len = 0;
+            for &b in line {
+                match b {
+                    b' ' => columns += 1,
+                    b'\t' => columns += self.tab_size_eval(columns),
+                    _ => break,
+                }
+                len += 1;
+            }
+
+            if tabs {
+                converted.resize(converted.len() + (columns / self.tab_size) as usize, b'\t');
+                columns %= self.tab_size;
+            }
+            converted.resize(converted.len() + columns as usize, b' ');
+            converted.extend_from_slice(&line[len..]);
+        }
+
+        let cursor = self.cursor.logical_pos;
+        self.replace_contents(&converted);
+        self.cursor_move_to_logical(cursor);
+
+        self.indent_with_tabs = tabs;
+    }
+
+    /// Removes the trailing whitespace of every line and/or makes the text end in exactly one
+    /// newline, as a single edit that can be undone. Text with NUL bytes in it is left alone,
+    /// because it's most likely not text and any change would corrupt it.
+    pub fn clean_up_whitespace(&mut self, trim_lines: bool, final_newline: bool) {
+        if (!trim_lines && !final_newline) || self.read_only {
+            return;
+        }
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..self.text_length(), &mut text, 0);
+        if text.contains(&0) {
+            return;
+        }
+
+        let mut cleaned = Vec::with_capacity(text.len());
+        for line in text.split_inclusive(|&b| b == b'\n') {
+            let newline_len = if line.ends_with(b"\r\n") {
+                2
+            } else if line.ends_with(b"\n") {
+                1
+            } else {
+                0
+            };
+            let (content, newline) = line.split_at(line.len() - newline_len);
+            let len = if trim_lines {
+                content.iter().rposition(|&b| b != b' ' && b != b'\t').map_or(0, |i| i + 1)
+            } else {
+                content.len()
+            };
+            cleaned.extend_from_slice(&content[..len]);
+            cleaned.extend_from_slice(newline);
+        }
+
+        if final_newline && !cleaned.is_empty() {
+            let len = cleaned.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |i| i + 1);
+            cleaned.truncate(len);
+            cleaned.extend_from_slice(if self.newlines_are_crlf { b"\r\n" } else { b"\n" });
+        }
+
+        // If the cursor was in the removed whitespace, this moves it to the end of its line.
+        let cursor = self.cursor.logical_pos;
+        self.replace_contents(&cleaned);
+        self.cursor_move_to_logical(cursor);
+    }
+
     /// Returns whether tabs are used for indentation.
     pub fn indent_with_tabs(&self) -> bool {
         self.indent_with_tabs
@@ -583,11 +983,31 @@ impl TextBuffer {
         self.line_highlight_enabled = enabled;
     }
 
-    /// Sets a ruler column, e.g. 80.
+    /// Sets a ruler column, e.g. 80. 0 disables it.
     pub fn set_ruler(&mut self, column: CoordType) {
         self.ruler = column;
     }
 
+    /// Returns how many lines are kept visible above and below the cursor when scrolling to it.
+    pub fn scroll_off(&self) -> CoordType {
+        self.scroll_off
+    }
+
+    /// Sets how many lines are kept visible above and below the cursor, like Vim's `scrolloff`.
+    pub fn set_scroll_off(&mut self, lines: CoordType) {
+        self.scroll_off = lines.max(0);
+    }
+
+    /// Returns which whitespace is made visible.
+    pub fn whitespace_visibility(&self) -> WhitespaceVisibility {
+        self.whitespace_visibility
+    }
+
+    /// Sets which whitespace is made visible. This only affects rendering.
+    pub fn set_whitespace_visibility(&mut self, visibility: WhitespaceVisibility) {
+        self.whitespace_visibility = visibility;
+    }
+
     pub fn reflow(&mut self) {
         self.reflow_internal(true);
     }
@@ -604,7 +1024,7 @@ impl TextBuffer {
             // +1 onto log10, because we want the digit width and not the actual log10.
             // +3 onto log10, because we append " | " to the line numbers to form the margin.
             self.margin_width = if self.margin_enabled {
-                self.stats.logical_lines.ilog10() as CoordType + 4
+                self.stats.logical_lines.ilog10() as CoordType + 4 + self.margin_annotation_width
             } else {
                 0
             };
@@ -635,7 +1055,11 @@ impl TextBuffer {
             } else {
                 self.stats.visual_lines = self.stats.logical_lines;
             }
+
+            self.scrollbar_markers_recalc();
         }
+
+        self.folds_recalc();
     }
 
     /// Replaces the entire buffer contents with the given `text`.
@@ -657,6 +1081,7 @@ impl TextBuffer {
         self.undo_stack.clear();
         self.redo_stack.clear();
         self.last_history_type = HistoryType::Other;
+        self.snippet_exit();
         self.cursor = Default::default();
         self.set_selection(None);
         self.mark_as_clean();
@@ -669,6 +1094,64 @@ impl TextBuffer {
         self.mark_as_clean();
     }
 
+    /// Copies the contents of the buffer into a string.
+    /// Unlike [`TextBuffer::save_as_string`], this doesn't affect the dirty state.
+    pub fn copy_into(&self, dst: &mut dyn WriteableDocument) {
+        self.buffer.copy_into(dst);
+    }
+
+    /// Re-reads a file from disk like [`TextBuffer::read_file`], but applies the new
+    /// contents as a single edit, so that the previous contents can be restored with undo.
+    pub fn reload_file(
+        &mut self,
+        file: &mut File,
+        encoding: Option<&'static str>,
+    ) -> apperr::Result<()> {
+        let mut fresh = TextBuffer::new(false)?;
+        fresh.read_file(file, encoding)?;
+
+        let mut text = Vec::new();
+        fresh.buffer.extract_raw(0..fresh.text_length(), &mut text, 0);
+
+        let cursor = self.cursor.logical_pos;
+        self.replace_contents(&text);
+        self.cursor_move_to_logical(cursor);
+
+        self.encoding = fresh.encoding;
+        self.newlines_are_crlf = fresh.newlines_are_crlf;
+        self.newlines_are_mixed = fresh.newlines_are_mixed;
+        self.invalid_utf8 = fresh.invalid_utf8;
+        self.insert_final_newline = fresh.insert_final_newline;
+        self.mark_as_clean();
+        Ok(())
+    }
+
+    /// Replaces the entire contents with `text` as a single edit. Unlike [`TextBuffer::write`],
+    /// this inserts the text verbatim, without translating newlines or indentation.
+    fn replace_contents(&mut self, text: &[u8]) {
+        let mut current = Vec::new();
+        self.buffer.extract_raw(0..self.text_length(), &mut current, 0);
+        if current == text {
+            return;
+        }
+
+        let beg = self.cursor_move_to_offset_internal(self.cursor, 0);
+        let end = self.cursor_move_to_logical_internal(beg, Point::MAX);
+
+        self.set_selection(None);
+        self.edit_begin(HistoryType::Other, beg);
+        if let Some(entry) = self.undo_stack.back() {
+            entry.borrow_mut().verbatim = Some(self.newlines_are_crlf);
+        }
+        if end.offset > 0 {
+            self.edit_delete(end);
+        }
+        if !text.is_empty() {
+            self.edit_write(text);
+        }
+        self.edit_end();
+    }
+
     /// Reads a file from disk into the text buffer, detecting encoding and BOM.
     pub fn read_file(
         &mut self,
@@ -699,10 +1182,31 @@ impl TextBuffer {
         // TODO: Since reading the file can fail, we should ensure that we also reset the cursor here.
         // I don't do it, so that `recalc_after_content_swap()` works.
         self.buffer.clear();
+        self.invalid_utf8 = false;
 
         let done = read == 0;
-        if self.encoding == "UTF-8" {
+        if self.encoding.starts_with("UTF-8") {
             self.read_file_as_utf8(file, &mut buf, first_chunk_len, done)?;
+
+            // Files without a BOM may be UTF-16 or, if they aren't valid UTF-8, in a legacy encoding.
+            // If we can rewind the file (i.e. it's not a pipe), we'll read it again in that encoding.
+            if encoding.is_none() && self.encoding == "UTF-8" {
+                let chunk = self.read_forward(0);
+                let sniffed = sniff(&chunk[..chunk.len().min(64 * KIBI)]);
+                let fallback = match sniffed {
+                    Sniffed::Utf16(encoding) => Some(encoding),
+                    // The most common single-byte encoding, and a superset of ISO-8859-1.
+                    Sniffed::Legacy => Some("windows-1252"),
+                    Sniffed::Utf8 | Sniffed::InvalidUtf8 | Sniffed::Binary => None,
+                };
+                if let Some(fallback) = fallback
+                    && icu::init().is_ok()
+                    && file.seek(SeekFrom::Start(0)).is_ok()
+                {
+                    return self.read_file(file, Some(fallback));
+                }
+                self.invalid_utf8 = matches!(sniffed, Sniffed::InvalidUtf8 | Sniffed::Legacy);
+            }
         } else {
             self.read_file_with_icu(file, &mut buf, first_chunk_len, done)?;
         }
@@ -775,7 +1279,13 @@ impl TextBuffer {
             }
 
             // We'll assume CRLF if more than half of the lines end in CRLF.
-            let newlines_are_crlf = crlf_count >= lines / 2;
+            // On a tie (e.g. there are no newlines at all), we keep the default.
+            let newlines_are_crlf = match (crlf_count * 2).cmp(&lines) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => self.newlines_are_crlf,
+                std::cmp::Ordering::Greater => true,
+            };
+            let newlines_are_mixed = crlf_count != 0 && crlf_count != lines;
 
             // We'll assume tabs if there are more lines starting with tabs than with spaces.
             let indent_with_tabs = tab_indentations > space_indentations;
@@ -808,9 +1318,14 @@ impl TextBuffer {
             self.stats.logical_lines = lines + 1;
             self.stats.visual_lines = self.stats.logical_lines;
             self.newlines_are_crlf = newlines_are_crlf;
+            self.newlines_are_mixed = newlines_are_mixed;
             self.insert_final_newline = final_newline;
-            self.indent_with_tabs = indent_with_tabs;
-            self.tab_size = tab_size;
+
+            // Files without any indentation keep the current settings, e.g. the user's defaults.
+            if tab_indentations + space_indentations > 0 {
+                self.indent_with_tabs = indent_with_tabs;
+                self.tab_size = tab_size;
+            }
         }
 
         self.recalc_after_content_swap();
@@ -936,7 +1451,7 @@ impl TextBuffer {
     }
 
     /// Writes the text buffer contents to a file, handling BOM and encoding.
-    pub fn write_file(&mut self, file: &mut File) -> apperr::Result<()> {
+    pub fn write_file(&mut self, file: &mut impl io::Write) -> apperr::Result<()> {
         let mut offset = 0;
 
         if self.encoding.starts_with("UTF-8") {
@@ -959,7 +1474,7 @@ impl TextBuffer {
         Ok(())
     }
 
-    fn write_file_with_icu(&mut self, file: &mut File) -> apperr::Result<()> {
+    fn write_file_with_icu(&mut self, file: &mut impl io::Write) -> apperr::Result<()> {
         let scratch = scratch_arena(None);
         let pivot_buffer = scratch.alloc_uninit_slice(4 * KIBI);
         let buf = scratch.alloc_uninit_slice(4 * KIBI);
@@ -1010,6 +1525,7 @@ impl TextBuffer {
 
     /// Moves the cursor to `visual_pos` and updates the selection to contain it.
     pub fn selection_update_visual(&mut self, visual_pos: Point) {
+        let visual_pos = Point { x: visual_pos.x, y: self.unfold_visual_y(visual_pos.y) };
         self.set_cursor_for_selection(self.cursor_move_to_visual_internal(self.cursor, visual_pos));
     }
 
@@ -1041,6 +1557,25 @@ impl TextBuffer {
         }));
     }
 
+    /// Returns the letters, digits and underscores right before the cursor,
+    /// e.g. the prefix of a snippet that was just typed.
+    pub fn word_before_cursor(&self) -> String {
+        let mut word = Vec::new();
+        let mut off = self.cursor.offset;
+        'outer: while off > 0 {
+            let chunk = self.read_backward(off);
+            for &c in chunk.iter().rev() {
+                if !c.is_ascii_alphanumeric() && c != b'_' {
+                    break 'outer;
+                }
+                word.push(c);
+                off -= 1;
+            }
+        }
+        word.reverse();
+        String::from_utf8(word).unwrap_or_default()
+    }
+
     /// Select the current line.
     pub fn select_line(&mut self) {
         let beg = self.cursor_move_to_logical_internal(
@@ -1056,6 +1591,66 @@ impl TextBuffer {
         }));
     }
 
+    /// Selects the given byte range, with the cursor at its end.
+    pub fn select_range(&mut self, range: Range<usize>) {
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let end = self.cursor_move_to_offset_internal(beg, range.end);
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+    }
+
+    /// Returns the smallest of the word, the lines, the paragraph and the whole document
+    /// around `range` that's larger than it. That's how a selection grows without a syntax tree.
+    /// Returns `None` if the `range` covers the entire document already.
+    pub fn expand_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let grows = |r: &Range<usize>| r.start <= range.start && r.end >= range.end && *r != range;
+
+        let word = navigation::word_select(&self.buffer, range.start);
+        if grows(&word) {
+            return Some(word);
+        }
+
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let end = self.cursor_move_to_offset_internal(beg, range.end);
+        let mut first = beg.logical_pos.y;
+        let mut last = end.logical_pos.y;
+        // A selection of whole lines ends at the start of the next one.
+        if end.logical_pos.x == 0 && last > first {
+            last -= 1;
+        }
+        let lines = |first: CoordType, last: CoordType| {
+            let start = self.cursor_move_to_logical_internal(beg, Point { x: 0, y: first });
+            let end = self.cursor_move_to_logical_internal(start, Point { x: 0, y: last + 1 });
+            start.offset..end.offset
+        };
+
+        let line = lines(first, last);
+        if grows(&line) {
+            return Some(line);
+        }
+
+        let is_blank = |y: CoordType| {
+            let start = self.cursor_move_to_logical_internal(beg, Point { x: 0, y });
+            self.trailing_whitespace_start(start) == start.offset
+        };
+        while first > 0 && !is_blank(first - 1) {
+            first -= 1;
+        }
+        while last + 1 < self.logical_line_count() && !is_blank(last + 1) {
+            last += 1;
+        }
+        let paragraph = lines(first, last);
+        if grows(&paragraph) {
+            return Some(paragraph);
+        }
+
+        let all = 0..self.text_length();
+        grows(&all).then_some(all)
+    }
+
     /// Select the entire document.
     pub fn select_all(&mut self) {
         let beg = Default::default();
@@ -1084,12 +1679,217 @@ impl TextBuffer {
         had_selection
     }
 
+    /// Returns whether there are any cursors besides the primary one.
+    pub fn has_extra_cursors(&self) -> bool {
+        !self.extra_cursors().is_empty()
+    }
+
+    fn extra_cursors(&self) -> &[Range<usize>] {
+        if self.extra_cursors_generation == self.buffer.generation() {
+            &self.extra_cursors
+        } else {
+            &[]
+        }
+    }
+
+    /// Removes all cursors but the primary one.
+    pub fn clear_extra_cursors(&mut self) -> bool {
+        let had_extra_cursors = self.has_extra_cursors();
+        self.extra_cursors.clear();
+        had_extra_cursors
+    }
+
+    /// Selects the next occurrence of the current selection with an additional cursor.
+    /// If there's no selection, the word under the cursor is selected instead.
+    pub fn add_cursor_at_next_occurrence(&mut self) {
+        let Some((beg, end)) = self.selection_range() else {
+            self.select_word();
+            return;
+        };
+
+        if !self.has_extra_cursors() {
+            self.extra_cursors.clear();
+        }
+
+        let mut needle = Vec::new();
+        let mut text = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut needle, 0);
+        self.buffer.extract_raw(0..self.text_length(), &mut text, 0);
+
+        let find = |from: usize| {
+            text[from..].windows(needle.len()).position(|w| w == needle).map(|i| from + i)
+        };
+        let is_taken = |hit: &Range<usize>| {
+            let primary = beg.offset..end.offset;
+            self.extra_cursors
+                .iter()
+                .chain([&primary])
+                .any(|r| r.start < hit.end && hit.start < r.end)
+        };
+
+        // Search forward from the primary cursor and wrap around once.
+        let mut from = end.offset;
+        let mut wrapped = false;
+        let hit = loop {
+            match find(from) {
+                Some(off) if wrapped && off >= end.offset => break None,
+                Some(off) => {
+                    let hit = off..off + needle.len();
+                    if !is_taken(&hit) {
+                        break Some(hit);
+                    }
+                    from = off + 1;
+                }
+                None if !wrapped => {
+                    wrapped = true;
+                    from = 0;
+                }
+                None => break None,
+            }
+        };
+
+        // The new occurrence becomes the primary cursor, so that the view follows it.
+        if let Some(hit) = hit {
+            self.extra_cursors.push(beg.offset..end.offset);
+            self.extra_cursors_generation = self.buffer.generation();
+            self.select_range_internal(hit);
+            self.make_cursor_visible();
+        }
+    }
+
+    /// Returns the corners of the block selection as (anchor, head), if there is one.
+    /// See [`TextBuffer::block_selection_extend`] for their coordinates.
+    pub fn block_selection(&self) -> Option<(Point, Point)> {
+        self.block_selection.filter(|_| {
+            self.block_selection_generation == self.selection_generation
+                && self.extra_cursors_generation == self.buffer.generation()
+        })
+    }
+
+    /// Moves the corner of the block selection opposite to its anchor by `delta`,
+    /// in logical lines (.y) and visual columns (.x). If there's no block selection,
+    /// one is started at the cursor. Columns past the end of a line are allowed.
+    pub fn block_selection_extend(&mut self, delta: Point) {
+        let head = match self.block_selection() {
+            Some((_, head)) => head,
+            None => Point { x: self.cursor.column, y: self.cursor.logical_pos.y },
+        };
+        self.block_selection_update(Point { x: head.x + delta.x, y: head.y + delta.y });
+    }
+
+    /// Like [`TextBuffer::block_selection_extend`], but moves the corner to `visual_pos`.
+    pub fn block_selection_update_visual(&mut self, visual_pos: Point) {
+        let visual_pos = Point { x: visual_pos.x, y: self.unfold_visual_y(visual_pos.y) };
+        let cursor = self.cursor_move_to_visual_internal(self.cursor, visual_pos);
+        // Without word wrap, visual columns map 1:1 to columns, including those past the line end.
+        let x = if self.word_wrap_column <= 0 { visual_pos.x } else { cursor.column };
+        self.block_selection_update(Point { x, y: cursor.logical_pos.y });
+    }
+
+    fn block_selection_update(&mut self, head: Point) {
+        let anchor = match self.block_selection() {
+            Some((anchor, _)) => anchor,
+            None => Point { x: self.cursor.column, y: self.cursor.logical_pos.y },
+        };
+        let head = Point { x: head.x.max(0), y: head.y.clamp(0, self.logical_line_count() - 1) };
+        let [left, right] = minmax(anchor.x, head.x);
+        let [top, bottom] = minmax(anchor.y, head.y);
+
+        let mut ranges: Vec<_> = (top..=bottom)
+            .map(|y| self.offset_at_column(y, left)..self.offset_at_column(y, right))
+            .collect();
+        let primary = ranges.remove((head.y - top) as usize);
+
+        // The caret goes on the side of the head, like with a regular selection.
+        let beg = self.cursor_move_to_offset_internal(self.cursor, primary.start);
+        let end = self.cursor_move_to_offset_internal(beg, primary.end);
+        let (from, to) = if head.x < anchor.x { (end, beg) } else { (beg, end) };
+        unsafe { self.set_cursor(to) };
+        self.block_selection_generation = self.set_selection(Some(TextBufferSelection {
+            beg: from.logical_pos,
+            end: to.logical_pos,
+        }));
+        self.block_selection = Some((anchor, head));
+        self.extra_cursors = ranges;
+        self.extra_cursors_generation = self.buffer.generation();
+        self.make_cursor_visible();
+    }
+
+    /// Returns the offset of visual `column` in logical line `y`,
+    /// or of the end of the line, if it's shorter than that.
+    fn offset_at_column(&self, y: CoordType, column: CoordType) -> usize {
+        let start = self.goto_line_start(self.cursor, y);
+        // Measured without word wrap, so that the visual position is the column.
+        let start = Cursor {
+            visual_pos: Point { x: 0, y: start.logical_pos.y },
+            column: 0,
+            wrap_opp: false,
+            ..start
+        };
+        MeasurementConfig::new(&self.buffer)
+            .with_tab_size(self.tab_size)
+            .with_cursor(start)
+            .goto_visual(Point { x: column, y: start.logical_pos.y })
+            .offset
+    }
+
+    /// Returns the ranges of a block selection from top to bottom,
+    /// unless it's just carets, which are better handled like any other cursors.
+    fn block_selection_ranges(&self) -> Option<Vec<Range<usize>>> {
+        self.block_selection()?;
+        let primary = match self.selection_range_internal(false) {
+            Some((beg, end)) => beg.offset..end.offset,
+            None => self.cursor.offset..self.cursor.offset,
+        };
+        let mut ranges = self.extra_cursors.clone();
+        ranges.push(primary);
+        ranges.sort_unstable_by_key(|r| r.start);
+        ranges.iter().any(|r| !r.is_empty()).then_some(ranges)
+    }
+
+    /// When typing into a block selection, lines that end before its left edge
+    /// are padded with spaces, so that the text lines up.
+    fn block_selection_pad(&mut self, column: CoordType) {
+        let line_end = self.cursor_move_to_logical_internal(
+            self.cursor,
+            Point { x: CoordType::MAX, y: self.cursor.logical_pos.y },
+        );
+        if self.selection.is_none()
+            && self.cursor.offset == line_end.offset
+            && self.cursor.column < column
+        {
+            let spaces = " ".repeat((column - self.cursor.column) as usize);
+            self.write(spaces.as_bytes(), self.cursor, true);
+        }
+    }
+
+    fn select_range_internal(&mut self, range: Range<usize>) {
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let end = self.cursor_move_to_offset_internal(beg, range.end);
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+    }
+
     /// Find the next occurrence of the given `pattern` and select it.
     pub fn find_and_select(&mut self, pattern: &str, options: SearchOptions) -> apperr::Result<()> {
+        self.find_and_select_in_range(pattern, options, 0..usize::MAX)
+    }
+
+    /// Like [`TextBuffer::find_and_select`], but only finds occurrences that lie
+    /// entirely within the byte offset `range`. Wraps around to the start of the range.
+    pub fn find_and_select_in_range(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+        range: Range<usize>,
+    ) -> apperr::Result<()> {
         if let Some(search) = &mut self.search {
             let search = search.get_mut();
             // When the search input changes we must reset the search.
-            if search.pattern != pattern || search.options != options {
+            if search.pattern != pattern || search.options != options || search.range != range {
                 self.search = None;
             }
 
@@ -1108,7 +1908,8 @@ impl TextBuffer {
         let search = match &self.search {
             Some(search) => unsafe { &mut *search.get() },
             None => {
-                let search = self.find_construct_search(pattern, options)?;
+                let mut search = self.find_construct_search(pattern, options)?;
+                search.range = range;
                 self.search = Some(UnsafeCell::new(search));
                 unsafe { &mut *self.search.as_ref().unwrap().get() }
             }
@@ -1138,26 +1939,46 @@ impl TextBuffer {
     }
 
     /// Find the next occurrence of the given `pattern` and replace it with `replacement`.
+    ///
+    /// With [`SearchOptions::preserve_case`], a replacement for a match that is all lowercase,
+    /// all uppercase, or capitalized (like "Color") is converted to the same case.
+    /// Any other match, e.g. "camelCase", leaves the replacement as typed.
     pub fn find_and_replace(
         &mut self,
         pattern: &str,
         options: SearchOptions,
         replacement: &[u8],
     ) -> apperr::Result<()> {
+        self.find_and_replace_in_range(pattern, options, replacement, 0..usize::MAX)?;
+        Ok(())
+    }
+
+    /// Like [`TextBuffer::find_and_replace`], but only for occurrences that lie
+    /// entirely within the byte offset `range`. Returns the range adjusted for the replacement.
+    pub fn find_and_replace_in_range(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+        replacement: &[u8],
+        mut range: Range<usize>,
+    ) -> apperr::Result<Range<usize>> {
         // Editors traditionally replace the previous search hit, not the next possible one.
-        if let (Some(search), Some(..)) = (&self.search, &self.selection) {
+        if let (Some(search), Some((_, hit_end))) = (&self.search, self.selection_range()) {
             let search = unsafe { &mut *search.get() };
-            if search.selection_generation == self.selection_generation {
+            if search.selection_generation == self.selection_generation && search.range == range {
                 let scratch = scratch_arena(None);
                 let parsed_replacements =
                     Self::find_parse_replacement(&scratch, &mut *search, replacement);
                 let replacement =
                     self.find_fill_replacement(&mut *search, replacement, &parsed_replacements);
                 self.write(&replacement, self.cursor, true);
+                // The end of the range moves by however much the replacement grew or shrunk the text.
+                range.end = (range.end - hit_end.offset).saturating_add(self.cursor.offset);
             }
         }
 
-        self.find_and_select(pattern, options)
+        self.find_and_select_in_range(pattern, options, range.clone())?;
+        Ok(range)
     }
 
     /// Find all occurrences of the given `pattern` and replace them with `replacement`.
@@ -1167,26 +1988,119 @@ impl TextBuffer {
         options: SearchOptions,
         replacement: &[u8],
     ) -> apperr::Result<()> {
-        let scratch = scratch_arena(None);
-        let mut search = self.find_construct_search(pattern, options)?;
-        let mut offset = 0;
-        let parsed_replacements = Self::find_parse_replacement(&scratch, &mut search, replacement);
-
-        loop {
-            self.find_select_next(&mut search, offset, false);
-            if !self.has_selection() {
-                break;
-            }
+        self.find_replace_all_internal(pattern, options, replacement, 0..usize::MAX)?;
+        Ok(())
+    }
 
-            let replacement =
-                self.find_fill_replacement(&mut search, replacement, &parsed_replacements);
-            self.write(&replacement, self.cursor, true);
-            offset = self.cursor.offset;
+    /// Find all occurrences of the given `pattern` that lie entirely within
+    /// the byte offset `range` and replace them with `replacement`.
+    ///
+    /// Afterwards, the range is selected again, adjusted for the replacements
+    /// that were made. The adjusted range is returned as well.
+    pub fn find_and_replace_all_in_range(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+        replacement: &[u8],
+        range: Range<usize>,
+    ) -> apperr::Result<Range<usize>> {
+        let range = self.find_replace_all_internal(pattern, options, replacement, range)?;
+
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let end = self.cursor_move_to_offset_internal(beg, range.end);
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+
+        Ok(range.start..end.offset)
+    }
+
+    /// Find all occurrences of the given `pattern` and select each of them with its own cursor.
+    /// The last occurrence becomes the primary cursor.
+    pub fn find_and_select_all(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+    ) -> apperr::Result<()> {
+        let mut ranges: Vec<_> = self.search_iter(pattern, options, 0..usize::MAX)?.collect();
+
+        if let Some(last) = ranges.pop() {
+            self.select_range_internal(last);
+            self.make_cursor_visible();
+            self.extra_cursors = ranges;
+            self.extra_cursors_generation = self.buffer.generation();
         }
 
         Ok(())
     }
 
+    /// Returns the byte ranges of the occurrences of `pattern` that lie entirely
+    /// within the byte offset `range`, without touching the cursor or selection.
+    ///
+    /// The matches are found as the iterator advances, so stopping early
+    /// (e.g. when counting up to a limit) skips searching the rest of the text.
+    pub fn search_iter(
+        &self,
+        pattern: &str,
+        options: SearchOptions,
+        range: Range<usize>,
+    ) -> apperr::Result<impl Iterator<Item = Range<usize>> + '_> {
+        let mut search = self.find_construct_search(pattern, options)?;
+        // ICU doesn't reset to the end of the text, but keeps its old position instead.
+        // There's nothing to find there anyway.
+        let exhausted = range.start >= self.text_length();
+        if !exhausted {
+            search.regex.reset(range.start);
+        }
+
+        let hits = std::iter::from_fn(move || {
+            if exhausted {
+                return None;
+            }
+            // Borrowing all of `search` moves it into the closure as a whole.
+            // Otherwise only the regex would be, and the text it refers to would be dropped.
+            let search = &mut search;
+            search.regex.next()
+        });
+        Ok(hits.take_while(move |hit| hit.end <= range.end))
+    }
+
+    fn find_replace_all_internal(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+        replacement: &[u8],
+        range: Range<usize>,
+    ) -> apperr::Result<Range<usize>> {
+        let scratch = scratch_arena(None);
+        let mut search = self.find_construct_search(pattern, options)?;
+        let mut offset = range.start;
+        let mut limit = range.end;
+        let parsed_replacements = Self::find_parse_replacement(&scratch, &mut search, replacement);
+
+        loop {
+            self.find_select_next(&mut search, offset, false);
+            let Some((_, hit_end)) = self.selection_range() else {
+                break;
+            };
+            if hit_end.offset > limit {
+                self.set_selection(None);
+                break;
+            }
+
+            let replacement =
+                self.find_fill_replacement(&mut search, replacement, &parsed_replacements);
+            self.write(&replacement, self.cursor, true);
+            offset = self.cursor.offset;
+            // The end of the range moves by however much the replacement grew or shrunk the text.
+            limit = (limit - hit_end.offset).saturating_add(offset);
+        }
+
+        Ok(range.start..limit)
+    }
+
     fn find_construct_search(
         &self,
         pattern: &str,
@@ -1243,11 +2157,14 @@ impl TextBuffer {
             buffer_generation: self.buffer.generation(),
             selection_generation: 0,
             next_search_offset: 0,
+            range: 0..usize::MAX,
             no_matches: false,
         })
     }
 
     fn find_select_next(&mut self, search: &mut ActiveSearch, offset: usize, wrap: bool) {
+        // Hits must not start before the range.
+        let offset = offset.max(search.range.start);
         if search.buffer_generation != self.buffer.generation() {
             unsafe { search.regex.set_text(&mut search.text, offset) };
             search.buffer_generation = self.buffer.generation();
@@ -1257,14 +2174,15 @@ impl TextBuffer {
             search.regex.reset(offset);
         }
 
-        let mut hit = search.regex.next();
+        let range = search.range.clone();
+        let mut hit = search.regex.next().filter(|hit| hit.end <= range.end);
 
         // If we hit the end of the buffer, and we know that there's something to find,
         // start the search again from the beginning (= wrap around).
-        if wrap && hit.is_none() && search.next_search_offset != 0 {
-            search.next_search_offset = 0;
-            search.regex.reset(0);
-            hit = search.regex.next();
+        if wrap && hit.is_none() && search.next_search_offset > range.start {
+            search.next_search_offset = range.start;
+            search.regex.reset(range.start);
+            hit = search.regex.next().filter(|hit| hit.end <= range.end);
         }
 
         search.selection_generation = if let Some(range) = hit {
@@ -1391,6 +2309,27 @@ impl TextBuffer {
         search: &mut ActiveSearch,
         replacement: &'a [u8],
         parsed_replacements: &[RegexReplacement],
+    ) -> Cow<'a, [u8]> {
+        let res = self.find_fill_replacement_groups(search, replacement, parsed_replacements);
+
+        if search.options.preserve_case
+            && let Some((beg, end)) = self.selection_range()
+        {
+            let mut matched = Vec::new();
+            self.buffer.extract_raw(beg.offset..end.offset, &mut matched, 0);
+            if let Some(res) = apply_case(&String::from_utf8_lossy(&matched), &res) {
+                return Cow::Owned(res.into_bytes());
+            }
+        }
+
+        res
+    }
+
+    fn find_fill_replacement_groups<'a>(
+        &self,
+        search: &mut ActiveSearch,
+        replacement: &'a [u8],
+        parsed_replacements: &[RegexReplacement],
     ) -> Cow<'a, [u8]> {
         if !search.options.use_regex {
             Cow::Borrowed(replacement)
@@ -1658,6 +2597,7 @@ impl TextBuffer {
 
     /// Moves the cursor to the given visual position.
     pub fn cursor_move_to_visual(&mut self, pos: Point) {
+        let pos = Point { x: pos.x, y: self.unfold_visual_y(pos.y) };
         unsafe { self.set_cursor(self.cursor_move_to_visual_internal(self.cursor, pos)) }
     }
 
@@ -1676,6 +2616,7 @@ impl TextBuffer {
         self.set_cursor_internal(cursor);
         self.last_history_type = HistoryType::Other;
         self.set_selection(None);
+        self.unfold_at_cursor();
     }
 
     fn set_cursor_for_selection(&mut self, cursor: Cursor) {
@@ -1689,6 +2630,7 @@ impl TextBuffer {
 
         let end = self.cursor.logical_pos;
         self.set_selection(if beg == end { None } else { Some(TextBufferSelection { beg, end }) });
+        self.unfold_at_cursor();
     }
 
     fn set_cursor_internal(&mut self, cursor: Cursor) {
@@ -1718,11 +2660,15 @@ impl TextBuffer {
         if destination.is_empty() {
             return None;
         }
+        if focused {
+            self.scroll_offset = origin;
+        }
 
         let scratch = scratch_arena(None);
         let width = destination.width();
         let height = destination.height();
-        let line_number_width = self.margin_width.max(3) as usize - 3;
+        let annotation_width = self.margin_annotation_width;
+        let line_number_width = (self.margin_width - annotation_width).max(3) as usize - 3;
         let text_width = width - self.margin_width;
         let mut visualizer_buf = [0xE2, 0x90, 0x80]; // U+2400 in UTF8
         let mut line = ArenaString::new_in(&scratch);
@@ -1742,12 +2688,34 @@ impl TextBuffer {
             Some(TextBufferSelection { beg, end }) => minmax(beg, end),
         };
 
+        let mut selection_bg = oklab_blend(
+            fb.indexed(IndexedColor::Foreground),
+            fb.indexed_alpha(IndexedColor::BrightBlue, 1, 2),
+        );
+        if !focused {
+            selection_bg =
+                oklab_blend(selection_bg, fb.indexed_alpha(IndexedColor::Background, 1, 2))
+        };
+        let selection_fg = fb.contrasted(selection_bg);
+
+        // The bracket next to the cursor and its partner get highlighted.
+        let brackets =
+            if focused && self.selection.is_none() { self.matching_bracket() } else { None };
+
+        // The rows whose margin gets marked as changed, see `set_diff_base`,
+        // and those whose line number gets colored, see `set_line_marks`.
+        let mut change_marks = Vec::new();
+        let mut number_marks = Vec::new();
+        if line_number_width != 0 {
+            self.line_changes();
+        }
+
         line.reserve(width as usize * 2);
 
         for y in 0..height {
             line.clear();
 
-            let visual_line = origin.y + y;
+            let visual_line = self.unfold_visual_y(origin.y + y);
             let mut cursor_beg =
                 self.cursor_move_to_visual_internal(cursor, Point { x: origin.x, y: visual_line });
             let cursor_end = self.cursor_move_to_visual_internal(
@@ -1761,6 +2729,20 @@ impl TextBuffer {
             }
 
             if line_number_width != 0 {
+                // The annotation is only shown once per logical line, just like its number.
+                if annotation_width != 0 {
+                    let annotation = if visual_line < self.stats.visual_lines
+                        && (self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0)
+                    {
+                        self.margin_annotations
+                            .get(cursor_beg.logical_pos.y as usize)
+                            .map_or("", |a| a.as_str())
+                    } else {
+                        ""
+                    };
+                    _ = write!(line, "{annotation:0$}", annotation_width as usize);
+                }
+
                 if visual_line >= self.stats.visual_lines {
                     // Past the end of the buffer? Place "    | " in the margin.
                     // Since we know that we won't see line numbers greater than i64::MAX (9223372036854775807)
@@ -1770,8 +2752,14 @@ impl TextBuffer {
                     unsafe { std::hint::assert_unchecked(off < MARGIN_TEMPLATE.len()) };
                     line.push_str(&MARGIN_TEMPLATE[off..]);
                 } else if self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0 {
-                    // Regular line? Place "123 | " in the margin.
-                    _ = write!(line, "{:1$} │ ", cursor_beg.logical_pos.y + 1, line_number_width);
+                    // Regular line? Place "123 | " in the margin, with a marker if it can be folded.
+                    _ = write!(
+                        line,
+                        "{:1$}{2}│ ",
+                        cursor_beg.logical_pos.y + 1,
+                        line_number_width,
+                        self.fold_marker(cursor_beg.logical_pos.y)
+                    );
                 } else {
                     // Wrapped line? Place " ... | " in the margin.
                     let number_width = (cursor_beg.logical_pos.y + 1).ilog10() as usize + 1;
@@ -1783,7 +2771,7 @@ impl TextBuffer {
                         number_width
                     );
                     // Blending in the background color will "dim" the indicator dots.
-                    let left = destination.left;
+                    let left = destination.left + annotation_width;
                     let top = destination.top + y;
                     fb.blend_fg(
                         Rect {
@@ -1795,6 +2783,19 @@ impl TextBuffer {
                         fb.indexed_alpha(IndexedColor::Background, 1, 2),
                     );
                 }
+
+                let logical_y = cursor_beg.logical_pos.y;
+                if visual_line < self.stats.visual_lines
+                    && let Ok(i) = self.line_changes.binary_search_by_key(&logical_y, |&(y, _)| y)
+                {
+                    change_marks.push((y, self.line_changes[i].1));
+                }
+                if visual_line < self.stats.visual_lines
+                    && (self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0)
+                    && let Ok(i) = self.line_marks.binary_search_by_key(&logical_y, |&(y, _)| y)
+                {
+                    number_marks.push((y, self.line_marks[i].1));
+                }
             }
 
             let mut selection_off = 0..0;
@@ -1839,20 +2840,82 @@ impl TextBuffer {
                     bottom: top + 1,
                 };
 
-                let mut bg = oklab_blend(
-                    fb.indexed(IndexedColor::Foreground),
-                    fb.indexed_alpha(IndexedColor::BrightBlue, 1, 2),
-                );
-                if !focused {
-                    bg = oklab_blend(bg, fb.indexed_alpha(IndexedColor::Background, 1, 2))
-                };
-                let fg = fb.contrasted(bg);
-                fb.blend_bg(rect, bg);
-                fb.blend_fg(rect, fg);
+                fb.blend_bg(rect, selection_bg);
+                fb.blend_fg(rect, selection_fg);
+            }
+
+            // Draw the selections and carets of any additional cursors.
+            for range in self.extra_cursors() {
+                if range.end < cursor_beg.offset
+                    || range.start > cursor_end.offset
+                    || (range.start == cursor_end.offset && !range.is_empty())
+                {
+                    continue;
+                }
+
+                let mut cursor = cursor_beg;
+                let mut pos_beg = 0;
+                let mut pos_end = COORD_TYPE_SAFE_MAX;
+                let left = destination.left + self.margin_width - origin.x;
+                let top = destination.top + y;
+
+                if range.start >= cursor_beg.offset {
+                    cursor = self.cursor_move_to_offset_internal(cursor, range.start);
+                    pos_beg = cursor.visual_pos.x;
+                }
+
+                if range.end <= cursor_end.offset {
+                    cursor = self.cursor_move_to_offset_internal(cursor, range.end);
+                    pos_end = cursor.visual_pos.x;
+
+                    // Carets exactly at a wrap point belong to the next visual line.
+                    if focused
+                        && cursor.visual_pos.y == visual_line
+                        && (origin.x..origin.x + text_width).contains(&pos_end)
+                    {
+                        let rect = Rect {
+                            left: left + pos_end,
+                            top,
+                            right: left + pos_end + 1,
+                            bottom: top + 1,
+                        };
+                        fb.blend_bg(rect, fb.indexed(IndexedColor::Foreground));
+                        fb.blend_fg(rect, fb.indexed(IndexedColor::Background));
+                    }
+                }
+
+                if pos_beg < pos_end {
+                    let rect = Rect {
+                        left: left + pos_beg.max(origin.x),
+                        top,
+                        right: left + pos_end.min(origin.x + text_width),
+                        bottom: top + 1,
+                    };
+                    fb.blend_bg(rect, selection_bg);
+                    fb.blend_fg(rect, selection_fg);
+                }
+            }
+
+            if let Some((bracket, partner)) = brackets {
+                for off in [bracket, partner] {
+                    if off < cursor_beg.offset || off >= cursor_end.offset {
+                        continue;
+                    }
+
+                    let x = self.cursor_move_to_offset_internal(cursor_beg, off).visual_pos.x;
+                    if x >= origin.x {
+                        let left = destination.left + self.margin_width - origin.x + x;
+                        let top = destination.top + y;
+                        fb.blend_bg(
+                            Rect { left, top, right: left + 1, bottom: top + 1 },
+                            fb.indexed_alpha(IndexedColor::Foreground, 1, 4),
+                        );
+                    }
+                }
             }
 
             if self.highlight_generation == self.buffer.generation() {
-                for (range, highlight) in &self.highlights {
+                for (range, color) in &self.highlights {
                     if range.start >= cursor_end.offset || range.end <= cursor_beg.offset {
                         continue;
                     }
@@ -1880,8 +2943,7 @@ impl TextBuffer {
                         bottom: top + 1,
                     };
 
-                    let fg = highlight_to_color(highlight.0, fb);
-                    fb.blend_fg(rect, fg);
+                    fb.blend_fg(rect, color.resolve(fb));
                 }
             }
 
@@ -1907,6 +2969,12 @@ impl TextBuffer {
                 let mut global_off = cursor_beg.offset;
                 let mut cursor_line = cursor_beg;
 
+                // Where the trailing whitespace of the logical line starts, if it's shown at all.
+                let trailing_off = match self.whitespace_visibility {
+                    WhitespaceVisibility::Off => usize::MAX,
+                    _ => self.trailing_whitespace_start(cursor_beg),
+                };
+
                 while global_off < cursor_end.offset {
                     let chunk = self.read_forward(global_off);
                     let chunk = &chunk[..chunk.len().min(cursor_end.offset - global_off)];
@@ -1923,7 +2991,10 @@ impl TextBuffer {
 
                         if ch == ' ' || ch == '\t' {
                             let is_tab = ch == '\t';
-                            let visualize = selection_off.contains(&global_off);
+                            let trailing = global_off >= trailing_off;
+                            let visualize = trailing
+                                || selection_off.contains(&global_off)
+                                || self.whitespace_visibility == WhitespaceVisibility::All;
                             let mut whitespace = TAB_WHITESPACE;
                             let mut prefix_add = 0;
 
@@ -1947,19 +3018,22 @@ impl TextBuffer {
                                     (VISUAL_SPACE, VISUAL_SPACE_PREFIX_ADD)
                                 };
 
-                                // Make the visualized characters slightly gray.
+                                // Make the visualized characters slightly gray,
+                                // or red if it's trailing whitespace that is likely unwanted.
                                 let visualizer_rect = {
                                     let left = destination.left
                                         + self.margin_width
                                         + cursor_line.visual_pos.x
                                         - origin.x;
-                                    let top = destination.top + cursor_line.visual_pos.y - origin.y;
+                                    let top = destination.top + y;
                                     Rect { left, top, right: left + 1, bottom: top + 1 }
                                 };
-                                fb.blend_fg(
-                                    visualizer_rect,
-                                    fb.indexed_alpha(IndexedColor::Foreground, 1, 2),
-                                );
+                                let color = if trailing {
+                                    IndexedColor::BrightRed
+                                } else {
+                                    IndexedColor::Foreground
+                                };
+                                fb.blend_fg(visualizer_rect, fb.indexed_alpha(color, 1, 2));
                             }
 
                             line.push_str(&whitespace[..prefix_add + tab_size as usize]);
@@ -1983,7 +3057,7 @@ impl TextBuffer {
                                 let left =
                                     destination.left + self.margin_width + cursor_line.visual_pos.x
                                         - origin.x;
-                                let top = destination.top + cursor_line.visual_pos.y - origin.y;
+                                let top = destination.top + y;
                                 Rect { left, top, right: left + 1, bottom: top + 1 }
                             };
                             let bg = fb.indexed(IndexedColor::Yellow);
@@ -2001,6 +3075,23 @@ impl TextBuffer {
                 visual_pos_x_max = visual_pos_x_max.max(cursor_end.visual_pos.x);
             }
 
+            // Folded lines are replaced by a placeholder at the end of the line before them.
+            if self.fold_rows().iter().any(|rows| rows.start == visual_line + 1) {
+                line.push_str(" …");
+
+                let left = destination.left
+                    + self.margin_width
+                    + (cursor_end.visual_pos.x - origin.x).max(0)
+                    + 1;
+                let top = destination.top + y;
+                if left < destination.right {
+                    fb.blend_fg(
+                        Rect { left, top, right: left + 1, bottom: top + 1 },
+                        fb.indexed_alpha(IndexedColor::Foreground, 1, 2),
+                    );
+                }
+            }
+
             fb.replace_text(destination.top + y, destination.left, destination.right, &line);
 
             cursor = cursor_end;
@@ -2015,22 +3106,42 @@ impl TextBuffer {
                 bottom: destination.bottom,
             };
             fb.blend_fg(margin, 0x7f3f3f3f);
+
+            // Changed lines get the separator between the line numbers and the text colored.
+            let left = destination.left + annotation_width + line_number_width as CoordType + 1;
+            for (y, color) in change_marks {
+                let top = destination.top + y;
+                let rect = Rect { left, top, right: left + 1, bottom: top + 1 };
+                fb.blend_fg(rect, fb.indexed(color));
+            }
+
+            let left = destination.left + annotation_width;
+            let right = left + line_number_width as CoordType;
+            for (y, color) in number_marks {
+                let top = destination.top + y;
+                let rect = Rect { left, top, right, bottom: top + 1 };
+                fb.blend_fg(rect, fb.indexed(color));
+            }
         }
 
-        if self.ruler > 0 {
-            let left = destination.left + self.margin_width + (self.ruler - origin.x).max(0);
-            let right = destination.right;
-            if left < right {
-                fb.blend_bg(
-                    Rect { left, top: destination.top, right, bottom: destination.bottom },
-                    fb.indexed_alpha(IndexedColor::BrightRed, 1, 4),
-                );
+        // The ruler is a thin column right after the given one, just like the right margin
+        // of a page. It's blended, so it doesn't hide the text or selection underneath.
+        if self.ruler > 0 && self.ruler >= origin.x {
+            let left = destination.left + self.margin_width + self.ruler - origin.x;
+            if left < destination.right {
+                let ruler = Rect {
+                    left,
+                    top: destination.top,
+                    right: left + 1,
+                    bottom: destination.bottom,
+                };
+                fb.blend_bg(ruler, fb.indexed_alpha(IndexedColor::BrightRed, 1, 4));
             }
         }
 
         if focused {
             let mut x = self.cursor.visual_pos.x;
-            let mut y = self.cursor.visual_pos.y;
+            let mut y = self.fold_visual_y(self.cursor.visual_pos.y);
 
             if self.word_wrap_column > 0 && x >= self.word_wrap_column {
                 // The line the cursor is on wraps exactly on the word wrap column which
@@ -2080,15 +3191,15 @@ impl TextBuffer {
     }
 
     fn cut_copy(&mut self, clipboard: &mut Clipboard, cut: bool) {
-        let line_copy = !self.has_selection();
-        let selection = self.extract_selection(cut);
+        let line_copy = !self.has_selection() && self.block_selection_ranges().is_none();
+        let selection = self.extract_selection(cut && !self.read_only);
         clipboard.write(selection);
         clipboard.write_was_line_copy(line_copy);
     }
 
     pub fn paste(&mut self, clipboard: &Clipboard) {
         let data = clipboard.read();
-        if data.is_empty() {
+        if data.is_empty() || self.read_only {
             return;
         }
 
@@ -2109,13 +3220,248 @@ impl TextBuffer {
     /// Inserts the user input `text` at the current cursor position.
     /// Replaces tabs with whitespace if needed, etc.
     pub fn write_canon(&mut self, text: &[u8]) {
+        if self.read_only {
+            return;
+        }
+        if self.wants_edit_each_cursor() {
+            let pad = self.block_selection().map(|(anchor, head)| anchor.x.min(head.x));
+            self.edit_each_cursor(|tb| {
+                if let Some(column) = pad {
+                    tb.block_selection_pad(column);
+                }
+                tb.write_typed(text);
+            });
+        } else {
+            self.write_typed(text);
+        }
+    }
+
+    /// Writes user input at the cursor, closing brackets and indenting new lines as needed.
+    fn write_typed(&mut self, text: &[u8]) {
+        if self.selection.is_some()
+            && let [ch] = *text
+            && self.write_surround(ch)
+        {
+            return;
+        }
+        if self.selection.is_none() && !self.overtype {
+            match *text {
+                [b'\n'] if self.write_newline_indented() || self.write_newline_by_syntax() => {
+                    return;
+                }
+                [ch] if self.write_auto_close(ch) => return,
+                _ => {}
+            }
+        }
         self.write(text, self.cursor, false);
     }
 
+    /// Returns the bytes right before and after the cursor.
+    fn bytes_around_cursor(&self) -> (Option<u8>, Option<u8>) {
+        let offset = self.cursor.offset;
+        let prev = self.read_backward(offset).last().copied();
+        let next = self.read_forward(offset).first().copied();
+        (prev, next)
+    }
+
+    /// Handles typing an opening or closing bracket or quote.
+    /// Returns false if it's to be written as usual.
+    fn write_auto_close(&mut self, ch: u8) -> bool {
+        let (prev, next) = self.bytes_around_cursor();
+
+        // Typing a closing character in front of the same one just moves past it.
+        // This check comes first, because quotes open and close with the same character.
+        if next == Some(ch) && self.auto_close_pairs.iter().any(|&(_, close)| close == ch) {
+            self.cursor_move_to_offset(self.cursor.offset + 1);
+            return true;
+        }
+
+        let Some(&(open, close)) = self.auto_close_pairs.iter().find(|&&(open, _)| open == ch)
+        else {
+            return false;
+        };
+
+        // Don't get in the way of typing in front of a word or, for quotes,
+        // after one, as in "don't". Strings and comments are left alone entirely.
+        let is_word = |b: Option<u8>| b.is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if is_word(next) || (open == close && is_word(prev)) || self.is_in_literal() {
+            return false;
+        }
+
+        self.write(&[open, close], self.cursor, false);
+        let cursor = self.cursor_move_to_offset_internal(self.cursor, self.cursor.offset - 1);
+        self.set_cursor_internal(cursor);
+        true
+    }
+
+    /// Handles typing an opening bracket or quote while there's a selection, by wrapping it
+    /// in the pair, as a single undoable edit. The wrapped text stays selected.
+    /// Returns false if it's to be written as usual, replacing the selection.
+    fn write_surround(&mut self, ch: u8) -> bool {
+        let Some(&(open, close)) = self.auto_close_pairs.iter().find(|&&(open, _)| open == ch)
+        else {
+            return false;
+        };
+        let Some((beg, end)) = self.selection_range_internal(false) else {
+            return false;
+        };
+        // The cursor stays at the same end of the selection.
+        let cursor_at_end = self.cursor.offset == end.offset;
+
+        // When editing multiple cursors, we're already inside a group.
+        let grouping = self.active_edit_group.is_none();
+        if grouping {
+            self.edit_begin_grouping();
+        }
+
+        // The end is edited first, so that `beg` stays valid.
+        self.edit_begin(HistoryType::Other, end);
+        self.edit_write(&[close]);
+        self.edit_end();
+
+        self.edit_begin(HistoryType::Other, beg);
+        self.edit_write(&[open]);
+        self.edit_end();
+
+        if grouping {
+            self.edit_end_grouping();
+        }
+
+        let line_start = self.goto_line_start(self.cursor, beg.logical_pos.y);
+        let beg = self.cursor_move_to_offset_internal(line_start, beg.offset + 1);
+        let end = self.cursor_move_to_offset_internal(beg, end.offset + 1);
+        let (anchor, head) = if cursor_at_end { (beg, end) } else { (end, beg) };
+
+        self.set_cursor_internal(head);
+        self.set_selection(Some(TextBufferSelection {
+            beg: anchor.logical_pos,
+            end: head.logical_pos,
+        }));
+        true
+    }
+
+    /// Handles a newline after one of the `auto_indent_after` characters,
+    /// by indenting the new line by one more level. If the cursor is right in between
+    /// a pair of brackets, the closing one is moved onto a line of its own.
+    /// Returns false if it's to be written as usual.
+    fn write_newline_indented(&mut self) -> bool {
+        let line_beg = self.goto_line_start(self.cursor, self.cursor.logical_pos.y);
+        let mut off = self.cursor.offset;
+        let mut prev = None;
+
+        // Find the last non-whitespace character on the line before the cursor.
+        'outer: while off > line_beg.offset {
+            let chunk = self.read_backward(off);
+            let chunk = &chunk[chunk.len().saturating_sub(off - line_beg.offset)..];
+            for &c in chunk.iter().rev() {
+                if c != b' ' && c != b'\t' {
+                    prev = Some(c);
+                    break 'outer;
+                }
+            }
+            off -= chunk.len();
+        }
+
+        let Some(prev) = prev.filter(|c| self.auto_indent_after.contains(c)) else {
+            return false;
+        };
+
+        let (_, next) = self.bytes_around_cursor();
+        let split = self.auto_close_pairs.iter().any(|&pair| pair == (prev, next.unwrap_or(0)));
+
+        if split {
+            // When editing multiple cursors, we're already inside a group.
+            let grouping = self.active_edit_group.is_none();
+            if grouping {
+                self.edit_begin_grouping();
+            }
+
+            // The closing bracket gets the indentation of the opening line,
+            // and the line in between gets one level more.
+            self.write(b"\n", self.cursor, false);
+            let cursor = self.cursor;
+            self.write(b"\n", self.cursor, false);
+            self.set_cursor_internal(cursor);
+            self.write(b"\t", self.cursor, false);
+
+            if grouping {
+                self.edit_end_grouping();
+            }
+        } else {
+            self.write(b"\n\t", self.cursor, false);
+        }
+
+        true
+    }
+
+    /// Writes a newline indented one level deeper than the line that the innermost
+    /// [`IndentRegion`] around the cursor starts on, or at that level if a closing bracket
+    /// follows. Returns false if there's no such region or it's not up to date.
+    fn write_newline_by_syntax(&mut self) -> bool {
+        if self.indent_regions_generation != self.buffer.generation() || self.is_in_literal() {
+            return false;
+        }
+
+        let off = self.cursor.offset;
+        let line_beg = self.goto_line_start(self.cursor, self.cursor.logical_pos.y);
+        let (indent_chars, _) = self.measure_indent_internal(line_beg.offset, CoordType::MAX);
+        // Within the indentation, the usual copying of it does the right thing.
+        if off <= line_beg.offset + indent_chars as usize {
+            return false;
+        }
+
+        let next =
+            off + self.read_forward(off).iter().take_while(|&&c| c == b' ' || c == b'\t').count();
+        let i = self.indent_regions.partition_point(|r| r.inner.start <= off);
+        let Some(region) = self.indent_regions[..i].iter().rev().find(|r| off <= r.inner.end)
+        else {
+            return false;
+        };
+
+        let anchor = self.cursor_move_to_offset_internal(self.cursor, region.anchor);
+        let anchor_beg = self.goto_line_start(anchor, anchor.logical_pos.y);
+        let (_, mut columns) = self.measure_indent_internal(anchor_beg.offset, CoordType::MAX);
+        if !(region.closed && next == region.inner.end) {
+            columns += self.tab_size;
+        }
+
+        let mut text = vec![b'\n'];
+        if self.indent_with_tabs {
+            text.extend(std::iter::repeat_n(b'\t', (columns / self.tab_size) as usize));
+            columns %= self.tab_size;
+        }
+        text.extend(std::iter::repeat_n(b' ', columns as usize));
+        self.write(&text, self.cursor, true);
+        true
+    }
+
+    /// Returns true if the cursor is inside a string or comment.
+    fn is_in_literal(&self) -> bool {
+        if self.literal_ranges_generation != self.buffer.generation() {
+            return false;
+        }
+        let offset = self.cursor.offset;
+        let i = self.literal_ranges.partition_point(|r| r.start < offset);
+        i > 0 && offset < self.literal_ranges[i - 1].end
+    }
+
     /// Inserts `text` as-is at the current cursor position.
     /// The only transformation applied is that newlines are normalized.
     pub fn write_raw(&mut self, text: &[u8]) {
-        self.write(text, self.cursor, true);
+        if self.read_only {
+            return;
+        }
+        if self.wants_edit_each_cursor() {
+            let pad = self.block_selection().map(|(anchor, head)| anchor.x.min(head.x));
+            self.edit_each_cursor(|tb| {
+                if let Some(column) = pad {
+                    tb.block_selection_pad(column);
+                }
+                tb.write(text, tb.cursor, true);
+            });
+        } else {
+            self.write(text, self.cursor, true);
+        }
     }
 
     fn write(&mut self, text: &[u8], at: Cursor, raw: bool) {
@@ -2281,7 +3627,12 @@ impl TextBuffer {
     /// The selection is cleared after the call.
     /// Deletes characters from the buffer based on a delta from the cursor.
     pub fn delete(&mut self, granularity: CursorMovement, delta: CoordType) {
-        if delta == 0 {
+        if delta == 0 || self.read_only {
+            return;
+        }
+
+        if self.wants_edit_each_cursor() {
+            self.edit_each_cursor(|tb| tb.delete(granularity, delta));
             return;
         }
 
@@ -2306,6 +3657,15 @@ impl TextBuffer {
             if beg.offset > end.offset {
                 mem::swap(&mut beg, &mut end);
             }
+
+            // Backspace in between an empty pair of brackets or quotes deletes both.
+            if delta == -1
+                && matches!(granularity, CursorMovement::Grapheme)
+                && let (Some(prev), Some(next)) = self.bytes_around_cursor()
+                && self.auto_close_pairs.contains(&(prev, next))
+            {
+                end = self.cursor_move_to_offset_internal(end, end.offset + 1);
+            }
         }
 
         self.edit_begin(HistoryType::Delete, beg);
@@ -2325,6 +3685,10 @@ impl TextBuffer {
 
     /// Indents/unindents the current selection or line.
     pub fn indent_change(&mut self, direction: CoordType) {
+        if self.read_only {
+            return;
+        }
+
         let selection = self.selection;
         let mut selection_beg = self.cursor.logical_pos;
         let mut selection_end = selection_beg;
@@ -2391,25 +3755,200 @@ impl TextBuffer {
         );
     }
 
-    fn measure_indent_internal(
-        &self,
-        mut offset: usize,
-        max_columns: CoordType,
-    ) -> (CoordType, CoordType) {
-        let mut chars = 0;
-        let mut columns = 0;
+    /// Comments out the current selection or line with the line comment `token`, e.g. `//`,
+    /// or uncomments it if all of its lines are commented out already. The token goes
+    /// after the indentation of each line. Blank lines are left alone.
+    pub fn line_comment_toggle(&mut self, token: &str) {
+        if self.read_only || token.is_empty() {
+            return;
+        }
 
-        'outer: loop {
-            let chunk = self.read_forward(offset);
-            if chunk.is_empty() {
-                break;
-            }
+        let selection = self.selection;
+        let mut selection_beg = self.cursor.logical_pos;
+        let mut selection_end = selection_beg;
 
-            for &c in chunk {
-                let next = match c {
-                    b' ' => columns + 1,
-                    b'\t' => columns + self.tab_size_eval(columns),
-                    _ => break 'outer,
+        if let Some(TextBufferSelection { beg, end }) = &selection {
+            selection_beg = *beg;
+            selection_end = *end;
+        }
+
+        // Find the indentation of each line that isn't blank, and whether it's commented out.
+        let token = token.as_bytes();
+        let mut lines = Vec::new();
+        let mut text = Vec::new();
+        let mut cursor = self.cursor;
+
+        for y in selection_beg.y.min(selection_end.y)..=selection_beg.y.max(selection_end.y) {
+            cursor = self.goto_line_start(cursor, y);
+            let (indent, _) = self.measure_indent_internal(cursor.offset, CoordType::MAX);
+            let beg = cursor.offset + indent as usize;
+            let end = (beg + token.len() + 1).min(self.text_length());
+
+            text.clear();
+            self.buffer.extract_raw(beg..end, &mut text, 0);
+            if matches!(text.first(), None | Some(b'\r' | b'\n')) {
+                continue;
+            }
+
+            let commented = text.starts_with(token);
+            let space = commented && text.get(token.len()) == Some(&b' ');
+            lines.push((y, indent, commented, space));
+        }
+
+        if lines.is_empty() {
+            return;
+        }
+
+        let uncomment = lines.iter().all(|&(_, _, commented, _)| commented);
+
+        self.edit_begin_grouping();
+
+        for (y, indent, _, space) in lines {
+            let at = self.cursor_move_to_logical_internal(self.cursor, Point { x: indent, y });
+            self.edit_begin(HistoryType::Other, at);
+
+            let delta = if uncomment {
+                let len = token.len() + space as usize;
+                self.edit_delete(self.cursor_move_to_offset_internal(at, at.offset + len));
+                -(len as CoordType)
+            } else {
+                self.edit_write(token);
+                self.edit_write(b" ");
+                token.len() as CoordType + 1
+            };
+
+            self.edit_end();
+
+            // Text after the indentation shifts along with the comment token.
+            // A lone cursor right at the indentation moves past the token as well.
+            for pos in [&mut selection_beg, &mut selection_end] {
+                if pos.y == y && (pos.x > indent || (pos.x == indent && selection.is_none())) {
+                    pos.x = (pos.x + delta).max(indent);
+                }
+            }
+        }
+
+        self.edit_end_grouping();
+
+        // Move the cursor to the new end of the selection.
+        self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, selection_end));
+
+        // NOTE: If the selection was previously `None`,
+        // it should continue to be `None` after this.
+        self.set_selection(
+            selection.map(|_| TextBufferSelection { beg: selection_beg, end: selection_end }),
+        );
+    }
+
+    /// Wraps the selection, or the current line without its indentation, in the block comment
+    /// delimiters `open` and `close`, e.g. `/*` and `*/`. If it's wrapped in them already,
+    /// they're removed instead. Returns false if it contains a block comment already,
+    /// which can't be wrapped unless block comments `nest` in the language.
+    pub fn block_comment_toggle(&mut self, open: &str, close: &str, nest: bool) -> bool {
+        if self.read_only {
+            return true;
+        }
+
+        let had_selection = self.selection.is_some();
+        let (beg, end) = match self.selection_range_internal(false) {
+            Some(range) => range,
+            None => {
+                let y = self.cursor.logical_pos.y;
+                let start = self.goto_line_start(self.cursor, y);
+                let (indent, _) = self.measure_indent_internal(start.offset, CoordType::MAX);
+                let beg = self.cursor_move_to_logical_internal(start, Point { x: indent, y });
+                let end = self.trailing_whitespace_start(beg).max(beg.offset);
+                (beg, self.cursor_move_to_offset_internal(beg, end))
+            }
+        };
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut text, 0);
+        let (open, close) = (open.as_bytes(), close.as_bytes());
+        let contains = |needle: &[u8]| text.windows(needle.len()).any(|w| w == needle);
+
+        let wrapped = text.len() >= open.len() + close.len()
+            && text.starts_with(open)
+            && text.ends_with(close);
+        if !wrapped && !nest && (contains(open) || contains(close)) {
+            return false;
+        }
+
+        // The end is edited first, so that `beg` and `end` stay valid.
+        self.edit_begin_grouping();
+
+        let len_after = if wrapped {
+            // The spaces that wrapping added go along with the delimiters.
+            let inner = &text[open.len()..text.len() - close.len()];
+            let open_len = open.len() + inner.starts_with(b" ") as usize;
+            let close_len = close.len() + (inner.len() >= 2 && inner.ends_with(b" ")) as usize;
+
+            let close_beg = self.cursor_move_to_offset_internal(beg, end.offset - close_len);
+            self.edit_begin(HistoryType::Other, close_beg);
+            self.edit_delete(end);
+            self.edit_end();
+
+            self.edit_begin(HistoryType::Other, beg);
+            self.edit_delete(self.cursor_move_to_offset_internal(beg, beg.offset + open_len));
+            self.edit_end();
+
+            text.len() - open_len - close_len
+        } else {
+            self.edit_begin(HistoryType::Other, end);
+            self.edit_write(b" ");
+            self.edit_write(close);
+            self.edit_end();
+
+            self.edit_begin(HistoryType::Other, beg);
+            self.edit_write(open);
+            self.edit_write(b" ");
+            self.edit_end();
+
+            text.len() + open.len() + close.len() + 2
+        };
+
+        self.edit_end_grouping();
+
+        // The result stays selected, so that toggling again reverts it. Without a selection,
+        // the cursor goes after it, or between the delimiters if there was nothing to wrap.
+        let line_start = self.goto_line_start(self.cursor, beg.logical_pos.y);
+        let beg = self.cursor_move_to_offset_internal(line_start, beg.offset);
+        let end_offset = if !wrapped && text.is_empty() {
+            beg.offset + open.len() + 1
+        } else {
+            beg.offset + len_after
+        };
+        let end = self.cursor_move_to_offset_internal(beg, end_offset);
+
+        self.set_cursor_internal(end);
+        if had_selection {
+            self.set_selection(Some(TextBufferSelection {
+                beg: beg.logical_pos,
+                end: end.logical_pos,
+            }));
+        }
+        true
+    }
+
+    fn measure_indent_internal(
+        &self,
+        mut offset: usize,
+        max_columns: CoordType,
+    ) -> (CoordType, CoordType) {
+        let mut chars = 0;
+        let mut columns = 0;
+
+        'outer: loop {
+            let chunk = self.read_forward(offset);
+            if chunk.is_empty() {
+                break;
+            }
+
+            for &c in chunk {
+                let next = match c {
+                    b' ' => columns + 1,
+                    b'\t' => columns + self.tab_size_eval(columns),
+                    _ => break 'outer,
                 };
                 if next > max_columns {
                     break 'outer;
@@ -2430,8 +3969,54 @@ impl TextBuffer {
         (chars, columns)
     }
 
+    /// Inserts a copy of the current line, or of the lines of the selection, below them.
+    /// The cursor and the selection move along to the copy.
+    pub fn duplicate_selected_lines(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        let selection = self.selection;
+        let cursor = self.cursor.logical_pos;
+
+        // If there's no selection, we duplicate the line the cursor is on instead.
+        let [beg, end] = match self.selection {
+            Some(s) => minmax(s.beg.y, s.end.y),
+            None => [cursor.y, cursor.y],
+        };
+
+        let start = self.goto_line_start(self.cursor, beg);
+        let next = self.cursor_move_to_logical_internal(start, Point { x: 0, y: end + 1 });
+        let mut text = Vec::new();
+        self.buffer.extract_raw(start.offset..next.offset, &mut text, 0);
+
+        self.edit_begin(HistoryType::Other, next);
+        // The last line has no newline to separate it from the copy, unless the file ends in one.
+        if next.logical_pos.y == end {
+            let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+            self.edit_write(newline);
+        }
+        if !text.is_empty() {
+            self.edit_write(&text);
+        }
+        self.edit_end();
+
+        // Shift the cursor and selection onto the copy.
+        let delta = end - beg + 1;
+        self.cursor_move_to_logical(Point { x: cursor.x, y: cursor.y + delta });
+        self.set_selection(selection.map(|mut s| {
+            s.beg.y += delta;
+            s.end.y += delta;
+            s
+        }));
+    }
+
     /// Displaces the current, cursor or the selection, line(s) in the given direction.
     pub fn move_selected_lines(&mut self, direction: MoveLineDirection) {
+        if self.read_only {
+            return;
+        }
+
         let selection = self.selection;
         let cursor = self.cursor;
 
@@ -2501,6 +4086,10 @@ impl TextBuffer {
     /// Extracts the contents of the current selection.
     /// May optionally delete it, if requested. This is meant to be used for Ctrl+X.
     fn extract_selection(&mut self, delete: bool) -> Vec<u8> {
+        if let Some(ranges) = self.block_selection_ranges() {
+            return self.extract_block_selection(&ranges, delete);
+        }
+
         let line_copy = !self.has_selection();
         let Some((beg, end)) = self.selection_range_internal(true) else {
             return Vec::new();
@@ -2524,6 +4113,26 @@ impl TextBuffer {
         out
     }
 
+    /// The lines of a block selection are copied as one line each.
+    fn extract_block_selection(&mut self, ranges: &[Range<usize>], delete: bool) -> Vec<u8> {
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let mut out = Vec::new();
+        for (i, range) in ranges.iter().enumerate() {
+            if i != 0 {
+                out.extend_from_slice(newline);
+            }
+            let at = out.len();
+            self.buffer.extract_raw(range.clone(), &mut out, at);
+        }
+
+        if delete {
+            // Writing nothing deletes the selection at each cursor.
+            self.edit_each_cursor(|tb| tb.write(b"", tb.cursor, true));
+        }
+
+        out
+    }
+
     /// Extracts the contents of the current selection the user made.
     /// This differs from [`TextBuffer::extract_selection()`] in that
     /// it does nothing if the selection was made by searching.
@@ -2569,6 +4178,63 @@ impl TextBuffer {
         if beg.offset < end.offset { Some((beg, end)) } else { None }
     }
 
+    fn wants_edit_each_cursor(&self) -> bool {
+        // Compound edits like `indent_change` are applied to the primary cursor only.
+        self.active_edit_group.is_none() && self.has_extra_cursors()
+    }
+
+    /// Applies `edit` at every cursor, from the last one in the document to the first,
+    /// so that the offsets of the remaining cursors stay valid. Undo reverts it as one step.
+    fn edit_each_cursor(&mut self, mut edit: impl FnMut(&mut Self)) {
+        let primary = match self.selection_range_internal(false) {
+            Some((beg, end)) => beg.offset..end.offset,
+            None => self.cursor.offset..self.cursor.offset,
+        };
+
+        let mut ranges = mem::take(&mut self.extra_cursors);
+        ranges.push(primary.clone());
+        ranges.sort_unstable_by(|a, b| b.start.cmp(&a.start).then(b.end.cmp(&a.end)));
+        ranges.dedup();
+
+        let mut carets: Vec<usize> = Vec::with_capacity(ranges.len());
+        let mut primary_caret = None;
+
+        self.edit_begin_grouping();
+
+        for range in ranges {
+            let length_before = self.text_length();
+
+            if range.is_empty() {
+                self.cursor_move_to_offset(range.start);
+            } else {
+                self.select_range_internal(range.clone());
+            }
+            edit(self);
+
+            // The carets we already visited are all further down and shift by the same amount.
+            let delta = self.text_length() as isize - length_before as isize;
+            for caret in carets.iter_mut().chain(&mut primary_caret) {
+                *caret = caret.saturating_add_signed(delta);
+            }
+
+            if range == primary {
+                primary_caret = Some(self.cursor.offset);
+            } else {
+                carets.push(self.cursor.offset);
+            }
+        }
+
+        self.edit_end_grouping();
+
+        let primary_caret = primary_caret.unwrap_or(self.cursor.offset);
+        self.cursor_move_to_offset(primary_caret);
+        carets.retain(|&caret| caret != primary_caret);
+        carets.sort_unstable();
+        carets.dedup();
+        self.extra_cursors = carets.into_iter().map(|caret| caret..caret).collect();
+        self.extra_cursors_generation = self.buffer.generation();
+    }
+
     fn edit_begin_grouping(&mut self) {
         self.active_edit_group = Some(ActiveEditGroupInfo {
             cursor_before: self.cursor.logical_pos,
@@ -2603,6 +4269,7 @@ impl TextBuffer {
             }
 
             self.last_history_type = history_type;
+            self.last_history_id += 1;
             self.undo_stack.push_back(SemiRefCell::new(HistoryEntry {
                 cursor_before: cursor_before.logical_pos,
                 selection_before: self.selection,
@@ -2611,6 +4278,9 @@ impl TextBuffer {
                 cursor: cursor.logical_pos,
                 deleted: Vec::new(),
                 added: Vec::new(),
+                verbatim: None,
+                id: self.last_history_id,
+                time: Instant::now(),
             }));
 
             if let Some(info) = &self.active_edit_group
@@ -2624,6 +4294,12 @@ impl TextBuffer {
             }
         }
 
+        if self.folds_generation != self.buffer.generation() {
+            self.folds.clear();
+        }
+        let added_len = self.undo_stack.back().unwrap().borrow().added.len();
+        self.active_edit_fold_info = (cursor.logical_pos.y, self.stats.logical_lines, added_len);
+
         self.active_edit_off = cursor.offset;
 
         // If word-wrap is enabled, the visual layout of all logical lines affected by the write
@@ -2657,6 +4333,7 @@ impl TextBuffer {
 
         // Write!
         self.buffer.replace(self.active_edit_off..self.active_edit_off, text);
+        self.snippet_adjust_insert(self.active_edit_off, text.len());
 
         // Move self.cursor to the end of the newly written text. Can't use `self.set_cursor_internal`,
         // because we're still in the progress of recalculating the line stats.
@@ -2673,6 +4350,7 @@ impl TextBuffer {
         let logical_y_before = self.cursor.logical_pos.y;
         let off = self.active_edit_off;
         let mut out_off = usize::MAX;
+        self.snippet_adjust_delete(off, to.offset);
 
         let mut undo = self.undo_stack.back_mut().unwrap().borrow_mut();
 
@@ -2739,9 +4417,39 @@ impl TextBuffer {
             self.stats.visual_lines = self.stats.logical_lines;
         }
 
+        self.folds_adjust_after_edit();
         self.recalc_after_content_changed();
     }
 
+    /// Shifts the folds below the edit by the number of lines it added or removed,
+    /// and unfolds any that the edit touched.
+    fn folds_adjust_after_edit(&mut self) {
+        let (y, lines_before, added_len) = self.active_edit_fold_info;
+        let delta = self.stats.logical_lines - lines_before;
+        let added = {
+            let entry = self.undo_stack.back().unwrap().borrow();
+            entry.added[added_len.min(entry.added.len())..].iter().filter(|&&b| b == b'\n').count()
+        };
+        let removed = added as CoordType - delta;
+
+        self.folds.retain_mut(|fold| {
+            let header = fold.start - 1;
+            if fold.end <= y {
+                // Above the edit.
+                true
+            } else if header > y + removed {
+                // Below the edit.
+                fold.start += delta;
+                fold.end += delta;
+                true
+            } else {
+                // An edit within the line before the fold doesn't affect it.
+                header == y && removed == 0 && delta == 0
+            }
+        });
+        self.folds_generation = self.buffer.generation();
+    }
+
     /// Undo the last edit operation.
     pub fn undo(&mut self) {
         self.undo_redo(true);
@@ -2752,7 +4460,67 @@ impl TextBuffer {
         self.undo_redo(false);
     }
 
+    /// Returns the undo history, oldest step first, followed by the steps that can be redone.
+    /// The second value is the number of steps that are currently applied, i.e. not undone.
+    pub fn history(&self) -> (Vec<HistoryStep>, usize) {
+        let mut steps: Vec<HistoryStep> = Vec::new();
+        // Redoing walks the redo stack from the back. Undone entries have their texts swapped.
+        let undo = self.undo_stack.iter().map(|entry| (entry, false));
+        let redo = self.redo_stack.iter().rev().map(|entry| (entry, true));
+        let mut generation = None;
+        let mut applied = 0;
+
+        for (entry, undone) in undo.chain(redo) {
+            let entry = entry.borrow();
+
+            // Entries with the same generation form a single step, see `HistoryEntry`.
+            if generation == Some((entry.generation_before, undone))
+                && let Some(step) = steps.last_mut()
+            {
+                step.edits += 1;
+                continue;
+            }
+            generation = Some((entry.generation_before, undone));
+
+            let (added, deleted) = if undone {
+                (&entry.deleted, &entry.added)
+            } else {
+                (&entry.added, &entry.deleted)
+            };
+            steps.push(HistoryStep {
+                id: entry.id,
+                time: entry.time,
+                added: history_excerpt(added),
+                deleted: history_excerpt(deleted),
+                edits: 1,
+            });
+            if !undone {
+                applied += 1;
+            }
+        }
+
+        (steps, applied)
+    }
+
+    /// Undoes or redoes steps until `applied` steps of the [`TextBuffer::history`] are applied.
+    pub fn history_goto(&mut self, applied: usize) {
+        let (steps, current) = self.history();
+        let applied = applied.min(steps.len());
+
+        for _ in applied..current {
+            self.undo();
+        }
+        for _ in current..applied {
+            self.redo();
+        }
+    }
+
     fn undo_redo(&mut self, undo: bool) {
+        if self.read_only {
+            return;
+        }
+        self.snippet_exit();
+
         let buffer_generation = self.buffer.generation();
         let mut entry_buffer_generation = None;
 
@@ -2810,7 +4578,10 @@ impl TextBuffer {
                 self.buffer.allocate_gap(cursor.offset, 0, change.deleted.len());
 
                 // Reinsert the deleted portion.
-                {
+                if let Some(crlf) = &mut change.verbatim {
+                    self.buffer.replace(cursor.offset..cursor.offset, &change.added);
+                    mem::swap(&mut self.newlines_are_crlf, crlf);
+                } else {
                     let added = &change.added[..];
                     let mut beg = 0;
                     let mut offset = cursor.offset;
@@ -2883,10 +4654,489 @@ impl TextBuffer {
         self.buffer.read_forward(off)
     }
 
-    pub fn set_highlights(&mut self, highlights: Vec<(Range<usize>, Highlight)>) {
+    /// Sets the colors of the syntax highlighting, as byte ranges sorted by their start.
+    /// They're only drawn as long as the text is unchanged.
+    pub fn set_highlights(&mut self, highlights: Vec<(Range<usize>, ThemeColor)>) {
         self.highlights = highlights;
         self.highlight_generation = self.buffer.generation();
     }
+
+    /// Sets the matching pairs of brackets, as byte offsets sorted by the opening bracket.
+    /// As long as the text is unchanged, these are used instead of scanning for brackets.
+    pub fn set_bracket_pairs(&mut self, pairs: Vec<(usize, usize)>) {
+        self.bracket_pairs = pairs;
+        self.bracket_pairs_generation = self.buffer.generation();
+    }
+
+    /// Sets the marks shown on the scrollbar: `matches` as byte offsets, e.g. for search matches,
+    /// and `lines` as logical lines, e.g. for errors. Both are sorted in ascending order.
+    /// One mark is shown per line, and the one from `lines` wins. They're shown
+    /// only as long as the text is unchanged.
+    pub fn set_scrollbar_markers(
+        &mut self,
+        matches: &[(usize, IndexedColor)],
+        lines: &[(CoordType, IndexedColor)],
+    ) {
+        let mut cursor = Cursor::default();
+        let mut markers = Vec::with_capacity(matches.len() + lines.len());
+        markers.extend_from_slice(lines);
+
+        for &(offset, color) in matches {
+            cursor = self.cursor_move_to_offset_internal(cursor, offset);
+            markers.push((cursor.logical_pos.y, color));
+        }
+
+        // The sort is stable, so the first mark on each line is kept.
+        markers.sort_by_key(|&(y, _)| y);
+        markers.dedup_by_key(|&mut (y, _)| y);
+        self.scrollbar_markers = markers;
+        self.scrollbar_markers_generation = self.buffer.generation();
+        self.scrollbar_markers_recalc();
+    }
+
+    /// Returns the marks to show on the scrollbar, as the visual lines they're shown on.
+    /// Like [`TextBuffer::visual_line_count`], these account for word wrap and folds.
+    pub fn scrollbar_markers(&self) -> impl Iterator<Item = (CoordType, IndexedColor)> {
+        let rows = if self.scrollbar_markers_generation == self.buffer.generation() {
+            &self.scrollbar_marker_rows[..]
+        } else {
+            &[]
+        };
+        rows.iter().map(|&(y, color)| (self.fold_visual_y(y), color))
+    }
+
+    // Word wrap moves the marks down by the lines wrapped above them.
+    fn scrollbar_markers_recalc(&mut self) {
+        let rows = if self.word_wrap_column <= 0 {
+            self.scrollbar_markers.clone()
+        } else {
+            let mut cursor = Cursor::default();
+            let mut rows = Vec::with_capacity(self.scrollbar_markers.len());
+            for &(y, color) in &self.scrollbar_markers {
+                cursor = self.cursor_move_to_logical_internal(cursor, Point { x: 0, y });
+                rows.push((cursor.visual_pos.y, color));
+            }
+            rows
+        };
+        self.scrollbar_marker_rows = rows;
+    }
+
+    /// Sets the text to compare against, e.g. the file as it's saved, in order to mark the lines
+    /// that were added, modified or deleted since in the margin. `None` turns the marks off.
+    pub fn set_diff_base(&mut self, base: Option<Vec<u8>>) {
+        self.diff_base = base;
+        self.diff_base_is_newer = false;
+        self.line_changes.clear();
+        self.line_changes_generation = None;
+    }
+
+    /// Like [`TextBuffer::set_diff_base`], but the other way around: `target` is what this text
+    /// changed into, as on the left side of a side by side diff. Lines that aren't in the `target`
+    /// are then marked as deleted, and the place where it has lines this text lacks as added.
+    pub fn set_diff_target(&mut self, target: Option<Vec<u8>>) {
+        self.set_diff_base(target);
+        self.diff_base_is_newer = true;
+    }
+
+    /// Moves the cursor to the first line of the next or previous run of changed lines.
+    /// Returns false if there's none in that direction. See [`TextBuffer::set_diff_base`].
+    pub fn cursor_move_to_change(&mut self, forward: bool) -> bool {
+        let y = self.cursor.logical_pos.y;
+        let changes = self.line_changes();
+        let mut starts = changes
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(line, color))| i == 0 || changes[i - 1] != (line - 1, color))
+            .map(|(_, &(line, _))| line);
+        let target = if forward { starts.find(|&s| s > y) } else { starts.rfind(|&s| s < y) };
+
+        let Some(target) = target else {
+            return false;
+        };
+        self.cursor_move_to_logical(Point { x: 0, y: target });
+        true
+    }
+
+    /// Sets the text to show in the margin in front of the number of each logical line,
+    /// e.g. who last changed it. They're padded to the width of the longest one.
+    /// An empty list removes them.
+    pub fn set_margin_annotations(&mut self, annotations: Vec<String>) {
+        let width =
+            annotations.iter().map(|a| a.chars().count() as CoordType + 1).max().unwrap_or(0);
+        self.margin_annotations = annotations;
+        if self.margin_annotation_width != width {
+            self.margin_annotation_width = width;
+            self.reflow();
+        }
+    }
+
+    /// Sets the logical lines whose number is shown in the given color, e.g. where errors are.
+    /// Unlike the other marks, they stay on their line numbers when the text changes.
+    pub fn set_line_marks(&mut self, mut marks: Vec<(CoordType, IndexedColor)>) {
+        marks.sort_by_key(|&(y, _)| y);
+        marks.dedup_by_key(|&mut (y, _)| y);
+        self.line_marks = marks;
+    }
+
+    /// Returns the lines marked as changed. See [`TextBuffer::set_diff_base`].
+    fn line_changes(&mut self) -> &[(CoordType, IndexedColor)] {
+        let Some(base) = &self.diff_base else {
+            return &[];
+        };
+
+        if self.line_changes_generation != Some(self.buffer.generation()) {
+            let mut text = Vec::new();
+            self.buffer.extract_raw(0..self.text_length(), &mut text, 0);
+
+            // Compared against a newer text, lines missing here were added, not deleted.
+            let (added, deleted) = if self.diff_base_is_newer {
+                (IndexedColor::BrightRed, IndexedColor::BrightGreen)
+            } else {
+                (IndexedColor::BrightGreen, IndexedColor::BrightRed)
+            };
+
+            self.line_changes.clear();
+            for hunk in diff::diff_lines(&diff::lines(base), &diff::lines(&text)) {
+                if hunk.new.is_empty() {
+                    // Deleted lines are marked at the line that took their place.
+                    self.line_changes.push((hunk.new.start as CoordType, deleted));
+                } else {
+                    let color = if hunk.old.is_empty() { added } else { IndexedColor::BrightBlue };
+                    self.line_changes.extend(hunk.new.map(|y| (y as CoordType, color)));
+                }
+            }
+
+            self.line_changes_generation = Some(self.buffer.generation());
+        }
+
+        &self.line_changes
+    }
+
+    /// Returns the offset of the bracket right after or before the cursor,
+    /// in that order of preference, along with the offset of its partner.
+    pub fn matching_bracket(&self) -> Option<(usize, usize)> {
+        let offset = self.cursor.offset;
+        let candidates = [Some(offset), offset.checked_sub(1)];
+
+        if self.bracket_pairs_generation == self.buffer.generation() {
+            candidates.into_iter().flatten().find_map(|off| {
+                self.bracket_pairs.iter().find_map(|&(open, close)| {
+                    if off == open {
+                        Some((open, close))
+                    } else if off == close {
+                        Some((close, open))
+                    } else {
+                        None
+                    }
+                })
+            })
+        } else {
+            candidates
+                .into_iter()
+                .flatten()
+                .find_map(|off| Some((off, self.scan_matching_bracket(off)?)))
+        }
+    }
+
+    /// Finds the partner of the bracket at `offset` by counting nesting levels.
+    /// Gives up after a while, so that unbalanced brackets don't scan the entire document.
+    fn scan_matching_bracket(&self, offset: usize) -> Option<usize> {
+        const LIMIT: usize = 256 * 1024;
+
+        let bracket = *self.read_forward(offset).first()?;
+        let (open, close, forward) = match bracket {
+            b'(' => (b'(', b')', true),
+            b'[' => (b'[', b']', true),
+            b'{' => (b'{', b'}', true),
+            b')' => (b'(', b')', false),
+            b']' => (b'[', b']', false),
+            b'}' => (b'{', b'}', false),
+            _ => return None,
+        };
+        let mut depth = 0usize;
+
+        if forward {
+            let end = self.text_length().min(offset.saturating_add(LIMIT));
+            let mut off = offset;
+            while off < end {
+                let chunk = self.read_forward(off);
+                let chunk = &chunk[..chunk.len().min(end - off)];
+                for (i, &b) in chunk.iter().enumerate() {
+                    if b == open {
+                        depth += 1;
+                    } else if b == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(off + i);
+                        }
+                    }
+                }
+                off += chunk.len();
+            }
+        } else {
+            let beg = offset.saturating_sub(LIMIT);
+            let mut off = offset + 1;
+            while off > beg {
+                let chunk = self.read_backward(off);
+                let chunk = &chunk[chunk.len().saturating_sub(off - beg)..];
+                for (i, &b) in chunk.iter().enumerate().rev() {
+                    if b == close {
+                        depth += 1;
+                    } else if b == open {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(off - chunk.len() + i);
+                        }
+                    }
+                }
+                off -= chunk.len();
+            }
+        }
+
+        None
+    }
+
+    /// Moves the cursor to the partner of the bracket next to it,
+    /// keeping the cursor on the same side of the bracket.
+    pub fn cursor_move_to_matching_bracket(&mut self) -> bool {
+        let Some((bracket, partner)) = self.matching_bracket() else {
+            return false;
+        };
+        let offset = if self.cursor.offset == bracket { partner } else { partner + 1 };
+        self.cursor_move_to_offset(offset);
+        self.make_cursor_visible();
+        true
+    }
+
+    /// Sets the byte ranges of strings and comments, sorted by their start.
+    /// As long as the text is unchanged, brackets typed inside them aren't closed automatically.
+    pub fn set_literal_ranges(&mut self, ranges: Vec<Range<usize>>) {
+        self.literal_ranges = ranges;
+        self.literal_ranges_generation = self.buffer.generation();
+    }
+
+    /// Sets the nodes that indent their contents, sorted by the start of their contents.
+    /// As long as the text is unchanged, a new line is indented based on the innermost one.
+    pub fn set_indent_regions(&mut self, regions: Vec<IndentRegion>) {
+        self.indent_regions = regions;
+        self.indent_regions_generation = self.buffer.generation();
+    }
+
+    /// Sets the logical lines that can be folded away, sorted by their start.
+    /// The line before each range is the one that stays visible.
+    pub fn set_fold_regions(&mut self, regions: Vec<Range<CoordType>>) {
+        self.fold_regions = regions;
+        self.fold_regions_generation = self.buffer.generation();
+    }
+
+    fn fold_regions(&self) -> &[Range<CoordType>] {
+        if self.fold_regions_generation == self.buffer.generation() {
+            &self.fold_regions
+        } else {
+            &[]
+        }
+    }
+
+    fn fold_rows(&self) -> &[Range<CoordType>] {
+        if self.folds_generation == self.buffer.generation() { &self.fold_rows } else { &[] }
+    }
+
+    /// Folds the innermost region around the cursor, or unfolds it if the cursor is on
+    /// the line before a folded region. Returns `false` if there's nothing to (un)fold.
+    pub fn fold_toggle(&mut self) -> bool {
+        let y = self.cursor.logical_pos.y;
+
+        if !self.fold_rows().is_empty()
+            && let Some(i) = self.folds.iter().position(|fold| fold.start == y + 1)
+        {
+            self.folds.remove(i);
+            self.folds_recalc();
+            return true;
+        }
+
+        // The regions are sorted by their start, so the last match is the innermost one.
+        let Some(region) =
+            self.fold_regions().iter().rev().find(|r| r.start - 1 <= y && y < r.end).cloned()
+        else {
+            return false;
+        };
+
+        let mut folds = self.valid_folds();
+        folds.retain(|fold| !(region.start <= fold.start && fold.end <= region.end));
+        let i = folds.partition_point(|fold| fold.start < region.start);
+        folds.insert(i, region);
+        self.folds_set(folds);
+        true
+    }
+
+    /// Folds all outermost regions.
+    pub fn fold_all(&mut self) {
+        let mut folds: Vec<Range<CoordType>> = Vec::new();
+        for region in self.fold_regions() {
+            if folds.last().is_none_or(|fold| region.start >= fold.end) {
+                folds.push(region.clone());
+            }
+        }
+        self.folds_set(folds);
+    }
+
+    /// Folds the given regions, sorted by their start, in addition to those folded already.
+    /// Folds within them are merged into them. Those that overlap a fold otherwise are skipped.
+    pub fn fold_ranges(&mut self, regions: &[Range<CoordType>]) {
+        let mut folds = self.valid_folds();
+        for region in regions {
+            let (start, end) = (region.start, region.end);
+            // The region overlaps the fold, and isn't around it.
+            let clashes = |f: &Range<CoordType>| {
+                f.start < end && start < f.end && (f.start < start || end < f.end)
+            };
+            if folds.iter().any(clashes) {
+                continue;
+            }
+            folds.retain(|f| !(start <= f.start && f.end <= end));
+            let i = folds.partition_point(|fold| fold.start < region.start);
+            folds.insert(i, region.clone());
+        }
+        self.folds_set(folds);
+    }
+
+    /// Unfolds all regions.
+    pub fn unfold_all(&mut self) {
+        self.folds_set(Vec::new());
+    }
+
+    /// Returns whether any lines are folded away.
+    pub fn has_folds(&self) -> bool {
+        !self.fold_rows().is_empty()
+    }
+
+    fn valid_folds(&self) -> Vec<Range<CoordType>> {
+        if self.folds_generation == self.buffer.generation() {
+            self.folds.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn folds_set(&mut self, folds: Vec<Range<CoordType>>) {
+        self.folds = folds;
+        self.folds_generation = self.buffer.generation();
+
+        // The cursor can't stay on a line that's hidden.
+        let y = self.cursor.logical_pos.y;
+        if let Some(fold) = self.folds.iter().find(|fold| fold.contains(&y)) {
+            let header = Point { x: CoordType::MAX, y: fold.start - 1 };
+            let cursor = self.cursor_move_to_logical_internal(self.cursor, header);
+            self.set_cursor_internal(cursor);
+            self.set_selection(None);
+        }
+
+        self.folds_recalc();
+    }
+
+    /// Unfolds the region the cursor is in, if it moved onto a hidden line.
+    fn unfold_at_cursor(&mut self) {
+        let y = self.cursor.logical_pos.y;
+        if self.fold_rows().is_empty() || !self.folds.iter().any(|fold| fold.contains(&y)) {
+            return;
+        }
+        self.folds.retain(|fold| !fold.contains(&y));
+        self.folds_recalc();
+    }
+
+    /// Drops outdated folds and translates the remaining ones into visual lines.
+    fn folds_recalc(&mut self) {
+        if self.folds_generation != self.buffer.generation() {
+            self.folds.clear();
+        }
+
+        let logical_lines = self.stats.logical_lines;
+        self.folds.retain(|fold| 0 < fold.start && fold.start < fold.end);
+        for fold in &mut self.folds {
+            fold.end = fold.end.min(logical_lines);
+        }
+        self.folds.retain(|fold| fold.start < fold.end);
+
+        let mut rows = Vec::with_capacity(self.folds.len());
+        if self.word_wrap_column <= 0 {
+            rows.extend_from_slice(&self.folds);
+        } else {
+            let mut cursor = Cursor::default();
+            for fold in &self.folds {
+                let beg =
+                    self.cursor_move_to_logical_internal(cursor, Point { x: 0, y: fold.start });
+                let end = if fold.end >= logical_lines {
+                    self.stats.visual_lines
+                } else {
+                    cursor = self.cursor_move_to_logical_internal(beg, Point { x: 0, y: fold.end });
+                    cursor.visual_pos.y
+                };
+                rows.push(beg.visual_pos.y..end);
+            }
+        }
+        self.fold_rows = rows;
+    }
+
+    /// Converts a visual line into the one it's shown on, with folded lines taken out.
+    /// Folded lines are shown on the line before them.
+    pub fn fold_visual_y(&self, y: CoordType) -> CoordType {
+        let mut hidden = 0;
+        for rows in self.fold_rows() {
+            if y < rows.start {
+                break;
+            }
+            if y < rows.end {
+                return rows.start - 1 - hidden;
+            }
+            hidden += rows.end - rows.start;
+        }
+        y - hidden
+    }
+
+    /// The inverse of [`TextBuffer::fold_visual_y`].
+    fn unfold_visual_y(&self, mut y: CoordType) -> CoordType {
+        for rows in self.fold_rows() {
+            if rows.start > y {
+                break;
+            }
+            y += rows.end - rows.start;
+        }
+        y
+    }
+
+    /// Returns the character to show in the margin of the given logical line,
+    /// depending on whether a region after it can be folded or is folded.
+    fn fold_marker(&self, y: CoordType) -> char {
+        let starts_after = |ranges: &[Range<CoordType>]| {
+            ranges.binary_search_by_key(&(y + 1), |r| r.start).is_ok()
+        };
+        if !self.fold_rows().is_empty() && starts_after(&self.folds) {
+            '▸'
+        } else if starts_after(self.fold_regions()) {
+            '▾'
+        } else {
+            ' '
+        }
+    }
+
+    /// Returns the offset at which the whitespace at the end of the logical line
+    /// that `cursor` is on starts. It's the end of the line if there's none.
+    fn trailing_whitespace_start(&self, cursor: Cursor) -> usize {
+        let line_end = self.cursor_move_to_logical_internal(
+            cursor,
+            Point { x: CoordType::MAX, y: cursor.logical_pos.y },
+        );
+        let mut off = line_end.offset;
+
+        loop {
+            let chunk = self.read_backward(off);
+            let len = chunk.iter().rev().take_while(|&&b| b == b' ' || b == b'\t').count();
+            off -= len;
+            if len < chunk.len() || chunk.is_empty() {
+                return off;
+            }
+        }
+    }
 }
 
 pub enum Bom {
@@ -2901,6 +5151,129 @@ pub enum Bom {
 
 const BOM_MAX_LEN: usize = 4;
 
+/// Shortens the text of a history entry to a single line for display.
+fn history_excerpt(text: &[u8]) -> String {
+    const MAX_CHARS: usize = 30;
+
+    let text = String::from_utf8_lossy(text);
+    let mut res = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if i == MAX_CHARS {
+            res.push('…');
+            break;
+        }
+        match c {
+            '\r' => {}
+            '\n' => res.push('⏎'),
+            '\t' => res.push('→'),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Converts `replacement` to the case of `matched`, if that's all lowercase, all uppercase,
+/// or capitalized. Returns `None` for any other case, if `matched` has no cased letters,
+/// or if `replacement` isn't valid UTF-8, since case only applies to text.
+fn apply_case(matched: &str, replacement: &[u8]) -> Option<String> {
+    let mut letters = matched.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+    let first = letters.next()?;
+    let rest: Vec<char> = letters.collect();
+    let rest_lower = rest.iter().all(|c| c.is_lowercase());
+    let rest_upper = rest.iter().all(|c| c.is_uppercase());
+    let replacement = str::from_utf8(replacement).ok()?;
+
+    if first.is_lowercase() && rest_lower {
+        Some(replacement.to_lowercase())
+    } else if first.is_uppercase() && rest_upper && !rest.is_empty() {
+        Some(replacement.to_uppercase())
+    } else if first.is_uppercase() && rest_lower {
+        // A single uppercase letter counts as capitalized, not as all uppercase.
+        // Only the first letter changes, so that "barBaz" becomes "BarBaz".
+        let mut chars = replacement.chars();
+        let mut res: String = chars.next()?.to_uppercase().collect();
+        res.push_str(chars.as_str());
+        Some(res)
+    } else {
+        None
+    }
+}
+
+/// What [`sniff`] makes of the start of a file without a BOM.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sniffed {
+    /// Valid UTF-8, which includes plain ASCII.
+    Utf8,
+    /// UTF-8 with a few invalid bytes in between, which are best kept as they are.
+    InvalidUtf8,
+    /// UTF-16 without a BOM. Contains the name of the encoding.
+    Utf16(&'static str),
+    /// Too many invalid bytes for UTF-8, so it's most likely a legacy single-byte encoding.
+    Legacy,
+    /// Not text at all.
+    Binary,
+}
+
+/// Guesses what the `sample` from the start of a file without a BOM contains.
+/// The sample may end in the middle of a multi-byte sequence.
+pub fn sniff(sample: &[u8]) -> Sniffed {
+    // Most text is ASCII, which makes UTF-16 without a BOM easy to spot:
+    // Every other byte is zero, the odd ones for little endian and the even ones for big endian.
+    let pairs = sample.len() / 2;
+    let mut zeros = [0; 2];
+    for pair in sample.chunks_exact(2) {
+        zeros[0] += (pair[0] == 0) as usize;
+        zeros[1] += (pair[1] == 0) as usize;
+    }
+    if pairs > 0 {
+        if zeros[0] == 0 && zeros[1] > pairs / 4 {
+            return Sniffed::Utf16("UTF-16LE");
+        }
+        if zeros[1] == 0 && zeros[0] > pairs / 4 {
+            return Sniffed::Utf16("UTF-16BE");
+        }
+    }
+    // Otherwise, NUL doesn't occur in text.
+    if sample.contains(&0) {
+        return Sniffed::Binary;
+    }
+
+    // Bytes that aren't part of a valid sequence.
+    let mut invalid = 0;
+    // Valid sequences of 2 to 4 bytes.
+    let mut multibyte = 0;
+    // Control characters, other than whitespace and ESC.
+    let mut control = 0;
+    let mut chunks = sample.utf8_chunks().peekable();
+    while let Some(chunk) = chunks.next() {
+        multibyte += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        control += chunk
+            .valid()
+            .bytes()
+            .filter(|b| matches!(b, 0x01..=0x08 | 0x0b | 0x0e..=0x1a | 0x1c..=0x1f | 0x7f))
+            .count();
+        // A sequence that's cut off at the end of the sample isn't invalid.
+        let cut_off = chunks.peek().is_none()
+            && str::from_utf8(chunk.invalid()).is_err_and(|err| err.error_len().is_none());
+        if !cut_off {
+            invalid += chunk.invalid().len();
+        }
+    }
+
+    // Binary files are full of control characters and bytes that aren't valid UTF-8,
+    // whereas text in a legacy encoding only has the latter.
+    if control * 10 > sample.len() || (control > 0 && invalid * 4 > sample.len()) {
+        Sniffed::Binary
+    } else if invalid == 0 {
+        Sniffed::Utf8
+    } else if multibyte >= invalid {
+        // A few stray bytes in otherwise valid UTF-8 don't make it a legacy encoding.
+        Sniffed::InvalidUtf8
+    } else {
+        Sniffed::Legacy
+    }
+}
+
 fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     if bytes.len() >= 4 {
         if bytes.starts_with(b"\xFF\xFE\x00\x00") {
@@ -2927,15 +5300,466 @@ fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     None
 }
 
-fn highlight_to_color(highlight: usize, fb: &Framebuffer) -> u32 {
-    match highlight {
-        0 => fb.indexed(IndexedColor::White),      // Default
-        1 => fb.indexed(IndexedColor::BrightBlue), // Keyword
-        2 => fb.indexed(IndexedColor::Green),      // String
-        3 => fb.indexed(IndexedColor::Yellow),     // Type
-        4 => fb.indexed(IndexedColor::Cyan),       // Function
-        5 => fb.indexed(IndexedColor::Red),        // Constant
-        6 => fb.indexed(IndexedColor::Magenta),    // Comment
-        _ => fb.indexed(IndexedColor::White),
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+    use super::*;
+    use crate::arena;
+
+    /// The scratch arenas are shared by all threads, so the tests that use them take turns.
+    fn setup() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static INIT: Once = Once::new();
+        let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        INIT.call_once(|| arena::init(16 * MEBI).unwrap());
+        guard
+    }
+
+    fn buffer(text: &str) -> TextBuffer {
+        let mut tb = TextBuffer::new(true).unwrap();
+        tb.write_raw(text.as_bytes());
+        tb
+    }
+
+    fn contents(tb: &TextBuffer) -> String {
+        let mut text = String::new();
+        tb.buffer.copy_into(&mut text);
+        text
+    }
+
+    fn selected(tb: &TextBuffer) -> Option<Range<usize>> {
+        tb.selection_range().map(|(beg, end)| beg.offset..end.offset)
+    }
+
+    /// Reads `bytes` as a file without a given encoding.
+    fn read_buffer(name: &str, bytes: &[u8]) -> TextBuffer {
+        let path = std::env::temp_dir().join(format!("edit-{}-{name}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let mut tb = TextBuffer::new(true).unwrap();
+        let result = tb.read_file(&mut File::open(&path).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        tb
+    }
+
+    /// Like [`read_buffer`]. Returns the detected encoding and the text.
+    fn read(name: &str, bytes: &[u8]) -> (&'static str, String) {
+        let tb = read_buffer(name, bytes);
+        (tb.encoding(), contents(&tb))
+    }
+
+    #[test]
+    fn test_replace_all_in_range_partial_matches() {
+        let _guard = setup();
+        let mut tb = buffer("foo foo foo foo");
+
+        // The first "foo" starts before the range and the third one ends after it.
+        let range = tb.find_and_replace_all_in_range("foo", SearchOptions::default(), b"x", 1..10);
+        assert_eq!(range.unwrap(), 1..8);
+        assert_eq!(contents(&tb), "foo x foo foo");
+        assert_eq!(selected(&tb), Some(1..8));
+    }
+
+    #[test]
+    fn test_replace_all_in_range_does_not_wrap() {
+        let _guard = setup();
+        let mut tb = buffer("ab ab ab ab");
+        tb.cursor_move_to_offset(8);
+
+        // The replacement contains the pattern, which must not be replaced again,
+        // and the search starts at the range, not at the cursor.
+        let range = tb.find_and_replace_all_in_range("ab", SearchOptions::default(), b"abab", 3..8);
+        assert_eq!(range.unwrap(), 3..12);
+        assert_eq!(contents(&tb), "ab abab abab ab");
+    }
+
+    #[test]
+    fn test_find_in_range_wraps_within_range() {
+        let _guard = setup();
+        let mut tb = buffer("a a a a");
+        let options = SearchOptions::default();
+
+        tb.find_and_select_in_range("a", options, 2..5).unwrap();
+        assert_eq!(selected(&tb), Some(2..3));
+        tb.find_and_select_in_range("a", options, 2..5).unwrap();
+        assert_eq!(selected(&tb), Some(4..5));
+        tb.find_and_select_in_range("a", options, 2..5).unwrap();
+        assert_eq!(selected(&tb), Some(2..3));
+    }
+
+    #[test]
+    fn test_replace_in_range() {
+        let _guard = setup();
+        let mut tb = buffer("a a a a");
+        let options = SearchOptions::default();
+
+        // The first call only selects the first match, like Find Next.
+        let range = tb.find_and_replace_in_range("a", options, b"bb", 2..5).unwrap();
+        assert_eq!(range, 2..5);
+        assert_eq!(selected(&tb), Some(2..3));
+        let range = tb.find_and_replace_in_range("a", options, b"bb", 2..5).unwrap();
+        assert_eq!(range, 2..6);
+        assert_eq!(contents(&tb), "a bb a a");
+        assert_eq!(selected(&tb), Some(5..6));
+        let range = tb.find_and_replace_in_range("a", options, b"bb", 2..6).unwrap();
+        assert_eq!(range, 2..7);
+        assert_eq!(contents(&tb), "a bb bb a");
+        // No matches are left in the range, and the last "a" is outside of it.
+        assert_eq!(selected(&tb), None);
+    }
+
+    #[test]
+    fn test_add_cursor_at_next_occurrence() {
+        let _guard = setup();
+        let mut tb = buffer("foo bar foo\nfoo");
+        tb.cursor_move_to_offset(0);
+
+        // Without a selection, the word under the cursor is selected first.
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(0..3));
+        assert!(!tb.has_extra_cursors());
+
+        // Occurrences on the last line are found, and the newest one is the primary cursor.
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(8..11));
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(12..15));
+        assert!(tb.has_extra_cursors());
+
+        // Once every occurrence has a cursor, nothing changes.
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(12..15));
+
+        tb.write_canon(b"x");
+        assert_eq!(contents(&tb), "x bar x\nx");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 1, y: 1 });
+
+        // Undo reverts the edit at all cursors as one step.
+        tb.undo();
+        assert_eq!(contents(&tb), "foo bar foo\nfoo");
+        tb.redo();
+        assert_eq!(contents(&tb), "x bar x\nx");
+    }
+
+    #[test]
+    fn test_add_cursor_at_next_occurrence_wraps_around() {
+        let _guard = setup();
+        let mut tb = buffer("ab ab ab");
+        tb.cursor_move_to_offset(6);
+
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(6..8));
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(0..2));
+        tb.add_cursor_at_next_occurrence();
+        assert_eq!(selected(&tb), Some(3..5));
+    }
+
+    #[test]
+    fn test_edit_each_cursor_at_document_edges() {
+        let _guard = setup();
+        // The cursor starts at the end, so the second occurrence wraps around to the first line.
+        let mut tb = buffer("one\ntwo\none");
+        tb.add_cursor_at_next_occurrence();
+        tb.add_cursor_at_next_occurrence();
+        tb.write_canon(b"");
+        assert_eq!(contents(&tb), "\ntwo\n");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 0, y: 0 });
+        // Backspacing at the start of the first line deletes nothing there,
+        // but still deletes before the other cursor.
+        tb.delete(CursorMovement::Grapheme, -1);
+        assert_eq!(contents(&tb), "\ntwo");
+        tb.undo();
+        assert_eq!(contents(&tb), "\ntwo\n");
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\none");
+    }
+
+    #[test]
+    fn test_search_iter() {
+        let _guard = setup();
+        let mut tb = buffer("foo Foo\nfoo foo");
+        tb.cursor_move_to_offset(2);
+        let options = SearchOptions::default();
+        let hits = |tb: &TextBuffer, pattern: &str, options, range| -> Vec<(usize, usize)> {
+            tb.search_iter(pattern, options, range).unwrap().map(|r| (r.start, r.end)).collect()
+        };
+
+        assert_eq!(hits(&tb, "foo", options, 0..usize::MAX), [(0, 3), (4, 7), (8, 11), (12, 15)]);
+        let options_case = SearchOptions { match_case: true, ..options };
+        assert_eq!(hits(&tb, "foo", options_case, 0..usize::MAX), [(0, 3), (8, 11), (12, 15)]);
+        let options_regex = SearchOptions { use_regex: true, ..options };
+        assert_eq!(hits(&tb, "^f..", options_regex, 0..usize::MAX), [(0, 3), (8, 11)]);
+
+        // Matches that stick out of the range at either end aren't included.
+        assert_eq!(hits(&tb, "foo", options, 1..11), [(4, 7), (8, 11)]);
+        assert_eq!(hits(&tb, "foo", options, 1..10), [(4, 7)]);
+        assert_eq!(hits(&tb, "foo", options, 12..15), [(12, 15)]);
+        // Nor are those before it, even if it starts at or past the end of the text.
+        assert!(hits(&tb, "foo", options, 14..15).is_empty());
+        assert!(hits(&tb, "foo", options, 15..15).is_empty());
+        assert!(hits(&tb, "foo", options, 16..20).is_empty());
+        // Stopping early is fine, too.
+        assert_eq!(tb.search_iter("foo", options, 0..usize::MAX).unwrap().nth(1), Some(4..7));
+
+        assert!(tb.search_iter("", options, 0..usize::MAX).is_err());
+        // Neither the cursor nor the selection is touched.
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 2, y: 0 });
+        assert_eq!(selected(&tb), None);
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let _guard = setup();
+        let mut tb = buffer("abcdef\nab\nabcdef");
+        tb.cursor_move_to_logical(Point { x: 2, y: 0 });
+
+        // The head is clamped to the last line, and the block to the end of shorter lines.
+        tb.block_selection_extend(Point { x: 2, y: 5 });
+        assert_eq!(tb.block_selection(), Some((Point { x: 2, y: 0 }, Point { x: 4, y: 2 })));
+        assert_eq!(selected(&tb), Some(12..14));
+
+        tb.write_canon(b"X");
+        assert_eq!(contents(&tb), "abXef\nabX\nabXef");
+        tb.undo();
+        assert_eq!(contents(&tb), "abcdef\nab\nabcdef");
+    }
+
+    #[test]
+    fn test_block_selection_pads_short_lines() {
+        let _guard = setup();
+        let mut tb = buffer("abcdef\nab\n\nabcdef");
+        tb.cursor_move_to_logical(Point { x: 4, y: 3 });
+
+        // A block of carets, extended upwards past the first line.
+        tb.block_selection_extend(Point { x: 0, y: -7 });
+        assert_eq!(tb.block_selection(), Some((Point { x: 4, y: 3 }, Point { x: 4, y: 0 })));
+
+        tb.write_canon(b"X");
+        assert_eq!(contents(&tb), "abcdXef\nab  X\n    X\nabcdXef");
+        // The padding is undone along with the text.
+        tb.undo();
+        assert_eq!(contents(&tb), "abcdef\nab\n\nabcdef");
+    }
+
+    #[test]
+    fn test_history() {
+        let _guard = setup();
+        let mut tb = TextBuffer::new(true).unwrap();
+        let added = |tb: &TextBuffer| -> Vec<String> {
+            tb.history().0.into_iter().map(|step| step.added).collect()
+        };
+
+        tb.write_raw(b"a b");
+        tb.write_raw(b" a");
+        // An edit at several cursors is a single step made of one edit per cursor.
+        tb.cursor_move_to_offset(0);
+        tb.add_cursor_at_next_occurrence();
+        tb.add_cursor_at_next_occurrence();
+        tb.write_raw(b"c");
+        assert_eq!(contents(&tb), "c b c");
+        let (steps, applied) = tb.history();
+        assert_eq!((steps.len(), applied), (3, 3));
+        assert_eq!(steps[2].edits, 2);
+        assert_eq!(added(&tb), ["a b", " a", "c"]);
+
+        // Going back keeps the undone steps around for going forward again.
+        tb.history_goto(1);
+        assert_eq!(contents(&tb), "a b");
+        assert_eq!(tb.history().1, 1);
+        assert_eq!(added(&tb), ["a b", " a", "c"]);
+        tb.history_goto(3);
+        assert_eq!(contents(&tb), "c b c");
+        tb.history_goto(0);
+        assert_eq!(contents(&tb), "");
+        assert_eq!(tb.history().1, 0);
+        // Going past the last step goes to the last step.
+        tb.history_goto(usize::MAX);
+        assert_eq!(contents(&tb), "c b c");
+        assert_eq!(tb.history().1, 3);
+
+        // A new edit drops the steps that were undone.
+        tb.history_goto(1);
+        tb.write_raw(b"!");
+        assert_eq!(added(&tb), ["a b", "!"]);
+        assert_eq!(tb.history().1, 2);
+    }
+
+    #[test]
+    fn test_duplicate_selected_lines() {
+        let _guard = setup();
+        let mut tb = buffer("one\ntwo\nthree");
+
+        tb.cursor_move_to_logical(Point { x: 2, y: 1 });
+        tb.duplicate_selected_lines();
+        assert_eq!(contents(&tb), "one\ntwo\ntwo\nthree");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 2, y: 2 });
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\nthree");
+
+        // The last line has no newline of its own to copy.
+        tb.cursor_move_to_logical(Point { x: 1, y: 2 });
+        tb.duplicate_selected_lines();
+        assert_eq!(contents(&tb), "one\ntwo\nthree\nthree");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 1, y: 3 });
+        tb.undo();
+
+        // A selection from the first line copies all of its lines, and moves along.
+        tb.cursor_move_to_logical(Point { x: 1, y: 0 });
+        tb.start_selection();
+        tb.selection_update_logical(Point { x: 1, y: 1 });
+        tb.duplicate_selected_lines();
+        assert_eq!(contents(&tb), "one\ntwo\none\ntwo\nthree");
+        assert_eq!(selected(&tb), Some(9..13));
+        tb.undo();
+        assert_eq!(contents(&tb), "one\ntwo\nthree");
+
+        // The empty line after a final newline.
+        let mut tb = buffer("one\n");
+        tb.duplicate_selected_lines();
+        assert_eq!(contents(&tb), "one\n\n");
+        assert_eq!(tb.cursor_logical_pos(), Point { x: 0, y: 2 });
+    }
+
+    #[test]
+    fn test_fold_ranges() {
+        let _guard = setup();
+        let mut tb = buffer("0\n1\n2\n3\n4\n5\n6\n7\n8\n9");
+
+        tb.fold_ranges(&[2..4, 7..9]);
+        assert_eq!(tb.valid_folds(), [2..4, 7..9]);
+        // Folds within a region are merged into it,
+        // and a region within a fold is skipped.
+        tb.fold_ranges(&[1..6, 8..9]);
+        assert_eq!(tb.valid_folds(), [1..6, 7..9]);
+        // As is one that partly overlaps a fold.
+        tb.fold_ranges(&[3..5, 5..8]);
+        assert_eq!(tb.valid_folds(), [1..6, 7..9]);
+    }
+
+    #[test]
+    fn test_scrollbar_markers() {
+        let _guard = setup();
+        let mut tb = buffer("aaaaaaaaaa\nb\nc\nd\ne\nf");
+        let markers = |tb: &TextBuffer| tb.scrollbar_markers().map(|(y, _)| y).collect::<Vec<_>>();
+
+        // The marks on "c" and "f" are placed on the lines the scrollbar counts: The first line
+        // wraps into 3, and the lines of a fold are shown on the line before it.
+        tb.set_word_wrap(true);
+        tb.set_width(4);
+        tb.set_scrollbar_markers(&[(13, IndexedColor::Red), (19, IndexedColor::Red)], &[]);
+        assert_eq!(markers(&tb), [4, 7]);
+        tb.folds_set(vec![1..2, 3..5]);
+        assert_eq!(markers(&tb), [3, 4]);
+
+        // They move along once the lines are wrapped differently.
+        tb.set_width(20);
+        assert_eq!(markers(&tb), [1, 2]);
+
+        // A mark given by line wins over a match on the same line.
+        let mut tb = buffer("a\nb\nc");
+        let (red, green) = (IndexedColor::Red, IndexedColor::Green);
+        tb.set_scrollbar_markers(&[(0, red), (2, red), (4, red)], &[(1, green), (2, green)]);
+        let marks: Vec<_> = tb.scrollbar_markers().map(|(y, color)| (y, color == green)).collect();
+        assert_eq!(marks, [(0, false), (1, true), (2, true)]);
+    }
+
+    #[test]
+    fn test_convert_newlines() {
+        let _guard = setup();
+        // Mixed newlines are kept as they are, and the majority decides what's typed.
+        let mut tb = read_buffer("mixed.txt", b"a\r\nb\nc\r\n");
+        assert_eq!(contents(&tb), "a\r\nb\nc\r\n");
+        assert!(tb.is_crlf() && tb.has_mixed_newlines());
+
+        tb.convert_newlines(false);
+        assert_eq!(contents(&tb), "a\nb\nc\n");
+        assert!(!tb.is_crlf() && !tb.has_mixed_newlines());
+
+        // Undo restores the newlines verbatim, along with the type of new ones.
+        tb.undo();
+        assert_eq!(contents(&tb), "a\r\nb\nc\r\n");
+        assert!(tb.is_crlf());
+        tb.redo();
+        assert_eq!(contents(&tb), "a\nb\nc\n");
+        assert!(!tb.is_crlf());
+        tb.undo();
+        tb.cursor_move_to_offset(tb.text_length());
+        tb.write_canon(b"\n");
+        assert_eq!(contents(&tb), "a\r\nb\nc\r\n\r\n");
+
+        // Converting to what the document uses already isn't an edit.
+        let mut tb = read_buffer("lf.txt", b"a\nb");
+        tb.convert_newlines(false);
+        assert_eq!(tb.history().0.len(), 0);
+        tb.convert_newlines(true);
+        assert_eq!(contents(&tb), "a\r\nb");
+        tb.undo();
+        assert_eq!(contents(&tb), "a\nb");
+        assert!(!tb.is_crlf());
+    }
+
+    #[test]
+    fn test_sniff_utf16() {
+        let _guard = setup();
+        let utf16 = |text: &str, le: bool| -> Vec<u8> {
+            text.encode_utf16()
+                .flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() })
+                .collect()
+        };
+        let text = "Grüße\nfrom edit\n";
+
+        let with_bom = [b"\xFF\xFE".as_slice(), &utf16(text, true)].concat();
+        assert_eq!(read("le-bom.txt", &with_bom), ("UTF-16LE", text.to_string()));
+        let with_bom = [b"\xFE\xFF".as_slice(), &utf16(text, false)].concat();
+        assert_eq!(read("be-bom.txt", &with_bom), ("UTF-16BE", text.to_string()));
+
+        assert_eq!(read("le.txt", &utf16(text, true)), ("UTF-16LE", text.to_string()));
+        assert_eq!(read("be.txt", &utf16(text, false)), ("UTF-16BE", text.to_string()));
+    }
+
+    #[test]
+    fn test_sniff_legacy_encoding() {
+        let _guard = setup();
+        assert_eq!(read("utf8.txt", "café\n".as_bytes()), ("UTF-8", "café\n".to_string()));
+        assert_eq!(read("1252.txt", b"caf\xE9 \x80\n"), ("windows-1252", "café €\n".to_string()));
+    }
+
+    #[test]
+    fn test_sniff_invalid_utf8() {
+        let _guard = setup();
+        // A stray byte next to valid multi-byte sequences doesn't make the file windows-1252.
+        let text = ["é".as_bytes(), b"\x80\n"].concat();
+        assert_eq!(read("stray.txt", &text), ("UTF-8", "é\u{FFFD}\n".to_string()));
+        assert_eq!(sniff(&text), Sniffed::InvalidUtf8);
+        assert_eq!(sniff(b"caf\xE9 \x80\n"), Sniffed::Legacy);
+        // A sequence cut off by the end of the sample is fine.
+        assert_eq!(sniff(&"aé".as_bytes()[..2]), Sniffed::Utf8);
+    }
+
+    #[test]
+    fn test_apply_case() {
+        assert_eq!(apply_case("foo", b"BarBaz").as_deref(), Some("barbaz"));
+        assert_eq!(apply_case("FOO", b"barBaz").as_deref(), Some("BARBAZ"));
+        assert_eq!(apply_case("Foo", b"barBaz").as_deref(), Some("BarBaz"));
+        assert_eq!(apply_case("Foo", b"\xC3\xA9t\xC3\xA9").as_deref(), Some("\u{C9}t\u{E9}"));
+        // Mixed case is left for the replacement to decide.
+        assert_eq!(apply_case("fooBar", b"baz"), None);
+        // A single letter is either lowercase or capitalized.
+        assert_eq!(apply_case("a", b"bar").as_deref(), Some("bar"));
+        assert_eq!(apply_case("A", b"bar").as_deref(), Some("Bar"));
+        assert_eq!(apply_case("123", b"bar"), None);
+        // Bytes that aren't valid UTF-8 are kept as they are.
+        assert_eq!(apply_case("Foo", b"bar\xFF"), None);
+    }
+
+    #[test]
+    fn test_sniff_binary() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"), Sniffed::Binary);
+        // Without NUL bytes, it takes control characters among invalid bytes.
+        assert_eq!(sniff(b"\x1f\x8b\x08\x08\xd3\xfe\x81\x92\x02\xff"), Sniffed::Binary);
+        assert_eq!(sniff(b"\xe0 \xe9t\xe9\x0c\n"), Sniffed::Legacy);
+        assert_eq!(sniff(b"\x1b[1mbold\x1b[0m\n"), Sniffed::Utf8);
     }
 }
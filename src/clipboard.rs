@@ -1,5 +1,7 @@
 //! Clipboard facilities for the editor.
 
+use crate::sys;
+
 /// The builtin, internal clipboard of the editor.
 ///
 /// This is useful particularly when the terminal doesn't support
@@ -46,6 +48,18 @@ impl Clipboard {
         }
     }
 
+    /// Picks up what other applications copied to the system clipboard
+    /// since we last wrote to it. Call this before pasting.
+    pub fn sync_from_system(&mut self) {
+        if let Some(data) = sys::clipboard_read()
+            && !data.is_empty()
+            && data != self.data
+        {
+            self.data = data;
+            self.line_copy = false;
+        }
+    }
+
     /// See [`Clipboard::is_line_copy`].
     pub fn write_was_line_copy(&mut self, line_copy: bool) {
         self.line_copy = line_copy;
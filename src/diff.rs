@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Line diffs, computed with the Myers algorithm.
+
+use std::ops::Range;
+
+use crate::hash::hash;
+
+/// A run of lines that differ: the `old` lines were replaced by the `new` ones.
+/// One of the two is empty if lines were only added or only deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Beyond this many differing lines the rest is reported as a single hunk,
+/// because the memory the algorithm needs grows with the square of it.
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// Splits `text` into lines, without their LF or CRLF newlines.
+/// A newline at the end terminates the last line instead of starting another one.
+pub fn lines(text: &[u8]) -> Vec<&[u8]> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let mut lines: Vec<_> =
+        text.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line)).collect();
+    if text.ends_with(b"\n") {
+        lines.pop();
+    }
+    lines
+}
+
+/// Returns the hunks that turn the `old` lines into the `new` ones, in order.
+pub fn diff_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<Hunk> {
+    let old: Vec<u64> = old.iter().map(|line| hash(0, line)).collect();
+    let new: Vec<u64> = new.iter().map(|line| hash(0, line)).collect();
+
+    // Usually most lines are the same at the start and the end, which is cheap to skip.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut hunks = myers(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    for hunk in &mut hunks {
+        hunk.old = hunk.old.start + prefix..hunk.old.end + prefix;
+        hunk.new = hunk.new.start + prefix..hunk.new.end + prefix;
+    }
+    hunks
+}
+
+fn myers(a: &[u64], b: &[u64]) -> Vec<Hunk> {
+    if a.is_empty() && b.is_empty() {
+        return Vec::new();
+    }
+
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let limit = (a.len() + b.len()).min(MAX_EDIT_DISTANCE) as isize;
+    let offset = limit + 1;
+
+    // `v[k + offset]` is the furthest x reached on diagonal k = x - y.
+    // `trace[d]` is a copy of diagonals -d..=d before step d, for backtracking.
+    let mut v = vec![0isize; 2 * limit as usize + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=limit {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x =
+                if k == -d || (k != d && v[i - 1] < v[i + 1]) { v[i + 1] } else { v[i - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+
+            if x >= n && y >= m {
+                return hunks_from_ops(&backtrack(&trace, n, m, d));
+            }
+        }
+    }
+
+    // Too different to be worth the effort.
+    vec![Hunk { old: 0..a.len(), new: 0..b.len() }]
+}
+
+/// Walks back from the end to find the path that step `d` completed. The ops are in reverse.
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize, d: isize) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for d in (1..=d).rev() {
+        let v = &trace[d as usize];
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let down = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let prev_k = if down { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        ops.push(if down { Op::Insert } else { Op::Delete });
+        (x, y) = (prev_x, prev_y);
+    }
+
+    for _ in 0..x.min(y) {
+        ops.push(Op::Equal);
+    }
+    ops
+}
+
+fn hunks_from_ops(ops: &[Op]) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut hunk: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+
+    for &op in ops.iter().rev() {
+        match op {
+            Op::Equal => {
+                hunks.extend(hunk.take());
+                i += 1;
+                j += 1;
+            }
+            Op::Insert => {
+                hunk.get_or_insert(Hunk { old: i..i, new: j..j }).new.end += 1;
+                j += 1;
+            }
+            Op::Delete => {
+                hunk.get_or_insert(Hunk { old: i..i, new: j..j }).old.end += 1;
+                i += 1;
+            }
+        }
+    }
+
+    hunks.extend(hunk);
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> Vec<Hunk> {
+        diff_lines(&lines(old.as_bytes()), &lines(new.as_bytes()))
+    }
+
+    #[test]
+    fn test_lines() {
+        assert!(lines(b"").is_empty());
+        assert_eq!(lines(b"a\r\nb"), [b"a".as_slice(), b"b"]);
+        assert_eq!(lines(b"a\n\n"), [b"a".as_slice(), b""]);
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\nc\n", "a\nb\nc\n"), []);
+        assert_eq!(diff("a\nc\n", "a\nb\nc\n"), [Hunk { old: 1..1, new: 1..2 }]);
+        assert_eq!(diff("a\nb\nc\n", "a\nc\n"), [Hunk { old: 1..2, new: 1..1 }]);
+        assert_eq!(diff("a\nb\nc\n", "a\nB\nc\n"), [Hunk { old: 1..2, new: 1..2 }]);
+        assert_eq!(diff("", "a\n"), [Hunk { old: 0..0, new: 0..1 }]);
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\n", "x\nb\nc\ne\ny\n"),
+            [
+                Hunk { old: 0..1, new: 0..1 },
+                Hunk { old: 3..4, new: 3..3 },
+                Hunk { old: 5..5, new: 4..5 },
+            ]
+        );
+    }
+}
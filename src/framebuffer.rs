@@ -30,7 +30,7 @@ const CACHE_TABLE_SIZE: usize = 1 << CACHE_TABLE_LOG2_SIZE;
 const CACHE_TABLE_SHIFT: usize = usize::BITS as usize - CACHE_TABLE_LOG2_SIZE;
 
 /// Standard 16 VT & default foreground/background colors.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum IndexedColor {
     Black,
     Red,
@@ -105,6 +105,9 @@ pub struct Framebuffer {
     contrast_colors: [Cell<(u32, u32)>; CACHE_TABLE_SIZE],
     background_fill: u32,
     foreground_fill: u32,
+    /// Whether the terminal understands 24-bit colors.
+    /// If not, colors are rendered as the closest palette entry.
+    truecolor: bool,
 }
 
 impl Framebuffer {
@@ -121,6 +124,7 @@ impl Framebuffer {
             contrast_colors: [const { Cell::new((0, 0)) }; CACHE_TABLE_SIZE],
             background_fill: DEFAULT_THEME[IndexedColor::Background as usize],
             foreground_fill: DEFAULT_THEME[IndexedColor::Foreground as usize],
+            truecolor: true,
         }
     }
 
@@ -142,6 +146,15 @@ impl Framebuffer {
         }
     }
 
+    /// Sets whether the terminal supports 24-bit colors (on by default).
+    ///
+    /// Without it, every color gets rendered as the closest one in the palette given to
+    /// [`Framebuffer::set_indexed_colors`]. Since the palette is what the terminal itself
+    /// uses, colors from [`Framebuffer::indexed`] still come out exactly right.
+    pub fn set_truecolor(&mut self, truecolor: bool) {
+        self.truecolor = truecolor;
+    }
+
     /// Begins a new frame with the given `size`.
     pub fn flip(&mut self, size: Size) {
         if size != self.buffers[0].bg_bitmap.size {
@@ -564,11 +577,40 @@ impl Framebuffer {
             color = oklab_blend(dst, color);
         }
 
+        if !self.truecolor {
+            match self.nearest_indexed(fg, color) {
+                i @ 0..8 => _ = write!(dst, "\x1b[{typ}{i}m"),
+                i @ 8..16 if fg => _ = write!(dst, "\x1b[9{}m", i - 8),
+                i @ 8..16 => _ = write!(dst, "\x1b[10{}m", i - 8),
+                _ => _ = write!(dst, "\x1b[{typ}9m"),
+            }
+            return;
+        }
+
         let r = color & 0xff;
         let g = (color >> 8) & 0xff;
         let b = (color >> 16) & 0xff;
         _ = write!(dst, "\x1b[{typ}8;2;{r};{g};{b}m");
     }
+
+    /// Returns the index of the palette color closest to `color`, measured in Oklab.
+    /// Besides the 16 base colors, this includes the default foreground or background color.
+    fn nearest_indexed(&self, fg: bool, color: u32) -> usize {
+        let default = if fg { IndexedColor::Foreground } else { IndexedColor::Background };
+        let target = srgb_to_oklab(color);
+        let mut best = (f32::MAX, default as usize);
+
+        for idx in (0..16).chain([default as usize]) {
+            let c = srgb_to_oklab(self.indexed_colors[idx]);
+            let (dl, da, db) = (c.l - target.l, c.a - target.a, c.b - target.b);
+            let dist = dl * dl + da * da + db * db;
+            if dist < best.0 {
+                best = (dist, idx);
+            }
+        }
+
+        best.1
+    }
 }
 
 #[derive(Default)]
@@ -11,6 +11,31 @@ use std::mem;
 use crate::helpers::{CoordType, Point, Size};
 use crate::vt;
 
+/// The names of the keys that aren't letters, digits or F-keys, for [`InputKey::parse`].
+/// The first name of each key is the one that [`InputKey::key_name`] returns.
+const KEY_NAMES: [(&str, InputKey); 20] = [
+    ("Backspace", vk::BACK),
+    ("Tab", vk::TAB),
+    ("Enter", vk::RETURN),
+    ("Return", vk::RETURN),
+    ("Esc", vk::ESCAPE),
+    ("Escape", vk::ESCAPE),
+    ("Space", vk::SPACE),
+    ("PageUp", vk::PRIOR),
+    ("PageDown", vk::NEXT),
+    ("End", vk::END),
+    ("Home", vk::HOME),
+    ("Left", vk::LEFT),
+    ("Up", vk::UP),
+    ("Right", vk::RIGHT),
+    ("Down", vk::DOWN),
+    ("Insert", vk::INSERT),
+    ("Ins", vk::INSERT),
+    ("Delete", vk::DELETE),
+    ("Del", vk::DELETE),
+    ("/", vk::OEM_2),
+];
+
 /// Represents a key/modifier combination.
 ///
 /// TODO: Is this a good idea? I did it to allow typing `kbmod::CTRL | vk::A`.
@@ -57,6 +82,66 @@ impl InputKey {
     pub(crate) const fn with_modifiers(&self, modifiers: InputKeyMod) -> Self {
         Self(self.0 | modifiers.0)
     }
+
+    /// Parses a shortcut like `Ctrl+Shift+P`, `Alt+Z` or `F3`. Case-insensitive.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (mods, key) = text.rsplit_once('+').unwrap_or(("", text));
+        let key = key.trim();
+        let mut modifiers = kbmod::NONE;
+
+        if !mods.is_empty() {
+            for m in mods.split('+').map(str::trim) {
+                modifiers |= if m.eq_ignore_ascii_case("ctrl") || m.eq_ignore_ascii_case("control")
+                {
+                    kbmod::CTRL
+                } else if m.eq_ignore_ascii_case("alt") {
+                    kbmod::ALT
+                } else if m.eq_ignore_ascii_case("shift") {
+                    kbmod::SHIFT
+                } else {
+                    return None;
+                };
+            }
+        }
+
+        let key = if let &[ch] = key.as_bytes()
+            && ch.is_ascii_alphanumeric()
+        {
+            Self::from_ascii(ch.to_ascii_lowercase() as char)?
+        } else if let Some(&(_, vk)) =
+            KEY_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            vk
+        } else if let Some(n) = key.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u32>().ok())
+            && (1..=24).contains(&n)
+        {
+            Self(vk::F1.0 + n - 1)
+        } else {
+            return None;
+        };
+
+        Some(key.with_modifiers(modifiers))
+    }
+
+    /// Returns the name of the key without its modifiers, the way [`InputKey::parse`] takes it,
+    /// e.g. "P", "F3" or "Up". Returns `None` for keys without a name, like [`vk::NULL`].
+    pub fn key_name(&self) -> Option<&'static str> {
+        const CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        const F_KEYS: [&str; 24] = [
+            "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "F13",
+            "F14", "F15", "F16", "F17", "F18", "F19", "F20", "F21", "F22", "F23", "F24",
+        ];
+
+        let key = self.key();
+        match key.0 {
+            0x30..=0x39 | 0x41..=0x5A => {
+                let index = CHARS.find(key.0 as u8 as char)?;
+                Some(&CHARS[index..index + 1])
+            }
+            0x70..=0x87 => Some(F_KEYS[(key.0 - 0x70) as usize]),
+            _ => KEY_NAMES.iter().find(|&&(_, vk)| vk == key).map(|&(name, _)| name),
+        }
+    }
 }
 
 /// A keyboard modifier. Ctrl/Alt/Shift.
@@ -202,6 +287,8 @@ pub mod vk {
     pub const F22: InputKey = InputKey::new(0x85);
     pub const F23: InputKey = InputKey::new(0x86);
     pub const F24: InputKey = InputKey::new(0x87);
+
+    pub const OEM_2: InputKey = InputKey::new(0xBF); // "/?" on US keyboards
 }
 
 /// Keyboard modifiers.
@@ -261,6 +348,8 @@ pub enum Input<'input> {
     Keyboard(InputKey),
     /// Mouse input.
     Mouse(InputMouse),
+    /// The terminal window gained (`true`) or lost (`false`) focus.
+    Focus(bool),
 }
 
 /// Parses VT sequences into input events.
@@ -338,6 +427,8 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                         let key = ch as u32 | 0x40;
                         return Some(Input::Keyboard(kbmod::CTRL | InputKey::new(key)));
                     }
+                    // Terminals send Ctrl+/ the same as Ctrl+_.
+                    '\x1f' => return Some(Input::Keyboard(kbmod::CTRL | vk::OEM_2)),
                     '\x7f' => return Some(Input::Keyboard(vk::BACK)),
                     _ => {}
                 },
@@ -379,6 +470,8 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                             }
                         }
                         'Z' => return Some(Input::Keyboard(kbmod::SHIFT | vk::TAB)),
+                        'I' => return Some(Input::Focus(true)),
+                        'O' => return Some(Input::Focus(false)),
                         '~' => {
                             const LUT: [u8; 35] = [
                                 0,
@@ -584,3 +677,44 @@ impl<'input> Stream<'_, '_, 'input> {
         modifiers
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert!(InputKey::parse("Ctrl+Shift+F") == Some(kbmod::CTRL_SHIFT | vk::F));
+        assert!(InputKey::parse("ctrl + shift + f") == Some(kbmod::CTRL_SHIFT | vk::F));
+        assert!(InputKey::parse("Control+P") == Some(kbmod::CTRL | vk::P));
+        assert!(InputKey::parse("Alt+1") == Some(kbmod::ALT | vk::N1));
+        assert!(InputKey::parse("Shift+PageDown") == Some(kbmod::SHIFT | vk::NEXT));
+        assert!(InputKey::parse("Esc") == Some(vk::ESCAPE));
+        assert!(InputKey::parse("F3") == Some(vk::F3));
+        assert!(InputKey::parse("f24") == Some(vk::F24));
+        assert!(InputKey::parse("Ctrl+/") == Some(kbmod::CTRL | vk::OEM_2));
+    }
+
+    #[test]
+    fn test_key_name() {
+        assert_eq!((kbmod::CTRL_SHIFT | vk::P).key_name(), Some("P"));
+        assert_eq!((kbmod::ALT | vk::N1).key_name(), Some("1"));
+        assert_eq!(vk::F3.key_name(), Some("F3"));
+        assert_eq!((kbmod::ALT | vk::UP).key_name(), Some("Up"));
+        assert_eq!((kbmod::CTRL | vk::OEM_2).key_name(), Some("/"));
+        assert_eq!(vk::RETURN.key_name(), Some("Enter"));
+        assert_eq!(vk::NULL.key_name(), None);
+    }
+
+    #[test]
+    fn test_parse_key_invalid() {
+        assert!(InputKey::parse("").is_none());
+        assert!(InputKey::parse("Ctrl+").is_none());
+        assert!(InputKey::parse("Ctrl+Foo").is_none());
+        assert!(InputKey::parse("F0").is_none());
+        assert!(InputKey::parse("F25").is_none());
+        assert!(InputKey::parse("Ctrl+!").is_none());
+        assert!(InputKey::parse("Super+A").is_none());
+        assert!(InputKey::parse("Ctrl++A").is_none());
+    }
+}
@@ -0,0 +1,415 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A small JSON reader and writer.
+//!
+//! This exists so that we can persist bits of state like the session
+//! without pulling in serde. It's not fast, but it doesn't need to be.
+//! Objects preserve the order of their members.
+
+use std::fmt;
+
+/// Objects and arrays nested deeper than this are rejected by [`parse`].
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the member named `key`, if this is an object that contains it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an integer, if it is one.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_f64().filter(|n| n.fract() == 0.0).map(|n| n as i64)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+/// Writes the value as compact JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            // JSON has no representation for NaN and infinities.
+            Value::Number(n) if !n.is_finite() => f.write_str("null"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+
+    let mut beg = 0;
+    for (i, ch) in s.char_indices() {
+        let escape = match ch {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\0'..='\x1f' => "",
+            _ => continue,
+        };
+
+        f.write_str(&s[beg..i])?;
+        if escape.is_empty() {
+            write!(f, "\\u{:04x}", ch as u32)?;
+        } else {
+            f.write_str(escape)?;
+        }
+        beg = i + 1;
+    }
+
+    f.write_str(&s[beg..])?;
+    f.write_str("\"")
+}
+
+/// Parses a JSON document. Returns `None` if it's malformed.
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser { bytes: text.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos == parser.bytes.len() { Some(value) } else { None }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, literal: &[u8]) -> bool {
+        let matches = self.bytes[self.pos..].starts_with(literal);
+        if matches {
+            self.pos += literal.len();
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'n' => self.eat(b"null").then_some(Value::Null),
+            b't' => self.eat(b"true").then_some(Value::Bool(true)),
+            b'f' => self.eat(b"false").then_some(Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => self.array(),
+            b'{' => self.object(),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn array(&mut self) -> Option<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+
+        self.pos += 1; // Skip the [
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if !self.eat(b"]") {
+            loop {
+                items.push(self.value()?);
+                self.skip_whitespace();
+                match self.peek()? {
+                    b',' => self.pos += 1,
+                    b']' => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return None,
+                }
+            }
+        }
+
+        self.depth -= 1;
+        Some(Value::Array(items))
+    }
+
+    fn object(&mut self) -> Option<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+
+        self.pos += 1; // Skip the {
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+        if !self.eat(b"}") {
+            loop {
+                self.skip_whitespace();
+                if self.peek()? != b'"' {
+                    return None;
+                }
+                let key = self.string()?;
+
+                self.skip_whitespace();
+                if !self.eat(b":") {
+                    return None;
+                }
+                let value = self.value()?;
+                members.push((key, value));
+
+                self.skip_whitespace();
+                match self.peek()? {
+                    b',' => self.pos += 1,
+                    b'}' => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return None,
+                }
+            }
+        }
+
+        self.depth -= 1;
+        Some(Value::Object(members))
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let beg = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        // The slice only contains ASCII, so this can't fail.
+        let text = std::str::from_utf8(&self.bytes[beg..self.pos]).ok()?;
+        text.parse::<f64>().ok().map(Value::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.pos += 1; // Skip the "
+        let mut out = Vec::new();
+
+        loop {
+            let b = self.peek()?;
+            self.pos += 1;
+
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let esc = self.peek()?;
+                    self.pos += 1;
+
+                    let ch = match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\x08',
+                        b'f' => '\x0c',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut c = self.hex4()?;
+                            // Combine surrogate pairs. Lone surrogates fail `char::from_u32` below.
+                            if (0xd800..0xdc00).contains(&c) && self.eat(b"\\u") {
+                                let lo = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&lo) {
+                                    return None;
+                                }
+                                c = 0x10000 + ((c - 0xd800) << 10) + (lo - 0xdc00);
+                            }
+                            char::from_u32(c)?
+                        }
+                        _ => return None,
+                    };
+
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                // Control characters must be escaped.
+                0..=0x1f => return None,
+                _ => out.push(b),
+            }
+        }
+
+        // The input was valid UTF-8 and we only ever split it at ASCII characters.
+        String::from_utf8(out).ok()
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let hex = self.bytes.get(self.pos..self.pos + 4)?;
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        self.pos += 4;
+        u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scalars() {
+        assert_eq!(parse("null"), Some(Value::Null));
+        assert_eq!(parse(" true "), Some(Value::Bool(true)));
+        assert_eq!(parse("false"), Some(Value::Bool(false)));
+        assert_eq!(parse("0"), Some(Value::Number(0.0)));
+        assert_eq!(parse("-12.5e2"), Some(Value::Number(-1250.0)));
+        assert_eq!(parse(r#""abc""#), Some(Value::String("abc".into())));
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        let value = parse(r#"{ "a": [1, {"b": null}], "c": "d" }"#).unwrap();
+        assert_eq!(value.get("c").and_then(Value::as_str), Some("d"));
+
+        let a = value.get("a").and_then(Value::as_array).unwrap();
+        assert_eq!(a[0].as_i64(), Some(1));
+        assert_eq!(a[1].get("b"), Some(&Value::Null));
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        assert_eq!(parse(r#""a\"b\\c\/d\n""#), Some(Value::String("a\"b\\c/d\n".into())));
+        assert_eq!(parse(r#""\u00e4\u20AC""#), Some(Value::String("ä€".into())));
+        assert_eq!(parse(r#""\ud83d\ude00""#), Some(Value::String("😀".into())));
+        assert_eq!(parse(r#""ünïcödé""#), Some(Value::String("ünïcödé".into())));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("nul"), None);
+        assert_eq!(parse("[1,]"), None);
+        assert_eq!(parse("[1 2]"), None);
+        assert_eq!(parse(r#"{"a" 1}"#), None);
+        assert_eq!(parse(r#"{a: 1}"#), None);
+        assert_eq!(parse(r#""abc"#), None);
+        assert_eq!(parse("\"a\nb\""), None);
+        assert_eq!(parse(r#""\x""#), None);
+        assert_eq!(parse(r#""\ud83d""#), None);
+        assert_eq!(parse("1 2"), None);
+        assert_eq!(parse(&"[".repeat(MAX_DEPTH + 1)), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let value = Value::Object(vec![
+            ("path".into(), "C:\\foo\\\"bar\".txt".into()),
+            ("line".into(), 123i64.into()),
+            ("ratio".into(), Value::Number(0.5)),
+            ("flags".into(), Value::Array(vec![true.into(), Value::Null])),
+            ("ctrl".into(), "\t\x01".into()),
+        ]);
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            r#"{"path":"C:\\foo\\\"bar\".txt","line":123,"ratio":0.5,"flags":[true,null],"ctrl":"\t\u0001"}"#
+        );
+        assert_eq!(parse(&text), Some(value));
+    }
+}
@@ -25,6 +25,7 @@ pub mod base64;
 pub mod buffer;
 pub mod cell;
 pub mod clipboard;
+pub mod diff;
 pub mod document;
 pub mod framebuffer;
 pub mod fuzzy;
@@ -32,9 +33,11 @@ pub mod hash;
 pub mod helpers;
 pub mod icu;
 pub mod input;
+pub mod json;
 pub mod oklab;
 pub mod path;
 pub mod simd;
+pub mod snippet;
 pub mod sys;
 pub mod syntax;
 pub mod tui;
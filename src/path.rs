@@ -3,8 +3,11 @@
 
 //! Path related helpers.
 
+use std::env;
 use std::ffi::{OsStr, OsString};
-use std::path::{Component, MAIN_SEPARATOR_STR, Path, PathBuf};
+use std::path::{Component, MAIN_SEPARATOR_STR, Path, PathBuf, is_separator};
+
+use crate::sys;
 
 /// Normalizes a given path by removing redundant components.
 /// The given path must be absolute (e.g. by joining it with the current working directory).
@@ -44,6 +47,76 @@ pub fn normalize(path: &Path) -> PathBuf {
     res
 }
 
+/// Expands a leading `~` or `~user` to the home directory, and `$VAR` or `${VAR}`
+/// to the value of the environment variable. Variables that aren't set expand to nothing.
+/// `$$` stands for a literal `$`, e.g. `a$$b.txt` for a file named `a$b.txt`.
+///
+/// The result is [`normalize`]d if it's absolute.
+/// Relative paths are left alone, because they still need to be joined with a directory.
+pub fn expand(path: &str) -> PathBuf {
+    expand_with(path, &mut |_| {})
+}
+
+/// Same as [`expand`], but calls `unknown` with the name of every variable that isn't set,
+/// so that the caller can warn about it.
+pub fn expand_with(path: &str, unknown: &mut dyn FnMut(&str)) -> PathBuf {
+    let mut res = OsString::with_capacity(path.len());
+    let mut rest = path;
+
+    if let Some(after) = path.strip_prefix('~') {
+        let end = after.find(is_separator).unwrap_or(after.len());
+        if let Some(home) = sys::home_dir(&after[..end]) {
+            res.push(home);
+            rest = &after[end..];
+        }
+    }
+
+    while let Some(i) = rest.find('$') {
+        res.push(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        if let Some(next) = after.strip_prefix('$') {
+            res.push("$");
+            rest = next;
+            continue;
+        }
+
+        let (name, next) = if let Some(braced) = after.strip_prefix('{')
+            && let Some(close) = braced.find('}')
+        {
+            (&braced[..close], &braced[close + 1..])
+        } else {
+            let len =
+                after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+            after.split_at(len)
+        };
+
+        if name.is_empty() {
+            // A lone `$` isn't a variable.
+            res.push("$");
+            rest = after;
+            continue;
+        }
+
+        match env::var_os(name) {
+            Some(value) => res.push(value),
+            None => unknown(name),
+        }
+        rest = next;
+    }
+    res.push(rest);
+
+    let res = PathBuf::from(res);
+    if res.is_absolute() { normalize(&res) } else { res }
+}
+
+/// Returns `path` relative to the directory `base`, e.g. `src/main.rs` for `/project/src/main.rs`
+/// and `/project`. Returns `None` if `path` isn't within `base`. Both must be [`normalize`]d.
+pub fn relativize(path: &Path, base: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(base).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| relative.to_path_buf())
+}
+
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
@@ -83,4 +156,52 @@ mod tests {
         assert_eq!(norm(r"C:\a\b\c\..\..\..\..\d"), r"C:\d");
         assert_eq!(norm(r"\\server\share\path"), r"\\server\share\path");
     }
+
+    #[test]
+    fn test_expand() {
+        let Some(home) = sys::home_dir("") else {
+            return;
+        };
+        let home = normalize(&home);
+
+        assert_eq!(expand("~"), home);
+        assert_eq!(expand("~/sub"), home.join("sub"));
+        assert_eq!(expand("~/sub/../other"), home.join("other"));
+        assert_eq!(expand("a~"), Path::new("a~"));
+        assert_eq!(expand("$"), Path::new("$"));
+        assert_eq!(expand("a$/b"), Path::new("a$/b"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_relativize() {
+        let rel = |path: &str, base: &str| relativize(Path::new(path), Path::new(base));
+        assert_eq!(rel("/a/b/c", "/a"), Some(PathBuf::from("b/c")));
+        assert_eq!(rel("/a/b/c", "/a/b/"), Some(PathBuf::from("c")));
+        assert_eq!(rel("/a/bc", "/a/b"), None);
+        assert_eq!(rel("/a", "/a"), None);
+        assert_eq!(rel("/a", "/a/b"), None);
+    }
+
+    #[test]
+    fn test_expand_undefined_variable() {
+        let mut unknown = Vec::new();
+        let mut report = |name: &str| unknown.push(name.to_string());
+
+        assert_eq!(expand_with("a/$EDIT_TEST_UNDEFINED/b", &mut report), Path::new("a//b"));
+        assert_eq!(expand_with("a/${EDIT_TEST_UNDEFINED}b", &mut report), Path::new("a/b"));
+        assert_eq!(unknown, ["EDIT_TEST_UNDEFINED", "EDIT_TEST_UNDEFINED"]);
+    }
+
+    #[test]
+    fn test_expand_escaped_dollar() {
+        let mut unknown = Vec::new();
+        let mut report = |name: &str| unknown.push(name.to_string());
+
+        assert_eq!(expand_with("a$$b.txt", &mut report), Path::new("a$b.txt"));
+        assert_eq!(expand_with("$${HOME}/a", &mut report), Path::new("${HOME}/a"));
+        assert_eq!(expand_with("a$$$$b", &mut report), Path::new("a$$b"));
+        assert_eq!(expand_with("a$$$EDIT_TEST_UNDEFINED", &mut report), Path::new("a$"));
+        assert_eq!(unknown, ["EDIT_TEST_UNDEFINED"]);
+    }
 }
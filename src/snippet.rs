@@ -0,0 +1,206 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Snippets in the syntax used by LSP and VS Code, e.g. `fn ${1:name}(${2:args}) {\n\t$0\n}`.
+//!
+//! Supported are tabstops (`$1`, `${1}`), placeholders (`${1:text}`, which may nest),
+//! choices (`${1|one,two|}`, of which the first is inserted) and the final tabstop `$0`.
+//! Variables like `$TM_FILENAME` insert their default (`${TM_FILENAME:default}`) or nothing.
+//! `\$`, `\}` and `\\` escape the respective character.
+
+use std::ops::Range;
+
+/// A parsed snippet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snippet {
+    /// The text to insert, with the placeholders filled in.
+    pub text: String,
+    /// The byte ranges of the tabstops within `text`, in the order they're visited.
+    /// The last one is where the cursor ends up, `$0` or otherwise the end of the text.
+    /// A tabstop that occurs more than once is only visited at its first occurrence.
+    pub tabstops: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// Parses `source`. Anything that isn't valid snippet syntax is taken literally.
+    pub fn parse(source: &str) -> Self {
+        let mut parser = Parser { source, pos: 0, text: String::new(), tabstops: Vec::new() };
+        parser.parse_text(false);
+
+        let Parser { text, mut tabstops, .. } = parser;
+        // `$0` goes last. Otherwise it's in numeric order, with the first occurrence winning.
+        tabstops.sort_by_key(|&(number, _)| (number == 0, number));
+        tabstops.dedup_by_key(|&mut (number, _)| number);
+        if tabstops.last().is_none_or(|&(number, _)| number != 0) {
+            tabstops.push((0, text.len()..text.len()));
+        }
+
+        Self { text, tabstops: tabstops.into_iter().map(|(_, range)| range).collect() }
+    }
+
+    /// Returns the snippet as it's to be inserted on a line indented by `indent`:
+    /// Each line after the first gets that indentation, and tabs are replaced with `unit`,
+    /// which is a tab or the equivalent number of spaces.
+    pub fn indented(&self, indent: &str, unit: &str) -> Self {
+        let mut text = String::with_capacity(self.text.len());
+        // `map[i]` is where the byte at `i` in the original text ended up.
+        let mut map = Vec::with_capacity(self.text.len() + 1);
+
+        for (i, ch) in self.text.char_indices() {
+            map.resize(i, text.len());
+            map.push(text.len());
+            match ch {
+                '\t' => text.push_str(unit),
+                '\n' => {
+                    text.push('\n');
+                    text.push_str(indent);
+                }
+                _ => text.push(ch),
+            }
+        }
+        map.resize(self.text.len(), text.len());
+        map.push(text.len());
+
+        let tabstops = self.tabstops.iter().map(|r| map[r.start]..map[r.end]).collect();
+        Self { text, tabstops }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    text: String,
+    tabstops: Vec<(u32, Range<usize>)>,
+}
+
+impl Parser<'_> {
+    /// Parses text up to the end, or within a placeholder up to its closing `}`.
+    fn parse_text(&mut self, nested: bool) {
+        while let Some(ch) = self.source[self.pos..].chars().next() {
+            match ch {
+                '\\' => {
+                    match self.source[self.pos + 1..].chars().next() {
+                        Some(next @ ('$' | '}' | '\\')) => {
+                            self.text.push(next);
+                            self.pos += 2;
+                        }
+                        _ => {
+                            self.text.push('\\');
+                            self.pos += 1;
+                        }
+                    }
+                    continue;
+                }
+                '}' if nested => {
+                    self.pos += 1;
+                    return;
+                }
+                '$' if self.parse_dollar() => continue,
+                _ => self.text.push(ch),
+            }
+            self.pos += ch.len_utf8();
+        }
+    }
+
+    /// Parses a tabstop, placeholder or variable at the `$` at `self.pos`.
+    /// Returns false if there's none, in which case the `$` is just text.
+    fn parse_dollar(&mut self) -> bool {
+        let rest = &self.source[self.pos + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let name = rest.bytes().take_while(|&b| b.is_ascii_alphanumeric() || b == b'_').count();
+
+        // `$1`
+        if digits > 0 {
+            let at = self.text.len();
+            self.tabstops.push((rest[..digits].parse().unwrap_or(u32::MAX), at..at));
+            self.pos += 1 + digits;
+            return true;
+        }
+        // `$NAME`
+        if name > 0 {
+            self.pos += 1 + name;
+            return true;
+        }
+
+        let Some(inner) = rest.strip_prefix('{') else {
+            return false;
+        };
+        let name = inner.bytes().take_while(|&b| b.is_ascii_alphanumeric() || b == b'_').count();
+        if name == 0 {
+            return false;
+        }
+        let number = inner[..name].parse::<u32>().ok();
+        let after = self.pos + 2 + name;
+        let start = self.text.len();
+
+        match self.source[after..].chars().next() {
+            // `${1}`
+            Some('}') => self.pos = after + 1,
+            // `${1:placeholder}`
+            Some(':') => {
+                self.pos = after + 1;
+                self.parse_text(true);
+            }
+            // `${1|one,two|}`
+            Some('|') if number.is_some() => {
+                let Some(len) = self.source[after + 1..].find("|}") else {
+                    return false;
+                };
+                let choices = &self.source[after + 1..after + 1 + len];
+                self.text.push_str(choices.split(',').next().unwrap_or_default());
+                self.pos = after + 1 + len + 2;
+            }
+            _ => return false,
+        }
+
+        if let Some(number) = number {
+            self.tabstops.push((number, start..self.text.len()));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The tabstops as pairs, which are easier to write out than ranges.
+    fn parse(source: &str) -> (String, Vec<(usize, usize)>) {
+        let snippet = Snippet::parse(source);
+        (snippet.text, snippet.tabstops.iter().map(|r| (r.start, r.end)).collect())
+    }
+
+    #[test]
+    fn test_tabstops() {
+        assert_eq!(parse("plain"), ("plain".to_string(), vec![(5, 5)]));
+        assert_eq!(parse("a$2b$1c"), ("abc".to_string(), vec![(2, 2), (1, 1), (3, 3)]));
+        assert_eq!(parse("a$0b${1}c"), ("abc".to_string(), vec![(2, 2), (1, 1)]));
+        assert_eq!(parse("$1 $1"), (" ".to_string(), vec![(0, 0), (1, 1)]));
+        assert_eq!(parse("$1ab"), ("ab".to_string(), vec![(0, 0), (2, 2)]));
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            parse("fn ${1:name}(${2:args}) { $0 }"),
+            ("fn name(args) {  }".to_string(), vec![(3, 7), (8, 12), (16, 16)])
+        );
+        assert_eq!(parse("${1:a ${2:b}}"), ("a b".to_string(), vec![(0, 3), (2, 3), (3, 3)]));
+        assert_eq!(parse("${1|one,two|}"), ("one".to_string(), vec![(0, 3), (3, 3)]));
+        assert_eq!(parse("$TM_FILENAME${X:x}"), ("x".to_string(), vec![(1, 1)]));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(parse("\\$1 \\} \\\\ \\n"), ("$1 } \\ \\n".to_string(), vec![(9, 9)]));
+        assert_eq!(parse("$ ${ ${1 ${"), ("$ ${ ${1 ${".to_string(), vec![(11, 11)]));
+        assert_eq!(parse("a}"), ("a}".to_string(), vec![(2, 2)]));
+    }
+
+    #[test]
+    fn test_indented() {
+        let snippet = Snippet::parse("if ${1:x} {\n\t$0\n}").indented("  ", "    ");
+        assert_eq!(snippet.text, "if x {\n      \n  }");
+        assert_eq!(snippet.tabstops, [3..4, 13..13]);
+    }
+}
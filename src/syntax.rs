@@ -1,9 +1,141 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use tree_sitter::{Language, Parser, Tree};
+use std::ops::Range;
+use std::sync::mpsc;
+use std::thread;
+
+use tree_sitter::{Language, Node, Parser, Tree};
 use tree_sitter_highlight::{Highlight, HighlightConfiguration, Highlighter, HighlightEvent};
 
+use crate::framebuffer::{Framebuffer, IndexedColor};
+use crate::helpers::CoordType;
+
+/// The highlight captures that are told apart, in the order of [`Highlight`] indices.
+/// A capture like `function.method` that isn't listed uses the closest listed prefix.
+pub const HIGHLIGHT_NAMES: [&str; 19] = [
+    "attribute",
+    "comment",
+    "constant",
+    "function.builtin",
+    "function",
+    "keyword",
+    "operator",
+    "property",
+    "punctuation",
+    "punctuation.bracket",
+    "punctuation.delimiter",
+    "string",
+    "string.special",
+    "tag",
+    "type",
+    "type.builtin",
+    "variable",
+    "variable.builtin",
+    "variable.parameter",
+];
+
+/// A color in a [`Theme`]. Indexed colors follow the terminal's palette.
+#[derive(Clone, Copy)]
+pub enum ThemeColor {
+    Indexed(IndexedColor),
+    /// A color in the `0xAABBGGRR` format.
+    Rgba(u32),
+}
+
+impl ThemeColor {
+    pub fn resolve(self, fb: &Framebuffer) -> u32 {
+        match self {
+            ThemeColor::Indexed(index) => fb.indexed(index),
+            ThemeColor::Rgba(color) => color,
+        }
+    }
+}
+
+/// Maps highlight captures to colors. Captures without a color are drawn in the default one.
+#[derive(Clone)]
+pub struct Theme {
+    colors: [Option<ThemeColor>; HIGHLIGHT_NAMES.len()],
+}
+
+impl Theme {
+    /// The default theme, for terminals with a dark background.
+    pub fn dark() -> Self {
+        use IndexedColor::*;
+        Self::from_list(&[
+            ("attribute", Yellow),
+            ("comment", BrightBlack),
+            ("constant", BrightMagenta),
+            ("function", BrightYellow),
+            ("keyword", BrightBlue),
+            ("property", Cyan),
+            ("string", Green),
+            ("string.special", Cyan),
+            ("tag", BrightBlue),
+            ("type", BrightCyan),
+            ("variable.builtin", BrightRed),
+        ])
+    }
+
+    /// A theme for terminals with a light background.
+    pub fn light() -> Self {
+        use IndexedColor::*;
+        Self::from_list(&[
+            ("attribute", Magenta),
+            ("comment", BrightBlack),
+            ("constant", Red),
+            ("function", Magenta),
+            ("keyword", Blue),
+            ("property", Cyan),
+            ("string", Green),
+            ("string.special", Cyan),
+            ("tag", Blue),
+            ("type", Cyan),
+            ("variable.builtin", Red),
+        ])
+    }
+
+    fn from_list(list: &[(&str, IndexedColor)]) -> Self {
+        let mut theme = Self { colors: [None; HIGHLIGHT_NAMES.len()] };
+        for &(capture, color) in list {
+            theme.set(capture, ThemeColor::Indexed(color));
+        }
+        theme
+    }
+
+    /// Sets the color of a capture, e.g. `keyword` or `function.builtin`.
+    /// Returns false if the capture isn't one of the [`HIGHLIGHT_NAMES`].
+    pub fn set(&mut self, capture: &str, color: ThemeColor) -> bool {
+        match HIGHLIGHT_NAMES.iter().position(|&name| name == capture) {
+            Some(index) => {
+                self.colors[index] = Some(color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the color of a highlight. If its capture has no color of its own,
+    /// the one of its parent is used, e.g. `function` for `function.builtin`.
+    pub fn color(&self, highlight: Highlight) -> Option<ThemeColor> {
+        let mut name = *HIGHLIGHT_NAMES.get(highlight.0)?;
+        loop {
+            if let Some(index) = HIGHLIGHT_NAMES.iter().position(|&n| n == name)
+                && let Some(color) = self.colors[index]
+            {
+                return Some(color);
+            }
+            name = name.rsplit_once('.')?.0;
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SupportedLanguage {
     Rust,
@@ -22,6 +154,58 @@ impl ToString for SupportedLanguage {
 }
 
 impl SupportedLanguage {
+    pub const ALL: [SupportedLanguage; 3] =
+        [SupportedLanguage::Rust, SupportedLanguage::Cpp, SupportedLanguage::Python];
+
+    /// The name of the language, as shown to the user.
+    pub fn name(self) -> &'static str {
+        match self {
+            SupportedLanguage::Rust => "Rust",
+            SupportedLanguage::Cpp => "C++",
+            SupportedLanguage::Python => "Python",
+        }
+    }
+
+    /// The brackets and quotes that are closed automatically while typing.
+    pub fn auto_close_pairs(self) -> &'static [(u8, u8)] {
+        match self {
+            // Single quotes are left out, because they also start lifetimes.
+            SupportedLanguage::Rust => &[(b'(', b')'), (b'[', b']'), (b'{', b'}'), (b'"', b'"')],
+            SupportedLanguage::Cpp | SupportedLanguage::Python => {
+                &[(b'(', b')'), (b'[', b']'), (b'{', b'}'), (b'"', b'"'), (b'\'', b'\'')]
+            }
+        }
+    }
+
+    /// The token that starts a comment running to the end of the line.
+    pub fn line_comment(self) -> &'static str {
+        match self {
+            SupportedLanguage::Rust | SupportedLanguage::Cpp => "//",
+            SupportedLanguage::Python => "#",
+        }
+    }
+
+    /// The delimiters of block comments, if the language has them.
+    pub fn block_comment(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            SupportedLanguage::Rust | SupportedLanguage::Cpp => Some(("/*", "*/")),
+            SupportedLanguage::Python => None,
+        }
+    }
+
+    /// Whether a block comment may contain another one. In C++ the first `*/` ends it.
+    pub fn block_comments_nest(self) -> bool {
+        matches!(self, SupportedLanguage::Rust)
+    }
+
+    /// The characters after which a new line is indented one more level.
+    pub fn indent_after(self) -> &'static [u8] {
+        match self {
+            SupportedLanguage::Rust | SupportedLanguage::Cpp => b"([{",
+            SupportedLanguage::Python => b"([{:",
+        }
+    }
+
     pub fn to_language(self) -> Language {
         match self {
             SupportedLanguage::Rust => tree_sitter_rust::language(),
@@ -58,35 +242,388 @@ impl SupportedLanguage {
             .unwrap(),
         };
 
-        let highlight_names = [
-            "attribute",
-            "constant",
-            "function.builtin",
-            "function",
-            "keyword",
-            "operator",
-            "property",
-            "punctuation",
-            "punctuation.bracket",
-            "punctuation.delimiter",
-            "string",
-            "string.special",
-            "tag",
-            "type",
-            "type.builtin",
-            "variable",
-            "variable.builtin",
-            "variable.parameter",
-        ]
-        .iter()
-        .map(AsRef::as_ref)
-        .collect::<Vec<&str>>();
-
-        config.configure(&highlight_names);
+        config.configure(&HIGHLIGHT_NAMES);
         config
     }
 }
 
+/// A definition in a document, like a function or a type.
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    /// The kind of the tree-sitter node, e.g. `function_item`.
+    pub kind: &'static str,
+    /// The byte offset of the name.
+    pub offset: usize,
+    /// The bytes covered by the whole definition, e.g. a function including its body.
+    pub range: Range<usize>,
+    /// The definitions nested inside this one, e.g. the methods of a class.
+    pub children: Vec<Symbol>,
+    pub expanded: bool,
+}
+
+/// Returns the name of the given node, if it's a definition.
+///
+/// Definitions are recognized as item, definition or specifier nodes with a `name` field.
+/// This covers functions, types, classes, modules and so on in all supported languages.
+fn definition_name(node: Node<'_>) -> Option<Node<'_>> {
+    let kind = node.kind();
+    if kind.ends_with("_item") || kind.ends_with("_definition") || kind.ends_with("_specifier") {
+        node.child_by_field_name("name")
+    } else {
+        None
+    }
+}
+
+/// Calls `visit` with every node of the tree, parents before their children.
+/// The children of a node are skipped if `visit` returns false for it.
+///
+/// This walks the tree iteratively, because deeply nested expressions could overflow the stack.
+fn walk<'tree>(tree: &'tree Tree, mut visit: impl FnMut(Node<'tree>) -> bool) {
+    let mut cursor = tree.walk();
+
+    loop {
+        let descend = visit(cursor.node());
+
+        if (descend && cursor.goto_first_child()) || cursor.goto_next_sibling() {
+            continue;
+        }
+
+        // Walk back up until we find an ancestor with an unvisited sibling.
+        loop {
+            if !cursor.goto_parent() {
+                return;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+/// Returns the byte offset of the definition named `name` that is closest to `offset`.
+pub fn find_symbol(tree: &Tree, code: &[u8], name: &str, offset: usize) -> Option<usize> {
+    let mut best: Option<usize> = None;
+
+    walk(tree, |node| {
+        if let Some(name_node) = definition_name(node)
+            && name_node.utf8_text(code).is_ok_and(|text| text == name)
+        {
+            let start = name_node.start_byte();
+            if best.is_none_or(|b| start.abs_diff(offset) < b.abs_diff(offset)) {
+                best = Some(start);
+            }
+        }
+        true
+    });
+
+    best
+}
+
+/// Returns the range of the smallest syntax node that contains `range` and is larger than it,
+/// for growing a selection node by node. `None` if not even the root node is larger.
+pub fn enclosing_node(tree: &Tree, range: Range<usize>) -> Option<Range<usize>> {
+    let mut node = tree.root_node().descendant_for_byte_range(range.start, range.end)?;
+    loop {
+        let r = node.byte_range();
+        if r.start <= range.start && r.end >= range.end && r != range {
+            return Some(r);
+        }
+        node = node.parent()?;
+    }
+}
+
+/// Returns the definitions in the document as a tree, in document order.
+pub fn document_symbols(tree: &Tree, code: &[u8]) -> Vec<Symbol> {
+    // Moves the symbols at `depth` or deeper into their parents,
+    // because the walk below has left the nodes they were defined by.
+    fn close(stack: &mut Vec<(usize, Symbol)>, symbols: &mut Vec<Symbol>, depth: usize) {
+        while stack.last().is_some_and(|&(d, _)| d >= depth) {
+            let (_, symbol) = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some((_, parent)) => parent.children.push(symbol),
+                None => symbols.push(symbol),
+            }
+        }
+    }
+
+    let mut cursor = tree.walk();
+    let mut depth = 0;
+    // The definitions enclosing the current node, along with their depth in the tree.
+    let mut stack = Vec::new();
+    let mut symbols = Vec::new();
+
+    // This walks the tree iteratively, because deeply nested expressions could overflow the stack.
+    loop {
+        let node = cursor.node();
+
+        if let Some(name_node) = definition_name(node)
+            && let Ok(name) = name_node.utf8_text(code)
+        {
+            stack.push((
+                depth,
+                Symbol {
+                    name: name.to_string(),
+                    kind: node.kind(),
+                    offset: name_node.start_byte(),
+                    range: node.byte_range(),
+                    children: Vec::new(),
+                    expanded: true,
+                },
+            ));
+        }
+
+        if cursor.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+
+        loop {
+            close(&mut stack, &mut symbols, depth);
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return symbols;
+            }
+            depth -= 1;
+        }
+    }
+}
+
+/// Returns the byte offsets of all matching pairs of brackets, sorted by the opening bracket.
+///
+/// Only brackets that are siblings in the tree are paired up, which means that brackets in
+/// strings and comments are ignored. Unbalanced brackets are left out.
+pub fn bracket_pairs(tree: &Tree) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    // The opening brackets among the children of the current node.
+    let mut open = Vec::new();
+
+    walk(tree, |node| {
+        if node.child_count() != 0 {
+            open.clear();
+
+            let mut children = node.walk();
+            for child in node.children(&mut children) {
+                if child.is_missing() || child.child_count() != 0 {
+                    continue;
+                }
+                match child.kind() {
+                    "(" | "[" | "{" => open.push((child.kind(), child.start_byte())),
+                    kind @ (")" | "]" | "}") => {
+                        let opener = match kind {
+                            ")" => "(",
+                            "]" => "[",
+                            _ => "{",
+                        };
+                        if let Some(&(k, start)) = open.last()
+                            && k == opener
+                        {
+                            open.pop();
+                            pairs.push((start, child.start_byte()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        true
+    });
+
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Returns the byte ranges of all strings and comments, sorted by their start.
+///
+/// The end of each comment is extended by one byte, so that the end of a line comment
+/// counts as being inside of it, while the position right after a closing quote does not.
+pub fn literal_ranges(tree: &Tree) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    walk(tree, |node| {
+        let kind = node.kind();
+        let is_comment = kind.contains("comment");
+
+        // Nested nodes, like escape sequences, are covered by their parent already.
+        if is_comment || kind.contains("string") || kind == "char_literal" {
+            ranges.push(node.start_byte()..node.end_byte() + is_comment as usize);
+            false
+        } else {
+            true
+        }
+    });
+
+    ranges
+}
+
+/// A node whose lines are indented one level deeper than the line it starts on, like a block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndentRegion {
+    /// An offset on the line whose indentation the contents are one level deeper than.
+    pub anchor: usize,
+    /// The byte range within the node, e.g. between its brackets.
+    pub inner: Range<usize>,
+    /// Whether `inner` is followed by a closing bracket, which goes back to the anchor's level.
+    pub closed: bool,
+}
+
+/// Returns the nodes that indent their contents, sorted by the start of their contents.
+///
+/// These are the bracketed nodes, like blocks, argument lists and arrays, and in Python
+/// the indented blocks, which are indented relative to the statement they belong to.
+pub fn indent_regions(tree: &Tree) -> Vec<IndentRegion> {
+    let mut regions = Vec::new();
+
+    walk(tree, |node| {
+        let count = node.child_count();
+
+        if count != 0 {
+            let first = node.child(0).unwrap();
+            let last = node.child(count - 1).unwrap();
+
+            if matches!(first.kind(), "{" | "(" | "[") && matches!(last.kind(), "}" | ")" | "]") {
+                regions.push(IndentRegion {
+                    anchor: node.start_byte(),
+                    inner: first.end_byte()..last.start_byte(),
+                    closed: true,
+                });
+            } else if node.kind() == "block"
+                && let Some(parent) = node.parent()
+            {
+                regions.push(IndentRegion {
+                    anchor: parent.start_byte(),
+                    inner: node.start_byte()..node.end_byte(),
+                    closed: false,
+                });
+            }
+        }
+        true
+    });
+
+    regions.sort_by_key(|r| r.inner.start);
+    regions
+}
+
+fn is_import(kind: &str) -> bool {
+    matches!(
+        kind,
+        "use_declaration"
+            | "import_statement"
+            | "import_from_statement"
+            | "future_import_statement"
+            | "preproc_include"
+    )
+}
+
+// The last line of the node, not counting a trailing newline.
+fn last_row(node: Node<'_>) -> usize {
+    let end = node.end_position();
+    if end.column == 0 { end.row.saturating_sub(1) } else { end.row }
+}
+
+fn push(regions: &mut Vec<Range<CoordType>>, header: usize, end: usize) {
+    if header + 1 < end {
+        regions.push(header as CoordType + 1..end as CoordType);
+    }
+}
+
+/// Returns the lines that can be folded away, sorted by their start. The line before each
+/// range is the one that stays visible, like the line with the opening brace of a block.
+///
+/// Bracketed nodes keep their closing line visible. Indented blocks, as in Python,
+/// and runs of imports are folded up to and including their last line.
+pub fn fold_regions(tree: &Tree) -> Vec<Range<CoordType>> {
+    let mut regions = Vec::new();
+
+    walk(tree, |node| {
+        let count = node.child_count();
+
+        if count != 0 {
+            let first = node.child(0).unwrap();
+            let last = node.child(count - 1).unwrap();
+            let start = node.start_position().row;
+
+            if matches!(first.kind(), "{" | "(" | "[") && matches!(last.kind(), "}" | ")" | "]") {
+                push(&mut regions, start, last_row(node));
+            } else if node.kind() == "block" && start > 0 {
+                push(&mut regions, start - 1, last_row(node) + 1);
+            }
+
+            let mut children = node.walk();
+            let mut imports: Option<(usize, usize)> = None;
+            for child in node.children(&mut children) {
+                if is_import(child.kind()) {
+                    let first = imports.map_or(child.start_position().row, |(first, _)| first);
+                    imports = Some((first, last_row(child)));
+                } else if let Some((first, last)) = imports.take() {
+                    push(&mut regions, first, last + 1);
+                }
+            }
+            if let Some((first, last)) = imports {
+                push(&mut regions, first, last + 1);
+            }
+        }
+        true
+    });
+
+    // If several regions start on the same line, keep the largest one.
+    regions.sort_unstable_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    regions.dedup_by_key(|r| r.start);
+    regions
+}
+
+/// The regions that [`smart_fold_regions`] finds.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SmartFold {
+    /// Runs of `use`, `import` or `#include`.
+    Imports,
+    /// Comments that span several lines, including runs of line comments like doc comments.
+    Comments,
+}
+
+/// Returns the regions of the given kind, like those of [`fold_regions`]: Each is folded
+/// up to and including its last line, while its first line stays visible.
+pub fn smart_fold_regions(tree: &Tree, kind: SmartFold) -> Vec<Range<CoordType>> {
+    let mut regions = Vec::new();
+
+    walk(tree, |node| {
+        let mut children = node.walk();
+        let mut run: Option<(usize, usize)> = None;
+        let mut prev_end = None;
+        for child in node.children(&mut children) {
+            let start = child.start_position().row;
+            let (matches, continues) = match kind {
+                SmartFold::Imports => (is_import(child.kind()), true),
+                // Comments after the code on a line belong to that line,
+                // and runs of line comments end at an empty line.
+                SmartFold::Comments => (
+                    child.kind().contains("comment") && prev_end != Some(start),
+                    run.is_some_and(|(_, last)| start <= last + 1),
+                ),
+            };
+            if !(matches && continues)
+                && let Some((first, last)) = run.take()
+            {
+                push(&mut regions, first, last + 1);
+            }
+            if matches {
+                let first = run.map_or(start, |(first, _)| first);
+                run = Some((first, last_row(child)));
+            }
+            prev_end = Some(last_row(child));
+        }
+        if let Some((first, last)) = run {
+            push(&mut regions, first, last + 1);
+        }
+        true
+    });
+
+    regions.sort_unstable_by_key(|r| r.start);
+    regions
+}
+
 pub struct Syntax {
     parser: Parser,
     highlighter: Highlighter,
@@ -147,10 +684,10 @@ impl Syntax {
             })
             .unwrap()
             .filter_map(move |event| match event.unwrap() {
-                HighlightEvent::Source { start, end } => Some((
-                    start..end,
-                    highlight_stack.last().copied().unwrap_or(Highlight(0)),
-                )),
+                // Text outside of any capture is left in the default color.
+                HighlightEvent::Source { start, end } => {
+                    highlight_stack.last().map(|&h| (start..end, h))
+                }
                 HighlightEvent::HighlightStart(h) => {
                     highlight_stack.push(h);
                     None
@@ -162,3 +699,209 @@ impl Syntax {
             })
     }
 }
+
+/// The result of a [`SyntaxWorker`] job.
+pub struct Analysis {
+    /// The text that was analyzed, handed back for any further processing.
+    pub code: String,
+    pub generation: u32,
+    pub tree: Option<Tree>,
+    pub highlights: Vec<(Range<usize>, Highlight)>,
+}
+
+struct Job {
+    code: String,
+    lang: SupportedLanguage,
+    generation: u32,
+    reply: mpsc::Sender<Analysis>,
+}
+
+/// Parses and highlights code on a background thread,
+/// so that large files don't hold up rendering.
+pub struct SyntaxWorker {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl SyntaxWorker {
+    pub fn new() -> Self {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            // Building the highlight configurations takes a while, which is another reason not
+            // to do it on the UI thread.
+            let mut syntax = Syntax::new();
+            for job in rx {
+                let tree = syntax.parse(&job.code, job.lang);
+                let highlights = syntax.highlight(&job.code, job.lang).collect();
+                // The document may have been closed in the meantime.
+                _ = job.reply.send(Analysis {
+                    code: job.code,
+                    generation: job.generation,
+                    tree,
+                    highlights,
+                });
+            }
+        });
+        Self { jobs }
+    }
+
+    /// Queues `code` for analysis. `generation` is handed back with the result,
+    /// so that the caller can tell which version of its text it belongs to.
+    pub fn submit(
+        &self,
+        code: String,
+        lang: SupportedLanguage,
+        generation: u32,
+    ) -> mpsc::Receiver<Analysis> {
+        let (reply, rx) = mpsc::channel();
+        // If the worker is gone, `rx` reports a disconnect.
+        _ = self.jobs.send(Job { code, lang, generation, reply });
+        rx
+    }
+}
+
+impl Default for SyntaxWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmartFold::{Comments, Imports};
+    use super::SupportedLanguage::{Cpp, Python, Rust};
+    use super::*;
+
+    fn parse(code: &str, lang: SupportedLanguage) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(&lang.to_language()).unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    // Ranges as tuples, since clippy takes `[1..3]` for a mistyped `[1; 3]`.
+    fn tuples<T>(ranges: Vec<Range<T>>) -> Vec<(T, T)> {
+        ranges.into_iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    #[test]
+    fn test_bracket_pairs() {
+        let rust = "fn f(a: [u8; 2]) { \"(\" }";
+        assert_eq!(bracket_pairs(&parse(rust, Rust)), [(4, 15), (8, 14), (17, 23)]);
+
+        // The unbalanced bracket in the comment is ignored.
+        let cpp = "int x[2] = {1, 2}; // )";
+        assert_eq!(bracket_pairs(&parse(cpp, Cpp)), [(5, 7), (11, 16)]);
+
+        let python = "f([1], {})";
+        assert_eq!(bracket_pairs(&parse(python, Python)), [(1, 9), (2, 4), (7, 8)]);
+    }
+
+    #[test]
+    fn test_fold_regions() {
+        let rust = "use a;\nuse b;\nfn f() {\n    g(\n        1,\n    );\n}\n";
+        // The closing line of a bracketed node stays visible.
+        assert_eq!(tuples(fold_regions(&parse(rust, Rust))), [(1, 2), (3, 6), (4, 5)]);
+
+        let cpp = "#include <a>\n#include <b>\nint main() {\n    return 0;\n}\n";
+        assert_eq!(tuples(fold_regions(&parse(cpp, Cpp))), [(1, 2), (3, 4)]);
+
+        // Indented blocks end at their last line.
+        let python = "def f():\n    x = 1\n    return x\n\ny = 2\n";
+        assert_eq!(tuples(fold_regions(&parse(python, Python))), [(1, 3)]);
+    }
+
+    #[test]
+    fn test_literal_ranges() {
+        let rust = "let s = \"a\\n\"; // c\nx";
+        // A comment extends one byte past its end, a string doesn't.
+        assert_eq!(tuples(literal_ranges(&parse(rust, Rust))), [(8, 13), (15, 20)]);
+
+        let cpp = "char c = 'x'; /* y */";
+        assert_eq!(tuples(literal_ranges(&parse(cpp, Cpp))), [(9, 12), (14, 22)]);
+
+        let python = "s = 'a'  # b";
+        assert_eq!(tuples(literal_ranges(&parse(python, Python))), [(4, 7), (9, 13)]);
+    }
+
+    #[test]
+    fn test_indent_regions() {
+        let rust = "fn f() {\n    g(1);\n}";
+        let regions = indent_regions(&parse(rust, Rust));
+        assert_eq!(
+            regions,
+            [
+                IndentRegion { anchor: 4, inner: 5..5, closed: true },
+                IndentRegion { anchor: 7, inner: 8..19, closed: true },
+                IndentRegion { anchor: 14, inner: 15..16, closed: true },
+            ]
+        );
+
+        let python = "if x:\n    y = [1]\n";
+        let regions = indent_regions(&parse(python, Python));
+        assert_eq!(
+            regions,
+            [
+                // The block is indented relative to the statement it belongs to.
+                IndentRegion { anchor: 0, inner: 10..17, closed: false },
+                IndentRegion { anchor: 14, inner: 15..16, closed: true },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_symbol() {
+        let rust = "fn a() {}\nstruct B;\nfn c() { fn a() {} }\n";
+        let tree = parse(rust, Rust);
+        // Of several definitions with the same name, the closest one wins.
+        assert_eq!(find_symbol(&tree, rust.as_bytes(), "a", 0), Some(3));
+        assert_eq!(find_symbol(&tree, rust.as_bytes(), "a", 40), Some(32));
+        assert_eq!(find_symbol(&tree, rust.as_bytes(), "B", 0), Some(17));
+        assert_eq!(find_symbol(&tree, rust.as_bytes(), "d", 0), None);
+
+        let cpp = "struct S {};\nint main() { return 0; }\n";
+        assert_eq!(find_symbol(&parse(cpp, Cpp), cpp.as_bytes(), "S", 0), Some(7));
+
+        let python = "class A:\n    def f(self): pass\n";
+        assert_eq!(find_symbol(&parse(python, Python), python.as_bytes(), "f", 0), Some(17));
+    }
+
+    #[test]
+    fn test_smart_fold_imports() {
+        let rust = "use a;\nuse b;\n\nuse c;\nfn f() {}\nuse d;\nuse e;\n";
+        // An empty line doesn't end a run of imports, but other code does.
+        assert_eq!(tuples(smart_fold_regions(&parse(rust, Rust), Imports)), [(1, 4), (6, 7)]);
+
+        let cpp = "#include <a>\n#include <b>\n#include <c>\nint x;\n";
+        assert_eq!(tuples(smart_fold_regions(&parse(cpp, Cpp), Imports)), [(1, 3)]);
+
+        let python = "import os\nfrom sys import argv\nimport re\n\ndef f():\n    import json\n";
+        // A single import isn't worth folding.
+        assert_eq!(tuples(smart_fold_regions(&parse(python, Python), Imports)), [(1, 3)]);
+    }
+
+    #[test]
+    fn test_smart_fold_comments() {
+        let rust = concat!(
+            "/// One.\n",            // 0
+            "/// Two.\n",            // 1
+            "/// Three.\n",          // 2
+            "fn f() {}\n",           // 3
+            "// Four.\n",            // 4
+            "\n",                    // 5
+            "// Five.\n",            // 6
+            "// Six.\n",             // 7
+            "fn g() {} // Seven.\n", // 8
+            "// Eight.\n",           // 9
+            "/* Nine\n",             // 10
+            "   Ten */\n",           // 11
+        );
+        // Runs end at an empty line, and a comment after code stays with its line.
+        // Line and block comments on adjacent lines form one run.
+        assert_eq!(
+            tuples(smart_fold_regions(&parse(rust, Rust), Comments)),
+            [(1, 3), (7, 8), (10, 12)]
+        );
+
+        let python = "# One.\n# Two.\nx = 1  # Three.\n# Four.\n";
+        assert_eq!(tuples(smart_fold_regions(&parse(python, Python), Comments)), [(1, 2)]);
+    }
+}
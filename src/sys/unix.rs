@@ -6,11 +6,12 @@
 //! Read the `windows` module for reference.
 //! TODO: This reminds me that the sys API should probably be a trait.
 
-use std::ffi::{CStr, c_char, c_int, c_void};
+use std::ffi::{CStr, CString, OsStr, c_char, c_int, c_void};
 use std::fs::File;
 use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::os::fd::{AsRawFd as _, FromRawFd as _};
-use std::path::Path;
+use std::os::unix::ffi::OsStrExt as _;
+use std::path::{Path, PathBuf};
 use std::ptr::{self, NonNull, null_mut};
 use std::{thread, time};
 
@@ -70,6 +71,10 @@ pub fn switch_modes() -> apperr::Result<()> {
         if libc::isatty(STATE.stdin) == 0 {
             STATE.stdin = check_int_return(libc::open(c"/dev/tty".as_ptr(), libc::O_RDONLY))?;
         }
+        // Same for stdout, so that the UI goes to the terminal and not into a pipe.
+        if libc::isatty(STATE.stdout) == 0 {
+            STATE.stdout = check_int_return(libc::open(c"/dev/tty".as_ptr(), libc::O_WRONLY))?;
+        }
 
         // Store the stdin flags so we can more easily toggle `O_NONBLOCK` later on.
         STATE.stdin_flags = check_int_return(libc::fcntl(STATE.stdin, libc::F_GETFL))?;
@@ -347,10 +352,10 @@ fn set_tty_nonblocking(nonblock: bool) {
     }
 }
 
+// These check the original file descriptors, as `switch_modes()` may or may not have replaced ours yet.
 pub fn open_stdin_if_redirected() -> Option<File> {
     unsafe {
-        // Did we reopen stdin during `init()`?
-        if STATE.stdin != libc::STDIN_FILENO {
+        if libc::isatty(libc::STDIN_FILENO) == 0 {
             Some(File::from_raw_fd(libc::STDIN_FILENO))
         } else {
             None
@@ -358,12 +363,152 @@ pub fn open_stdin_if_redirected() -> Option<File> {
     }
 }
 
+pub fn open_stdout_if_redirected() -> Option<File> {
+    unsafe {
+        if libc::isatty(libc::STDOUT_FILENO) == 0 {
+            Some(File::from_raw_fd(libc::STDOUT_FILENO))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct FileId {
     st_dev: libc::dev_t,
     st_ino: libc::ino_t,
 }
 
+/// Returns the directory for configuration and session files:
+/// `$XDG_CONFIG_HOME/edit`, falling back to `~/.config/edit`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").filter(|h| !h.is_empty())?).join(".config"),
+    };
+    Some(base.join("edit"))
+}
+
+/// Returns true if the current user may not write to the file, because of its
+/// permissions or a read-only file system. Unlike opening the file for writing,
+/// this doesn't count as a write for anyone watching the file.
+pub fn is_read_only(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe {
+        libc::access(path.as_ptr(), libc::W_OK) != 0
+            && matches!(errno(), libc::EACCES | libc::EPERM | libc::EROFS)
+    }
+}
+
+/// Returns true if a process with the given ID is running.
+pub fn is_process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks whether the signal could be sent. EPERM means the
+    // process exists, but belongs to another user.
+    unsafe { libc::kill(pid, 0) == 0 || errno() == libc::EPERM }
+}
+
+/// Returns the directory for files that may be deleted at any time:
+/// `$XDG_CACHE_HOME/edit`, falling back to `~/.cache/edit`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").filter(|h| !h.is_empty())?).join(".cache"),
+    };
+    Some(base.join("edit"))
+}
+
+/// Returns the home directory of the given user, or of the current one if `user` is empty.
+pub fn home_dir(user: &str) -> Option<PathBuf> {
+    if user.is_empty() {
+        return std::env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from);
+    }
+
+    let user = CString::new(user).ok()?;
+    unsafe {
+        let pw = libc::getpwnam(user.as_ptr());
+        if pw.is_null() || (*pw).pw_dir.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*pw).pw_dir);
+        Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+    }
+}
+
+/// Returns the commands that copy to and paste from the system clipboard.
+/// There's no clipboard API without a display server (or on a remote Mac),
+/// in which case the caller falls back to OSC 52.
+fn clipboard_commands() -> Option<(&'static [&'static str], &'static [&'static str])> {
+    let has_env = |name| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+    if has_env("WAYLAND_DISPLAY") {
+        Some((&["wl-copy"], &["wl-paste", "--no-newline"]))
+    } else if has_env("DISPLAY") {
+        Some((&["xclip", "-selection", "clipboard"], &["xclip", "-selection", "clipboard", "-o"]))
+    } else if cfg!(target_os = "macos") && !has_env("SSH_CONNECTION") {
+        Some((&["pbcopy"], &["pbpaste"]))
+    } else {
+        None
+    }
+}
+
+/// Copies `data` to the system clipboard. Returns false if there's none.
+pub fn clipboard_write(data: &[u8]) -> bool {
+    use std::io::Write as _;
+    use std::process::{Command, Stdio};
+
+    let Some(([program, args @ ..], _)) = clipboard_commands() else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+
+    // The tools fork off a process that owns the clipboard, so this doesn't block for long.
+    let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(data).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+/// Returns the contents of the system clipboard, or `None` if there's none.
+pub fn clipboard_read() -> Option<Vec<u8>> {
+    use std::process::{Command, Stdio};
+
+    let (_, [program, args @ ..]) = clipboard_commands()? else {
+        return None;
+    };
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/// Starts the child process of `command` in a process group of its own,
+/// so that [`kill_process_tree`] reaches the processes it starts in turn.
+pub fn command_isolate(command: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt as _;
+    command.process_group(0);
+}
+
+/// Terminates `child`, which was started with [`command_isolate`], along with the processes
+/// it started, like the compiler that a shell runs.
+pub fn kill_process_tree(child: &mut std::process::Child) {
+    // The child leads its process group, so the group's ID is its process ID.
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    _ = child.wait();
+}
+
 /// Returns a unique identifier for the given file by handle or path.
 pub fn file_id(file: Option<&File>, path: &Path) -> apperr::Result<FileId> {
     let file = match file {
@@ -8,11 +8,13 @@ use std::mem::MaybeUninit;
 use std::os::windows::io::{AsRawHandle as _, FromRawHandle};
 use std::path::{Path, PathBuf};
 use std::ptr::{self, NonNull, null, null_mut};
-use std::{mem, time};
+use std::{mem, slice, time};
 
 use windows_sys::Win32::Storage::FileSystem;
 use windows_sys::Win32::System::Diagnostics::Debug;
-use windows_sys::Win32::System::{Console, IO, LibraryLoader, Memory, Threading};
+use windows_sys::Win32::System::{
+    Console, DataExchange, IO, LibraryLoader, Memory, Ole, Threading,
+};
 use windows_sys::Win32::{Foundation, Globalization};
 use windows_sys::w;
 
@@ -141,9 +143,7 @@ pub fn switch_modes() -> apperr::Result<()> {
         };
 
         // Reopen stdin if it's redirected (= piped input).
-        if ptr::eq(STATE.stdin, Foundation::INVALID_HANDLE_VALUE)
-            || !matches!(FileSystem::GetFileType(STATE.stdin), FileSystem::FILE_TYPE_CHAR)
-        {
+        if !is_console(STATE.stdin) {
             STATE.stdin = FileSystem::CreateFileW(
                 w!("CONIN$"),
                 Foundation::GENERIC_READ | Foundation::GENERIC_WRITE,
@@ -154,6 +154,18 @@ pub fn switch_modes() -> apperr::Result<()> {
                 null_mut(),
             );
         }
+        // Same for stdout, so that the UI goes to the console and not into a pipe.
+        if !is_console(STATE.stdout) {
+            STATE.stdout = FileSystem::CreateFileW(
+                w!("CONOUT$"),
+                Foundation::GENERIC_READ | Foundation::GENERIC_WRITE,
+                FileSystem::FILE_SHARE_READ | FileSystem::FILE_SHARE_WRITE,
+                null_mut(),
+                FileSystem::OPEN_EXISTING,
+                0,
+                null_mut(),
+            );
+        }
         if ptr::eq(STATE.stdin, Foundation::INVALID_HANDLE_VALUE)
             || ptr::eq(STATE.stdout, Foundation::INVALID_HANDLE_VALUE)
         {
@@ -416,14 +428,33 @@ pub fn write_stdout(text: &str) {
 ///
 /// * `Some(file)` if stdin is redirected.
 /// * Otherwise, `None`.
-pub fn open_stdin_if_redirected() -> Option<File> {
+unsafe fn is_console(handle: Foundation::HANDLE) -> bool {
+    unsafe {
+        !ptr::eq(handle, Foundation::INVALID_HANDLE_VALUE)
+            && matches!(FileSystem::GetFileType(handle), FileSystem::FILE_TYPE_CHAR)
+    }
+}
+
+unsafe fn open_std_handle_if_redirected(std_handle: Console::STD_HANDLE) -> Option<File> {
     unsafe {
-        let handle = Console::GetStdHandle(Console::STD_INPUT_HANDLE);
-        // Did we reopen stdin during `init()`?
-        if !std::ptr::eq(STATE.stdin, handle) { Some(File::from_raw_handle(handle)) } else { None }
+        let handle = Console::GetStdHandle(std_handle);
+        // Check the original handle, as `switch_modes()` may or may not have replaced ours yet.
+        if handle.is_null() || is_console(handle) {
+            None
+        } else {
+            Some(File::from_raw_handle(handle))
+        }
     }
 }
 
+pub fn open_stdin_if_redirected() -> Option<File> {
+    unsafe { open_std_handle_if_redirected(Console::STD_INPUT_HANDLE) }
+}
+
+pub fn open_stdout_if_redirected() -> Option<File> {
+    unsafe { open_std_handle_if_redirected(Console::STD_OUTPUT_HANDLE) }
+}
+
 pub fn drives() -> impl Iterator<Item = char> {
     unsafe {
         let mut mask = FileSystem::GetLogicalDrives();
@@ -439,6 +470,87 @@ pub fn drives() -> impl Iterator<Item = char> {
     }
 }
 
+/// Copies `data` to the system clipboard. Returns false if it's in use by another application.
+pub fn clipboard_write(data: &[u8]) -> bool {
+    let mut wide: Vec<u16> = String::from_utf8_lossy(data).encode_utf16().collect();
+    wide.push(0);
+
+    unsafe {
+        if DataExchange::OpenClipboard(null_mut()) == 0 {
+            return false;
+        }
+
+        let mut ok = false;
+        let bytes = wide.len() * mem::size_of::<u16>();
+        let handle = Memory::GlobalAlloc(Memory::GMEM_MOVEABLE, bytes);
+        if !handle.is_null() {
+            let dst = Memory::GlobalLock(handle);
+            if !dst.is_null() {
+                ptr::copy_nonoverlapping(wide.as_ptr(), dst as *mut u16, wide.len());
+                Memory::GlobalUnlock(handle);
+                ok = DataExchange::EmptyClipboard() != 0
+                    && !DataExchange::SetClipboardData(Ole::CF_UNICODETEXT as u32, handle)
+                        .is_null();
+            }
+            // On success, the clipboard owns the memory.
+            if !ok {
+                Memory::GlobalFree(handle);
+            }
+        }
+
+        DataExchange::CloseClipboard();
+        ok
+    }
+}
+
+/// Returns the text in the system clipboard, or `None` if there's none.
+pub fn clipboard_read() -> Option<Vec<u8>> {
+    unsafe {
+        if DataExchange::OpenClipboard(null_mut()) == 0 {
+            return None;
+        }
+
+        let mut result = None;
+        let handle = DataExchange::GetClipboardData(Ole::CF_UNICODETEXT as u32);
+        if !handle.is_null() {
+            let src = Memory::GlobalLock(handle) as *const u16;
+            if !src.is_null() {
+                // The size of the allocation may be larger than the NUL-terminated string.
+                let cap = Memory::GlobalSize(handle) / mem::size_of::<u16>();
+                let wide = slice::from_raw_parts(src, cap);
+                let len = wide.iter().position(|&c| c == 0).unwrap_or(cap);
+                result = Some(String::from_utf16_lossy(&wide[..len]).into_bytes());
+                Memory::GlobalUnlock(handle);
+            }
+        }
+
+        DataExchange::CloseClipboard();
+        result
+    }
+}
+
+/// Prepares `command` for [`kill_process_tree`]. Nothing needs to be done on Windows.
+pub fn command_isolate(_command: &mut std::process::Command) {}
+
+/// Terminates `child` along with the processes it started, like the compiler that a shell runs.
+pub fn kill_process_tree(child: &mut std::process::Child) {
+    use std::process::{Command, Stdio};
+
+    // Short of a job object, taskkill is the way to reach the whole tree.
+    let killed = Command::new("taskkill")
+        .args(["/T", "/F", "/PID"])
+        .arg(child.id().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        _ = child.kill();
+    }
+    _ = child.wait();
+}
+
 /// A unique identifier for a file.
 pub enum FileId {
     Id(FileSystem::FILE_ID_INFO),
@@ -463,6 +575,46 @@ impl PartialEq for FileId {
 
 impl Eq for FileId {}
 
+/// Returns the directory for configuration and session files: `%APPDATA%\edit`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("APPDATA").filter(|dir| !dir.is_empty())?;
+    Some(PathBuf::from(base).join("edit"))
+}
+
+/// Returns true if the file has the read-only attribute.
+pub fn is_read_only(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly())
+}
+
+/// Returns true if a process with the given ID is running.
+pub fn is_process_alive(pid: u32) -> bool {
+    unsafe {
+        let handle = Threading::OpenProcess(Threading::PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return false;
+        }
+        let mut code = 0;
+        let ok = Threading::GetExitCodeProcess(handle, &mut code) != 0;
+        Foundation::CloseHandle(handle);
+        ok && code == Foundation::STILL_ACTIVE as u32
+    }
+}
+
+/// Returns the directory for files that may be deleted at any time: `%LOCALAPPDATA%\edit`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("LOCALAPPDATA").filter(|dir| !dir.is_empty())?;
+    Some(PathBuf::from(base).join("edit"))
+}
+
+/// Returns the home directory of the current user, if `user` is empty.
+/// Looking up other users isn't supported.
+pub fn home_dir(user: &str) -> Option<PathBuf> {
+    if !user.is_empty() {
+        return None;
+    }
+    std::env::var_os("USERPROFILE").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
 /// Returns a unique identifier for the given file by handle or path.
 pub fn file_id(file: Option<&File>, path: &Path) -> apperr::Result<FileId> {
     let file = match file {
@@ -150,7 +150,7 @@ use std::fmt::Write as _;
 use std::{iter, mem, ptr, time};
 
 use crate::arena::{Arena, ArenaString, scratch_arena};
-use crate::buffer::{CursorMovement, MoveLineDirection, RcTextBuffer, TextBuffer, TextBufferCell};
+use crate::buffer::{CursorMovement, RcTextBuffer, TextBuffer, TextBufferCell};
 use crate::cell::*;
 use crate::clipboard::Clipboard;
 use crate::document::WriteableDocument;
@@ -158,12 +158,18 @@ use crate::framebuffer::{Attributes, Framebuffer, INDEXED_COLORS_COUNT, IndexedC
 use crate::hash::*;
 use crate::helpers::*;
 use crate::input::{InputKeyMod, kbmod, vk};
-use crate::{apperr, arena_format, input, simd, unicode};
+use crate::{apperr, arena_format, input, unicode};
 
 const ROOT_ID: u64 = 0x14057B7EF767814F; // Knuth's MMIX constant
 const SHIFT_TAB: InputKey = vk::TAB.with_modifiers(kbmod::SHIFT);
 const KBMOD_FOR_WORD_NAV: InputKeyMod =
     if cfg!(target_os = "macos") { kbmod::ALT } else { kbmod::CTRL };
+// On macOS, Alt+Shift+Left/Right already selects words.
+const KBMOD_FOR_BLOCK_SELECT: InputKeyMod =
+    if cfg!(target_os = "macos") { kbmod::CTRL_ALT_SHIFT } else { kbmod::ALT_SHIFT };
+// Left/Right with these are left to the app, for going back and forth between locations.
+const KBMOD_FOR_GO_BACK: InputKeyMod =
+    if cfg!(target_os = "macos") { kbmod::CTRL_ALT } else { kbmod::ALT };
 
 type Input<'input> = input::Input<'input>;
 type InputKey = input::InputKey;
@@ -429,6 +435,12 @@ impl Tui {
         self.framebuffer.set_indexed_colors(colors);
     }
 
+    /// Sets whether the terminal supports 24-bit colors.
+    /// See [`Framebuffer::set_truecolor`].
+    pub fn setup_truecolor(&mut self, truecolor: bool) {
+        self.framebuffer.set_truecolor(truecolor);
+    }
+
     /// Set up translations for Ctrl/Alt/Shift modifiers.
     pub fn setup_modifier_translations(&mut self, translations: ModifierTranslations) {
         self.modifier_translations = translations;
@@ -519,6 +531,7 @@ impl Tui {
 
         let now = std::time::Instant::now();
         let mut input_text = None;
+        let mut input_paste = None;
         let mut input_keyboard = None;
         let mut input_mouse_modifiers = kbmod::NONE;
         let mut input_mouse_click = 0;
@@ -553,14 +566,13 @@ impl Tui {
                 }
             }
             Some(Input::Paste(paste)) => {
-                let clipboard = self.clipboard_mut();
-                clipboard.write(paste);
-                clipboard.mark_as_synchronized();
-                input_keyboard = Some(kbmod::CTRL | vk::V);
+                input_paste = Some(paste);
             }
             Some(Input::Keyboard(keyboard)) => {
                 input_keyboard = Some(keyboard);
             }
+            // Focus changes are of interest to the application, not the UI.
+            Some(Input::Focus(_)) => {}
             Some(Input::Mouse(mouse)) => {
                 let mut next_state = mouse.state;
                 let next_position = mouse.position;
@@ -688,6 +700,7 @@ impl Tui {
             tui: self,
 
             input_text,
+            input_paste,
             input_keyboard,
             input_mouse_modifiers,
             input_mouse_click,
@@ -786,6 +799,12 @@ impl Tui {
                 x += (float.offset_x - float.gravity_x * size.width as f32) as CoordType;
                 y += (float.offset_y - float.gravity_y * size.height as f32) as CoordType;
 
+                // Move floaters that stick out of the viewport back in, e.g. after the terminal
+                // shrank or a popup got anchored close to its edge. Only what's larger than
+                // the viewport gets cut off below.
+                x = x.min(viewport.right - size.width).max(viewport.left);
+                y = y.min(viewport.bottom - size.height).max(viewport.top);
+
                 root.outer.left = x;
                 root.outer.top = y;
                 root.outer.right = x + size.width;
@@ -998,6 +1017,21 @@ impl Tui {
                         tc.scroll_offset.y,
                         tb.visual_line_count() + inner.height() - 1,
                     );
+
+                    // The marks are placed relative to the document's length, on top of the thumb.
+                    // A thumb height of 0 means that the whole document fits and there's no track.
+                    if tc.thumb_height != 0 {
+                        let lines = tb.visual_line_count().max(1) as i64;
+                        for (line, color) in tb.scrollbar_markers() {
+                            let y = track.top
+                                + (line as i64 * track.height() as i64 / lines) as CoordType;
+                            let rect =
+                                Rect { top: y, bottom: y + 1, ..track }.intersect(inner_clipped);
+                            let color = self.framebuffer.indexed(color);
+                            self.framebuffer.blend_bg(rect, color);
+                            self.framebuffer.blend_fg(rect, color);
+                        }
+                    }
                 }
             }
             NodeContent::Scrollarea(sc) => {
@@ -1315,6 +1349,8 @@ pub struct Context<'a, 'input> {
 
     /// Current text input, if any.
     input_text: Option<&'input str>,
+    /// Text from a bracketed paste, if any.
+    input_paste: Option<Vec<u8>>,
     /// Current keyboard input, if any.
     input_keyboard: Option<InputKey>,
     input_mouse_modifiers: InputKeyMod,
@@ -1393,6 +1429,11 @@ impl<'a> Context<'a, '_> {
         self.needs_settling = true;
     }
 
+    /// Like [`Context::needs_rerender`], but after `timeout` and only if there's no input before then.
+    pub fn needs_rerender_in(&mut self, timeout: time::Duration) {
+        self.tui.read_timeout = self.tui.read_timeout.min(timeout);
+    }
+
     /// Begins a generic UI block (container) with a unique ID derived from the given `classname`.
     pub fn block_begin(&mut self, classname: &'static str) {
         let parent = self.tree.current_node;
@@ -1694,6 +1735,12 @@ impl<'a> Context<'a, '_> {
         self.tui.was_mouse_down_on_node(last_node.id)
     }
 
+    /// Returns how many times in a row the current node was just clicked:
+    /// 1 for a single click, 2 for a double-click and so on. 0 if it wasn't clicked.
+    pub fn click_count(&mut self) -> CoordType {
+        if self.was_mouse_down() { self.input_mouse_click } else { 0 }
+    }
+
     /// Returns whether the mouse was pressed down on the current node's subtree.
     pub fn contains_mouse_down(&mut self) -> bool {
         let last_node = self.tree.last_node.borrow();
@@ -2130,6 +2177,9 @@ impl<'a> Context<'a, '_> {
                 let mut make_cursor_visible;
                 {
                     let mut tb = content.buffer.borrow_mut();
+                    if let Some(offset) = tb.take_scroll_request() {
+                        content.scroll_offset = offset;
+                    }
                     make_cursor_visible = tb.take_cursor_visibility_request();
                     make_cursor_visible |= tb.set_width(text_width);
                 }
@@ -2215,8 +2265,14 @@ impl<'a> Context<'a, '_> {
                 };
 
                 if text_rect.contains(self.tui.mouse_down_position) {
+                    tb.clear_extra_cursors();
+
                     if self.tui.mouse_is_drag {
-                        tb.selection_update_visual(pos);
+                        if self.input_mouse_modifiers.contains(kbmod::ALT) {
+                            tb.block_selection_update_visual(pos);
+                        } else {
+                            tb.selection_update_visual(pos);
+                        }
                         tc.preferred_column = tb.cursor_visual_pos().x;
 
                         let height = inner.height();
@@ -2278,6 +2334,27 @@ impl<'a> Context<'a, '_> {
                 } else if track_rect.contains(self.tui.mouse_down_position) {
                     if self.tui.mouse_state == InputMouseState::Release {
                         tc.scroll_offset_y_drag_start = CoordType::MIN;
+
+                        // Clicking the track next to the thumb scrolls to that relative position.
+                        let scrollable_height = tb.visual_line_count() - 1;
+                        let track_height = track_rect.height();
+                        if !self.tui.mouse_is_drag
+                            && self.input_mouse_click != 0
+                            && scrollable_height > 0
+                            && track_height > 1
+                        {
+                            let y = mouse.y - track_rect.top;
+                            let trackable = track_height - tc.thumb_height;
+                            let thumb_top = (tc.scroll_offset.y.clamp(0, scrollable_height) as i64
+                                * trackable as i64
+                                / scrollable_height as i64)
+                                as CoordType;
+                            if !(thumb_top..thumb_top + tc.thumb_height).contains(&y) {
+                                tc.scroll_offset.y = (y as i64 * scrollable_height as i64
+                                    / (track_height - 1) as i64)
+                                    as CoordType;
+                            }
+                        }
                     } else if self.tui.mouse_is_drag {
                         if tc.scroll_offset_y_drag_start == CoordType::MIN {
                             tc.scroll_offset_y_drag_start = tc.scroll_offset.y;
@@ -2307,16 +2384,38 @@ impl<'a> Context<'a, '_> {
             return false;
         }
 
+        // A copy, since the clipboard shortcuts below need `self` while `write` is alive.
+        let paste = self.input_paste.clone();
         let mut write: &[u8] = &[];
+        let mut write_raw = false;
 
         if let Some(input) = &self.input_text {
             write = input.as_bytes();
+        } else if let Some(paste) = &paste {
+            // Pasted text must not be auto-indented or auto-closed like typed text would be.
+            write = paste;
+            write_raw = true;
         } else if let Some(input) = &self.input_keyboard {
             let key = input.key();
             let modifiers = input.modifiers();
 
             make_cursor_visible = true;
 
+            // Additional cursors follow along with edits, but not with navigation.
+            if matches!(
+                key,
+                vk::LEFT
+                    | vk::RIGHT
+                    | vk::UP
+                    | vk::DOWN
+                    | vk::HOME
+                    | vk::END
+                    | vk::PRIOR
+                    | vk::NEXT
+            ) {
+                tb.clear_extra_cursors();
+            }
+
             match key {
                 vk::BACK => {
                     let granularity = if modifiers == kbmod::CTRL {
@@ -2331,6 +2430,10 @@ impl<'a> Context<'a, '_> {
                         // If this is just a simple input field, don't consume Tab (= early return).
                         return false;
                     }
+                    if modifiers.contains(kbmod::CTRL) {
+                        // Ctrl+Tab is left to the application, e.g. for switching documents.
+                        return false;
+                    }
                     tb.indent_change(if modifiers == kbmod::SHIFT { -1 } else { 1 });
                 }
                 vk::RETURN => {
@@ -2341,8 +2444,9 @@ impl<'a> Context<'a, '_> {
                     write = b"\n";
                 }
                 vk::ESCAPE => {
-                    // If there was a selection, clear it and show the cursor (= fallthrough).
-                    if !tb.clear_selection() {
+                    // If there were additional cursors or a selection,
+                    // clear them and show the cursor (= fallthrough).
+                    if !tb.clear_extra_cursors() && !tb.clear_selection() {
                         if single_line {
                             // If this is just a simple input field, don't consume the escape key
                             // (early return) and don't show the cursor (= return false).
@@ -2354,6 +2458,8 @@ impl<'a> Context<'a, '_> {
                         make_cursor_visible = false;
                     }
                 }
+                // Ctrl+PageUp/Down are left to the app, e.g. for moving tabs.
+                vk::PRIOR | vk::NEXT if modifiers.contains(kbmod::CTRL) => return false,
                 vk::PRIOR => {
                     let height = node_prev.inner.height() - 1;
 
@@ -2489,12 +2595,17 @@ impl<'a> Context<'a, '_> {
                     }
                 }
                 vk::LEFT => {
+                    if modifiers == KBMOD_FOR_GO_BACK {
+                        return false;
+                    }
                     let granularity = if modifiers.contains(KBMOD_FOR_WORD_NAV) {
                         CursorMovement::Word
                     } else {
                         CursorMovement::Grapheme
                     };
-                    if modifiers.contains(kbmod::SHIFT) {
+                    if modifiers == KBMOD_FOR_BLOCK_SELECT {
+                        tb.block_selection_extend(Point { x: -1, y: 0 });
+                    } else if modifiers.contains(kbmod::SHIFT) {
                         tb.selection_update_delta(granularity, -1);
                     } else if let Some((beg, _)) = tb.selection_range() {
                         unsafe { tb.set_cursor(beg) };
@@ -2514,7 +2625,7 @@ impl<'a> Context<'a, '_> {
                             // If there's a selection we put the cursor above it.
                             if let Some((beg, _)) = tb.selection_range() {
                                 x = beg.visual_pos.x;
-                                y = beg.visual_pos.y - 1;
+                                y = tb.fold_visual_y(beg.visual_pos.y) - 1;
                                 tc.preferred_column = x;
                             }
 
@@ -2543,7 +2654,7 @@ impl<'a> Context<'a, '_> {
                                 y: tb.cursor_visual_pos().y - 1,
                             });
                         }
-                        kbmod::ALT => tb.move_selected_lines(MoveLineDirection::Up),
+                        KBMOD_FOR_BLOCK_SELECT => tb.block_selection_extend(Point { x: 0, y: -1 }),
                         kbmod::CTRL_ALT => {
                             // TODO: Add cursor above
                         }
@@ -2551,12 +2662,17 @@ impl<'a> Context<'a, '_> {
                     }
                 }
                 vk::RIGHT => {
+                    if modifiers == KBMOD_FOR_GO_BACK {
+                        return false;
+                    }
                     let granularity = if modifiers.contains(KBMOD_FOR_WORD_NAV) {
                         CursorMovement::Word
                     } else {
                         CursorMovement::Grapheme
                     };
-                    if modifiers.contains(kbmod::SHIFT) {
+                    if modifiers == KBMOD_FOR_BLOCK_SELECT {
+                        tb.block_selection_extend(Point { x: 1, y: 0 });
+                    } else if modifiers.contains(kbmod::SHIFT) {
                         tb.selection_update_delta(granularity, 1);
                     } else if let Some((_, end)) = tb.selection_range() {
                         unsafe { tb.set_cursor(end) };
@@ -2576,7 +2692,7 @@ impl<'a> Context<'a, '_> {
                             // If there's a selection we put the cursor below it.
                             if let Some((_, end)) = tb.selection_range() {
                                 x = end.visual_pos.x;
-                                y = end.visual_pos.y + 1;
+                                y = tb.fold_visual_y(end.visual_pos.y) + 1;
                                 tc.preferred_column = x;
                             }
 
@@ -2614,7 +2730,7 @@ impl<'a> Context<'a, '_> {
                                 tc.preferred_column = tb.cursor_visual_pos().x;
                             }
                         }
-                        kbmod::ALT => tb.move_selected_lines(MoveLineDirection::Down),
+                        KBMOD_FOR_BLOCK_SELECT => tb.block_selection_extend(Point { x: 0, y: 1 }),
                         kbmod::CTRL_ALT => {
                             // TODO: Add cursor above
                         }
@@ -2622,7 +2738,11 @@ impl<'a> Context<'a, '_> {
                     }
                 }
                 vk::INSERT => match modifiers {
-                    kbmod::SHIFT => tb.paste(self.clipboard_ref()),
+                    kbmod::SHIFT => {
+                        let clipboard = self.clipboard_mut();
+                        clipboard.sync_from_system();
+                        tb.paste(clipboard);
+                    }
                     kbmod::CTRL => tb.copy(self.clipboard_mut()),
                     _ => tb.set_overtype(!tb.is_overtype()),
                 },
@@ -2643,6 +2763,10 @@ impl<'a> Context<'a, '_> {
                     }
                     _ => return false,
                 },
+                vk::D => match modifiers {
+                    kbmod::CTRL if !single_line => tb.add_cursor_at_next_occurrence(),
+                    _ => return false,
+                },
                 vk::F => match modifiers {
                     kbmod::ALT if cfg!(target_os = "macos") => {
                         // On macOS, terminals commonly emit the Emacs style
@@ -2668,7 +2792,11 @@ impl<'a> Context<'a, '_> {
                     _ => return false,
                 },
                 vk::V => match modifiers {
-                    kbmod::CTRL => tb.paste(self.clipboard_ref()),
+                    kbmod::CTRL => {
+                        let clipboard = self.clipboard_mut();
+                        clipboard.sync_from_system();
+                        tb.paste(clipboard);
+                    }
                     _ => return false,
                 },
                 vk::Y => match modifiers {
@@ -2678,7 +2806,6 @@ impl<'a> Context<'a, '_> {
                 vk::Z => match modifiers {
                     kbmod::CTRL => tb.undo(),
                     kbmod::CTRL_SHIFT => tb.redo(),
-                    kbmod::ALT => tb.set_word_wrap(!tb.is_word_wrap_enabled()),
                     _ => return false,
                 },
                 _ => return false,
@@ -2690,11 +2817,16 @@ impl<'a> Context<'a, '_> {
         }
 
         if single_line && !write.is_empty() {
-            let (end, _) = simd::lines_fwd(write, 0, 0, 1);
-            write = unicode::strip_newline(&write[..end]);
+            // Bracketed paste uses CR for newlines, so `simd::lines_fwd` won't do.
+            let end = write.iter().position(|&c| c == b'\r' || c == b'\n').unwrap_or(write.len());
+            write = &write[..end];
         }
         if !write.is_empty() {
-            tb.write_canon(write);
+            if write_raw {
+                tb.write_raw(write);
+            } else {
+                tb.write_canon(write);
+            }
             change_preferred_column = true;
             make_cursor_visible = true;
         }
@@ -2719,10 +2851,14 @@ impl<'a> Context<'a, '_> {
 
         let viewport_height = node_prev.inner.height();
         let cursor_y = tb.cursor_visual_pos().y;
+        // The lines to keep visible around the cursor. At most half of the viewport,
+        // or the cursor couldn't be placed anywhere. Near the end there are fewer of them.
+        let scroll_off = tb.scroll_off().min((viewport_height - 1) / 2).max(0);
+        let scroll_off_below = scroll_off.min(tb.visual_line_count() - 1 - cursor_y).max(0);
         // Scroll up if the cursor is above the visible area.
-        scroll_y = scroll_y.min(cursor_y);
+        scroll_y = scroll_y.min(cursor_y - scroll_off);
         // Scroll down if the cursor is below the visible area.
-        scroll_y = scroll_y.max(cursor_y - viewport_height + 1);
+        scroll_y = scroll_y.max(cursor_y - viewport_height + 1 + scroll_off_below);
 
         tc.scroll_offset.x = scroll_x;
         tc.scroll_offset.y = scroll_y;
@@ -3298,9 +3434,22 @@ impl<'a> Context<'a, '_> {
     }
 
     fn menubar_shortcut(&mut self, shortcut: InputKey) {
-        let shortcut_letter = shortcut.value() as u8 as char;
-        if shortcut_letter.is_ascii_uppercase() {
-            let mut shortcut_text = ArenaString::new_in(self.arena());
+        let shortcut_text = self.shortcut_text(shortcut);
+        if !shortcut_text.is_empty() {
+            self.label("shortcut", &shortcut_text);
+        } else {
+            self.block_begin("shortcut");
+            self.block_end();
+        }
+        self.attr_padding(Rect { left: 2, top: 0, right: 2, bottom: 0 });
+    }
+
+    /// Formats a shortcut the way menus display it, e.g. "Ctrl+Shift+P" or "Alt+Up".
+    /// Returns an empty string for shortcuts without a key, like [`vk::NULL`].
+    pub fn shortcut_text(&self, shortcut: InputKey) -> ArenaString<'a> {
+        let mut shortcut_text = ArenaString::new_in(self.arena());
+
+        if let Some(key_name) = shortcut.key_name() {
             if shortcut.modifiers_contains(kbmod::CTRL) {
                 shortcut_text.push_str(self.tui.modifier_translations.ctrl);
                 shortcut_text.push('+');
@@ -3313,14 +3462,10 @@ impl<'a> Context<'a, '_> {
                 shortcut_text.push_str(self.tui.modifier_translations.shift);
                 shortcut_text.push('+');
             }
-            shortcut_text.push(shortcut_letter);
-
-            self.label("shortcut", &shortcut_text);
-        } else {
-            self.block_begin("shortcut");
-            self.block_end();
+            shortcut_text.push_str(key_name);
         }
-        self.attr_padding(Rect { left: 2, top: 0, right: 2, bottom: 0 });
+
+        shortcut_text
     }
 }
 